@@ -0,0 +1,104 @@
+use crate::{config::AlertRule, prices::PriceSeries};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use std::fmt;
+
+/// An alert raised by an [AlertRule].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub commodity: String,
+    pub base: String,
+    pub date: NaiveDate,
+    pub price: Decimal,
+    pub kind: AlertKind,
+}
+
+/// What an [Alert] is about.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlertKind {
+    /// The price moved by `percent` (signed) from `previous`.
+    Change { previous: Decimal, percent: Decimal },
+    /// The price crossed `level`, upwards if `rising`.
+    Crossed { level: Decimal, rising: bool },
+}
+
+/// Decimal places of the prices in an alert's message, which are only for
+/// reading.
+const MESSAGE_DP: u32 = 6;
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let price = self.price.round_dp(MESSAGE_DP);
+        let pair = format!("{}/{}", self.commodity, self.base);
+        let date = self.date.format("%Y-%m-%d");
+        match &self.kind {
+            AlertKind::Change { previous, percent } => write!(
+                f,
+                "{} moved {}{}% on {} ({} to {})",
+                pair,
+                if percent.is_sign_positive() { "+" } else { "" },
+                percent.round_dp(2),
+                date,
+                previous.round_dp(MESSAGE_DP),
+                price
+            ),
+            AlertKind::Crossed { level, rising } => write!(
+                f,
+                "{} crossed {} {} on {} ({})",
+                pair,
+                if *rising { "above" } else { "below" },
+                level,
+                date,
+                price
+            ),
+        }
+    }
+}
+
+/// Check `rule` against the new prices in `prices`, comparing each with the
+/// one before it, starting with `previous` (the last price before these,
+/// if there is one). Series for other pairs raise nothing.
+pub fn evaluate(rule: &AlertRule, prices: &PriceSeries, previous: Option<Decimal>) -> Vec<Alert> {
+    let mut alerts = Vec::new();
+    if prices.commodity != rule.commodity || prices.base != rule.base {
+        return alerts;
+    }
+
+    let mut previous = previous;
+    for (date, price) in &prices.prices {
+        let rate = price.rate;
+        let alert = |kind| Alert {
+            commodity: prices.commodity.to_string(),
+            base: prices.base.to_string(),
+            date: *date,
+            price: rate,
+            kind,
+        };
+
+        if let Some(previous) = previous {
+            if let Some(threshold) = rule.change_percent {
+                if !previous.is_zero() {
+                    let percent = (rate - previous) / previous * Decimal::from(100);
+                    if percent.abs() > threshold {
+                        alerts.push(alert(AlertKind::Change { previous, percent }));
+                    }
+                }
+            }
+            if let Some(level) = rule.crosses {
+                if previous < level && rate >= level {
+                    alerts.push(alert(AlertKind::Crossed {
+                        level,
+                        rising: true,
+                    }));
+                } else if previous > level && rate <= level {
+                    alerts.push(alert(AlertKind::Crossed {
+                        level,
+                        rising: false,
+                    }));
+                }
+            }
+        }
+        previous = Some(rate);
+    }
+    alerts
+}
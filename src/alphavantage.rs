@@ -0,0 +1,233 @@
+use crate::{
+    config::ProviderConfig,
+    metadata,
+    network::{request_json, RequestError},
+    pricesource::{self, PriceSource},
+    series::Closes,
+};
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "alphavantage";
+
+/// The environment variable the API key can be given in.
+pub const API_KEY_ENV: &str = "ALPHAVANTAGE_API_KEY";
+
+const API_URL: &str = "https://www.alphavantage.co/query";
+
+/// The `compact` output size has this many of the latest trading days,
+/// older ranges need `full`.
+const COMPACT_DAYS: i64 = 100;
+
+/// Which of the daily time series a symbol is fetched from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Market {
+    /// `FX_DAILY`, a currency against the base.
+    Fx,
+    /// `DIGITAL_CURRENCY_DAILY`, a cryptocurrency in the base.
+    Crypto,
+    /// `TIME_SERIES_DAILY`, a stock or ETF in its listing's currency.
+    Equity,
+}
+
+impl Market {
+    /// The market of `symbol` and the symbol without its prefix: `fx:`,
+    /// `crypto:` or `stock:` choose one, otherwise an ISO 4217 currency is
+    /// fetched as FX and anything else as a stock.
+    fn of(symbol: &str) -> (Market, &str) {
+        for (prefix, market) in &[
+            ("fx:", Market::Fx),
+            ("crypto:", Market::Crypto),
+            ("stock:", Market::Equity),
+        ] {
+            if let Some(symbol) = symbol.strip_prefix(prefix) {
+                return (*market, symbol);
+            }
+        }
+        match metadata::iso_decimal_places(symbol) {
+            Some(_) => (Market::Fx, symbol),
+            None => (Market::Equity, symbol),
+        }
+    }
+}
+
+/// A response from one of the daily time series functions. The series
+/// is under a key which depends on the function (e.g.
+/// `Time Series FX (Daily)`), and errors come back with a 200.
+#[derive(Deserialize, Debug)]
+struct DailyResponse {
+    #[serde(rename = "Error Message")]
+    error_message: Option<String>,
+    /// Sent instead of the series when the per minute limit is hit.
+    #[serde(rename = "Note")]
+    note: Option<String>,
+    /// Sent instead of the series for the daily limit and premium
+    /// functions.
+    #[serde(rename = "Information")]
+    information: Option<String>,
+    #[serde(flatten)]
+    rest: BTreeMap<String, serde_json::Value>,
+}
+
+/// Fetch the daily closing prices of the Alpha Vantage `symbol` (see
+/// [Market::of()]) in `base` from `start` to `end`, as the prices of
+/// `commodity`.
+///
+/// Alpha Vantage doesn't say which currency a stock is quoted in, so its
+/// prices are taken to be in `base`.
+pub async fn get_closes(
+    client: &Client,
+    api_key: &str,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Closes> {
+    let (market, symbol) = Market::of(symbol);
+    let output_size = if *start >= Utc::now().date_naive() - Duration::days(COMPACT_DAYS) {
+        "compact"
+    } else {
+        "full"
+    };
+    let encode =
+        |value: &str| url::form_urlencoded::byte_serialize(value.as_bytes()).collect::<String>();
+    let query = match market {
+        Market::Fx => format!(
+            "function=FX_DAILY&from_symbol={}&to_symbol={}&outputsize={}",
+            encode(symbol),
+            base,
+            output_size
+        ),
+        Market::Crypto => format!(
+            "function=DIGITAL_CURRENCY_DAILY&symbol={}&market={}",
+            encode(symbol),
+            base
+        ),
+        Market::Equity => format!(
+            "function=TIME_SERIES_DAILY&symbol={}&outputsize={}",
+            encode(symbol),
+            output_size
+        ),
+    };
+    let url = format!("{}?{}&apikey={}", API_URL, query, encode(api_key));
+    let response: DailyResponse = request_json(client, &url).await?;
+
+    if let Some(message) = response.error_message {
+        return Err(anyhow!(
+            "{} has no prices for {}: {}",
+            PROVIDER_NAME,
+            symbol,
+            message
+        ));
+    }
+    if response.note.is_some() {
+        return Err(RequestError::TooManyRequests.into());
+    }
+    if let Some(message) = response.information {
+        return Err(anyhow!(
+            "{} refused the request: {}",
+            PROVIDER_NAME,
+            message
+        ));
+    }
+
+    let series = response
+        .rest
+        .into_iter()
+        .find(|(key, _)| key.starts_with("Time Series"))
+        .map(|(_, series)| series)
+        .ok_or_else(|| anyhow!("{} has no prices for {}", PROVIDER_NAME, symbol))?;
+    let days: BTreeMap<String, BTreeMap<String, String>> = serde_json::from_value(series)
+        .map_err(|err| RequestError::InvalidResponse(err.to_string()))?;
+
+    let mut closes = BTreeMap::new();
+    for (date, fields) in days {
+        let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+            .map_err(|err| RequestError::InvalidResponse(err.to_string()))?;
+        if !(start..=end).contains(&&date) {
+            continue;
+        }
+        // The crypto series used to give the close in the market and in
+        // USD, as `4a. close (EUR)` and `4b. close (USD)`.
+        let close = fields
+            .iter()
+            .find(|(field, _)| field.starts_with("4. close") || field.starts_with("4a. close"))
+            .map(|(_, close)| close)
+            .ok_or_else(|| {
+                RequestError::InvalidResponse(format!("no close for {} on {}", symbol, date))
+            })?;
+        let close = Decimal::from_str(close)
+            .map_err(|err| RequestError::InvalidResponse(err.to_string()))?;
+        closes.insert(date, close);
+    }
+
+    Ok(Closes {
+        commodity,
+        currency: base,
+        closes,
+    })
+}
+
+/// Alpha Vantage as a [PriceSource], with the symbols in its `config` and
+/// the prices in `base`.
+pub struct AlphaVantage<'a> {
+    client: &'a Client,
+    api_key: &'a str,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> AlphaVantage<'a> {
+    pub fn new(
+        client: &'a Client,
+        api_key: &'a str,
+        config: &'a ProviderConfig,
+        base: CommodityTypeID,
+    ) -> AlphaVantage<'a> {
+        AlphaVantage {
+            client,
+            api_key,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for AlphaVantage<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            self.client,
+            self.api_key,
+            commodity,
+            &self.config.symbol(&commodity),
+            self.base,
+            start,
+            end,
+        )
+        .await
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{
+    network::{request_json, RequestError},
+    pricesource::{self, PriceSource},
+    series::{Snapshot, TimeSeries},
+};
+use anyhow::anyhow;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "bankofcanada";
+
+/// The commodity the rates are quoted against.
+pub const BASE: &str = "CAD";
+
+/// The observations of the group of daily exchange rates, which are
+/// published from 2017.
+const API_URL: &str = "https://www.bankofcanada.ca/valet/observations/group/FX_RATES_DAILY/json";
+
+/// Data from the Valet observations endpoint.
+#[derive(Deserialize, Debug)]
+struct Observations {
+    /// The values of each day, keyed by series (e.g. `FXUSDCAD`), and its
+    /// date as `d`.
+    observations: Vec<BTreeMap<String, serde_json::Value>>,
+}
+
+/// Fetch the daily exchange rates from `start` to `end`. The Bank of Canada
+/// only publishes rates for business days.
+pub async fn get_series(
+    client: &Client,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<TimeSeries> {
+    let url = format!(
+        "{}?start_date={}&end_date={}",
+        API_URL,
+        start.format("%Y-%m-%d"),
+        end.format("%Y-%m-%d")
+    );
+    let mut map = parse(request_json(client, &url).await?)?;
+    map.retain(|date, _| (start..=end).contains(&date));
+    Ok(TimeSeries::new(map))
+}
+
+/// Fetch the latest day's exchange rates.
+pub async fn get_latest(client: &Client) -> anyhow::Result<ExchangeRate> {
+    let url = format!("{}?recent=1", API_URL);
+    parse(request_json(client, &url).await?)?
+        .into_iter()
+        .next_back()
+        .map(|(_, exchange_rate)| exchange_rate)
+        .ok_or_else(|| anyhow!("{} has no latest rates", PROVIDER_NAME))
+}
+
+/// The exchange rates of each day of `observations`, whose series are the
+/// CAD value of a unit of each currency (e.g. `FXUSDCAD`) given as
+/// `{"v": "1.2988"}`.
+fn parse(observations: Observations) -> anyhow::Result<BTreeMap<NaiveDate, ExchangeRate>> {
+    let invalid = |message: String| anyhow::Error::from(RequestError::InvalidResponse(message));
+    let base = CommodityTypeID::from_str(BASE).expect("expected a valid commodity");
+    let mut map = BTreeMap::new();
+    for observation in observations.observations {
+        let date = observation
+            .get("d")
+            .and_then(|date| date.as_str())
+            .ok_or_else(|| invalid(String::from("an observation without a date")))?;
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|err| invalid(format!("unable to parse the date {:?}: {}", date, err)))?;
+        let mut rates = BTreeMap::new();
+        for (series, value) in &observation {
+            let currency = match series
+                .strip_prefix("FX")
+                .and_then(|series| series.strip_suffix(BASE))
+            {
+                Some(currency) => currency,
+                None => continue,
+            };
+            // A day a series wasn't published has no value.
+            let value = match value.get("v").and_then(|value| value.as_str()) {
+                Some(value) => value,
+                None => continue,
+            };
+            let currency = CommodityTypeID::from_str(currency)
+                .map_err(|err| invalid(format!("unable to parse the currency: {}", err)))?;
+            let value = Decimal::from_str(value)
+                .map_err(|err| invalid(format!("unable to parse the rate {:?}: {}", value, err)))?;
+            if !value.is_zero() {
+                rates.insert(currency, Decimal::ONE / value);
+            }
+        }
+        map.insert(
+            date,
+            ExchangeRate {
+                date: Some(date),
+                obtained_datetime: Some(Utc::now()),
+                base: Some(base),
+                rates,
+            },
+        );
+    }
+    Ok(map)
+}
+
+/// The Bank of Canada's rates as a [PriceSource], a table for the whole
+/// range.
+pub struct BankOfCanada<'a> {
+    client: &'a Client,
+}
+
+impl<'a> BankOfCanada<'a> {
+    pub fn new(client: &'a Client) -> BankOfCanada<'a> {
+        BankOfCanada { client }
+    }
+}
+
+impl PriceSource for BankOfCanada<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        _include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        Ok((get_latest(self.client).await?, None))
+    }
+
+    async fn series(
+        &self,
+        start: &NaiveDate,
+        end: &NaiveDate,
+        _include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<Vec<Snapshot>> {
+        Ok(pricesource::snapshots(
+            get_series(self.client, start, end).await?,
+        ))
+    }
+}
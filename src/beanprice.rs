@@ -0,0 +1,254 @@
+use crate::{
+    config::ProviderConfig,
+    pricesource::{self, PriceSource},
+    series::Closes,
+};
+use anyhow::anyhow;
+use chrono::{NaiveDate, NaiveDateTime};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use futures::channel::oneshot;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::{collections::BTreeMap, process::Command, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "beanprice";
+
+/// The Python interpreter run when the provider doesn't set `python`.
+const DEFAULT_PYTHON: &str = "python3";
+
+/// The script run with the module, ticker, start and end dates as its
+/// arguments, which prints each price the module's `Source` gives as a
+/// line of JSON. It uses the source's `get_prices_series()` if it has
+/// one, and otherwise asks `get_historical_price()` for each day, at the
+/// end of the day in UTC.
+const SCRIPT: &str = r#"
+import datetime, importlib, json, sys
+
+module, ticker, start, end = sys.argv[1:5]
+source = importlib.import_module(module).Source()
+start = datetime.date.fromisoformat(start)
+end = datetime.date.fromisoformat(end)
+
+def at_end_of(date):
+    return datetime.datetime.combine(
+        date, datetime.time(23, 59, 59), tzinfo=datetime.timezone.utc
+    )
+
+def emit(price, date):
+    if price is None or price.price is None:
+        return
+    time = price.time.date() if price.time is not None else date
+    print(json.dumps({
+        "date": time.isoformat(),
+        "price": str(price.price),
+        "currency": price.quote_currency,
+    }))
+
+if hasattr(source, "get_prices_series"):
+    for price in source.get_prices_series(ticker, at_end_of(start), at_end_of(end)) or []:
+        emit(price, None)
+else:
+    date = start
+    while date <= end:
+        emit(source.get_historical_price(ticker, at_end_of(date)), date)
+        date += datetime.timedelta(days=1)
+"#;
+
+/// A price printed by the [SCRIPT].
+#[derive(Deserialize, Debug)]
+struct Price {
+    date: NaiveDate,
+    price: String,
+    currency: Option<String>,
+}
+
+/// How the `beanprice` source runs bean-price's source modules, from its
+/// provider's config.
+#[derive(Debug)]
+pub struct Bridge {
+    python: String,
+    /// The module of the commodities whose symbol doesn't name one.
+    module: Option<String>,
+}
+
+impl Bridge {
+    /// The bridge in the `provider_config` of the `beanprice` source.
+    pub fn from_config(provider_config: &ProviderConfig) -> Bridge {
+        Bridge {
+            python: provider_config
+                .python
+                .clone()
+                .unwrap_or_else(|| DEFAULT_PYTHON.to_string()),
+            module: provider_config.module.clone(),
+        }
+    }
+
+    /// The module and ticker of `symbol`, which is written like in
+    /// bean-price's price sources (`module/TICKER`, e.g.
+    /// `beanprice.sources.yahoo/VAS.AX`), or is just the ticker to fetch
+    /// from the provider's `module`.
+    fn source<'a>(&'a self, symbol: &'a str) -> anyhow::Result<(&'a str, &'a str)> {
+        match (symbol.rsplit_once('/'), &self.module) {
+            (Some((module, ticker)), _) => Ok((module, ticker)),
+            (None, Some(module)) => Ok((module, symbol)),
+            (None, None) => Err(anyhow!(
+                "no module for {} (set module in providers.{} in the config, or give its \
+                symbol as module/TICKER)",
+                symbol,
+                PROVIDER_NAME
+            )),
+        }
+    }
+}
+
+/// Fetch the prices of `commodity` from `start` to `end` by running the
+/// bean-price source module of `symbol` (see [Bridge::source()]) in
+/// Python, in a thread of its own so commodities are fetched in
+/// parallel. The prices are in the quote currency the module gives, or
+/// `base` if it doesn't.
+pub async fn get_closes(
+    bridge: &Bridge,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Closes> {
+    let (module, ticker) = bridge.source(symbol)?;
+    let mut command = Command::new(&bridge.python);
+    command
+        .arg("-c")
+        .arg(SCRIPT)
+        .arg(module)
+        .arg(ticker)
+        .arg(start.format("%Y-%m-%d").to_string())
+        .arg(end.format("%Y-%m-%d").to_string());
+    let (sender, receiver) = oneshot::channel();
+    std::thread::spawn(move || sender.send(command.output()));
+    let output = receiver
+        .await
+        .map_err(|_| anyhow!("Unable to run {}: it was stopped", bridge.python))?
+        .map_err(|err| {
+            anyhow!(
+                "Unable to run {} (is it installed, and on the PATH?): {}",
+                bridge.python,
+                err
+            )
+        })?;
+    if !output.status.success() {
+        // The last line of a traceback is the exception.
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!(
+            "{} failed to fetch {}: {}",
+            module,
+            ticker,
+            stderr
+                .lines()
+                .rev()
+                .find(|line| !line.trim().is_empty())
+                .unwrap_or("it exited with an error")
+        ));
+    }
+
+    let mut currency = None;
+    let mut closes = BTreeMap::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let line = line.trim();
+        // Anything else the module prints.
+        let price: Price = match serde_json::from_str(line) {
+            Ok(price) => price,
+            Err(_) => continue,
+        };
+        let price_currency = match &price.currency {
+            Some(price_currency) => CommodityTypeID::from_str(price_currency).map_err(|err| {
+                anyhow!(
+                    "Unable to parse the quote currency {:?} of {}: {}",
+                    price_currency,
+                    ticker,
+                    err
+                )
+            })?,
+            None => base,
+        };
+        match currency {
+            None => currency = Some(price_currency),
+            Some(currency) if currency != price_currency => {
+                return Err(anyhow!(
+                    "{} quoted {} in both {} and {}",
+                    module,
+                    ticker,
+                    currency,
+                    price_currency
+                ))
+            }
+            Some(_) => {}
+        }
+        let value = Decimal::from_str(&price.price).map_err(|err| {
+            anyhow!(
+                "Unable to parse the price {:?} of {}: {}",
+                price.price,
+                ticker,
+                err
+            )
+        })?;
+        if (start..=end).contains(&&price.date) {
+            closes.insert(price.date, value);
+        }
+    }
+
+    Ok(Closes {
+        commodity,
+        currency: currency.unwrap_or(base),
+        closes,
+    })
+}
+
+/// The bean-price source modules run by `bridge` as a [PriceSource], with
+/// the symbols in its `config` and the prices in `base`.
+pub struct BeanPrice<'a> {
+    bridge: Bridge,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> BeanPrice<'a> {
+    pub fn new(bridge: Bridge, config: &'a ProviderConfig, base: CommodityTypeID) -> BeanPrice<'a> {
+        BeanPrice {
+            bridge,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for BeanPrice<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            &self.bridge,
+            commodity,
+            &self.config.symbol(&commodity),
+            self.base,
+            start,
+            end,
+        )
+        .await
+    }
+}
@@ -0,0 +1,282 @@
+use crate::warnings::{warn, Kind};
+use anyhow::anyhow;
+use chrono::NaiveDate;
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// An on-disk cache of the daily exchange rates fetched from a provider,
+/// stored as one JSON file per day in `<cache-dir>/<provider>/`.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    /// Cache for the provider called `provider`, within `cache_dir`.
+    pub fn new<P: AsRef<Path>>(cache_dir: P, provider: &str) -> Cache {
+        Cache {
+            dir: cache_dir.as_ref().join(provider),
+        }
+    }
+
+    fn path(&self, date: &NaiveDate) -> PathBuf {
+        self.dir.join(format!("{}.json", date.format("%Y-%m-%d")))
+    }
+
+    /// The cached exchange rate for `date`, if there is one.
+    pub fn get(&self, date: &NaiveDate) -> anyhow::Result<Option<ExchangeRate>> {
+        let path = self.path(date);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        read(&path)
+    }
+
+    /// The cached exchange rate for `date`, only if it includes every
+    /// one of the `commodities`.
+    pub fn get_complete(
+        &self,
+        date: &NaiveDate,
+        commodities: &[CommodityTypeID],
+    ) -> anyhow::Result<Option<ExchangeRate>> {
+        Ok(self.get(date)?.filter(|exchange_rate| {
+            commodities.iter().all(|commodity| {
+                exchange_rate.base.as_ref() == Some(commodity)
+                    || exchange_rate.rates.contains_key(commodity)
+            })
+        }))
+    }
+
+    /// The cached exchange rate with all the `commodities` (see
+    /// [Cache::get_complete()]) for `date`, or failing that, for the
+    /// nearest date before it up to `max_days` earlier, with the date it
+    /// was cached for.
+    pub fn get_nearest_prior_complete(
+        &self,
+        date: &NaiveDate,
+        commodities: &[CommodityTypeID],
+        max_days: i64,
+    ) -> anyhow::Result<Option<(NaiveDate, ExchangeRate)>> {
+        for cached in self.dates()?.into_iter().rev() {
+            if cached > *date {
+                continue;
+            }
+            if (*date - cached).num_days() > max_days {
+                break;
+            }
+            if let Some(exchange_rate) = self.get_complete(&cached, commodities)? {
+                return Ok(Some((cached, exchange_rate)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// All the dates which have a cached exchange rate, in ascending
+    /// order.
+    pub fn dates(&self) -> anyhow::Result<Vec<NaiveDate>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut dates: Vec<NaiveDate> = std::fs::read_dir(&self.dir)
+            .map_err(|err| anyhow!("Unable to read cache directory {:?}: {}", self.dir, err))?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let file_name = entry.file_name();
+                let date = file_name.to_str()?.strip_suffix(".json")?;
+                NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+            })
+            .collect();
+        dates.sort();
+        Ok(dates)
+    }
+
+    /// The cached dates within `tolerance` days of `date` (either side),
+    /// nearest first, the earlier of two equally near dates first.
+    pub fn dates_near(&self, date: &NaiveDate, tolerance: i64) -> anyhow::Result<Vec<NaiveDate>> {
+        let mut dates: Vec<NaiveDate> = self
+            .dates()?
+            .into_iter()
+            .filter(|d| (*d - *date).num_days().abs() <= tolerance)
+            .collect();
+        dates.sort_by_key(|d| ((*d - *date).num_days().abs(), *d));
+        Ok(dates)
+    }
+
+    /// The cached file `name` (which isn't named after a date, e.g.
+    /// `currencies.json`), if it exists and was stored less than `max_age`
+    /// ago.
+    pub fn get_file<T: DeserializeOwned>(
+        &self,
+        name: &str,
+        max_age: Duration,
+    ) -> anyhow::Result<Option<T>> {
+        let path = self.dir.join(name);
+        let fresh = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age < max_age);
+        if !fresh {
+            return Ok(None);
+        }
+
+        read(&path)
+    }
+
+    /// Store `value` in the cache as the file `name`, see [Cache::get_file()].
+    pub fn put_file<T: Serialize>(&self, name: &str, value: &T) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|err| anyhow!("Unable to create cache directory {:?}: {}", self.dir, err))?;
+        let path = self.dir.join(name);
+        let contents = serde_json::to_string_pretty(value)?;
+        write(&path, &contents)
+    }
+
+    /// Store `exchange_rate` in the cache. Rates already cached for the
+    /// same date and base which aren't in `exchange_rate` are kept.
+    pub fn put(&self, exchange_rate: &ExchangeRate) -> anyhow::Result<()> {
+        let date = exchange_rate
+            .date
+            .ok_or_else(|| anyhow!("Unable to cache an exchange rate without a date"))?;
+
+        let mut merged = exchange_rate.clone();
+        if let Some(existing) = self.get(&date)? {
+            if existing.base == exchange_rate.base {
+                for (commodity, rate) in existing.rates {
+                    merged.rates.entry(commodity).or_insert(rate);
+                }
+            }
+        }
+
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|err| anyhow!("Unable to create cache directory {:?}: {}", self.dir, err))?;
+        let path = self.path(&date);
+        let contents = serde_json::to_string_pretty(&merged)?;
+        write(&path, &contents)
+    }
+}
+
+/// Read the cache file at `path`. A file which can't be parsed (e.g. one
+/// left truncated by an interrupted write from an older version) is
+/// treated as missing, so that it's fetched and stored again.
+fn read<T: DeserializeOwned>(path: &Path) -> anyhow::Result<Option<T>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| anyhow!("Unable to read cache file {:?}: {}", path, err))?;
+    match serde_json::from_str(&contents) {
+        Ok(value) => Ok(Some(value)),
+        Err(err) => {
+            warn(
+                Kind::Cache,
+                format!(
+                    "the cache file {:?} couldn't be parsed ({}), it's treated as missing",
+                    path, err
+                ),
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// Write `contents` to the cache file at `path` via a temporary file in
+/// the same directory which is renamed over it, so an interrupted write
+/// never leaves a truncated file behind.
+fn write(path: &Path, contents: &str) -> anyhow::Result<()> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("Unable to write cache file {:?}: no file name", path))?;
+    let temporary = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+    std::fs::write(&temporary, contents)
+        .and_then(|_| std::fs::rename(&temporary, path))
+        .map_err(|err| {
+            let _ = std::fs::remove_file(&temporary);
+            anyhow!("Unable to write cache file {:?}: {}", path, err)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cache;
+    use chrono::NaiveDate;
+    use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+    use rust_decimal::Decimal;
+    use std::str::FromStr;
+
+    #[test]
+    fn truncated_file_is_a_miss() {
+        let dir = std::env::temp_dir().join(format!(
+            "beancount-price-fetcher-cache-{}",
+            std::process::id()
+        ));
+        let cache = Cache::new(&dir, "test");
+        let date = NaiveDate::from_ymd_opt(2021, 3, 4).unwrap();
+        let usd = CommodityTypeID::from_str("USD").unwrap();
+        let aud = CommodityTypeID::from_str("AUD").unwrap();
+        let exchange_rate = ExchangeRate {
+            date: Some(date),
+            obtained_datetime: None,
+            base: Some(usd),
+            rates: vec![(aud, Decimal::from_str("1.3").unwrap())]
+                .into_iter()
+                .collect(),
+        };
+
+        cache.put(&exchange_rate).unwrap();
+        let path = cache.path(&date);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, &contents[..contents.len() / 2]).unwrap();
+        assert!(cache.get(&date).unwrap().is_none());
+
+        cache.put(&exchange_rate).unwrap();
+        assert_eq!(cache.get(&date).unwrap(), Some(exchange_rate));
+        assert_eq!(cache.dates().unwrap(), vec![date]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn nearest_prior_complete() {
+        let dir = std::env::temp_dir().join(format!(
+            "beancount-price-fetcher-cache-nearest-{}",
+            std::process::id()
+        ));
+        let cache = Cache::new(&dir, "test");
+        let date = |day| NaiveDate::from_ymd_opt(2021, 3, day).unwrap();
+        let commodity = |code: &str| CommodityTypeID::from_str(code).unwrap();
+        let exchange_rate = |day: Option<u32>, codes: &[&str]| ExchangeRate {
+            date: day.map(date),
+            obtained_datetime: None,
+            base: Some(commodity("USD")),
+            rates: codes
+                .iter()
+                .map(|code| (commodity(code), Decimal::ONE))
+                .collect(),
+        };
+        cache.put(&exchange_rate(Some(1), &["AUD", "EUR"])).unwrap();
+        cache.put(&exchange_rate(Some(3), &["AUD"])).unwrap();
+        // A file without a date in it is still cached for the date it is named after.
+        let undated = serde_json::to_string(&exchange_rate(None, &["AUD", "EUR"])).unwrap();
+        std::fs::write(cache.path(&date(5)), undated).unwrap();
+
+        let nearest = |day, codes: &[&str], max_days| {
+            let codes: Vec<CommodityTypeID> = codes.iter().map(|code| commodity(code)).collect();
+            cache
+                .get_nearest_prior_complete(&date(day), &codes, max_days)
+                .unwrap()
+                .map(|(cached, _)| cached)
+        };
+        assert_eq!(nearest(4, &["AUD"], 7), Some(date(3)));
+        assert_eq!(nearest(4, &["AUD", "EUR"], 7), Some(date(1)));
+        assert_eq!(nearest(4, &["AUD", "EUR"], 2), None);
+        assert_eq!(nearest(6, &["EUR"], 7), Some(date(5)));
+        assert_eq!(nearest(2, &["USD"], 0), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
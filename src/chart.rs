@@ -0,0 +1,39 @@
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Render `values` as a unicode sparkline at most `width` characters
+/// wide. When there are more values than characters, each character
+/// shows the mean of the values it covers.
+pub fn sparkline(values: &[Decimal], width: usize) -> String {
+    if values.is_empty() || width == 0 {
+        return String::new();
+    }
+
+    let values: Vec<f64> = values.iter().filter_map(|value| value.to_f64()).collect();
+    let buckets = values.len().min(width);
+    let points: Vec<f64> = (0..buckets)
+        .map(|bucket| {
+            let start = bucket * values.len() / buckets;
+            let end = ((bucket + 1) * values.len() / buckets).max(start + 1);
+            let covered = &values[start..end];
+            covered.iter().sum::<f64>() / covered.len() as f64
+        })
+        .collect();
+
+    let min = points.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = points.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let range = max - min;
+
+    points
+        .iter()
+        .map(|point| {
+            if range <= 0.0 {
+                BARS[BARS.len() / 2]
+            } else {
+                let index = ((point - min) / range * (BARS.len() - 1) as f64).round() as usize;
+                BARS[index.min(BARS.len() - 1)]
+            }
+        })
+        .collect()
+}
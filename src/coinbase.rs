@@ -0,0 +1,153 @@
+use crate::{
+    config::ProviderConfig,
+    network::{send_json, RequestError},
+    pricesource::{self, PriceSource},
+    series::Closes,
+};
+use anyhow::anyhow;
+use chrono::{NaiveDate, NaiveDateTime};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::{header::USER_AGENT, Client, StatusCode};
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use std::collections::BTreeMap;
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "coinbase";
+
+const API_URL: &str = "https://api.exchange.coinbase.com/products";
+
+/// Daily candles, in seconds.
+const GRANULARITY: u32 = 86400;
+
+/// The most candles a request may return, so the most days it can
+/// cover.
+pub const MAX_DAYS: i64 = 300;
+
+/// Coinbase allows 10 public requests a second, this leaves room for
+/// bursts.
+pub const REQUESTS_PER_MINUTE: u32 = 300;
+
+/// The product for `symbol` against `base`: `symbol` itself if it is a
+/// product (e.g. `BTC-USDC`), otherwise the pair of the two.
+fn product(symbol: &str, base: CommodityTypeID) -> String {
+    if symbol.contains('-') {
+        symbol.to_string()
+    } else {
+        format!("{}-{}", symbol, base)
+    }
+}
+
+/// Fetch the daily closes of the product of `symbol` (see [product()])
+/// and `base` from `start` to `end` (at most [MAX_DAYS] days), as the
+/// prices of `commodity`, from the daily candles (which are dated in UTC).
+pub async fn get_closes(
+    client: &Client,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Closes> {
+    let product = product(symbol, base);
+    let midnight = |date: &NaiveDate| {
+        date.and_hms_opt(0, 0, 0)
+            .expect("midnight is a valid time")
+            .format("%Y-%m-%dT%H:%M:%SZ")
+    };
+    let url = format!(
+        "{}/{}/candles?granularity={}&start={}&end={}",
+        API_URL,
+        url::form_urlencoded::byte_serialize(product.as_bytes()).collect::<String>(),
+        GRANULARITY,
+        midnight(start),
+        midnight(end),
+    );
+    // Each candle is the time it starts, low, high, open, close and
+    // volume, the latest first. The api refuses requests without a user
+    // agent.
+    let candles: Vec<Vec<f64>> = send_json(client.get(&url).header(
+        USER_AGENT,
+        concat!("beancount-price-fetcher/", env!("CARGO_PKG_VERSION")),
+    ))
+    .await
+    .map_err(|error| match error.downcast_ref::<RequestError>() {
+        Some(RequestError::Status(StatusCode::NOT_FOUND)) => {
+            anyhow!("{} has no product {:?}", PROVIDER_NAME, product)
+        }
+        _ => error,
+    })?;
+
+    let mut closes = BTreeMap::new();
+    for candle in candles {
+        let (time, close) = match (candle.first(), candle.get(4)) {
+            (Some(time), Some(close)) => (*time as i64, *close),
+            _ => return Err(anyhow!("Unable to parse a candle for {}", product)),
+        };
+        let date = match NaiveDateTime::from_timestamp_opt(time, 0) {
+            Some(time) => time.date(),
+            None => continue,
+        };
+        if let (true, Some(close)) = ((start..=end).contains(&&date), Decimal::from_f64(close)) {
+            closes.insert(date, close);
+        }
+    }
+
+    Ok(Closes {
+        commodity,
+        currency: base,
+        closes,
+    })
+}
+
+/// Coinbase Exchange as a [PriceSource], with the products in its `config`
+/// and the prices in `base`.
+pub struct Coinbase<'a> {
+    client: &'a Client,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> Coinbase<'a> {
+    pub fn new(
+        client: &'a Client,
+        config: &'a ProviderConfig,
+        base: CommodityTypeID,
+    ) -> Coinbase<'a> {
+        Coinbase {
+            client,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for Coinbase<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            self.client,
+            commodity,
+            &self.config.symbol(&commodity),
+            self.base,
+            start,
+            end,
+        )
+        .await
+    }
+}
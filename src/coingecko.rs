@@ -0,0 +1,188 @@
+use crate::{
+    config::ProviderConfig,
+    network::{send_json, RequestError},
+    pricesource::{self, PriceSource},
+    series::Closes,
+};
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::{Client, StatusCode};
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "coingecko";
+
+/// The environment variable the (optional) API key can be given in.
+pub const API_KEY_ENV: &str = "COINGECKO_API_KEY";
+
+const API_URL: &str = "https://api.coingecko.com/api/v3";
+
+/// CoinGecko's IDs for the commonest coins, so they work without
+/// `symbols` in the config. Anything else is looked up by its lowercased
+/// commodity.
+const COIN_IDS: &[(&str, &str)] = &[
+    ("BTC", "bitcoin"),
+    ("ETH", "ethereum"),
+    ("USDT", "tether"),
+    ("USDC", "usd-coin"),
+    ("BNB", "binancecoin"),
+    ("XRP", "ripple"),
+    ("ADA", "cardano"),
+    ("SOL", "solana"),
+    ("DOGE", "dogecoin"),
+    ("DOT", "polkadot"),
+    ("LTC", "litecoin"),
+    ("XMR", "monero"),
+    ("XLM", "stellar"),
+    ("AVAX", "avalanche-2"),
+    ("LINK", "chainlink"),
+    ("ATOM", "cosmos"),
+];
+
+/// The CoinGecko coin ID for `symbol` (a commodity, or an ID from the
+/// config).
+pub fn coin_id(symbol: &str) -> String {
+    COIN_IDS
+        .iter()
+        .find(|(commodity, _)| *commodity == symbol)
+        .map(|(_, id)| id.to_string())
+        .unwrap_or_else(|| symbol.to_lowercase())
+}
+
+/// Data from the `/coins/{id}/market_chart/range` endpoint.
+#[derive(Deserialize, Debug)]
+struct MarketChart {
+    /// Each price as the time (in milliseconds since the epoch) and the
+    /// price at that time.
+    prices: Vec<(i64, f64)>,
+}
+
+/// Fetch the price of the coin `symbol` (see [coin_id()]) in `base` at the
+/// end of each day (UTC) from `start` to `end`, as the prices of
+/// `commodity`. `api_key` is a demo API key, which has a higher rate
+/// limit than none.
+///
+/// CoinGecko gives hourly prices for a range of up to 90 days, and daily
+/// prices (at midnight UTC) for a longer one, so each date's price is the
+/// last one CoinGecko has on that date.
+pub async fn get_closes(
+    client: &Client,
+    api_key: Option<&str>,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Closes> {
+    let id = coin_id(symbol);
+    let timestamp = |date: NaiveDate| {
+        date.and_hms_opt(0, 0, 0)
+            .expect("midnight is a valid time")
+            .and_utc()
+            .timestamp()
+    };
+    let url = format!(
+        "{api_url}/coins/{id}/market_chart/range?vs_currency={base}&from={from}&to={to}",
+        api_url = API_URL,
+        id = url::form_urlencoded::byte_serialize(id.as_bytes()).collect::<String>(),
+        base = base.to_string().to_lowercase(),
+        from = timestamp(*start),
+        // Up to the end of the last day.
+        to = timestamp(*end + Duration::days(1)) - 1,
+    );
+    let mut request = client.get(&url);
+    if let Some(api_key) = api_key {
+        request = request.header("x-cg-demo-api-key", api_key);
+    }
+    let chart: MarketChart =
+        send_json(request)
+            .await
+            .map_err(|error| match error.downcast_ref::<RequestError>() {
+                Some(RequestError::Status(StatusCode::NOT_FOUND)) => anyhow!(
+                    "{} has no coin {:?}, map {} to its coin ID in [providers.{}.symbols]",
+                    PROVIDER_NAME,
+                    id,
+                    commodity,
+                    PROVIDER_NAME
+                ),
+                _ => error,
+            })?;
+
+    // The prices are in time order, so the last on each date wins.
+    let mut closes = BTreeMap::new();
+    for (millis, price) in chart.prices {
+        let date = match NaiveDateTime::from_timestamp_millis(millis) {
+            Some(time) => time.date(),
+            None => continue,
+        };
+        if let (true, Some(price)) = ((start..=end).contains(&&date), Decimal::from_f64(price)) {
+            closes.insert(date, price);
+        }
+    }
+
+    Ok(Closes {
+        commodity,
+        currency: base,
+        closes,
+    })
+}
+
+/// CoinGecko as a [PriceSource], with the symbols in its `config` and the
+/// prices in `base`. The `api_key` is optional, for the higher limits.
+pub struct CoinGecko<'a> {
+    client: &'a Client,
+    api_key: Option<&'a str>,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> CoinGecko<'a> {
+    pub fn new(
+        client: &'a Client,
+        api_key: Option<&'a str>,
+        config: &'a ProviderConfig,
+        base: CommodityTypeID,
+    ) -> CoinGecko<'a> {
+        CoinGecko {
+            client,
+            api_key,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for CoinGecko<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            self.client,
+            self.api_key,
+            commodity,
+            &self.config.symbol(&commodity),
+            self.base,
+            start,
+            end,
+        )
+        .await
+    }
+}
@@ -0,0 +1,266 @@
+use crate::{
+    config::ProviderConfig,
+    network::send_json,
+    pricesource::{self, PriceSource},
+    series::Closes,
+};
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::{Client, RequestBuilder};
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "coinmarketcap";
+
+/// The environment variable the API key can be given in.
+pub const API_KEY_ENV: &str = "CMC_PRO_API_KEY";
+
+const API_URL: &str = "https://pro-api.coinmarketcap.com";
+
+/// Number of data points in a historical quotes response which cost a
+/// credit (for each currency converted to).
+const POINTS_PER_CREDIT: usize = 100;
+
+/// The `status` of every response.
+#[derive(Deserialize, Debug)]
+struct Status {
+    error_code: i64,
+    error_message: Option<String>,
+    /// Number of credits the request used.
+    #[serde(default)]
+    credit_count: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct Response<T> {
+    data: Option<T>,
+    status: Status,
+}
+
+/// Data from the `/v1/key/info` endpoint.
+#[derive(Deserialize, Debug)]
+struct KeyInfo {
+    usage: KeyUsage,
+}
+
+#[derive(Deserialize, Debug)]
+struct KeyUsage {
+    current_day: UsagePeriod,
+    current_month: UsagePeriod,
+}
+
+#[derive(Deserialize, Debug)]
+struct UsagePeriod {
+    /// Missing for a plan without a limit for the period.
+    credits_left: Option<u64>,
+}
+
+/// A coin in a `/v2/cryptocurrency/quotes/historical` response.
+#[derive(Deserialize, Debug)]
+struct Coin {
+    quotes: Vec<HistoricalQuote>,
+}
+
+#[derive(Deserialize, Debug)]
+struct HistoricalQuote {
+    /// The price in each currency converted to.
+    quote: BTreeMap<String, Price>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Price {
+    price: Option<f64>,
+    timestamp: DateTime<Utc>,
+}
+
+/// Send `request` with the API key, failing with the provider's error
+/// message if it has one.
+async fn send<T: serde::de::DeserializeOwned>(
+    request: RequestBuilder,
+    api_key: &str,
+) -> anyhow::Result<T> {
+    let response: Response<T> = send_json(
+        request
+            .header("X-CMC_PRO_API_KEY", api_key)
+            .header(reqwest::header::ACCEPT, "application/json"),
+    )
+    .await?;
+    if response.status.error_code != 0 {
+        return Err(anyhow!(
+            "{} responded with error {}: {}",
+            PROVIDER_NAME,
+            response.status.error_code,
+            response
+                .status
+                .error_message
+                .as_deref()
+                .unwrap_or("unknown error")
+        ));
+    }
+    log::debug!(
+        "{} request used {} credit(s)",
+        PROVIDER_NAME,
+        response.status.credit_count
+    );
+    response
+        .data
+        .ok_or_else(|| anyhow!("{} responded without data", PROVIDER_NAME))
+}
+
+/// The number of credits the plan has left, the fewer of today's and this
+/// month's (the key info request itself is free).
+pub async fn get_credits_left(client: &Client, api_key: &str) -> anyhow::Result<Option<u64>> {
+    let info: KeyInfo = send(client.get(&format!("{}/v1/key/info", API_URL)), api_key).await?;
+    let usage = info.usage;
+    Ok(
+        match (
+            usage.current_day.credits_left,
+            usage.current_month.credits_left,
+        ) {
+            (Some(day), Some(month)) => Some(std::cmp::min(day, month)),
+            (day, month) => day.or(month),
+        },
+    )
+}
+
+/// The number of credits fetching a commodity's daily quotes from `start`
+/// to `end` uses: one for each [POINTS_PER_CREDIT] days.
+pub fn expected_credits(start: &NaiveDate, end: &NaiveDate) -> usize {
+    let days = (end.signed_duration_since(*start).num_days() + 1).max(1) as usize;
+    days.div_ceil(POINTS_PER_CREDIT)
+}
+
+/// Fetch the daily quotes of the coin `symbol` (its ticker, or its
+/// CoinMarketCap ID as `id:1`) in `base` (a fiat currency or another
+/// coin) from `start` to `end`, as the prices of `commodity`. Each date's
+/// price is the last quote CoinMarketCap has on that day (UTC).
+pub async fn get_closes(
+    client: &Client,
+    api_key: &str,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Closes> {
+    let coin = match symbol.strip_prefix("id:") {
+        Some(id) => format!("id={}", id),
+        None => format!(
+            "symbol={}",
+            url::form_urlencoded::byte_serialize(symbol.as_bytes()).collect::<String>()
+        ),
+    };
+    let midnight = |date: NaiveDate| {
+        date.and_hms_opt(0, 0, 0)
+            .expect("midnight is a valid time")
+            .and_utc()
+            .format("%Y-%m-%dT%H:%M:%SZ")
+    };
+    let url = format!(
+        "{api_url}/v2/cryptocurrency/quotes/historical?{coin}&convert={base}&interval=daily\
+        &time_start={start}&time_end={end}",
+        api_url = API_URL,
+        coin = coin,
+        base = base,
+        start = midnight(*start),
+        end = midnight(*end + Duration::days(1)),
+    );
+    // The coin itself for an ID, or keyed by the symbol (a list, as a
+    // symbol may be shared by several coins, the first being the largest).
+    let data: serde_json::Value = send(client.get(&url), api_key).await?;
+    let coin = match data {
+        serde_json::Value::Object(coin) if coin.contains_key("quotes") => {
+            Some(serde_json::Value::Object(coin))
+        }
+        serde_json::Value::Object(coins) => match coins.into_iter().next() {
+            Some((_, serde_json::Value::Array(coins))) => coins.into_iter().next(),
+            Some((_, coin)) => Some(coin),
+            None => None,
+        },
+        _ => None,
+    }
+    .ok_or_else(|| anyhow!("{} has no coin {:?}", PROVIDER_NAME, symbol))?;
+    let coin: Coin = serde_json::from_value(coin)
+        .map_err(|err| anyhow!("Unable to parse the quotes for {}: {}", symbol, err))?;
+
+    let mut closes = BTreeMap::new();
+    for quote in coin.quotes {
+        let price = match quote.quote.get(&base.to_string()) {
+            Some(price) => price,
+            None => continue,
+        };
+        let date = price.timestamp.date_naive();
+        if let (true, Some(close)) = (
+            (start..=end).contains(&&date),
+            price.price.and_then(Decimal::from_f64),
+        ) {
+            closes.insert(date, close);
+        }
+    }
+
+    Ok(Closes {
+        commodity,
+        currency: base,
+        closes,
+    })
+}
+
+/// CoinMarketCap as a [PriceSource], with the symbols in its `config` and
+/// the prices in `base`.
+pub struct CoinMarketCap<'a> {
+    client: &'a Client,
+    api_key: &'a str,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> CoinMarketCap<'a> {
+    pub fn new(
+        client: &'a Client,
+        api_key: &'a str,
+        config: &'a ProviderConfig,
+        base: CommodityTypeID,
+    ) -> CoinMarketCap<'a> {
+        CoinMarketCap {
+            client,
+            api_key,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for CoinMarketCap<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            self.client,
+            self.api_key,
+            commodity,
+            &self.config.symbol(&commodity),
+            self.base,
+            start,
+            end,
+        )
+        .await
+    }
+}
@@ -0,0 +1,1152 @@
+use crate::{
+    alphavantage, bankofcanada, beanprice, coinbase, coingecko, coinmarketcap, credentials, csv,
+    ecb, exchangeratehost, federalreserve, finnhub, fmp, frankfurter, html, iex, json, kraken,
+    marketstack, metalsapi, mock, morningstar, nasdaqdatalink, oanda, openexchangerate, polygon,
+    rba, stooq, tiingo, wise, yahoo,
+};
+use anyhow::anyhow;
+use commodity::{CommodityType, CommodityTypeID};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Names of the providers which can have a section under `providers`.
+pub const PROVIDERS: &[&str] = &[
+    openexchangerate::PROVIDER_NAME,
+    yahoo::PROVIDER_NAME,
+    alphavantage::PROVIDER_NAME,
+    coingecko::PROVIDER_NAME,
+    coinmarketcap::PROVIDER_NAME,
+    ecb::PROVIDER_NAME,
+    frankfurter::PROVIDER_NAME,
+    exchangeratehost::PROVIDER_NAME,
+    iex::PROVIDER_NAME,
+    finnhub::PROVIDER_NAME,
+    tiingo::PROVIDER_NAME,
+    kraken::PROVIDER_NAME,
+    coinbase::PROVIDER_NAME,
+    polygon::PROVIDER_NAME,
+    nasdaqdatalink::PROVIDER_NAME,
+    stooq::PROVIDER_NAME,
+    bankofcanada::PROVIDER_NAME,
+    rba::PROVIDER_NAME,
+    federalreserve::PROVIDER_NAME,
+    metalsapi::PROVIDER_NAME,
+    oanda::PROVIDER_NAME,
+    wise::PROVIDER_NAME,
+    marketstack::PROVIDER_NAME,
+    fmp::PROVIDER_NAME,
+    morningstar::PROVIDER_NAME,
+    json::PROVIDER_NAME,
+    csv::PROVIDER_NAME,
+    html::PROVIDER_NAME,
+    beanprice::PROVIDER_NAME,
+];
+
+/// Names of the sources rates can be fetched from, for `--source` and the
+/// `source` of a job.
+pub const SOURCES: &[&str] = &[
+    openexchangerate::PROVIDER_NAME,
+    mock::PROVIDER_NAME,
+    yahoo::PROVIDER_NAME,
+    alphavantage::PROVIDER_NAME,
+    coingecko::PROVIDER_NAME,
+    coinmarketcap::PROVIDER_NAME,
+    ecb::PROVIDER_NAME,
+    frankfurter::PROVIDER_NAME,
+    exchangeratehost::PROVIDER_NAME,
+    iex::PROVIDER_NAME,
+    finnhub::PROVIDER_NAME,
+    tiingo::PROVIDER_NAME,
+    kraken::PROVIDER_NAME,
+    coinbase::PROVIDER_NAME,
+    polygon::PROVIDER_NAME,
+    nasdaqdatalink::PROVIDER_NAME,
+    stooq::PROVIDER_NAME,
+    bankofcanada::PROVIDER_NAME,
+    rba::PROVIDER_NAME,
+    federalreserve::PROVIDER_NAME,
+    metalsapi::PROVIDER_NAME,
+    oanda::PROVIDER_NAME,
+    wise::PROVIDER_NAME,
+    marketstack::PROVIDER_NAME,
+    fmp::PROVIDER_NAME,
+    morningstar::PROVIDER_NAME,
+    json::PROVIDER_NAME,
+    csv::PROVIDER_NAME,
+    html::PROVIDER_NAME,
+    beanprice::PROVIDER_NAME,
+];
+
+/// The configuration file, written in [TOML](https://toml.io/).
+///
+/// ```toml
+/// cache_dir = "/home/user/.cache/beancount-price-fetcher"
+/// pivot = "USD"
+///
+/// [defaults]
+/// commodities = ["NZD", "USD"]
+/// base = "AUD"
+/// rounding = 4
+///
+/// [providers.openexchangerates]
+/// app_id = "keyring:"
+/// parallel_requests = 4
+/// requests_per_minute = 60
+/// max_requests = 100
+/// ```
+#[derive(Deserialize, Debug, Default)]
+pub struct Config {
+    /// Directory to cache fetched exchange rates in.
+    pub cache_dir: Option<PathBuf>,
+    /// Commodity to triangulate prices through (see `--pivot`).
+    pub pivot: Option<CommodityTypeID>,
+    /// age identity file used to decrypt `age:` credentials (see
+    /// [credentials::AGE_PREFIX]).
+    pub age_identity: Option<PathBuf>,
+    /// Defaults for command line arguments, keyed by the argument's long
+    /// name (with `_` in place of `-`), used when the argument isn't given.
+    #[serde(default)]
+    pub defaults: BTreeMap<String, toml::Value>,
+    /// Settings for each provider, keyed by the provider name (e.g.
+    /// `openexchangerates`).
+    #[serde(default)]
+    pub providers: BTreeMap<String, ProviderConfig>,
+    /// Named jobs run by the `run` subcommand.
+    #[serde(default)]
+    pub jobs: BTreeMap<String, Job>,
+    /// Notifications about the jobs run by the `run` and `daemon`
+    /// subcommands.
+    #[serde(default)]
+    pub notify: NotifyConfig,
+    /// Rules checked against the prices fetched by the jobs, see
+    /// [crate::alert].
+    #[serde(default)]
+    pub alerts: Vec<AlertRule>,
+    /// Plausible ranges for the prices of pairs, see [Bounds].
+    #[serde(default)]
+    pub bounds: Vec<Bounds>,
+    /// The sources to fetch each commodity from in turn, until one has
+    /// its prices, rather than only the `--source`, e.g.
+    /// `BTC = ["coingecko", "kraken", "openexchangerates"]`.
+    #[serde(default)]
+    pub fallbacks: BTreeMap<String, Vec<String>>,
+}
+
+/// The range of prices of `commodity` in `base` considered plausible,
+/// prices outside it are treated as corrupt data from the provider (see
+/// `--out-of-bounds`).
+///
+/// ```toml
+/// [[bounds]]
+/// commodity = "EUR"
+/// base = "USD"
+/// min = 0.5
+/// max = 2.0
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+pub struct Bounds {
+    pub commodity: CommodityTypeID,
+    pub base: CommodityTypeID,
+    pub min: Option<Decimal>,
+    pub max: Option<Decimal>,
+}
+
+impl Bounds {
+    /// Whether `rate` is within the bounds.
+    pub fn contains(&self, rate: Decimal) -> bool {
+        self.min.is_none_or(|min| rate >= min) && self.max.is_none_or(|max| rate <= max)
+    }
+}
+
+impl fmt::Display for Bounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => write!(f, "{} to {}", min, max),
+            (Some(min), None) => write!(f, "at least {}", min),
+            (None, Some(max)) => write!(f, "at most {}", max),
+            (None, None) => write!(f, "any"),
+        }
+    }
+}
+
+/// A rule raising an alert about the price of `commodity` in `base`,
+/// delivered with the [NotifyConfig] destinations.
+///
+/// ```toml
+/// [[alerts]]
+/// commodity = "EUR"
+/// base = "USD"
+/// change_percent = 5
+///
+/// [[alerts]]
+/// commodity = "EUR"
+/// base = "USD"
+/// crosses = 1.10
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+pub struct AlertRule {
+    pub commodity: CommodityTypeID,
+    pub base: CommodityTypeID,
+    /// Alert when the price moves more than this percentage from one
+    /// price to the next (day over day, for daily jobs).
+    pub change_percent: Option<Decimal>,
+    /// Alert when the price crosses this level, in either direction.
+    pub crosses: Option<Decimal>,
+}
+
+/// Where and when to send notifications about jobs (see [crate::notify]).
+///
+/// ```toml
+/// [notify]
+/// webhook = "https://example.com/hooks/prices"
+/// desktop = true
+/// quota_threshold = 100
+///
+/// [notify.slack]
+/// token = "keyring:slack"
+/// channel = "#home-ops"
+/// ```
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct NotifyConfig {
+    /// URL to POST a JSON description of each event to.
+    pub webhook: Option<String>,
+    /// Show a desktop notification, when there is a desktop session.
+    #[serde(default)]
+    pub desktop: bool,
+    /// Also notify when a job succeeds, not only when it fails.
+    #[serde(default)]
+    pub on_success: bool,
+    /// Notify when the provider's remaining quota (as of the last quota
+    /// check) is below this many requests.
+    pub quota_threshold: Option<u32>,
+    /// Post to a Slack channel.
+    pub slack: Option<SlackConfig>,
+    /// Send to a Telegram chat.
+    pub telegram: Option<TelegramConfig>,
+}
+
+/// Post notifications to a Slack channel, as a bot with the `chat:write`
+/// scope.
+///
+/// ```toml
+/// [notify.slack]
+/// token = "xoxb-..."
+/// channel = "#home-ops"
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+pub struct SlackConfig {
+    /// Bot token, which may be a credential reference (see
+    /// [crate::credentials::resolve()]).
+    pub token: String,
+    /// Name or ID of the channel to post to.
+    pub channel: String,
+}
+
+/// Send notifications to a Telegram chat, from a bot created with
+/// @BotFather.
+///
+/// ```toml
+/// [notify.telegram]
+/// token = "123456:ABC-DEF..."
+/// chat_id = -1001234567890
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+pub struct TelegramConfig {
+    /// Bot token, which may be a credential reference (see
+    /// [crate::credentials::resolve()]).
+    pub token: String,
+    /// Chat to send to.
+    pub chat_id: ChatId,
+}
+
+/// A Telegram chat: its numeric ID, or `@username` for a public channel.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ChatId {
+    Id(i64),
+    Username(String),
+}
+
+/// A job which keeps a price file up to date, see the `run` subcommand.
+///
+/// ```toml
+/// [jobs.fx]
+/// commodities = ["NZD", "USD"]
+/// base = "AUD"
+/// output = "/home/user/ledger/prices/fx.beancount"
+/// start = "2020-01-01"
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+pub struct Job {
+    /// Source to fetch the prices from, one of [SOURCES].
+    #[serde(default = "default_source")]
+    pub source: String,
+    pub commodities: Vec<CommodityTypeID>,
+    pub base: CommodityTypeID,
+    /// File the prices are appended to.
+    pub output: PathBuf,
+    /// Format of the output file, only `beancount` for now.
+    #[serde(default = "default_format")]
+    pub format: String,
+    /// Which dates get a price (see `--sample`): `daily`, `weekly` or
+    /// `monthly`.
+    #[serde(default = "default_frequency")]
+    pub frequency: String,
+    /// Date of the first price, used when the output has no prices yet.
+    /// Once it does, the job continues from the day after its latest
+    /// price.
+    pub start: Option<String>,
+    /// Date of the last price, `yesterday` (the last complete day) by
+    /// default.
+    pub end: Option<String>,
+    /// Number of decimal places to round to.
+    pub rounding: Option<u32>,
+    /// Any other `series` arguments, e.g. `["--forward-fill"]`.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+fn default_source() -> String {
+    openexchangerate::PROVIDER_NAME.to_string()
+}
+
+fn default_format() -> String {
+    String::from("beancount")
+}
+
+fn default_frequency() -> String {
+    String::from("daily")
+}
+
+/// Settings for an individual provider.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct ProviderConfig {
+    /// App ID for providers which use one (e.g. OpenExchangeRates), may
+    /// be a `keyring:` or `age:` reference (see [crate::credentials]).
+    pub app_id: Option<String>,
+    /// API key for providers which use one, may be a `keyring:`
+    /// or `age:` reference (see [crate::credentials]).
+    pub key: Option<String>,
+    /// Number of network requests to this provider that may be in
+    /// flight at the same time.
+    pub parallel_requests: Option<usize>,
+    /// Maximum number of requests to start against this provider per
+    /// minute. Sources with a limit of their own (e.g. Polygon's free
+    /// plan) are held to it when this isn't set.
+    pub requests_per_minute: Option<u32>,
+    /// Maximum number of requests to this provider in a single run, so
+    /// a run never uses more than a set slice of the quota.
+    pub max_requests: Option<u32>,
+    /// Number of requests in a single run above which to ask for
+    /// confirmation before fetching (see `--yes`).
+    pub confirm_requests: Option<u32>,
+    /// The provider's symbols for the commodities whose symbol differs
+    /// from the beancount commodity, e.g. `SPX = "^GSPC"`.
+    #[serde(default)]
+    pub symbols: BTreeMap<String, String>,
+    /// The url the `json` and `html` sources request for each commodity,
+    /// with `{symbol}`, `{base}`, `{start}`, `{end}` and `{key}` replaced
+    /// (see [crate::json::url()]).
+    pub url: Option<String>,
+    /// JSONPath of the prices in the `json` source's responses.
+    pub price_path: Option<String>,
+    /// JSONPath of the date of each price in the `json` source's
+    /// responses.
+    pub date_path: Option<String>,
+    /// Format of the `json`, `html` and `csv` sources' dates, `%Y-%m-%d`
+    /// by default.
+    pub date_format: Option<String>,
+    /// CSS selector of the dates in the `html` source's pages, with
+    /// `{symbol}` replaced (see [crate::html]).
+    pub date_selector: Option<String>,
+    /// CSS selector of the price for each date in the `html` source's
+    /// pages.
+    pub price_selector: Option<String>,
+    /// The bean-price source module (e.g. `beanprice.sources.yahoo`) the
+    /// `beanprice` source runs for commodities whose symbol doesn't name
+    /// one (see [crate::beanprice]).
+    pub module: Option<String>,
+    /// The Python interpreter the `beanprice` source runs, `python3` by
+    /// default.
+    pub python: Option<String>,
+    /// The file the `csv` source reads (see [crate::csv]).
+    pub path: Option<PathBuf>,
+    /// Column of the `csv` source's dates, `date` by default.
+    pub date_column: Option<String>,
+    /// Column of the `csv` source's commodities, `commodity` by default.
+    pub commodity_column: Option<String>,
+    /// Column of the `csv` source's prices, `price` by default.
+    pub price_column: Option<String>,
+    /// Column of the currency each of the `csv` source's prices is in,
+    /// `currency` by default. Without one the prices are in the base.
+    pub currency_column: Option<String>,
+}
+
+/// Commented example config written by `config init`, with `{app_id}`
+/// replaced by the App ID.
+pub const EXAMPLE: &str = r#"# beancount-price-fetcher config, see
+# https://github.com/kellpossible/beancount-price-fetcher#configuration
+
+# Directory to cache fetched exchange rates in.
+# cache_dir = "/home/user/.cache/beancount-price-fetcher"
+
+# Commodity to triangulate prices through.
+# pivot = "USD"
+
+# age identity used to decrypt credentials given as "age:/path/to/file.age".
+# age_identity = "/home/user/.config/age/key.txt"
+
+# Defaults for command line arguments, keyed by the argument's long name
+# (with _ in place of -). Arguments given on the command line override
+# these.
+[defaults]
+# commodities = ["NZD", "USD"]
+# base = "AUD"
+# rounding = 4
+# desc = true
+
+[providers.openexchangerates]
+# App ID from https://openexchangerates.org/account/app-ids, or "keyring:"
+# to read it from the OS keychain.
+{app_id}
+# Number of requests in flight at the same time.
+# parallel_requests = 2
+# Maximum number of requests started per minute.
+# requests_per_minute = 60
+# Maximum number of requests in a single run.
+# max_requests = 100
+# Ask before a run makes more requests than this.
+# confirm_requests = 500
+"#;
+
+/// The config file in the platform's config directory, e.g.
+/// `~/.config/beancount-price-fetcher/config.toml` on Linux.
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(config_in)
+}
+
+fn config_in(dir: PathBuf) -> PathBuf {
+    dir.join("beancount-price-fetcher").join("config.toml")
+}
+
+/// The places a config file is looked for, in order:
+///
+/// 1. The platform's config directory ([default_path()]): `$XDG_CONFIG_HOME`
+///    (or `~/.config`) on Linux, `~/Library/Application Support` on macOS,
+///    and `%APPDATA%` on Windows.
+/// 2. On other Unix platforms (e.g. macOS), `$XDG_CONFIG_HOME` (or
+///    `~/.config`), which is where many command line tools keep their
+///    config.
+/// 3. On Unix platforms, each directory in `$XDG_CONFIG_DIRS` (or
+///    `/etc/xdg`), for system wide configs.
+pub fn search_paths() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = default_path().into_iter().collect();
+
+    if cfg!(unix) {
+        let xdg_config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|home| home.join(".config")));
+        paths.extend(xdg_config_home.map(config_in));
+
+        let xdg_config_dirs = std::env::var("XDG_CONFIG_DIRS")
+            .ok()
+            .filter(|dirs| !dirs.is_empty())
+            .unwrap_or_else(|| String::from("/etc/xdg"));
+        paths.extend(
+            xdg_config_dirs
+                .split(':')
+                .filter(|dir| !dir.is_empty())
+                .map(|dir| config_in(PathBuf::from(dir))),
+        );
+    }
+
+    let mut unique: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        if !unique.contains(&path) {
+            unique.push(path);
+        }
+    }
+    unique
+}
+
+/// The first of the [search_paths()] which has a config file, if any.
+pub fn discover() -> Option<PathBuf> {
+    search_paths().into_iter().find(|path| path.is_file())
+}
+
+/// The [EXAMPLE] config with `app_id` filled in, or left commented out if
+/// it is `None`.
+pub fn example(app_id: Option<&str>) -> String {
+    let app_id = match app_id {
+        // Serialized so that quotes in the App ID are escaped.
+        Some(app_id) => format!("app_id = {}", toml::Value::String(app_id.to_string())),
+        None => String::from("# app_id = \"{YOUR_APP_ID}\""),
+    };
+    EXAMPLE.replace("{app_id}", &app_id)
+}
+
+impl ProviderConfig {
+    /// The credential for the provider, whichever of `app_id` or `key`
+    /// it uses.
+    pub fn credential(&self) -> Option<&str> {
+        self.app_id.as_deref().or(self.key.as_deref())
+    }
+
+    /// The provider's symbol for `commodity`, see [ProviderConfig::symbols].
+    pub fn symbol(&self, commodity: &CommodityTypeID) -> String {
+        let commodity = commodity.to_string();
+        self.symbols.get(&commodity).cloned().unwrap_or(commodity)
+    }
+
+    /// Each of the `commodities` with its [ProviderConfig::symbol()].
+    pub fn symbols_for(&self, commodities: &[CommodityTypeID]) -> Vec<(CommodityTypeID, String)> {
+        commodities
+            .iter()
+            .map(|commodity| (*commodity, self.symbol(commodity)))
+            .collect()
+    }
+}
+
+impl Config {
+    /// Read and parse the config file at `path`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Config> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("Unable to read config file {:?}: {}", path, err))?;
+        toml::from_str(&contents)
+            .map_err(|err| anyhow!("Unable to parse config file {:?}: {}", path, err))
+    }
+
+    /// Settings for the provider called `name`, or the defaults if the
+    /// provider has no section in the config.
+    pub fn provider(&self, name: &str) -> ProviderConfig {
+        self.providers.get(name).cloned().unwrap_or_default()
+    }
+}
+
+/// How serious a [Problem] found by [check()] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Probably a mistake, but the config can still be used.
+    Warning,
+    /// The config can't be used as it is.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem with a config file, found by [check()].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub severity: Severity,
+    /// Line (starting at 1) of the setting with the problem, if known.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "{}: ", line)?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// Keys which may appear at the top level of the config.
+const TOP_LEVEL_KEYS: &[&str] = &[
+    "cache_dir",
+    "pivot",
+    "age_identity",
+    "defaults",
+    "providers",
+    "jobs",
+    "notify",
+    "alerts",
+    "bounds",
+    "fallbacks",
+];
+
+/// Keys which may appear in the `notify` section.
+const NOTIFY_KEYS: &[&str] = &[
+    "webhook",
+    "desktop",
+    "on_success",
+    "quota_threshold",
+    "slack",
+    "telegram",
+];
+
+/// Keys which may appear in the `notify.slack` section.
+const SLACK_KEYS: &[&str] = &["token", "channel"];
+
+/// Keys which may appear in the `notify.telegram` section.
+const TELEGRAM_KEYS: &[&str] = &["token", "chat_id"];
+
+/// Keys which may appear in a provider's section.
+const PROVIDER_KEYS: &[&str] = &[
+    "app_id",
+    "key",
+    "parallel_requests",
+    "requests_per_minute",
+    "max_requests",
+    "confirm_requests",
+    "symbols",
+    "url",
+    "price_path",
+    "date_path",
+    "date_format",
+    "date_selector",
+    "price_selector",
+    "module",
+    "python",
+    "path",
+    "date_column",
+    "commodity_column",
+    "price_column",
+    "currency_column",
+];
+
+/// The line (starting at 1) which sets `key` within the table `section`
+/// (e.g. `providers.openexchangerates`, or `""` for the top level) in
+/// `contents`, if it can be found.
+fn line_of(contents: &str, section: &str, key: &str) -> Option<usize> {
+    let mut current = "";
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('[') {
+            current = header.trim_end_matches(']').trim();
+            if current == key && section.is_empty() || format!("{}.{}", section, key) == current {
+                return Some(i + 1);
+            }
+            continue;
+        }
+        if current == section {
+            if let Some(rest) = line.strip_prefix(key) {
+                if rest.trim_start().starts_with('=') {
+                    return Some(i + 1);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Check the config file `contents` for problems: syntax errors, unknown
+/// settings and providers, invalid commodity codes, unusable paths, and
+/// missing credentials. `arguments` are the long names of the command
+/// line arguments which may be given a default.
+pub fn check(contents: &str, arguments: &[&str]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut problem = |severity: Severity, line: Option<usize>, message: String| {
+        problems.push(Problem {
+            severity,
+            line,
+            message,
+        })
+    };
+
+    let value: toml::Value = match toml::from_str(contents) {
+        Ok(value) => value,
+        Err(err) => {
+            let line = err.line_col().map(|(line, _)| line + 1);
+            problem(Severity::Error, line, err.to_string());
+            return problems;
+        }
+    };
+    let config: Config = match toml::from_str(contents) {
+        Ok(config) => config,
+        Err(err) => {
+            let line = err.line_col().map(|(line, _)| line + 1);
+            problem(Severity::Error, line, err.to_string());
+            return problems;
+        }
+    };
+
+    if let Some(table) = value.as_table() {
+        for key in table.keys() {
+            if !TOP_LEVEL_KEYS.contains(&key.as_str()) {
+                problem(
+                    Severity::Warning,
+                    line_of(contents, "", key),
+                    format!("unknown setting {:?}, it is ignored", key),
+                );
+            }
+        }
+    }
+
+    if let Some(age_identity) = credentials::age_identity(config.age_identity.as_deref()) {
+        if !age_identity.is_file() {
+            problem(
+                Severity::Error,
+                line_of(contents, "", "age_identity"),
+                format!("age identity {:?} doesn't exist", age_identity),
+            );
+        }
+    }
+
+    if let Some(table) = value.get("notify").and_then(|notify| notify.as_table()) {
+        for key in table.keys() {
+            if !NOTIFY_KEYS.contains(&key.as_str()) {
+                problem(
+                    Severity::Warning,
+                    line_of(contents, "notify", key),
+                    format!("unknown setting {:?} in notify, it is ignored", key),
+                );
+            }
+        }
+    }
+    for (name, keys) in [("slack", SLACK_KEYS), ("telegram", TELEGRAM_KEYS)] {
+        let table = value
+            .get("notify")
+            .and_then(|notify| notify.get(name))
+            .and_then(|table| table.as_table());
+        let section = format!("notify.{}", name);
+        for key in table.into_iter().flat_map(|table| table.keys()) {
+            if !keys.contains(&key.as_str()) {
+                problem(
+                    Severity::Warning,
+                    line_of(contents, &section, key),
+                    format!("unknown setting {:?} in {}, it is ignored", key, section),
+                );
+            }
+        }
+    }
+    let tokens = [
+        (
+            "slack",
+            config.notify.slack.as_ref().map(|slack| &slack.token),
+        ),
+        (
+            "telegram",
+            config
+                .notify
+                .telegram
+                .as_ref()
+                .map(|telegram| &telegram.token),
+        ),
+    ];
+    for (name, token) in tokens {
+        let token = match token {
+            Some(token) => token,
+            None => continue,
+        };
+        let line = line_of(contents, &format!("notify.{}", name), "token");
+        if token.trim().is_empty() {
+            problem(
+                Severity::Error,
+                line,
+                format!("token for notify.{} is empty", name),
+            );
+        } else if let Err(err) = credentials::resolve(
+            token,
+            name,
+            credentials::age_identity(config.age_identity.as_deref()).as_deref(),
+        ) {
+            problem(Severity::Error, line, err.to_string());
+        }
+    }
+    if let Some(webhook) = &config.notify.webhook {
+        let valid = url::Url::parse(webhook)
+            .map(|url| url.scheme() == "http" || url.scheme() == "https")
+            .unwrap_or(false);
+        if !valid {
+            problem(
+                Severity::Error,
+                line_of(contents, "notify", "webhook"),
+                format!("notify webhook {:?} is not an http(s) URL", webhook),
+            );
+        }
+    }
+
+    for (i, rule) in config.alerts.iter().enumerate() {
+        let pair = format!("{}/{}", rule.commodity, rule.base);
+        if rule.change_percent.is_none() && rule.crosses.is_none() {
+            problem(
+                Severity::Error,
+                None,
+                format!("alert {} ({}) needs change_percent or crosses", i + 1, pair),
+            );
+        }
+        if matches!(rule.change_percent, Some(percent) if percent <= Decimal::ZERO) {
+            problem(
+                Severity::Error,
+                None,
+                format!(
+                    "alert {} ({}) change_percent must be more than 0",
+                    i + 1,
+                    pair
+                ),
+            );
+        }
+        let fetched = config
+            .jobs
+            .values()
+            .any(|job| job.base == rule.base && job.commodities.contains(&rule.commodity));
+        if !fetched {
+            problem(
+                Severity::Warning,
+                None,
+                format!(
+                    "alert {} ({}) isn't for a pair fetched by any job, it is never checked",
+                    i + 1,
+                    pair
+                ),
+            );
+        }
+    }
+
+    for (i, bounds) in config.bounds.iter().enumerate() {
+        let pair = format!("{}/{}", bounds.commodity, bounds.base);
+        match (bounds.min, bounds.max) {
+            (None, None) => problem(
+                Severity::Error,
+                None,
+                format!("bounds {} ({}) needs min or max", i + 1, pair),
+            ),
+            (Some(min), Some(max)) if min > max => problem(
+                Severity::Error,
+                None,
+                format!(
+                    "bounds {} ({}) min ({}) is more than max ({})",
+                    i + 1,
+                    pair,
+                    min,
+                    max
+                ),
+            ),
+            _ => {}
+        }
+    }
+
+    if let Some(cache_dir) = &config.cache_dir {
+        let line = line_of(contents, "", "cache_dir");
+        if cache_dir.exists() && !cache_dir.is_dir() {
+            problem(
+                Severity::Error,
+                line,
+                format!("cache_dir {:?} is not a directory", cache_dir),
+            );
+        } else if !cache_dir.exists() {
+            problem(
+                Severity::Warning,
+                line,
+                format!(
+                    "cache_dir {:?} doesn't exist yet, it will be created",
+                    cache_dir
+                ),
+            );
+        }
+    }
+
+    let mut commodities: Vec<(String, Option<usize>, String)> = Vec::new();
+    if let Some(pivot) = &config.pivot {
+        commodities.push((
+            String::from("pivot"),
+            line_of(contents, "", "pivot"),
+            pivot.to_string(),
+        ));
+    }
+
+    for (commodity, sources) in &config.fallbacks {
+        let line = line_of(contents, "fallbacks", commodity);
+        commodities.push((String::from("fallbacks"), line, commodity.clone()));
+        if sources.is_empty() {
+            problem(
+                Severity::Error,
+                line,
+                format!("fallbacks for {} has no sources", commodity),
+            );
+        }
+        for source in sources {
+            if !SOURCES.contains(&source.as_str()) {
+                problem(
+                    Severity::Error,
+                    line,
+                    format!(
+                        "fallbacks for {} has an unknown source {:?}, expected one of: {}",
+                        commodity,
+                        source,
+                        SOURCES.join(", ")
+                    ),
+                );
+            }
+        }
+    }
+
+    for (name, default) in &config.defaults {
+        let line = line_of(contents, "defaults", name);
+        let argument = name.replace('_', "-");
+        if !arguments.contains(&argument.as_str()) {
+            problem(
+                Severity::Error,
+                line,
+                format!(
+                    "defaults has unknown argument {:?} (no --{})",
+                    name, argument
+                ),
+            );
+            continue;
+        }
+        if let "commodities" | "base" | "pivot" = name.as_str() {
+            let values = match default {
+                toml::Value::Array(values) => values.iter().collect(),
+                value => vec![value],
+            };
+            for value in values {
+                match value.as_str() {
+                    Some(code) => commodities.push((name.clone(), line, code.to_string())),
+                    None => problem(
+                        Severity::Error,
+                        line,
+                        format!("defaults.{} should be a commodity code string", name),
+                    ),
+                }
+            }
+        }
+    }
+
+    for (setting, line, code) in commodities {
+        if let Err(err) = CommodityTypeID::from_str(&code) {
+            problem(
+                Severity::Error,
+                line,
+                format!(
+                    "{} has an invalid commodity code {:?}: {}",
+                    setting, code, err
+                ),
+            );
+        } else if CommodityType::from_currency_alpha3(&code).is_err() {
+            problem(
+                Severity::Warning,
+                line,
+                format!(
+                    "{} has {:?}, which isn't an ISO 4217 currency code",
+                    setting, code
+                ),
+            );
+        }
+    }
+
+    let provider_tables = value
+        .get("providers")
+        .and_then(|providers| providers.as_table());
+    for (name, provider) in &config.providers {
+        let section = format!("providers.{}", name);
+        let header = line_of(contents, "providers", name);
+        if !PROVIDERS.contains(&name.as_str()) {
+            problem(
+                Severity::Error,
+                header,
+                format!(
+                    "unknown provider {:?}, expected one of: {}",
+                    name,
+                    PROVIDERS.join(", ")
+                ),
+            );
+            continue;
+        }
+
+        if let Some(table) = provider_tables
+            .and_then(|providers| providers.get(name))
+            .and_then(|provider| provider.as_table())
+        {
+            for key in table.keys() {
+                if !PROVIDER_KEYS.contains(&key.as_str()) {
+                    problem(
+                        Severity::Warning,
+                        line_of(contents, &section, key),
+                        format!(
+                            "unknown setting {:?} for provider {}, it is ignored",
+                            key, name
+                        ),
+                    );
+                }
+            }
+        }
+
+        for (key, value) in &[
+            (
+                "parallel_requests",
+                provider.parallel_requests.map(|n| n as u64),
+            ),
+            (
+                "requests_per_minute",
+                provider.requests_per_minute.map(u64::from),
+            ),
+            ("max_requests", provider.max_requests.map(u64::from)),
+            ("confirm_requests", provider.confirm_requests.map(u64::from)),
+        ] {
+            if *value == Some(0) {
+                problem(
+                    Severity::Error,
+                    line_of(contents, &section, key),
+                    format!("{} for provider {} must be at least 1", key, name),
+                );
+            }
+        }
+
+        for (key, path) in &[
+            ("price_path", &provider.price_path),
+            ("date_path", &provider.date_path),
+        ] {
+            if let Some(Err(err)) = path.as_deref().map(json::Path::from_str) {
+                problem(
+                    Severity::Error,
+                    line_of(contents, &section, key),
+                    err.to_string(),
+                );
+            }
+        }
+        // Invalid paths were reported above, on their own lines.
+        let missing =
+            provider.url.is_none() || provider.price_path.is_none() || provider.date_path.is_none();
+        if name == json::PROVIDER_NAME && missing {
+            if let Err(err) = json::Template::from_config(provider) {
+                problem(Severity::Error, header, err.to_string());
+            }
+        }
+        for (key, selector) in &[
+            ("date_selector", &provider.date_selector),
+            ("price_selector", &provider.price_selector),
+        ] {
+            if let Some(Err(err)) = selector.as_deref().map(html::check_selector) {
+                problem(
+                    Severity::Error,
+                    line_of(contents, &section, key),
+                    err.to_string(),
+                );
+            }
+        }
+        let missing = provider.url.is_none()
+            || provider.date_selector.is_none()
+            || provider.price_selector.is_none();
+        if name == html::PROVIDER_NAME && missing {
+            if let Err(err) = html::Page::from_config(provider) {
+                problem(Severity::Error, header, err.to_string());
+            }
+        }
+        if name == csv::PROVIDER_NAME {
+            match &provider.path {
+                None => problem(
+                    Severity::Error,
+                    header,
+                    format!(
+                        "the {} source needs a path in providers.{}",
+                        csv::PROVIDER_NAME,
+                        csv::PROVIDER_NAME
+                    ),
+                ),
+                Some(path) if !path.is_file() => problem(
+                    Severity::Error,
+                    line_of(contents, &section, "path"),
+                    format!("path for provider {} isn't a file: {:?}", name, path),
+                ),
+                Some(_) => {}
+            }
+        }
+    }
+
+    for (name, job) in &config.jobs {
+        let line = line_of(contents, "jobs", name);
+        if !SOURCES.contains(&job.source.as_str()) {
+            problem(
+                Severity::Error,
+                line,
+                format!("job {} has an unknown source {:?}", name, job.source),
+            );
+        }
+        if job.format != "beancount" {
+            problem(
+                Severity::Error,
+                line,
+                format!(
+                    "job {} has an unknown format {:?}, expected beancount",
+                    name, job.format
+                ),
+            );
+        }
+        if !["daily", "weekly", "monthly"].contains(&job.frequency.as_str()) {
+            problem(
+                Severity::Error,
+                line,
+                format!(
+                    "job {} has an unknown frequency {:?}, expected daily, weekly or monthly",
+                    name, job.frequency
+                ),
+            );
+        }
+        if job.output.is_dir() {
+            problem(
+                Severity::Error,
+                line,
+                format!(
+                    "job {} has an output {:?} which is a directory",
+                    name, job.output
+                ),
+            );
+        }
+    }
+
+    let oxr = config.provider(openexchangerate::PROVIDER_NAME);
+    let oxr_section = format!("providers.{}", openexchangerate::PROVIDER_NAME);
+    match oxr.credential() {
+        Some(credential) => {
+            let key = if oxr.app_id.is_some() {
+                "app_id"
+            } else {
+                "key"
+            };
+            let line = line_of(contents, &oxr_section, key);
+            if credential.trim().is_empty() {
+                problem(
+                    Severity::Error,
+                    line,
+                    format!(
+                        "{} for provider {} is empty",
+                        key,
+                        openexchangerate::PROVIDER_NAME
+                    ),
+                );
+            } else if let Err(err) = credentials::resolve(
+                credential,
+                openexchangerate::PROVIDER_NAME,
+                credentials::age_identity(config.age_identity.as_deref()).as_deref(),
+            ) {
+                problem(Severity::Error, line, err.to_string());
+            }
+        }
+        None => {
+            if std::env::var_os(openexchangerate::APP_ID_ENV).is_none() {
+                problem(
+                    Severity::Warning,
+                    None,
+                    format!(
+                        "no App ID for provider {} (app_id in [{}], or {} in the environment), \
+                        it must be given with --app-id or --app-id-file",
+                        openexchangerate::PROVIDER_NAME,
+                        oxr_section,
+                        openexchangerate::APP_ID_ENV,
+                    ),
+                );
+            }
+        }
+    }
+
+    problems.sort_by_key(|problem| problem.line);
+    problems
+}
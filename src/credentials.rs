@@ -0,0 +1,193 @@
+use anyhow::anyhow;
+use std::path::{Path, PathBuf};
+
+/// Prefix of a credential which is stored in the OS keychain, rather than
+/// given directly. It may be followed by the name of the keychain entry,
+/// which defaults to the provider name, e.g. `keyring:` or
+/// `keyring:openexchangerates-work`.
+pub const KEYRING_PREFIX: &str = "keyring:";
+
+/// Service name the keychain entries are stored under.
+pub const KEYRING_SERVICE: &str = "beancount-price-fetcher";
+
+/// Prefix of a credential which is stored in a file encrypted with
+/// [age](https://age-encryption.org), followed by the path to the file, e.g.
+/// `age:~/dotfiles/secrets/oxr.age`. It is decrypted with the age identity
+/// (see [age_identity()]).
+pub const AGE_PREFIX: &str = "age:";
+
+/// First line of an ASCII armored age file, which may also be given inline
+/// as the credential.
+const AGE_ARMOR_BEGIN: &str = "-----BEGIN AGE ENCRYPTED FILE-----";
+
+/// Environment variable with the path to the age identity file, which
+/// overrides `age_identity` in the config.
+pub const AGE_IDENTITY_ENV: &str = "BEANCOUNT_PRICE_FETCHER_AGE_IDENTITY";
+
+/// Resolve a credential given for `provider`, looking it up in the OS
+/// keychain if it is a [KEYRING_PREFIX] reference, decrypting it with the
+/// age identity at `age_identity` if it is an [AGE_PREFIX] reference or
+/// inline armored age data, otherwise using it as is.
+pub fn resolve(
+    credential: &str,
+    provider: &str,
+    age_identity: Option<&Path>,
+) -> anyhow::Result<String> {
+    if let Some(entry) = credential.strip_prefix(KEYRING_PREFIX) {
+        return keyring_get(entry_name(entry, provider));
+    }
+
+    let encrypted = if let Some(path) = credential.strip_prefix(AGE_PREFIX) {
+        let path = expand_home(path);
+        std::fs::read(&path)
+            .map_err(|err| anyhow!("Unable to read encrypted credential {:?}: {}", path, err))?
+    } else if credential.trim_start().starts_with(AGE_ARMOR_BEGIN) {
+        credential.as_bytes().to_vec()
+    } else {
+        return Ok(credential.to_string());
+    };
+
+    let identity = age_identity.ok_or_else(|| {
+        anyhow!(
+            "The credential for {} is encrypted with age, but no identity was given, \
+            use age_identity in the config or the {} environment variable",
+            provider,
+            AGE_IDENTITY_ENV
+        )
+    })?;
+    let decrypted = age_decrypt(&encrypted, identity)?;
+    let credential = decrypted.trim_end_matches(&['\r', '\n'][..]);
+    if credential.is_empty() {
+        return Err(anyhow!(
+            "The encrypted credential for {} is empty",
+            provider
+        ));
+    }
+    Ok(credential.to_string())
+}
+
+/// The age identity file to decrypt credentials with, from the
+/// [AGE_IDENTITY_ENV] environment variable, otherwise `configured` (from the
+/// config).
+pub fn age_identity(configured: Option<&Path>) -> Option<PathBuf> {
+    std::env::var_os(AGE_IDENTITY_ENV)
+        .map(PathBuf::from)
+        .or_else(|| configured.map(|path| expand_home(&path.to_string_lossy())))
+}
+
+/// Expand a leading `~/` in `path` to the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Read a credential from the file at `path` (e.g. a Docker or Kubernetes
+/// secret, or the output of `pass`), without its trailing newline.
+pub fn read_file<P: AsRef<Path>>(path: P) -> anyhow::Result<String> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| anyhow!("Unable to read credential file {:?}: {}", path, err))?;
+    let credential = contents.trim_end_matches(&['\r', '\n'][..]);
+    if credential.is_empty() {
+        return Err(anyhow!("Credential file {:?} is empty", path));
+    }
+    Ok(credential.to_string())
+}
+
+/// Store `secret` in the OS keychain (macOS Keychain, GNOME Keyring/Secret
+/// Service, or Windows Credential Manager) under the entry `entry`, or
+/// `provider` if `entry` is empty, so it can be referred to with
+/// [KEYRING_PREFIX].
+pub fn store(entry: &str, provider: &str, secret: &str) -> anyhow::Result<()> {
+    keyring_set(entry_name(entry, provider), secret)
+}
+
+fn entry_name<'a>(entry: &'a str, provider: &'a str) -> &'a str {
+    if entry.is_empty() {
+        provider
+    } else {
+        entry
+    }
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_entry(entry: &str) -> anyhow::Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, entry)
+        .map_err(|err| anyhow!("Unable to open keychain entry {:?}: {}", entry, err))
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_get(entry: &str) -> anyhow::Result<String> {
+    keyring_entry(entry)?
+        .get_password()
+        .map_err(|err| anyhow!("Unable to read {:?} from the keychain: {}", entry, err))
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_set(entry: &str, secret: &str) -> anyhow::Result<()> {
+    keyring_entry(entry)?
+        .set_password(secret)
+        .map_err(|err| anyhow!("Unable to store {:?} in the keychain: {}", entry, err))
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_unsupported() -> anyhow::Error {
+    anyhow!("This build doesn't support the keychain, reinstall with `--features keyring`")
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_get(_entry: &str) -> anyhow::Result<String> {
+    Err(keyring_unsupported())
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_set(_entry: &str, _secret: &str) -> anyhow::Result<()> {
+    Err(keyring_unsupported())
+}
+
+#[cfg(feature = "age")]
+fn age_decrypt(encrypted: &[u8], identity: &Path) -> anyhow::Result<String> {
+    use std::io::Read;
+
+    let identities = age::IdentityFile::from_file(identity.to_string_lossy().into_owned())
+        .map_err(|err| anyhow!("Unable to read age identity {:?}: {}", identity, err))?
+        .into_identities()
+        .into_iter()
+        .map(|entry| match entry {
+            age::IdentityFileEntry::Native(identity) => identity,
+        })
+        .collect::<Vec<_>>();
+
+    let decryptor = match age::Decryptor::new(age::armor::ArmoredReader::new(encrypted))
+        .map_err(|err| anyhow!("Unable to read encrypted credential: {}", err))?
+    {
+        age::Decryptor::Recipients(decryptor) => decryptor,
+        age::Decryptor::Passphrase(_) => {
+            return Err(anyhow!(
+                "Credentials encrypted with a passphrase aren't supported, \
+                encrypt them to the age identity instead"
+            ))
+        }
+    };
+
+    let mut decrypted = String::new();
+    decryptor
+        .decrypt(
+            identities
+                .iter()
+                .map(|identity| identity as &dyn age::Identity),
+        )
+        .map_err(|err| anyhow!("Unable to decrypt credential with {:?}: {}", identity, err))?
+        .read_to_string(&mut decrypted)
+        .map_err(|err| anyhow!("Unable to decrypt credential with {:?}: {}", identity, err))?;
+    Ok(decrypted)
+}
+
+#[cfg(not(feature = "age"))]
+fn age_decrypt(_encrypted: &[u8], _identity: &Path) -> anyhow::Result<String> {
+    Err(anyhow!(
+        "This build doesn't support age encrypted credentials, reinstall with `--features age`"
+    ))
+}
@@ -0,0 +1,152 @@
+//! Cross rates computed locally from an exchange rate table.
+//!
+//! Providers like OpenExchangeRates (on the free plan) only quote rates
+//! against a single base commodity (USD). The rate between any other
+//! pair of commodities is computed locally from two quoted rates:
+//!
+//! ```text
+//! EUR→NZD = (USD→NZD) / (USD→EUR)
+//! ```
+//!
+//! ## Precision
+//!
+//! The division uses [Decimal] arithmetic, which keeps up to 28
+//! significant digits, so the computation itself adds no meaningful
+//! error. The result is only as precise as the quoted rates though: if
+//! the provider quotes rates to 6 decimal places, the relative error of a
+//! cross rate is roughly the sum of the relative errors of the two
+//! quotes. Cross rates are not rounded until they are output (see
+//! `--rounding`), except for dropping the noise in the last couple of
+//! digits of the division, so a rate which was inverted twice (e.g. the
+//! closing price of a security, see [crate::series::Closes]) comes out as
+//! it was quoted.
+//!
+//! ## Triangulation
+//!
+//! A pair can also be derived through a configurable pivot commodity,
+//! as the product of the two legs `commodity→pivot` and `pivot→base`
+//! (e.g. AUD/NZD via USD). Rates derived this way are annotated with the
+//! pivot for auditability.
+
+use anyhow::anyhow;
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use rust_decimal::Decimal;
+use std::fmt;
+
+/// Significant digits of a division which are kept, two fewer than
+/// [Decimal] has.
+const SIGNIFICANT_DIGITS: u32 = 26;
+
+/// How much of the table's base commodity one unit of `commodity` is
+/// quoted at, `None` if it isn't in the table.
+fn quoted_rate(exchange_rate: &ExchangeRate, commodity: &CommodityTypeID) -> Option<Decimal> {
+    if exchange_rate.base.as_ref() == Some(commodity) {
+        Some(Decimal::ONE)
+    } else {
+        exchange_rate.rates.get(commodity).copied()
+    }
+}
+
+/// The price of one unit of `commodity` in `base`, computed through the
+/// base commodity of `exchange_rate` when neither of them is it. `None` if
+/// either commodity is missing from the table.
+pub fn cross_rate(
+    exchange_rate: &ExchangeRate,
+    commodity: &CommodityTypeID,
+    base: &CommodityTypeID,
+) -> anyhow::Result<Option<Decimal>> {
+    if commodity == base {
+        return Ok(Some(Decimal::ONE));
+    }
+
+    let (commodity_rate, base_rate) = match (
+        quoted_rate(exchange_rate, commodity),
+        quoted_rate(exchange_rate, base),
+    ) {
+        (Some(commodity_rate), Some(base_rate)) => (commodity_rate, base_rate),
+        _ => return Ok(None),
+    };
+
+    base_rate
+        .checked_div(commodity_rate)
+        .map(|rate| Some(drop_noise(rate)))
+        .ok_or_else(|| {
+            anyhow!(
+                "Unable to calculate the exchange rate between {} and {} because \
+            of a divide overflow ({}/{})",
+                commodity,
+                base,
+                base_rate,
+                commodity_rate
+            )
+        })
+}
+
+/// `rate` without the noise in the last digits of a division, e.g.
+/// `150` rather than `149.99999999999999999999999925` for `1 / (1 / 150)`.
+/// A rate which needs no rounding is left exactly as it is.
+fn drop_noise(rate: Decimal) -> Decimal {
+    match rate.round_sf(SIGNIFICANT_DIGITS) {
+        Some(rounded) if rounded != rate => rounded.normalize(),
+        _ => rate,
+    }
+}
+
+/// How a rate between two commodities was derived.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Derivation {
+    /// Quoted in the table, or computed as a cross rate through the
+    /// table's own base commodity.
+    Quoted,
+    /// Triangulated through a pivot commodity.
+    Pivot { via: CommodityTypeID },
+}
+
+impl fmt::Display for Derivation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Derivation::Quoted => write!(f, "quoted"),
+            Derivation::Pivot { via } => write!(f, "derived via {}", via),
+        }
+    }
+}
+
+/// A rate along with how it was derived, see [derive_rate()].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DerivedRate {
+    pub rate: Decimal,
+    pub derivation: Derivation,
+}
+
+/// The price of one unit of `commodity` in `base`. When `pivot` is
+/// given (and is neither of the two commodities)
+/// the rate is triangulated as `commodity→pivot × pivot→base`, otherwise
+/// it is the [cross_rate()]. `None` if any leg is missing from the table.
+pub fn derive_rate(
+    exchange_rate: &ExchangeRate,
+    commodity: &CommodityTypeID,
+    base: &CommodityTypeID,
+    pivot: Option<&CommodityTypeID>,
+) -> anyhow::Result<Option<DerivedRate>> {
+    let pivot = pivot.filter(|pivot| *pivot != commodity && *pivot != base);
+
+    match pivot {
+        Some(pivot) => {
+            let to_pivot = cross_rate(exchange_rate, commodity, pivot)?;
+            let from_pivot = cross_rate(exchange_rate, pivot, base)?;
+            Ok(match (to_pivot, from_pivot) {
+                (Some(to_pivot), Some(from_pivot)) => Some(DerivedRate {
+                    rate: to_pivot * from_pivot,
+                    derivation: Derivation::Pivot { via: *pivot },
+                }),
+                _ => None,
+            })
+        }
+        None => Ok(
+            cross_rate(exchange_rate, commodity, base)?.map(|rate| DerivedRate {
+                rate,
+                derivation: Derivation::Quoted,
+            }),
+        ),
+    }
+}
@@ -0,0 +1,408 @@
+use crate::{
+    config::ProviderConfig,
+    pricesource::{self, PriceSource},
+    series::{Closes, Snapshot, TimeSeries},
+};
+use anyhow::anyhow;
+use chrono::{NaiveDate, NaiveDateTime};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use rust_decimal::Decimal;
+use std::{collections::BTreeMap, path::PathBuf, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "csv";
+
+/// The format of the dates when the provider doesn't set `date_format`.
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// The fields of a line of CSV, which may be quoted (with `""` for a
+/// quote inside them).
+pub fn fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field).trim().to_string()),
+            c => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+/// Which file the `csv` source reads, and which of its columns (by the
+/// names in its header) have what, from its provider's config.
+#[derive(Debug)]
+pub struct Import {
+    path: PathBuf,
+    date_column: String,
+    commodity_column: String,
+    price_column: String,
+    /// The column of the currency each price is in, `None` if the
+    /// provider doesn't set one and the file has no `currency` column, in
+    /// which case the prices are taken to be in the base.
+    currency_column: Option<String>,
+    date_format: String,
+}
+
+impl Import {
+    /// The import in the `provider_config` of the `csv` source, which
+    /// needs a `path`. The columns are `date`, `commodity`, `price` and
+    /// `currency` unless the provider sets `date_column`,
+    /// `commodity_column`, `price_column` or `currency_column`.
+    pub fn from_config(provider_config: &ProviderConfig) -> anyhow::Result<Import> {
+        let path = provider_config.path.clone().ok_or_else(|| {
+            anyhow!(
+                "the {} source needs a path in providers.{} in the config",
+                PROVIDER_NAME,
+                PROVIDER_NAME
+            )
+        })?;
+        let column = |value: &Option<String>, default: &str| {
+            value.clone().unwrap_or_else(|| default.to_string())
+        };
+        Ok(Import {
+            path,
+            date_column: column(&provider_config.date_column, "date"),
+            commodity_column: column(&provider_config.commodity_column, "commodity"),
+            price_column: column(&provider_config.price_column, "price"),
+            currency_column: provider_config.currency_column.clone(),
+            date_format: column(&provider_config.date_format, DEFAULT_DATE_FORMAT),
+        })
+    }
+
+    /// A date in the `date_format`, which may have a time too.
+    fn date(&self, date: &str) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(date, &self.date_format)
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(date, &self.date_format).map(|time| time.date())
+            })
+            .ok()
+    }
+}
+
+/// A price, which may have thousands separators, e.g. `1,234.50`.
+fn price(price: &str) -> Option<Decimal> {
+    Decimal::from_str(&price.replace(',', "")).ok()
+}
+
+/// Read the prices of `symbols` (each commodity and the name it has in
+/// the file's commodity column) from `start` to `end` from the `import`'s
+/// file. A commodity with prices in several currencies has those in
+/// `base`, or otherwise those in the currency of its first price (which
+/// is an error naming the currency, like a stock quoted in another
+/// currency). A header line, blank lines and lines starting with `#` are
+/// skipped, along with the lines of other commodities and those without
+/// a price.
+pub fn get_closes(
+    import: &Import,
+    symbols: &[(CommodityTypeID, String)],
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Vec<Closes>> {
+    let path = &import.path;
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| anyhow!("Unable to read {:?}: {}", path, err))?;
+    let mut lines = contents
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim().starts_with('#'));
+
+    let header = match lines.next() {
+        Some((_, header)) => fields(header),
+        None => return Err(anyhow!("{} is empty", path.display())),
+    };
+    let position = |column: &str| {
+        header
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(column))
+    };
+    let required = |column: &str, key: &str| {
+        position(column).ok_or_else(|| {
+            anyhow!(
+                "{} has no {:?} column, expected one of: {} (see {} in providers.{})",
+                path.display(),
+                column,
+                header.join(", "),
+                key,
+                PROVIDER_NAME
+            )
+        })
+    };
+    let date_column = required(&import.date_column, "date_column")?;
+    let commodity_column = required(&import.commodity_column, "commodity_column")?;
+    let price_column = required(&import.price_column, "price_column")?;
+    let currency_column = match &import.currency_column {
+        Some(column) => Some(required(column, "currency_column")?),
+        None => position("currency"),
+    };
+
+    // The prices of each commodity in each currency, in the order they
+    // first appear.
+    let mut prices: Vec<Closes> = Vec::new();
+    for (i, line) in lines {
+        let fields = fields(line);
+        let field = |column: usize| fields.get(column).map(String::as_str).unwrap_or_default();
+        let commodity = match symbols
+            .iter()
+            .find(|(_, symbol)| symbol == field(commodity_column))
+        {
+            Some((commodity, _)) => *commodity,
+            None => continue,
+        };
+        if field(price_column).is_empty() {
+            continue;
+        }
+        let date = import.date(field(date_column)).ok_or_else(|| {
+            anyhow!(
+                "{}:{}: unable to parse the date {:?} with the date_format {:?}",
+                path.display(),
+                i + 1,
+                field(date_column),
+                import.date_format
+            )
+        })?;
+        let price = price(field(price_column)).ok_or_else(|| {
+            anyhow!(
+                "{}:{}: unable to parse the price {:?}",
+                path.display(),
+                i + 1,
+                field(price_column)
+            )
+        })?;
+        let currency = match currency_column.map(field) {
+            Some(currency) if !currency.is_empty() => {
+                CommodityTypeID::from_str(currency).map_err(|err| {
+                    anyhow!(
+                        "{}:{}: unable to parse the currency {:?}: {}",
+                        path.display(),
+                        i + 1,
+                        currency,
+                        err
+                    )
+                })?
+            }
+            _ => base,
+        };
+        if !(start..=end).contains(&&date) {
+            continue;
+        }
+        match prices
+            .iter_mut()
+            .find(|closes| closes.commodity == commodity && closes.currency == currency)
+        {
+            Some(closes) => {
+                closes.closes.insert(date, price);
+            }
+            None => prices.push(Closes {
+                commodity,
+                currency,
+                closes: std::iter::once((date, price)).collect(),
+            }),
+        }
+    }
+
+    Ok(symbols
+        .iter()
+        .map(|(commodity, _)| {
+            let of_commodity = |closes: &&Closes| closes.commodity == *commodity;
+            prices
+                .iter()
+                .filter(of_commodity)
+                .find(|closes| closes.currency == base)
+                .or_else(|| prices.iter().find(of_commodity))
+                .cloned()
+                .unwrap_or_else(|| Closes {
+                    commodity: *commodity,
+                    currency: base,
+                    closes: BTreeMap::new(),
+                })
+        })
+        .collect())
+}
+
+/// The prices in the CSV file set up by `import` as a [PriceSource], with
+/// the symbols in its `config` and the prices in `base`.
+pub struct Csv<'a> {
+    import: Import,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> Csv<'a> {
+    pub fn new(import: Import, config: &'a ProviderConfig, base: CommodityTypeID) -> Csv<'a> {
+        Csv {
+            import,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for Csv<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn series(
+        &self,
+        start: &NaiveDate,
+        end: &NaiveDate,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<Vec<Snapshot>> {
+        let closes = get_closes(
+            &self.import,
+            &self
+                .config
+                .symbols_for(&pricesource::priced(self.base, include)),
+            self.base,
+            start,
+            end,
+        )?;
+        Ok(pricesource::snapshots(TimeSeries::from_closes(
+            self.base, &closes,
+        )?))
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        let symbol = self.config.symbol(&commodity);
+        get_closes(
+            &self.import,
+            &[(commodity, symbol.clone())],
+            self.base,
+            start,
+            end,
+        )?
+        .pop()
+        .ok_or_else(|| anyhow!("{} has no prices for {}", PROVIDER_NAME, symbol))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fields, get_closes, Import};
+    use crate::config::ProviderConfig;
+    use chrono::NaiveDate;
+    use commodity::CommodityTypeID;
+    use std::{path::PathBuf, str::FromStr};
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2021, 3, day).unwrap()
+    }
+
+    fn commodity(code: &str) -> CommodityTypeID {
+        CommodityTypeID::from_str(code).unwrap()
+    }
+
+    /// Write `contents` to a file of its own for the `test`.
+    fn file(test: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "beancount-price-fetcher-csv-{}-{}.csv",
+            test,
+            std::process::id()
+        ));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn quoted_fields() {
+        assert_eq!(
+            fields("a, \"b,c\" ,\"say \"\"hi\"\"\","),
+            vec!["a", "b,c", "say \"hi\"", ""]
+        );
+        assert_eq!(fields(""), vec![""]);
+    }
+
+    #[test]
+    fn closes() {
+        let path = file(
+            "closes",
+            "\u{feff}# exported prices\n\
+            Day,Symbol,Close,Currency\n\
+            \n\
+            2021-03-01,VTI,\"1,234.50\",USD\n\
+            2021-03-02,VTI,,USD\n\
+            2021-03-02,VTI,1240,AUD\n\
+            2021-03-03,VTI,1250,\n\
+            2021-03-10,VTI,1300,USD\n\
+            2021-03-01,BHP,45.1,AUD\n\
+            not a date,OTHER,1,USD\n",
+        );
+        let import = Import::from_config(&ProviderConfig {
+            path: Some(path.clone()),
+            date_column: Some("Day".to_string()),
+            commodity_column: Some("Symbol".to_string()),
+            price_column: Some("Close".to_string()),
+            ..ProviderConfig::default()
+        })
+        .unwrap();
+        let symbols: Vec<(CommodityTypeID, String)> = ["VTI", "BHP", "XYZ"]
+            .iter()
+            .map(|code| (commodity(code), code.to_string()))
+            .collect();
+
+        let closes = get_closes(&import, &symbols, commodity("USD"), &date(1), &date(5)).unwrap();
+        // Each commodity's closes, e.g. `VTI/USD 2021-03-01 1234.50`.
+        let closes: Vec<String> = closes
+            .into_iter()
+            .map(|closes| {
+                let mut line = format!("{}/{}", closes.commodity, closes.currency);
+                for (date, close) in closes.closes {
+                    line.push_str(&format!(" {} {}", date, close));
+                }
+                line
+            })
+            .collect();
+        assert_eq!(
+            closes,
+            vec![
+                // Those in the base over the others, an empty currency
+                // being the base.
+                "VTI/USD 2021-03-01 1234.50 2021-03-03 1250",
+                "BHP/AUD 2021-03-01 45.1",
+                "XYZ/USD",
+            ]
+        );
+
+        // The lines of commodities which weren't asked for aren't parsed.
+        let other = [(commodity("OTHER"), "OTHER".to_string())];
+        let err = get_closes(&import, &other, commodity("USD"), &date(1), &date(5)).unwrap_err();
+        assert!(
+            err.to_string().contains(":10: unable to parse the date"),
+            "{}",
+            err
+        );
+
+        let import = Import::from_config(&ProviderConfig {
+            path: Some(path.clone()),
+            ..ProviderConfig::default()
+        })
+        .unwrap();
+        let err = get_closes(&import, &symbols, commodity("USD"), &date(1), &date(5)).unwrap_err();
+        assert!(err.to_string().contains("no \"date\" column"), "{}", err);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{
+    network::{send_text, RequestError},
+    pricesource::{self, PriceSource},
+    series::{Snapshot, TimeSeries},
+};
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "ecb";
+
+/// The commodity the reference rates are quoted against.
+pub const BASE: &str = "EUR";
+
+/// The latest day's reference rates.
+const DAILY_URL: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-daily.xml";
+
+/// The reference rates of the last 90 days.
+const RECENT_URL: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-hist-90d.xml";
+
+/// Every reference rate since 1999, a few megabytes.
+const HISTORY_URL: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-hist.xml";
+
+/// Number of days [RECENT_URL] goes back, less a few for the time it is
+/// published.
+const RECENT_DAYS: i64 = 85;
+
+/// Fetch the reference rates from `start` to `end`, from the 90 day file
+/// if it covers the range, otherwise the full history. There are no rates
+/// for weekends and TARGET holidays.
+pub async fn get_series(
+    client: &Client,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<TimeSeries> {
+    let url = if *start >= Utc::now().date_naive() - Duration::days(RECENT_DAYS) {
+        RECENT_URL
+    } else {
+        HISTORY_URL
+    };
+    let mut map = parse(&send_text(client.get(url)).await?)?;
+    map.retain(|date, _| (start..=end).contains(&date));
+    Ok(TimeSeries::new(map))
+}
+
+/// Fetch the latest day's reference rates.
+pub async fn get_latest(client: &Client) -> anyhow::Result<ExchangeRate> {
+    parse(&send_text(client.get(DAILY_URL)).await?)?
+        .into_iter()
+        .next_back()
+        .map(|(_, exchange_rate)| exchange_rate)
+        .ok_or_else(|| anyhow!("{} has no latest rates", PROVIDER_NAME))
+}
+
+/// Parse the rates in one of the ECB's eurofxref files, which have the
+/// rates for each day as
+///
+/// ```xml
+/// <Cube time="2020-01-03">
+///     <Cube currency="USD" rate="1.1147"/>
+///     ...
+/// </Cube>
+/// ```
+fn parse(xml: &str) -> anyhow::Result<BTreeMap<NaiveDate, ExchangeRate>> {
+    let invalid = |message: String| anyhow::Error::from(RequestError::InvalidResponse(message));
+    let base = CommodityTypeID::from_str(BASE).expect("expected a valid commodity");
+    let mut map = BTreeMap::new();
+    let mut current: Option<ExchangeRate> = None;
+
+    for tag in xml.split('<').skip(1) {
+        let tag = match tag.strip_prefix("Cube") {
+            Some(tag) => tag.split('>').next().unwrap_or_default(),
+            None => continue,
+        };
+        if let Some(time) = attribute(tag, "time") {
+            let date = NaiveDate::parse_from_str(time, "%Y-%m-%d")
+                .map_err(|err| invalid(format!("unable to parse the date {:?}: {}", time, err)))?;
+            if let Some(exchange_rate) = current.replace(ExchangeRate {
+                date: Some(date),
+                obtained_datetime: Some(Utc::now()),
+                base: Some(base),
+                rates: BTreeMap::new(),
+            }) {
+                map.insert(exchange_rate.date.expect("expected a date"), exchange_rate);
+            }
+        } else if let (Some(currency), Some(rate)) =
+            (attribute(tag, "currency"), attribute(tag, "rate"))
+        {
+            let exchange_rate = current
+                .as_mut()
+                .ok_or_else(|| invalid(format!("a rate for {} without a date", currency)))?;
+            let currency = CommodityTypeID::from_str(currency)
+                .map_err(|err| invalid(format!("unable to parse the currency: {}", err)))?;
+            let rate = Decimal::from_str(rate)
+                .map_err(|err| invalid(format!("unable to parse the rate {:?}: {}", rate, err)))?;
+            exchange_rate.rates.insert(currency, rate);
+        }
+    }
+    if let Some(exchange_rate) = current {
+        map.insert(exchange_rate.date.expect("expected a date"), exchange_rate);
+    }
+    Ok(map)
+}
+
+/// The value of the attribute `name` in `tag` (the inside of an XML
+/// tag), quoted with either `"` or `'`.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+    while let Some(i) = rest.find(name) {
+        let after = &rest[i + name.len()..];
+        let starts_word = rest[..i].ends_with(char::is_whitespace);
+        if let (true, Some(value)) = (starts_word, after.trim_start().strip_prefix('=')) {
+            let value = value.trim_start();
+            let quote = value.chars().next()?;
+            if quote == '"' || quote == '\'' {
+                let value = &value[1..];
+                return value.find(quote).map(|end| &value[..end]);
+            }
+        }
+        rest = after;
+    }
+    None
+}
+
+/// The ECB's reference rates as a [PriceSource], a table for the whole
+/// range.
+pub struct Ecb<'a> {
+    client: &'a Client,
+}
+
+impl<'a> Ecb<'a> {
+    pub fn new(client: &'a Client) -> Ecb<'a> {
+        Ecb { client }
+    }
+}
+
+impl PriceSource for Ecb<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        _include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        Ok((get_latest(self.client).await?, None))
+    }
+
+    async fn series(
+        &self,
+        start: &NaiveDate,
+        end: &NaiveDate,
+        _include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<Vec<Snapshot>> {
+        Ok(pricesource::snapshots(
+            get_series(self.client, start, end).await?,
+        ))
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{
+    network::request_json,
+    pricesource::{self, PriceSource},
+    series::{Snapshot, TimeSeries},
+};
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "exchangeratehost";
+
+/// The environment variable the access key can be given in.
+pub const API_KEY_ENV: &str = "EXCHANGERATE_HOST_ACCESS_KEY";
+
+const API_URL: &str = "https://api.exchangerate.host";
+
+/// The longest range (in days) a `/timeseries` request may cover.
+const MAX_DAYS: i64 = 365;
+
+#[derive(Deserialize, Debug)]
+struct ApiError {
+    code: i64,
+    info: Option<String>,
+}
+
+/// Data from the `/timeseries` endpoint (with `quotes` keyed by date) or
+/// the `/live` endpoint (with `quotes` for the time in `timestamp`). Each
+/// quote is keyed by the source and the currency, e.g. `USDEUR`.
+#[derive(Deserialize, Debug)]
+struct Response<Q> {
+    success: bool,
+    error: Option<ApiError>,
+    source: Option<String>,
+    timestamp: Option<i64>,
+    quotes: Option<Q>,
+}
+
+impl<Q> Response<Q> {
+    fn check(self) -> anyhow::Result<(String, Option<i64>, Q)> {
+        if !self.success {
+            let error = self.error.as_ref();
+            return Err(anyhow!(
+                "{} responded with error {}: {}",
+                PROVIDER_NAME,
+                error.map(|error| error.code).unwrap_or_default(),
+                error
+                    .and_then(|error| error.info.as_deref())
+                    .unwrap_or("unknown error")
+            ));
+        }
+        match (self.source, self.quotes) {
+            (Some(source), Some(quotes)) => Ok((source, self.timestamp, quotes)),
+            _ => Err(anyhow!("{} responded without quotes", PROVIDER_NAME)),
+        }
+    }
+}
+
+/// The number of `/timeseries` requests fetching `start` to `end` takes.
+pub fn requests(start: &NaiveDate, end: &NaiveDate) -> usize {
+    let days = (end.signed_duration_since(*start).num_days() + 1).max(1) as usize;
+    days.div_ceil(MAX_DAYS as usize)
+}
+
+fn currencies(base: CommodityTypeID, commodities: &[CommodityTypeID]) -> String {
+    commodities
+        .iter()
+        .filter(|commodity| **commodity != base)
+        .map(|commodity| commodity.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// The exchange rate for `date` from `quotes` against `source`.
+fn exchange_rate(
+    date: NaiveDate,
+    source: &str,
+    quotes: BTreeMap<String, Decimal>,
+) -> anyhow::Result<ExchangeRate> {
+    let mut rates = BTreeMap::new();
+    for (pair, rate) in quotes {
+        let currency = pair.strip_prefix(source).unwrap_or(&pair);
+        let currency = CommodityTypeID::from_str(currency)
+            .map_err(|err| anyhow!("Unable to parse the currency of {}: {}", pair, err))?;
+        rates.insert(currency, rate);
+    }
+    Ok(ExchangeRate {
+        date: Some(date),
+        obtained_datetime: Some(Utc::now()),
+        base: Some(
+            CommodityTypeID::from_str(source)
+                .map_err(|err| anyhow!("Unable to parse the source currency: {}", err))?,
+        ),
+        rates,
+    })
+}
+
+/// Fetch the rates of `commodities` against `base` from `start` to `end`
+/// with the `/timeseries` endpoint, a request for each year of the range.
+pub async fn get_series(
+    client: &Client,
+    access_key: &str,
+    base: CommodityTypeID,
+    commodities: &[CommodityTypeID],
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<TimeSeries> {
+    let mut map = BTreeMap::new();
+    let mut chunk_start = *start;
+    while chunk_start <= *end {
+        let chunk_end = std::cmp::min(*end, chunk_start + Duration::days(MAX_DAYS - 1));
+        let url = format!(
+            "{api_url}/timeseries?access_key={access_key}&start_date={start}&end_date={end}\
+            &source={base}&currencies={currencies}",
+            api_url = API_URL,
+            access_key = access_key,
+            start = chunk_start.format("%Y-%m-%d"),
+            end = chunk_end.format("%Y-%m-%d"),
+            base = base,
+            currencies = currencies(base, commodities),
+        );
+        let response: Response<BTreeMap<NaiveDate, BTreeMap<String, Decimal>>> =
+            request_json(client, &url).await?;
+        let (source, _, quotes) = response.check()?;
+        for (date, quotes) in quotes {
+            map.insert(date, exchange_rate(date, &source, quotes)?);
+        }
+        chunk_start = chunk_end + Duration::days(1);
+    }
+    Ok(TimeSeries::new(map))
+}
+
+/// Fetch the latest rates of `commodities` against `base` with the
+/// `/live` endpoint, and when they are from.
+pub async fn get_latest(
+    client: &Client,
+    access_key: &str,
+    base: CommodityTypeID,
+    commodities: &[CommodityTypeID],
+) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+    let url = format!(
+        "{}/live?access_key={}&source={}&currencies={}",
+        API_URL,
+        access_key,
+        base,
+        currencies(base, commodities)
+    );
+    let response: Response<BTreeMap<String, Decimal>> = request_json(client, &url).await?;
+    let (source, timestamp, quotes) = response.check()?;
+    let timestamp = timestamp.and_then(|timestamp| NaiveDateTime::from_timestamp_opt(timestamp, 0));
+    let date = timestamp
+        .map(|timestamp| timestamp.date())
+        .unwrap_or_else(|| Utc::now().date_naive());
+    Ok((exchange_rate(date, &source, quotes)?, timestamp))
+}
+
+/// exchangerate.host as a [PriceSource], with the rates against `base` in a
+/// table for the whole range.
+pub struct ExchangerateHost<'a> {
+    client: &'a Client,
+    api_key: &'a str,
+    base: CommodityTypeID,
+}
+
+impl<'a> ExchangerateHost<'a> {
+    pub fn new(
+        client: &'a Client,
+        api_key: &'a str,
+        base: CommodityTypeID,
+    ) -> ExchangerateHost<'a> {
+        ExchangerateHost {
+            client,
+            api_key,
+            base,
+        }
+    }
+}
+
+impl PriceSource for ExchangerateHost<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        get_latest(
+            self.client,
+            self.api_key,
+            self.base,
+            &include.unwrap_or_default(),
+        )
+        .await
+    }
+
+    async fn series(
+        &self,
+        start: &NaiveDate,
+        end: &NaiveDate,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<Vec<Snapshot>> {
+        Ok(pricesource::snapshots(
+            get_series(
+                self.client,
+                self.api_key,
+                self.base,
+                &include.unwrap_or_default(),
+                start,
+                end,
+            )
+            .await?,
+        ))
+    }
+}
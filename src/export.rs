@@ -0,0 +1,157 @@
+use crate::prices::{self, PriceSeries};
+use anyhow::anyhow;
+use std::{
+    fmt::Write as _,
+    io::Write as _,
+    path::Path,
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+/// The database a series is exported into, through its command line shell
+/// (`sqlite3` or `duckdb`) so neither needs to be linked in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    Sqlite,
+    Duckdb,
+}
+
+impl Engine {
+    fn program(self) -> &'static str {
+        match self {
+            Engine::Sqlite => "sqlite3",
+            Engine::Duckdb => "duckdb",
+        }
+    }
+}
+
+impl FromStr for Engine {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sqlite" => Ok(Engine::Sqlite),
+            "duckdb" => Ok(Engine::Duckdb),
+            _ => Err(anyhow!(
+                "unknown database engine {:?}, expected one of: sqlite, duckdb",
+                s
+            )),
+        }
+    }
+}
+
+/// Check that `table` can be used as a table name without quoting: letters,
+/// digits and underscores, not starting with a digit.
+pub fn check_table(table: &str) -> anyhow::Result<()> {
+    let mut chars = table.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(anyhow!(
+            "Invalid table name {:?}, expected letters, digits and underscores, e.g. prices",
+            table
+        ));
+    }
+    Ok(())
+}
+
+/// The SQL which creates `table` (if it doesn't exist yet) and inserts the
+/// prices in `series`, fetched from `source`, replacing any already there
+/// for the same date and pair. It is the same for SQLite and DuckDB:
+///
+/// ```sql
+/// CREATE TABLE IF NOT EXISTS prices (
+///     date DATE NOT NULL,
+///     commodity TEXT NOT NULL,
+///     base TEXT NOT NULL,
+///     rate DECIMAL(38, 18) NOT NULL,
+///     source TEXT NOT NULL,
+///     annotation TEXT,
+///     PRIMARY KEY (date, commodity, base)
+/// );
+/// ```
+pub fn sql(table: &str, series: &[PriceSeries], source: &str, rounding: Option<u32>) -> String {
+    let mut sql = format!(
+        "CREATE TABLE IF NOT EXISTS {table} (\n    \
+        date DATE NOT NULL,\n    \
+        commodity TEXT NOT NULL,\n    \
+        base TEXT NOT NULL,\n    \
+        rate DECIMAL(38, 18) NOT NULL,\n    \
+        source TEXT NOT NULL,\n    \
+        annotation TEXT,\n    \
+        PRIMARY KEY (date, commodity, base)\n\
+        );\n\
+        BEGIN TRANSACTION;\n",
+        table = table
+    );
+
+    for (prices, date, price) in prices::ordered(series, false) {
+        let mut rate = price.rate;
+        if let Some(dp) = rounding {
+            rate = rate.round_dp(dp);
+        }
+        let annotation = match &price.annotation {
+            Some(annotation) => quote(annotation),
+            None => String::from("NULL"),
+        };
+        writeln!(
+            sql,
+            "INSERT OR REPLACE INTO {table} (date, commodity, base, rate, source, annotation) \
+            VALUES ('{date}', {commodity}, {base}, {rate}, {source}, {annotation});",
+            table = table,
+            date = date.format("%Y-%m-%d"),
+            commodity = quote(&prices.commodity.to_string()),
+            base = quote(&prices.base.to_string()),
+            rate = rate,
+            source = quote(source),
+            annotation = annotation,
+        )
+        .expect("writing to a String can't fail");
+    }
+
+    sql.push_str("COMMIT;\n");
+    sql
+}
+
+/// `value` as an SQL string literal.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Run `sql` against the `engine` database at `database` (created if it
+/// doesn't exist), with the engine's command line shell.
+pub fn execute(engine: Engine, database: &Path, sql: &str) -> anyhow::Result<()> {
+    let mut command = Command::new(engine.program());
+    if engine == Engine::Sqlite {
+        command.arg("-bail");
+    }
+    command.arg(database).stdin(Stdio::piped());
+
+    let mut child = command.spawn().map_err(|err| {
+        anyhow!(
+            "Unable to run {} (is it installed, and on the PATH?): {}",
+            engine.program(),
+            err
+        )
+    })?;
+    child
+        .stdin
+        .take()
+        .expect("expected stdin to be piped")
+        .write_all(sql.as_bytes())
+        .map_err(|err| anyhow!("Unable to write to {}: {}", engine.program(), err))?;
+    let status = child
+        .wait()
+        .map_err(|err| anyhow!("Unable to run {}: {}", engine.program(), err))?;
+    if !status.success() {
+        return Err(anyhow!(
+            "Unable to export to {:?}, {} exited with {}",
+            database,
+            engine.program(),
+            status
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,219 @@
+use crate::{
+    network::{send_text, RequestError},
+    pricesource::{self, PriceSource},
+    series::{Snapshot, TimeSeries},
+};
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "federalreserve";
+
+/// The commodity the rates are quoted against.
+pub const BASE: &str = "USD";
+
+/// The H.10 series as republished by FRED, which can be downloaded as a
+/// CSV without a key.
+const API_URL: &str = "https://fred.stlouisfed.org/graph/fredgraph.csv";
+
+/// Number of days [get_latest()] looks back for the latest rates, the
+/// release being weekly.
+const LATEST_DAYS: i64 = 14;
+
+/// The FRED series of each currency in the H.10 release, and whether it
+/// is quoted as USD per unit of the currency rather than the other way
+/// around.
+const SERIES: &[(&str, &str, bool)] = &[
+    ("AUD", "DEXUSAL", true),
+    ("BRL", "DEXBZUS", false),
+    ("CAD", "DEXCAUS", false),
+    ("CNY", "DEXCHUS", false),
+    ("DKK", "DEXDNUS", false),
+    ("EUR", "DEXUSEU", true),
+    ("HKD", "DEXHKUS", false),
+    ("INR", "DEXINUS", false),
+    ("JPY", "DEXJPUS", false),
+    ("KRW", "DEXKOUS", false),
+    ("LKR", "DEXSLUS", false),
+    ("MXN", "DEXMXUS", false),
+    ("MYR", "DEXMAUS", false),
+    ("NOK", "DEXNOUS", false),
+    ("NZD", "DEXUSNZ", true),
+    ("SEK", "DEXSDUS", false),
+    ("SGD", "DEXSIUS", false),
+    ("CHF", "DEXSZUS", false),
+    ("THB", "DEXTHUS", false),
+    ("TWD", "DEXTAUS", false),
+    ("GBP", "DEXUSUK", true),
+    ("ZAR", "DEXSFUS", false),
+];
+
+/// Fetch the noon buying rates in New York of the `commodities` (those in
+/// the H.10 release) from `start` to `end`. There are none for weekends and
+/// US holidays.
+pub async fn get_series(
+    client: &Client,
+    commodities: &[CommodityTypeID],
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<TimeSeries> {
+    let series: Vec<(CommodityTypeID, &str, bool)> = commodities
+        .iter()
+        .filter_map(|commodity| {
+            let code = commodity.to_string();
+            SERIES
+                .iter()
+                .find(|(currency, _, _)| *currency == code)
+                .map(|(_, id, inverted)| (*commodity, *id, *inverted))
+        })
+        .collect();
+    if series.is_empty() {
+        return Err(anyhow!(
+            "{} has none of the commodities, the H.10 release has {}",
+            PROVIDER_NAME,
+            SERIES
+                .iter()
+                .map(|(currency, _, _)| *currency)
+                .collect::<Vec<&str>>()
+                .join(", ")
+        ));
+    }
+    let ids: Vec<&str> = series.iter().map(|(_, id, _)| *id).collect();
+    let url = format!(
+        "{}?id={}&cosd={}&coed={}",
+        API_URL,
+        ids.join(","),
+        start.format("%Y-%m-%d"),
+        end.format("%Y-%m-%d")
+    );
+    let mut map = parse(&send_text(client.get(&url)).await?, &series)?;
+    map.retain(|date, _| (start..=end).contains(&date));
+    Ok(TimeSeries::new(map))
+}
+
+/// Fetch the latest rates of the `commodities`, from the last two weeks.
+pub async fn get_latest(
+    client: &Client,
+    commodities: &[CommodityTypeID],
+) -> anyhow::Result<ExchangeRate> {
+    let end = Utc::now().date_naive();
+    let start = end - Duration::days(LATEST_DAYS);
+    get_series(client, commodities, &start, &end)
+        .await?
+        .map
+        .into_iter()
+        .next_back()
+        .map(|(_, exchange_rate)| exchange_rate)
+        .ok_or_else(|| anyhow!("{} has no latest rates", PROVIDER_NAME))
+}
+
+/// Parse the rates of the `series` in a FRED CSV, with a header line
+/// naming the date and then the series of each column, and a line for
+/// each day such as `2020-01-02,1.1193,108.43`. A day a rate wasn't
+/// published is empty (or `.` in older files).
+fn parse(
+    csv: &str,
+    series: &[(CommodityTypeID, &str, bool)],
+) -> anyhow::Result<BTreeMap<NaiveDate, ExchangeRate>> {
+    let invalid = |message: String| anyhow::Error::from(RequestError::InvalidResponse(message));
+    let base = CommodityTypeID::from_str(BASE).expect("expected a valid commodity");
+    let mut lines = csv.lines().map(str::trim).filter(|line| !line.is_empty());
+    let columns: Vec<Option<(CommodityTypeID, bool)>> = match lines.next() {
+        Some(header) => header
+            .split(',')
+            .skip(1)
+            .map(|id| {
+                series
+                    .iter()
+                    .find(|(_, series_id, _)| *series_id == id.trim())
+                    .map(|(commodity, _, inverted)| (*commodity, *inverted))
+            })
+            .collect(),
+        None => return Ok(BTreeMap::new()),
+    };
+
+    let mut map = BTreeMap::new();
+    for line in lines {
+        let mut fields = line.split(',').map(str::trim);
+        let date = fields.next().unwrap_or_default();
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|err| invalid(format!("unable to parse the date {:?}: {}", date, err)))?;
+        let mut rates = BTreeMap::new();
+        for (column, value) in columns.iter().zip(fields) {
+            let (commodity, inverted) = match column {
+                Some(column) => *column,
+                None => continue,
+            };
+            if value.is_empty() || value == "." {
+                continue;
+            }
+            let value = Decimal::from_str(value)
+                .map_err(|err| invalid(format!("unable to parse the rate {:?}: {}", value, err)))?;
+            if value.is_zero() {
+                continue;
+            }
+            rates.insert(
+                commodity,
+                if inverted {
+                    Decimal::ONE / value
+                } else {
+                    value
+                },
+            );
+        }
+        map.insert(
+            date,
+            ExchangeRate {
+                date: Some(date),
+                obtained_datetime: Some(Utc::now()),
+                base: Some(base),
+                rates,
+            },
+        );
+    }
+    Ok(map)
+}
+
+/// The Federal Reserve's rates as a [PriceSource], a table for the whole
+/// range.
+pub struct FederalReserve<'a> {
+    client: &'a Client,
+}
+
+impl<'a> FederalReserve<'a> {
+    pub fn new(client: &'a Client) -> FederalReserve<'a> {
+        FederalReserve { client }
+    }
+}
+
+impl PriceSource for FederalReserve<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        Ok((
+            get_latest(self.client, &include.unwrap_or_default()).await?,
+            None,
+        ))
+    }
+
+    async fn series(
+        &self,
+        start: &NaiveDate,
+        end: &NaiveDate,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<Vec<Snapshot>> {
+        Ok(pricesource::snapshots(
+            get_series(self.client, &include.unwrap_or_default(), start, end).await?,
+        ))
+    }
+}
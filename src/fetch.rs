@@ -0,0 +1,402 @@
+use crate::{
+    cache::Cache,
+    metrics::SERVICE_METRICS,
+    network::RequestError,
+    openexchangerate,
+    pricesource::{self, DynPriceSource},
+    ratelimit::RateLimiter,
+    series::{Closes, DateMismatch, Sampling, Snapshot, SnapshotTime, TimeSeries},
+    source::Source,
+    warnings::{warn, Kind},
+};
+use chrono::{Duration, NaiveDate};
+use commodity::CommodityTypeID;
+use futures::{stream, StreamExt};
+use std::collections::BTreeSet;
+
+/// How the requests for a series are made.
+pub struct FetchOptions<'a> {
+    pub parallel_requests: usize,
+    pub rate_limiter: Option<&'a RateLimiter>,
+    /// Number of times a request that failed with a transient error is
+    /// tried again.
+    pub retries: u32,
+    pub cache: Option<&'a Cache>,
+    /// Use the nearest cached exchange rate for a date when it can't be
+    /// fetched, from up to this many days before it.
+    pub stale_ok: Option<i64>,
+    /// Request the full rate table rather than only the needed symbols,
+    /// to warm the cache.
+    pub full_table: bool,
+    /// Which dates in the range are fetched.
+    pub sampling: Sampling,
+    /// The time of day which defines the daily price.
+    pub snapshot_time: Option<SnapshotTime>,
+    /// Which date a response dated differently to the requested date is
+    /// attributed to.
+    pub date_mismatch: DateMismatch,
+    /// Where the rates are fetched from.
+    pub source: Source,
+}
+
+/// How long the requests to a provider are held back after it refused one
+/// for its rate limit, a whole minute as most limits are per minute.
+const RATE_LIMIT_PAUSE: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Run `request` with the rate limit applied, trying it again (with
+/// exponential backoff, or after a [RATE_LIMIT_PAUSE] of all the requests
+/// if the provider refused it for the rate limit) up to `retries` times if
+/// it fails with a transient error. `what` describes the request, for the
+/// log.
+async fn with_retries<T, F, R>(
+    options: &FetchOptions<'_>,
+    what: &str,
+    request: F,
+) -> anyhow::Result<T>
+where
+    F: Fn() -> R,
+    R: std::future::Future<Output = anyhow::Result<T>>,
+{
+    let mut attempt: u32 = 0;
+    loop {
+        if let Some(rate_limiter) = options.rate_limiter {
+            rate_limiter.wait().await;
+        }
+
+        match request().await {
+            Ok(result) => return Ok(result),
+            Err(error) => {
+                let request_error = error.downcast_ref::<RequestError>();
+                let transient = request_error
+                    .map(RequestError::is_transient)
+                    .unwrap_or(false);
+                if !transient || attempt >= options.retries {
+                    return Err(error);
+                }
+                log::debug!("retrying request for {} after error: {}", what, error);
+                match options.rate_limiter {
+                    // The provider's limits are per minute, so all of the
+                    // requests wait for the next one rather than each
+                    // backing off on its own.
+                    Some(rate_limiter)
+                        if request_error
+                            .map(RequestError::is_rate_limited)
+                            .unwrap_or(false) =>
+                    {
+                        rate_limiter.pause(RATE_LIMIT_PAUSE)
+                    }
+                    _ => {
+                        tokio::time::delay_for(std::time::Duration::from_secs(1 << attempt.min(6)))
+                            .await
+                    }
+                }
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// [DynPriceSource::historical()] [with_retries()].
+async fn get_historical_with_retries(
+    provider: &dyn DynPriceSource,
+    options: &FetchOptions<'_>,
+    date: &NaiveDate,
+    include: Option<Vec<CommodityTypeID>>,
+) -> anyhow::Result<Snapshot> {
+    with_retries(options, &date.to_string(), || {
+        provider.historical(date, include.clone())
+    })
+    .await
+}
+
+/// Get the exchange rate for `date`, from the cache if possible,
+/// otherwise from the `provider`.
+async fn get_day(
+    provider: &dyn DynPriceSource,
+    options: &FetchOptions<'_>,
+    date: &NaiveDate,
+    include: Option<Vec<CommodityTypeID>>,
+) -> anyhow::Result<Snapshot> {
+    // A full table request is always made, it is meant to refresh the cache.
+    if let (Some(cache), false) = (options.cache, options.full_table) {
+        let commodities = include.clone().unwrap_or_default();
+        let cached = cache.get_complete(date, &commodities)?;
+        SERVICE_METRICS.record_cache(cached.is_some());
+        if let Some(exchange_rate) = cached {
+            return Ok(Snapshot {
+                requested_date: *date,
+                timestamp: None,
+                exchange_rate,
+            });
+        }
+    }
+
+    // Only the needed symbols are requested, unless deliberately
+    // fetching the full table for the cache.
+    let request_include = if options.full_table {
+        None
+    } else {
+        include.clone()
+    };
+
+    match get_historical_with_retries(provider, options, date, request_include).await {
+        Ok(snapshot) => {
+            if let Some(cache) = options.cache {
+                cache.put(&snapshot.exchange_rate)?;
+            }
+            Ok(snapshot)
+        }
+        Err(error) => {
+            if let (Some(max_days), Some(cache)) = (options.stale_ok, options.cache) {
+                let commodities = include.unwrap_or_default();
+                if let Some((cached, mut stale)) =
+                    cache.get_nearest_prior_complete(date, &commodities, max_days)?
+                {
+                    warn(
+                        Kind::StaleRates,
+                        format!(
+                            "unable to fetch {} ({}), using the cached rates from {} ({} day(s) \
+                            old)",
+                            date.format("%Y-%m-%d"),
+                            error,
+                            cached.format("%Y-%m-%d"),
+                            (*date - cached).num_days(),
+                        ),
+                    );
+                    stale.date = Some(*date);
+                    return Ok(Snapshot {
+                        requested_date: *date,
+                        timestamp: None,
+                        exchange_rate: stale,
+                    });
+                }
+            }
+            Err(error)
+        }
+    }
+}
+
+/// The series from `start` to `end` from the `provider`, with a
+/// [get_day()] for each sampled date.
+pub async fn get_time_series_with_historical(
+    provider: &dyn DynPriceSource,
+    options: &FetchOptions<'_>,
+    start: &NaiveDate,
+    end: &NaiveDate,
+    include: Option<Vec<CommodityTypeID>>,
+) -> anyhow::Result<TimeSeries> {
+    let dates: Vec<NaiveDate> = options.sampling.dates(start, end);
+
+    let buffer = stream::iter(dates)
+        .map(|date| {
+            let include = include.clone();
+            async move { get_day(provider, options, &date, include).await }
+        })
+        .buffer_unordered(options.parallel_requests);
+
+    let results: Vec<anyhow::Result<Snapshot>> = buffer.collect().await;
+    let snapshots = results
+        .into_iter()
+        .collect::<anyhow::Result<Vec<Snapshot>>>()?;
+
+    series_from_snapshots(options, snapshots, start, end)
+}
+
+/// The series from `start` to `end` of the `snapshots`, warning about
+/// the dates which were mismatched or collided (see
+/// [TimeSeries::from_snapshots()]).
+fn series_from_snapshots(
+    options: &FetchOptions<'_>,
+    snapshots: Vec<Snapshot>,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<TimeSeries> {
+    let (mut series, collisions, mismatches) = TimeSeries::from_snapshots(
+        snapshots,
+        options.snapshot_time.as_ref(),
+        options.date_mismatch,
+    )?;
+    for mismatch in &mismatches {
+        let attributed = match options.date_mismatch {
+            DateMismatch::Requested => mismatch.requested,
+            _ => mismatch.response,
+        };
+        warn(
+            Kind::DateMismatch,
+            format!(
+                "{}, using it for {} (see --date-mismatch)",
+                mismatch,
+                attributed.format("%Y-%m-%d")
+            ),
+        );
+    }
+    for collision in &collisions {
+        warn(Kind::Collision, collision.to_string());
+    }
+
+    // Re-dated snapshots (see --snapshot-time and --date-mismatch) may
+    // fall outside the range.
+    series.map.retain(|date, _| (start..=end).contains(&date));
+
+    Ok(series)
+}
+
+/// The series of the `include` commodities from `start` to `end` from
+/// the bulk source in `options` (see [Source::is_bulk()]) quoting in
+/// `base`, with a request for each commodity (or one for them all, or
+/// several each, see [request_ranges()]), keeping only the sampled dates.
+pub async fn get_bulk_series(
+    provider: &dyn DynPriceSource,
+    options: &FetchOptions<'_>,
+    start: &NaiveDate,
+    end: &NaiveDate,
+    base: CommodityTypeID,
+    include: Vec<CommodityTypeID>,
+) -> anyhow::Result<TimeSeries> {
+    let dates: BTreeSet<NaiveDate> = options.sampling.dates(start, end).into_iter().collect();
+    if options.source.is_table() {
+        let snapshots = with_retries(options, provider.name(), || {
+            provider.series(start, end, Some(include.clone()))
+        })
+        .await?;
+        let mut series = series_from_snapshots(options, snapshots, start, end)?;
+        series.map.retain(|date, _| dates.contains(date));
+        return Ok(series);
+    }
+
+    // Each request of each commodity is fetched in parallel, and the
+    // closes of a commodity put back together afterwards.
+    let ranges = request_ranges(options.source, start, end);
+    let requests: Vec<(CommodityTypeID, NaiveDate, NaiveDate)> =
+        pricesource::priced(base, Some(include))
+            .into_iter()
+            .flat_map(|commodity| {
+                ranges
+                    .iter()
+                    .map(move |(range_start, range_end)| (commodity, *range_start, *range_end))
+            })
+            .collect();
+    let buffer = stream::iter(requests)
+        .map(|(commodity, range_start, range_end)| async move {
+            with_retries(options, &commodity.to_string(), || {
+                provider.closes(commodity, &range_start, &range_end)
+            })
+            .await
+        })
+        .buffer_unordered(options.parallel_requests);
+    let results: Vec<anyhow::Result<Closes>> = buffer.collect().await;
+    let mut closes: Vec<Closes> = Vec::new();
+    for result in results {
+        let result = result?;
+        match closes
+            .iter_mut()
+            .find(|closes| closes.commodity == result.commodity)
+        {
+            Some(closes) => closes.closes.extend(result.closes),
+            None => closes.push(result),
+        }
+    }
+
+    let mut series = TimeSeries::from_closes(base, &closes)?;
+    series.map.retain(|date, _| dates.contains(date));
+    Ok(series)
+}
+
+/// The ranges the requests for a commodity from `start` to `end` cover:
+/// the whole range, or for a source with a
+/// [Source::max_days_per_request()] consecutive ranges of up to that many
+/// days.
+pub fn request_ranges(
+    source: Source,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> Vec<(NaiveDate, NaiveDate)> {
+    let max_days = match source.max_days_per_request() {
+        Some(max_days) => max_days,
+        None => return vec![(*start, *end)],
+    };
+    let mut ranges = Vec::new();
+    let mut range_start = *start;
+    while range_start <= *end {
+        let range_end = std::cmp::min(*end, range_start + Duration::days(max_days - 1));
+        ranges.push((range_start, range_end));
+        range_start = range_end + Duration::days(1);
+    }
+    ranges
+}
+
+/// The series from `start` to `end` with [DynPriceSource::series()], e.g.
+/// openexchangerates' time-series.json api for the plans with the
+/// `time_series` feature: a request for each
+/// [openexchangerate::TIME_SERIES_MAX_DAYS] of the sampled dates which
+/// aren't cached (see [time_series_ranges()]), rather than one for each
+/// date. The fetched rates are cached, like those of [get_day()].
+pub async fn get_time_series(
+    provider: &dyn DynPriceSource,
+    options: &FetchOptions<'_>,
+    start: &NaiveDate,
+    end: &NaiveDate,
+    include: Option<Vec<CommodityTypeID>>,
+) -> anyhow::Result<TimeSeries> {
+    let mut snapshots = Vec::new();
+    let mut uncached = Vec::new();
+    for date in options.sampling.dates(start, end) {
+        // A full table request is always made, it is meant to refresh the
+        // cache.
+        if let (Some(cache), false) = (options.cache, options.full_table) {
+            let cached = cache.get_complete(&date, &include.clone().unwrap_or_default())?;
+            SERVICE_METRICS.record_cache(cached.is_some());
+            if let Some(exchange_rate) = cached {
+                snapshots.push(Snapshot {
+                    requested_date: date,
+                    timestamp: None,
+                    exchange_rate,
+                });
+                continue;
+            }
+        }
+        uncached.push(date);
+    }
+
+    // Only the needed symbols are requested, unless deliberately
+    // fetching the full table for the cache.
+    let include = if options.full_table { None } else { include };
+    for (range_start, range_end) in time_series_ranges(&uncached) {
+        let what = format!("{} to {}", range_start, range_end);
+        let fetched = with_retries(options, &what, || {
+            provider.series(&range_start, &range_end, include.clone())
+        })
+        .await?;
+        for snapshot in fetched {
+            // The unsampled dates between the sampled ones.
+            if uncached.binary_search(&snapshot.requested_date).is_err() {
+                continue;
+            }
+            if let Some(cache) = options.cache {
+                cache.put(&snapshot.exchange_rate)?;
+            }
+            snapshots.push(snapshot);
+        }
+    }
+
+    series_from_snapshots(options, snapshots, start, end)
+}
+
+/// The ranges of up to [openexchangerate::TIME_SERIES_MAX_DAYS] which
+/// cover the (sorted) `dates`, each starting at a date so a range is only
+/// requested where a date is needed.
+pub fn time_series_ranges(dates: &[NaiveDate]) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut ranges = Vec::new();
+    let mut dates = dates.iter().peekable();
+    while let Some(range_start) = dates.next() {
+        let mut range_end = *range_start;
+        while let Some(date) = dates.next_if(|date| {
+            date.signed_duration_since(*range_start).num_days()
+                < openexchangerate::TIME_SERIES_MAX_DAYS
+        }) {
+            range_end = *date;
+        }
+        ranges.push((*range_start, range_end));
+    }
+    ranges
+}
@@ -0,0 +1,194 @@
+use crate::{
+    config::ProviderConfig,
+    metadata,
+    network::request_json,
+    pricesource::{self, PriceSource},
+    series::{Closes, SnapshotTime},
+};
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "finnhub";
+
+/// The environment variable the API key can be given in.
+pub const API_KEY_ENV: &str = "FINNHUB_API_KEY";
+
+const API_URL: &str = "https://finnhub.io/api/v1";
+
+/// The exchange whose rates are used for a forex commodity without a
+/// symbol in the config.
+const FOREX_EXCHANGE: &str = "OANDA";
+
+/// Data from the candle endpoints, each field a list with an entry for
+/// each candle.
+#[derive(Deserialize, Debug)]
+struct Candles {
+    /// `ok`, or `no_data` if there are no candles in the range.
+    s: String,
+    /// When each candle starts (UNIX time).
+    #[serde(default)]
+    t: Vec<i64>,
+    /// The closing price of each candle.
+    #[serde(default)]
+    c: Vec<f64>,
+}
+
+/// The candle resolution for the daily prices: daily candles, or hourly
+/// ones to find the price at the `snapshot_time`.
+fn resolution(snapshot_time: Option<&SnapshotTime>) -> (&'static str, i64) {
+    match snapshot_time {
+        Some(_) => ("60", 60 * 60),
+        None => ("D", 24 * 60 * 60),
+    }
+}
+
+/// Fetch the daily closing prices of `symbol` from `start` to `end`, as
+/// the prices of `commodity`. A symbol with an exchange (e.g.
+/// `OANDA:EUR_USD`) or an ISO 4217 currency (against `base`, from OANDA)
+/// is fetched from the forex candles, anything else from the stock
+/// candles, and taken to be quoted in `base`.
+///
+/// Each date has the close of its daily candle (dated in UTC), or with a
+/// `snapshot_time` that of the last hourly candle to close by the snapshot
+/// time.
+#[allow(clippy::too_many_arguments)]
+pub async fn get_closes(
+    client: &Client,
+    api_key: &str,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+    snapshot_time: Option<&SnapshotTime>,
+) -> anyhow::Result<Closes> {
+    let (endpoint, symbol) = if symbol.contains(':') {
+        ("forex", symbol.to_string())
+    } else if metadata::iso_decimal_places(symbol).is_some() {
+        ("forex", format!("{}:{}_{}", FOREX_EXCHANGE, symbol, base))
+    } else {
+        ("stock", symbol.to_string())
+    };
+    let (resolution, candle_seconds) = resolution(snapshot_time);
+    let timestamp = |date: NaiveDate| {
+        date.and_hms_opt(0, 0, 0)
+            .expect("midnight is a valid time")
+            .and_utc()
+            .timestamp()
+    };
+    // A day either side, for snapshot times away from UTC.
+    let url = format!(
+        "{api_url}/{endpoint}/candle?symbol={symbol}&resolution={resolution}\
+        &from={from}&to={to}&token={token}",
+        api_url = API_URL,
+        endpoint = endpoint,
+        symbol = url::form_urlencoded::byte_serialize(symbol.as_bytes()).collect::<String>(),
+        resolution = resolution,
+        from = timestamp(*start - Duration::days(1)),
+        to = timestamp(*end + Duration::days(2)),
+        token = api_key,
+    );
+    let candles: Candles = request_json(client, &url).await?;
+    if candles.s != "ok" && candles.s != "no_data" {
+        return Err(anyhow!(
+            "{} responded with status {:?} for {}",
+            PROVIDER_NAME,
+            candles.s,
+            symbol
+        ));
+    }
+
+    // The candles are in time order, so the last on each date wins.
+    let mut closes = BTreeMap::new();
+    for (start_time, close) in candles.t.iter().zip(candles.c) {
+        let date = match snapshot_time {
+            Some(snapshot_time) => {
+                match NaiveDateTime::from_timestamp_opt(start_time + candle_seconds, 0) {
+                    Some(close_time) => snapshot_time.date_of(&close_time),
+                    None => continue,
+                }
+            }
+            None => match NaiveDateTime::from_timestamp_opt(*start_time, 0) {
+                Some(start_time) => start_time.date(),
+                None => continue,
+            },
+        };
+        if let (true, Some(close)) = ((start..=end).contains(&&date), Decimal::from_f64(close)) {
+            closes.insert(date, close);
+        }
+    }
+
+    Ok(Closes {
+        commodity,
+        currency: base,
+        closes,
+    })
+}
+
+/// Finnhub as a [PriceSource], with the symbols in its `config` and the
+/// prices in `base`, at the `snapshot_time` rather than the close when
+/// there is one.
+pub struct Finnhub<'a> {
+    client: &'a Client,
+    api_key: &'a str,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+    snapshot_time: Option<SnapshotTime>,
+}
+
+impl<'a> Finnhub<'a> {
+    pub fn new(
+        client: &'a Client,
+        api_key: &'a str,
+        config: &'a ProviderConfig,
+        base: CommodityTypeID,
+        snapshot_time: Option<SnapshotTime>,
+    ) -> Finnhub<'a> {
+        Finnhub {
+            client,
+            api_key,
+            config,
+            base,
+            snapshot_time,
+        }
+    }
+}
+
+impl PriceSource for Finnhub<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            self.client,
+            self.api_key,
+            commodity,
+            &self.config.symbol(&commodity),
+            self.base,
+            start,
+            end,
+            self.snapshot_time.as_ref(),
+        )
+        .await
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{
+    config::ProviderConfig,
+    network::request_json,
+    pricesource::{self, PriceSource},
+    series::Closes,
+};
+use anyhow::anyhow;
+use chrono::{NaiveDate, NaiveDateTime};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use serde::Deserialize;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "fmp";
+
+/// The environment variable the API key can be given in.
+pub const API_KEY_ENV: &str = "FMP_API_KEY";
+
+const API_URL: &str = "https://financialmodelingprep.com/api/v3";
+
+/// The requests fetching a commodity takes: its profile (for the currency
+/// it is quoted in) and its prices.
+pub const REQUESTS: usize = 2;
+
+/// A company's (or fund's) part of a `/profile` response.
+#[derive(Deserialize, Debug)]
+struct Profile {
+    currency: Option<String>,
+}
+
+/// Data from the `/historical-price-full` endpoint, which is empty for a
+/// symbol FMP doesn't have.
+#[derive(Deserialize, Debug)]
+struct Historical {
+    #[serde(default)]
+    historical: Vec<Day>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Day {
+    date: NaiveDate,
+    /// The unadjusted close.
+    close: Option<f64>,
+}
+
+/// Fetch the daily closes of the stock, ETF or mutual fund `symbol` from
+/// `start` to `end`, as the prices of `commodity`, in the currency its
+/// profile says it is quoted in.
+pub async fn get_closes(
+    client: &Client,
+    api_key: &str,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Closes> {
+    let ticker = url::form_urlencoded::byte_serialize(symbol.as_bytes()).collect::<String>();
+    let url = format!("{}/profile/{}?apikey={}", API_URL, ticker, api_key);
+    let profiles: Vec<Profile> = request_json(client, &url).await?;
+    let currency = profiles
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("{} has no symbol {:?}", PROVIDER_NAME, symbol))?
+        .currency
+        .ok_or_else(|| {
+            anyhow!(
+                "{} doesn't say which currency {} is quoted in",
+                PROVIDER_NAME,
+                symbol
+            )
+        })?;
+    let currency = CommodityTypeID::from_str(&currency)
+        .map_err(|err| anyhow!("Unable to parse the currency of {}: {}", symbol, err))?;
+
+    let url = format!(
+        "{}/historical-price-full/{}?from={}&to={}&apikey={}",
+        API_URL,
+        ticker,
+        start.format("%Y-%m-%d"),
+        end.format("%Y-%m-%d"),
+        api_key
+    );
+    let historical: Historical = request_json(client, &url).await?;
+    let closes = historical
+        .historical
+        .into_iter()
+        .filter(|day| (start..=end).contains(&&day.date))
+        .filter_map(|day| Some((day.date, day.close.and_then(Decimal::from_f64)?)))
+        .collect::<BTreeMap<NaiveDate, Decimal>>();
+
+    Ok(Closes {
+        commodity,
+        currency,
+        closes,
+    })
+}
+
+/// Financial Modeling Prep as a [PriceSource], with the symbols in its
+/// `config`. The prices are in each symbol's currency, which must be
+/// `base`.
+pub struct Fmp<'a> {
+    client: &'a Client,
+    api_key: &'a str,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> Fmp<'a> {
+    pub fn new(
+        client: &'a Client,
+        api_key: &'a str,
+        config: &'a ProviderConfig,
+        base: CommodityTypeID,
+    ) -> Fmp<'a> {
+        Fmp {
+            client,
+            api_key,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for Fmp<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            self.client,
+            self.api_key,
+            commodity,
+            &self.config.symbol(&commodity),
+            start,
+            end,
+        )
+        .await
+    }
+}
@@ -0,0 +1,158 @@
+use crate::{
+    network::{request_json, RequestError},
+    pricesource::{self, PriceSource},
+    series::{Snapshot, TimeSeries},
+};
+use anyhow::anyhow;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::{Client, StatusCode};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "frankfurter";
+
+const API_URL: &str = "https://api.frankfurter.app";
+
+/// Data from the time series endpoint, e.g.
+/// https://api.frankfurter.app/2020-01-01..2020-01-31?from=AUD&to=NZD
+#[derive(Deserialize, Debug)]
+struct SeriesResponse {
+    base: CommodityTypeID,
+    rates: BTreeMap<NaiveDate, BTreeMap<CommodityTypeID, Decimal>>,
+}
+
+/// Data from the latest endpoint.
+#[derive(Deserialize, Debug)]
+struct LatestResponse {
+    base: CommodityTypeID,
+    date: NaiveDate,
+    rates: BTreeMap<CommodityTypeID, Decimal>,
+}
+
+/// The query choosing the rates of `commodities` against `base`, the
+/// base itself left out (Frankfurter rejects it).
+fn query(base: CommodityTypeID, commodities: &[CommodityTypeID]) -> String {
+    let to: Vec<String> = commodities
+        .iter()
+        .filter(|commodity| **commodity != base)
+        .map(|commodity| commodity.to_string())
+        .collect();
+    format!("from={}&to={}", base, to.join(","))
+}
+
+/// GET `url`, explaining a 404 (which is what an unknown currency gives).
+async fn request<T: serde::de::DeserializeOwned>(client: &Client, url: &str) -> anyhow::Result<T> {
+    request_json(client, url)
+        .await
+        .map_err(|error| match error.downcast_ref::<RequestError>() {
+            Some(RequestError::Status(StatusCode::NOT_FOUND)) => anyhow!(
+                "{} doesn't have the rates, it only has the currencies the ECB publishes",
+                PROVIDER_NAME
+            ),
+            _ => error,
+        })
+}
+
+fn exchange_rate(
+    date: NaiveDate,
+    base: CommodityTypeID,
+    rates: BTreeMap<CommodityTypeID, Decimal>,
+) -> ExchangeRate {
+    ExchangeRate {
+        date: Some(date),
+        obtained_datetime: Some(Utc::now()),
+        base: Some(base),
+        rates,
+    }
+}
+
+/// Fetch the rates of `commodities` against `base` from `start` to `end`
+/// in a single request. These are the ECB's reference rates, so there are
+/// none for weekends and TARGET holidays.
+pub async fn get_series(
+    client: &Client,
+    base: CommodityTypeID,
+    commodities: &[CommodityTypeID],
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<TimeSeries> {
+    let url = format!(
+        "{api_url}/{start}..{end}?{query}",
+        api_url = API_URL,
+        start = start.format("%Y-%m-%d"),
+        end = end.format("%Y-%m-%d"),
+        query = query(base, commodities),
+    );
+    let response: SeriesResponse = request(client, &url).await?;
+    // The range starts at the last rates before `start`.
+    let base = response.base;
+    let map = response
+        .rates
+        .into_iter()
+        .filter(|(date, _)| (start..=end).contains(&date))
+        .map(|(date, rates)| (date, exchange_rate(date, base, rates)))
+        .collect();
+    Ok(TimeSeries::new(map))
+}
+
+/// Fetch the latest rates of `commodities` against `base`.
+pub async fn get_latest(
+    client: &Client,
+    base: CommodityTypeID,
+    commodities: &[CommodityTypeID],
+) -> anyhow::Result<ExchangeRate> {
+    let url = format!("{}/latest?{}", API_URL, query(base, commodities));
+    let response: LatestResponse = request(client, &url).await?;
+    Ok(exchange_rate(response.date, response.base, response.rates))
+}
+
+/// Frankfurter as a [PriceSource], with the rates against `base` in a table
+/// for the whole range.
+pub struct Frankfurter<'a> {
+    client: &'a Client,
+    base: CommodityTypeID,
+}
+
+impl<'a> Frankfurter<'a> {
+    pub fn new(client: &'a Client, base: CommodityTypeID) -> Frankfurter<'a> {
+        Frankfurter { client, base }
+    }
+}
+
+impl PriceSource for Frankfurter<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        Ok((
+            get_latest(self.client, self.base, &include.unwrap_or_default()).await?,
+            None,
+        ))
+    }
+
+    async fn series(
+        &self,
+        start: &NaiveDate,
+        end: &NaiveDate,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<Vec<Snapshot>> {
+        Ok(pricesource::snapshots(
+            get_series(
+                self.client,
+                self.base,
+                &include.unwrap_or_default(),
+                start,
+                end,
+            )
+            .await?,
+        ))
+    }
+}
@@ -0,0 +1,516 @@
+use crate::warnings::{warn, Kind};
+use anyhow::anyhow;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    str::FromStr,
+};
+
+/// Account roots whose postings are counted by default: what is owned and
+/// what is owed, i.e. net worth.
+pub const DEFAULT_ACCOUNTS: &[&str] = &["Assets", "Liabilities"];
+
+/// A change in the quantity held of a commodity.
+#[derive(Debug, Clone)]
+struct Change {
+    /// The date of the posting it came from, or `None` for a holding from
+    /// a CSV, which is held on every date.
+    date: Option<NaiveDate>,
+    commodity: String,
+    quantity: Decimal,
+}
+
+/// Quantities of commodities held, from the postings of a beancount
+/// journal or a CSV of commodity/quantity pairs.
+#[derive(Debug, Clone, Default)]
+pub struct Holdings {
+    changes: Vec<Change>,
+}
+
+impl Holdings {
+    /// Read the holdings from a CSV with a commodity and a quantity on each
+    /// line, e.g. `NZD,1000.50`. A header line, blank lines and lines
+    /// starting with `#` are skipped, and a commodity listed more than once
+    /// is summed.
+    pub fn from_csv(path: &Path) -> anyhow::Result<Holdings> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| anyhow!("Unable to read {:?}: {}", path, err))?;
+        let mut changes = Vec::new();
+        let mut first = true;
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let header = std::mem::replace(&mut first, false);
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let (commodity, quantity) = match fields.as_slice() {
+                [commodity, quantity] => (*commodity, *quantity),
+                _ => {
+                    return Err(anyhow!(
+                        "{}:{}: expected a commodity and a quantity, e.g. NZD,1000.50",
+                        path.display(),
+                        i + 1
+                    ))
+                }
+            };
+            let quantity = match parse_number(quantity) {
+                Some(quantity) => quantity,
+                None if header => continue,
+                None => {
+                    return Err(anyhow!(
+                        "{}:{}: unable to parse the quantity {:?}",
+                        path.display(),
+                        i + 1,
+                        quantity
+                    ))
+                }
+            };
+            changes.push(Change {
+                date: None,
+                commodity: commodity.to_string(),
+                quantity,
+            });
+        }
+        Ok(Holdings { changes })
+    }
+
+    /// Read the holdings from the postings to `accounts` (and their
+    /// sub-accounts) in the beancount journal at `path`, following its
+    /// `include`s. A posting's units are what is held, the cost or price
+    /// only matters for working out an elided amount.
+    ///
+    /// This reads only transactions, it doesn't run beancount: `pad`
+    /// directives (which need the balances worked out) and plugins which
+    /// add postings aren't applied.
+    pub fn from_journal(path: &Path, accounts: &[&str]) -> anyhow::Result<Holdings> {
+        let mut holdings = Holdings::default();
+        let mut pads = 0;
+        read_journal(path, accounts, &mut holdings.changes, &mut pads)?;
+        if pads > 0 {
+            warn(
+                Kind::Holdings,
+                format!(
+                    "the journal has {} pad directive(s), which aren't applied to the holdings",
+                    pads
+                ),
+            );
+        }
+        Ok(holdings)
+    }
+
+    /// Every commodity which is held at some point.
+    pub fn commodities(&self) -> BTreeSet<String> {
+        self.changes
+            .iter()
+            .map(|change| change.commodity.clone())
+            .collect()
+    }
+
+    /// The quantity of each commodity held at the end of `date`, leaving
+    /// out those which are none.
+    pub fn on(&self, date: &NaiveDate) -> BTreeMap<String, Decimal> {
+        let mut quantities: BTreeMap<String, Decimal> = BTreeMap::new();
+        for change in &self.changes {
+            if change.date.is_none_or(|change_date| change_date <= *date) {
+                *quantities.entry(change.commodity.clone()).or_default() += change.quantity;
+            }
+        }
+        quantities.retain(|_, quantity| !quantity.is_zero());
+        quantities
+    }
+}
+
+/// A posting of a transaction being read.
+struct Posting {
+    account: String,
+    /// The units, if they weren't elided.
+    units: Option<(Decimal, String)>,
+    /// What the units are worth for balancing the transaction (the cost,
+    /// or the price, or the units themselves).
+    weight: Option<(Decimal, String)>,
+}
+
+/// A transaction being read, its postings are only known at its end.
+struct Transaction {
+    date: NaiveDate,
+    postings: Vec<Posting>,
+    /// Where it starts, for errors.
+    line: usize,
+}
+
+fn read_journal(
+    path: &Path,
+    accounts: &[&str],
+    changes: &mut Vec<Change>,
+    pads: &mut usize,
+) -> anyhow::Result<()> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| anyhow!("Unable to read {:?}: {}", path, err))?;
+    let mut transaction: Option<Transaction> = None;
+
+    for (i, line) in contents.lines().enumerate() {
+        let line_number = i + 1;
+        let error = |message: String| anyhow!("{}:{}: {}", path.display(), line_number, message);
+        let code = strip_comment(line);
+        if code.trim().is_empty() {
+            continue;
+        }
+
+        if code.starts_with(char::is_whitespace) {
+            if let Some(transaction) = &mut transaction {
+                if let Some(posting) = parse_posting(code).map_err(error)? {
+                    transaction.postings.push(posting);
+                }
+            }
+            continue;
+        }
+
+        // Any other line ends the transaction.
+        if let Some(transaction) = transaction.take() {
+            finish(path, transaction, accounts, changes)?;
+        }
+
+        let mut words = code.split_whitespace();
+        let first = words.next().unwrap_or_default();
+        if first == "include" {
+            let included = unquote(code.trim_start_matches("include").trim())
+                .ok_or_else(|| error(String::from("expected a quoted path to include")))?;
+            let included = path
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .join(included);
+            read_journal(&included, accounts, changes, pads)?;
+            continue;
+        }
+        let date = match NaiveDate::parse_from_str(first, "%Y-%m-%d") {
+            Ok(date) => date,
+            // An option, plugin, pushtag and the like.
+            Err(_) => continue,
+        };
+        match words.next() {
+            Some("*") | Some("!") | Some("txn") => {}
+            Some(flag) if flag.len() == 1 && !flag.starts_with(char::is_alphanumeric) => {}
+            Some("pad") => {
+                *pads += 1;
+                continue;
+            }
+            _ => continue,
+        }
+        transaction = Some(Transaction {
+            date,
+            postings: Vec::new(),
+            line: line_number,
+        });
+    }
+
+    if let Some(transaction) = transaction.take() {
+        finish(path, transaction, accounts, changes)?;
+    }
+    Ok(())
+}
+
+/// Add the changes from the postings in `transaction` to `accounts`,
+/// working out the amount of a posting which was elided from the others.
+fn finish(
+    path: &Path,
+    transaction: Transaction,
+    accounts: &[&str],
+    changes: &mut Vec<Change>,
+) -> anyhow::Result<()> {
+    let mut residual: BTreeMap<String, Decimal> = BTreeMap::new();
+    let mut elided = None;
+    for posting in &transaction.postings {
+        match &posting.weight {
+            Some((amount, commodity)) => {
+                *residual.entry(commodity.clone()).or_default() += *amount;
+            }
+            None if elided.is_some() => {
+                return Err(anyhow!(
+                    "{}:{}: only one posting of a transaction can have its amount left out",
+                    path.display(),
+                    transaction.line
+                ))
+            }
+            None => elided = Some(&posting.account),
+        }
+    }
+
+    let counted = |account: &str| {
+        accounts.iter().any(|root| {
+            account == *root
+                || account
+                    .strip_prefix(root)
+                    .is_some_and(|rest| rest.starts_with(':'))
+        })
+    };
+    for posting in &transaction.postings {
+        if let (Some((quantity, commodity)), true) = (&posting.units, counted(&posting.account)) {
+            changes.push(Change {
+                date: Some(transaction.date),
+                commodity: commodity.clone(),
+                quantity: *quantity,
+            });
+        }
+    }
+    if elided.is_some_and(|account| counted(account)) {
+        for (commodity, amount) in residual {
+            changes.push(Change {
+                date: Some(transaction.date),
+                commodity,
+                quantity: -amount,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Parse an indented line of a transaction, `None` if it is metadata rather
+/// than a posting.
+fn parse_posting(line: &str) -> Result<Option<Posting>, String> {
+    let mut rest = line.trim();
+    // A flag on the posting.
+    if let Some(stripped) = rest.strip_prefix(['*', '!']) {
+        rest = stripped.trim_start();
+    }
+    let account = rest.split_whitespace().next().unwrap_or_default();
+    if !account.starts_with(|c: char| c.is_ascii_uppercase()) || !account.contains(':') {
+        return Ok(None);
+    }
+    let rest = rest[account.len()..].trim();
+    if rest.is_empty() {
+        return Ok(Some(Posting {
+            account: account.to_string(),
+            units: None,
+            weight: None,
+        }));
+    }
+
+    // The units come before any cost ({...}) or price (@ or @@).
+    let end = rest.find(['{', '@']).unwrap_or(rest.len());
+    let (quantity, commodity) = parse_amount(&rest[..end])?;
+    let mut weight = (quantity, commodity.clone());
+
+    let annotations = &rest[end..];
+    if let Some(cost) = annotations.strip_prefix("{{") {
+        let total = parse_amount(cost_amount(cost, "}}"))?;
+        weight = (with_sign(total.0, quantity), total.1);
+    } else if let Some(cost) = annotations.strip_prefix('{') {
+        let per_unit = cost_amount(cost, "}");
+        // An empty cost is booked against the existing lots, which needs
+        // the inventory, so it is left as the units.
+        if !per_unit.trim().is_empty() {
+            let per_unit = parse_amount(per_unit)?;
+            weight = (quantity * per_unit.0, per_unit.1);
+        }
+    } else if let Some(price) = annotations.find("@@").map(|i| &annotations[i + 2..]) {
+        let total = parse_amount(price)?;
+        weight = (with_sign(total.0, quantity), total.1);
+    } else if let Some(price) = annotations.find('@').map(|i| &annotations[i + 1..]) {
+        let per_unit = parse_amount(price)?;
+        weight = (quantity * per_unit.0, per_unit.1);
+    }
+
+    Ok(Some(Posting {
+        account: account.to_string(),
+        units: Some((quantity, commodity)),
+        weight: Some(weight),
+    }))
+}
+
+/// The amount in a cost up to the `close`ing bracket, e.g. `150 USD` in
+/// `150 USD, 2020-01-01}`, leaving out any date or label.
+fn cost_amount<'a>(cost: &'a str, close: &str) -> &'a str {
+    let cost = cost.split(close).next().unwrap_or_default();
+    cost.split(',').next().unwrap_or_default()
+}
+
+/// The total cost or price `total` of units `quantity`, which are
+/// negative when they are sold.
+fn with_sign(total: Decimal, quantity: Decimal) -> Decimal {
+    if quantity.is_sign_negative() {
+        -total
+    } else {
+        total
+    }
+}
+
+/// Parse an amount such as `-1,000.50 NZD`.
+fn parse_amount(amount: &str) -> Result<(Decimal, String), String> {
+    let mut words = amount.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some(number), Some(commodity), None) => {
+            let number = parse_number(number)
+                .ok_or_else(|| format!("unable to parse the number {:?}", number))?;
+            Ok((number, commodity.to_string()))
+        }
+        _ => Err(format!(
+            "unable to parse the amount {:?}, expected a number and a commodity",
+            amount.trim()
+        )),
+    }
+}
+
+/// Parse a number which may have thousands separators, e.g. `1,000.50`.
+fn parse_number(number: &str) -> Option<Decimal> {
+    Decimal::from_str(&number.replace(',', "")).ok()
+}
+
+/// `line` without its comment (from a `;` outside of a string).
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// The contents of the string `value`, e.g. `"prices.beancount"`.
+fn unquote(value: &str) -> Option<&str> {
+    value.strip_prefix('"')?.strip_suffix('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Write the `files` (each a path and its contents) into a directory
+    /// of their own for the `test`, returning the path of the first.
+    fn journal(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "beancount-price-fetcher-holdings-{}-{}",
+            test,
+            std::process::id()
+        ));
+        for (path, contents) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        dir.join(files[0].0)
+    }
+
+    fn on(holdings: &Holdings, day: u32) -> Vec<(String, Decimal)> {
+        holdings
+            .on(&NaiveDate::from_ymd_opt(2020, 1, day).unwrap())
+            .into_iter()
+            .collect()
+    }
+
+    fn held(quantities: &[(&str, &str)]) -> Vec<(String, Decimal)> {
+        quantities
+            .iter()
+            .map(|(commodity, quantity)| {
+                (commodity.to_string(), Decimal::from_str(quantity).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn elided_amounts() {
+        let path = journal(
+            "elided",
+            &[(
+                "main.beancount",
+                r#"
+2020-01-01 * "Opening balance"
+  Assets:Bank  1,000.00 NZD ; in the bank
+  Equity:Opening-Balances
+
+2020-01-05 * "Groceries"
+  Assets:Bank
+  Expenses:Food  25.50 NZD
+"#,
+            )],
+        );
+        let holdings = Holdings::from_journal(&path, DEFAULT_ACCOUNTS).unwrap();
+        assert_eq!(on(&holdings, 1), held(&[("NZD", "1000.00")]));
+        assert_eq!(on(&holdings, 5), held(&[("NZD", "974.50")]));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn costs_and_prices() {
+        let path = journal(
+            "costs",
+            &[(
+                "main.beancount",
+                r#"
+2020-01-02 * "Buy at a cost per unit"
+  Assets:Brokerage  10 VAS {80.00 AUD}
+  Assets:Cash
+
+2020-01-03 * "Buy at a total cost, with a date"
+  Assets:Brokerage  2 VAS {{170 AUD, 2020-01-03}}
+  Assets:Cash
+
+2020-01-04 * "Sell from the existing lots"
+  Assets:Brokerage  -1 VAS {}
+  Assets:Cash  90 AUD
+  Income:Gains
+
+2020-01-05 * "Exchange at a price per unit"
+  Assets:Cash  100 USD @ 1.5 AUD
+  Assets:Cash
+
+2020-01-06 * "Exchange at a total price"
+  Assets:Cash  -50 USD @@ 70 AUD
+  Assets:Cash
+"#,
+            )],
+        );
+        let holdings = Holdings::from_journal(&path, DEFAULT_ACCOUNTS).unwrap();
+        assert_eq!(on(&holdings, 2), held(&[("AUD", "-800.00"), ("VAS", "10")]));
+        assert_eq!(on(&holdings, 3), held(&[("AUD", "-970.00"), ("VAS", "12")]));
+        // The gain's posting is elided, which isn't one of the accounts.
+        assert_eq!(on(&holdings, 4), held(&[("AUD", "-880.00"), ("VAS", "11")]));
+        assert_eq!(
+            on(&holdings, 5),
+            held(&[("AUD", "-1030.0"), ("USD", "100"), ("VAS", "11")])
+        );
+        assert_eq!(
+            on(&holdings, 6),
+            held(&[("AUD", "-960.0"), ("USD", "50"), ("VAS", "11")])
+        );
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn includes() {
+        let path = journal(
+            "includes",
+            &[
+                (
+                    "main.beancount",
+                    r#"
+option "title" "Test"
+include "years/2020.beancount"
+
+2020-01-01 * "Opening balance"
+  Assets:Bank  500 AUD
+  Equity:Opening-Balances
+"#,
+                ),
+                (
+                    "years/2020.beancount",
+                    r#"
+2020-01-02 * "Transfer"
+  Assets:Bank  -200 AUD
+  Assets:Brokerage:Cash
+"#,
+                ),
+            ],
+        );
+        let holdings = Holdings::from_journal(&path, &["Assets:Bank"]).unwrap();
+        assert_eq!(on(&holdings, 1), held(&[("AUD", "500")]));
+        assert_eq!(on(&holdings, 2), held(&[("AUD", "300")]));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
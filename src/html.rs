@@ -0,0 +1,839 @@
+use crate::{
+    config::ProviderConfig,
+    json,
+    network::send_text,
+    pricesource::{self, PriceSource},
+    series::Closes,
+};
+use anyhow::anyhow;
+use chrono::{NaiveDate, NaiveDateTime};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::{header::USER_AGENT, Client};
+use rust_decimal::Decimal;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "html";
+
+/// The format of the dates when the provider doesn't set `date_format`.
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Elements which never have children or an end tag.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Elements whose contents are text up to their end tag, rather than
+/// markup.
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style", "textarea", "title"];
+
+/// A node of a [Document].
+#[derive(Debug)]
+enum Node {
+    /// The element at this index of the document's elements.
+    Element(usize),
+    Text(String),
+}
+
+#[derive(Debug)]
+struct Element {
+    /// The tag name, in lower case. The document's root has none.
+    name: String,
+    /// Each attribute's name (in lower case) and value.
+    attributes: Vec<(String, String)>,
+    parent: Option<usize>,
+    children: Vec<Node>,
+}
+
+/// The elements of an HTML page, in the order their start tags appear,
+/// the first being the root the page's elements are in.
+///
+/// This isn't a full HTML parser: it copes with the end tags pages
+/// commonly leave out (of `p`, `li`, table cells and rows, and `option`)
+/// and with stray end tags, which is enough to find the prices on them.
+#[derive(Debug)]
+pub struct Document {
+    elements: Vec<Element>,
+}
+
+/// The element names (`closes`) a start tag of an element ends if one is
+/// open, unless one of the `within` elements is open inside it.
+fn implied_end(name: &str) -> (&'static [&'static str], &'static [&'static str]) {
+    match name {
+        "li" => (&["li"], &["ul", "ol"]),
+        "td" | "th" => (&["td", "th"], &["tr", "table"]),
+        "tr" => (&["tr", "td", "th"], &["table", "thead", "tbody", "tfoot"]),
+        "thead" | "tbody" | "tfoot" => (&["thead", "tbody", "tfoot", "tr", "td", "th"], &["table"]),
+        "option" => (&["option"], &["select", "datalist"]),
+        "p" | "div" | "ul" | "ol" | "table" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6"
+        | "section" | "article" | "header" | "footer" | "form" | "pre" | "blockquote" => (
+            &["p"],
+            &["div", "td", "th", "li", "section", "article", "button"],
+        ),
+        _ => (&[], &[]),
+    }
+}
+
+/// `text` with its character references (e.g. `&amp;` or `&#36;`)
+/// replaced, leaving those it doesn't know as they are.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let reference = rest
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| &rest[1..end]);
+        let replacement = reference.and_then(|reference| match reference {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            reference => match reference.strip_prefix('#') {
+                Some(code) => match code.strip_prefix('x').or_else(|| code.strip_prefix('X')) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => code.parse().ok(),
+                }
+                .and_then(char::from_u32),
+                None => None,
+            },
+        });
+        match (reference, replacement) {
+            (Some(reference), Some(replacement)) => {
+                decoded.push(replacement);
+                rest = &rest[reference.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+impl Document {
+    /// Parse the page `html`.
+    pub fn parse(html: &str) -> Document {
+        let mut document = Document {
+            elements: vec![Element {
+                name: String::new(),
+                attributes: Vec::new(),
+                parent: None,
+                children: Vec::new(),
+            }],
+        };
+        // The elements which are open, innermost last.
+        let mut open: Vec<usize> = vec![0];
+        let mut rest = html;
+        while !rest.is_empty() {
+            let current = *open.last().expect("expected the root to be open");
+            if let Some(after) = rest.strip_prefix("<!--") {
+                rest = after.find("-->").map(|end| &after[end + 3..]).unwrap_or("");
+            } else if rest.starts_with("<!") || rest.starts_with("<?") {
+                rest = rest.find('>').map(|end| &rest[end + 1..]).unwrap_or("");
+            } else if let Some(after) = rest
+                .strip_prefix("</")
+                .filter(|after| after.starts_with(|c: char| c.is_ascii_alphabetic()))
+            {
+                let end = after.find('>').unwrap_or(after.len());
+                let name = after[..end]
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .to_ascii_lowercase();
+                // A stray end tag is ignored.
+                if let Some(i) = open
+                    .iter()
+                    .rposition(|element| document.elements[*element].name == name)
+                {
+                    open.truncate(i);
+                }
+                rest = after.get(end + 1..).unwrap_or("");
+            } else if let Some(after) = rest
+                .strip_prefix('<')
+                .filter(|after| after.starts_with(|c: char| c.is_ascii_alphabetic()))
+            {
+                let (name, attributes, self_closing, after) = start_tag(after);
+                // The outermost of the elements it closes, short of any it
+                // is within, e.g. a `<tr>` closes the open `td` and `tr`.
+                let (closes, within) = implied_end(&name);
+                let mut ended = None;
+                for (i, element) in open.iter().enumerate().rev() {
+                    let open_name = document.elements[*element].name.as_str();
+                    if closes.contains(&open_name) {
+                        ended = Some(i);
+                    } else if within.contains(&open_name) {
+                        break;
+                    }
+                }
+                if let Some(i) = ended {
+                    open.truncate(i);
+                }
+                let parent = *open.last().expect("expected the root to be open");
+                let index = document.elements.len();
+                document.elements[parent]
+                    .children
+                    .push(Node::Element(index));
+                document.elements.push(Element {
+                    name: name.clone(),
+                    attributes,
+                    parent: Some(parent),
+                    children: Vec::new(),
+                });
+                rest = after;
+                if RAW_TEXT_ELEMENTS.contains(&name.as_str()) && !self_closing {
+                    let end_tag = format!("</{}", name);
+                    let end = rest
+                        .to_ascii_lowercase()
+                        .find(&end_tag)
+                        .unwrap_or(rest.len());
+                    // Scripts and styles aren't part of the page's text.
+                    if name == "textarea" || name == "title" {
+                        document.elements[index]
+                            .children
+                            .push(Node::Text(decode_entities(&rest[..end])));
+                    }
+                    rest = &rest[end..];
+                    rest = rest.find('>').map(|end| &rest[end + 1..]).unwrap_or("");
+                } else if !self_closing && !VOID_ELEMENTS.contains(&name.as_str()) {
+                    open.push(index);
+                }
+            } else {
+                // Up to the next tag, a `<` which doesn't start one is text.
+                let end = rest
+                    .char_indices()
+                    .skip(1)
+                    .find(|(_, c)| *c == '<')
+                    .map(|(end, _)| end)
+                    .unwrap_or(rest.len());
+                document.elements[current]
+                    .children
+                    .push(Node::Text(decode_entities(&rest[..end])));
+                rest = &rest[end..];
+            }
+        }
+        document
+    }
+
+    /// The text in the element `index`, with runs of whitespace collapsed
+    /// to a space.
+    fn text(&self, index: usize) -> String {
+        fn collect<'a>(document: &'a Document, index: usize, text: &mut Vec<&'a str>) {
+            for child in &document.elements[index].children {
+                match child {
+                    Node::Element(child) => collect(document, *child, text),
+                    Node::Text(child) => text.push(child),
+                }
+            }
+        }
+        let mut text = Vec::new();
+        collect(self, index, &mut text);
+        text.concat()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The text of each element `selector` selects, in the order they
+    /// appear in the page.
+    pub fn select(&self, selector: &Selector) -> Vec<String> {
+        (1..self.elements.len())
+            .filter(|index| selector.matches(self, *index, selector.compounds.len() - 1))
+            .map(|index| self.text(index))
+            .collect()
+    }
+
+    /// The position (starting at 1) of the element `index` among its
+    /// parent's elements, and how many there are.
+    fn position(&self, index: usize) -> (usize, usize) {
+        let siblings: Vec<usize> = self.elements[index]
+            .parent
+            .map(|parent| {
+                self.elements[parent]
+                    .children
+                    .iter()
+                    .filter_map(|child| match child {
+                        Node::Element(child) => Some(*child),
+                        Node::Text(_) => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let position = siblings
+            .iter()
+            .position(|sibling| *sibling == index)
+            .unwrap_or_default();
+        (position + 1, siblings.len())
+    }
+}
+
+/// Parse the rest of a start tag, `after` its `<`: its name, attributes,
+/// whether it ends with `/>`, and what comes after it.
+fn start_tag(after: &str) -> (String, Vec<(String, String)>, bool, &str) {
+    let is_name_end = |c: char| c.is_whitespace() || c == '>' || c == '/';
+    let end = after.find(is_name_end).unwrap_or(after.len());
+    let name = after[..end].to_ascii_lowercase();
+    let mut rest = &after[end..];
+    let mut attributes = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix("/>") {
+            return (name, attributes, true, after);
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            return (name, attributes, false, after);
+        }
+        if rest.is_empty() {
+            return (name, attributes, false, rest);
+        }
+        if let Some(after) = rest.strip_prefix('/') {
+            rest = after;
+            continue;
+        }
+        let end = rest
+            .find(|c: char| is_name_end(c) || c == '=')
+            .unwrap_or(rest.len())
+            .max(1);
+        let attribute = rest[..end].to_ascii_lowercase();
+        rest = rest[end..].trim_start();
+        let value = match rest.strip_prefix('=') {
+            Some(after) => {
+                let after = after.trim_start();
+                let (value, after) = match after.chars().next() {
+                    Some(quote) if quote == '"' || quote == '\'' => {
+                        let end = after[1..].find(quote).map(|end| end + 1);
+                        match end {
+                            Some(end) => (&after[1..end], &after[end + 1..]),
+                            None => (&after[1..], ""),
+                        }
+                    }
+                    _ => {
+                        let end = after
+                            .find(|c: char| c.is_whitespace() || c == '>')
+                            .unwrap_or(after.len());
+                        (&after[..end], &after[end..])
+                    }
+                };
+                rest = after;
+                decode_entities(value)
+            }
+            None => String::new(),
+        };
+        attributes.push((attribute, value));
+    }
+}
+
+/// How a [Compound] relates to the one before it in a [Selector].
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combinator {
+    /// Inside it, at any depth (` `).
+    Descendant,
+    /// One of its children (`>`).
+    Child,
+}
+
+/// A condition on an element, other than its name.
+#[derive(Debug, Clone)]
+enum Condition {
+    /// `#id`.
+    Id(String),
+    /// `.class`.
+    Class(String),
+    /// `[name]`, or with a value `[name=value]`.
+    Attribute(String, Option<String>),
+    /// `:nth-child(n)`, or `:first-child` for the first.
+    NthChild(usize),
+    /// `:last-child`.
+    LastChild,
+}
+
+/// The conditions on an element in a [Selector], e.g. `td.price`.
+#[derive(Debug, Clone)]
+struct Compound {
+    /// The tag name, or `None` for any (`*`).
+    name: Option<String>,
+    conditions: Vec<Condition>,
+}
+
+/// A CSS selector, of the subset with tag names, `*`, `#id`, `.class`,
+/// `[name]` and `[name=value]` attributes, `:nth-child(n)`,
+/// `:first-child` and `:last-child`, combined with descendant (` `) and
+/// child (`>`) combinators, e.g. `table#prices tr > td:nth-child(2)`.
+#[derive(Debug, Clone)]
+pub struct Selector {
+    /// Each compound, and how it relates to the one before it.
+    compounds: Vec<(Combinator, Compound)>,
+}
+
+impl FromStr for Selector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |message: &str| anyhow!("Unable to parse the selector {:?}: {}", s, message);
+        let is_identifier = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+        let identifier = |rest: &str| -> anyhow::Result<usize> {
+            match rest.find(|c: char| !is_identifier(c)).unwrap_or(rest.len()) {
+                0 => Err(invalid("expected a name")),
+                end => Ok(end),
+            }
+        };
+
+        let mut compounds = Vec::new();
+        let mut rest = s.trim();
+        let mut combinator = Combinator::Descendant;
+        while !rest.is_empty() {
+            let mut compound = Compound {
+                name: None,
+                conditions: Vec::new(),
+            };
+            let any = rest.starts_with('*');
+            if any {
+                rest = &rest[1..];
+            } else if rest.starts_with(is_identifier) {
+                let end = identifier(rest)?;
+                compound.name = Some(rest[..end].to_ascii_lowercase());
+                rest = &rest[end..];
+            }
+            loop {
+                if let Some(after) = rest.strip_prefix('#') {
+                    let end = identifier(after)?;
+                    compound
+                        .conditions
+                        .push(Condition::Id(after[..end].to_string()));
+                    rest = &after[end..];
+                } else if let Some(after) = rest.strip_prefix('.') {
+                    let end = identifier(after)?;
+                    compound
+                        .conditions
+                        .push(Condition::Class(after[..end].to_string()));
+                    rest = &after[end..];
+                } else if let Some(after) = rest.strip_prefix('[') {
+                    let end = after
+                        .find(']')
+                        .ok_or_else(|| invalid("expected a ] after ["))?;
+                    let inside = &after[..end];
+                    let condition = match inside.split_once('=') {
+                        Some((name, value)) => {
+                            let value = value.trim();
+                            let value = value
+                                .strip_prefix('"')
+                                .and_then(|value| value.strip_suffix('"'))
+                                .or_else(|| {
+                                    value
+                                        .strip_prefix('\'')
+                                        .and_then(|value| value.strip_suffix('\''))
+                                })
+                                .unwrap_or(value);
+                            Condition::Attribute(
+                                name.trim().to_ascii_lowercase(),
+                                Some(value.to_string()),
+                            )
+                        }
+                        None => Condition::Attribute(inside.trim().to_ascii_lowercase(), None),
+                    };
+                    compound.conditions.push(condition);
+                    rest = &after[end + 1..];
+                } else if let Some(after) = rest.strip_prefix(':') {
+                    let end = identifier(after)?;
+                    let condition = match &after[..end] {
+                        "first-child" => Condition::NthChild(1),
+                        "last-child" => Condition::LastChild,
+                        "nth-child" => {
+                            let argument = after[end..]
+                                .strip_prefix('(')
+                                .and_then(|argument| argument.split_once(')'))
+                                .ok_or_else(|| invalid("expected :nth-child(n)"))?;
+                            let n = usize::from_str(argument.0.trim())
+                                .ok()
+                                .filter(|n| *n > 0)
+                                .ok_or_else(|| invalid("expected a number from 1 in :nth-child"))?;
+                            rest = argument.1;
+                            compound.conditions.push(Condition::NthChild(n));
+                            continue;
+                        }
+                        _ => {
+                            return Err(invalid(
+                                "expected :nth-child(n), :first-child or :last-child",
+                            ))
+                        }
+                    };
+                    compound.conditions.push(condition);
+                    rest = &after[end..];
+                } else {
+                    break;
+                }
+            }
+            if !any && compound.name.is_none() && compound.conditions.is_empty() {
+                return Err(invalid("expected a name, *, #, ., [ or :"));
+            }
+            compounds.push((combinator, compound));
+
+            let trimmed = rest.trim_start();
+            combinator = match trimmed.strip_prefix('>') {
+                Some(after) => {
+                    rest = after.trim_start();
+                    Combinator::Child
+                }
+                None if trimmed.len() < rest.len() => {
+                    rest = trimmed;
+                    Combinator::Descendant
+                }
+                None if rest.is_empty() => break,
+                None => return Err(invalid("expected a space or > between elements")),
+            };
+            if rest.is_empty() {
+                return Err(invalid("expected an element after >"));
+            }
+        }
+        if compounds.is_empty() {
+            return Err(invalid("it is empty"));
+        }
+        Ok(Selector { compounds })
+    }
+}
+
+impl Selector {
+    /// Whether the element `index` of `document` matches the compounds of
+    /// the selector up to and including the compound `last`.
+    fn matches(&self, document: &Document, index: usize, last: usize) -> bool {
+        let (combinator, compound) = &self.compounds[last];
+        if index == 0 || !compound.matches(document, index) {
+            return false;
+        }
+        if last == 0 {
+            return true;
+        }
+        let mut ancestor = document.elements[index].parent;
+        while let Some(parent) = ancestor {
+            if self.matches(document, parent, last - 1) {
+                return true;
+            }
+            if *combinator == Combinator::Child {
+                return false;
+            }
+            ancestor = document.elements[parent].parent;
+        }
+        false
+    }
+}
+
+impl Compound {
+    fn matches(&self, document: &Document, index: usize) -> bool {
+        let element = &document.elements[index];
+        let attribute = |name: &str| {
+            element
+                .attributes
+                .iter()
+                .find(|(attribute, _)| attribute == name)
+                .map(|(_, value)| value.as_str())
+        };
+        self.name.as_ref().is_none_or(|name| *name == element.name)
+            && self.conditions.iter().all(|condition| match condition {
+                Condition::Id(id) => attribute("id") == Some(id.as_str()),
+                Condition::Class(class) => attribute("class")
+                    .is_some_and(|classes| classes.split_whitespace().any(|other| other == class)),
+                Condition::Attribute(name, None) => attribute(name).is_some(),
+                Condition::Attribute(name, Some(value)) => attribute(name) == Some(value.as_str()),
+                Condition::NthChild(n) => document.position(index).0 == *n,
+                Condition::LastChild => {
+                    let (position, siblings) = document.position(index);
+                    position == siblings
+                }
+            })
+    }
+}
+
+/// How the `html` source scrapes prices, from its provider's config.
+#[derive(Debug)]
+pub struct Page {
+    /// The url to request for each commodity, see [json::url()].
+    url: String,
+    /// The selectors, with `{symbol}` still to be replaced.
+    date_selector: String,
+    price_selector: String,
+    date_format: String,
+}
+
+impl Page {
+    /// The page in the `provider_config` of the `html` source, which needs
+    /// a `url`, `date_selector` and `price_selector`.
+    pub fn from_config(provider_config: &ProviderConfig) -> anyhow::Result<Page> {
+        let setting = |value: &Option<String>, key: &str| {
+            value.clone().ok_or_else(|| {
+                anyhow!(
+                    "the {} source needs a {} in providers.{} in the config",
+                    PROVIDER_NAME,
+                    key,
+                    PROVIDER_NAME
+                )
+            })
+        };
+        let page = Page {
+            url: setting(&provider_config.url, "url")?,
+            date_selector: setting(&provider_config.date_selector, "date_selector")?,
+            price_selector: setting(&provider_config.price_selector, "price_selector")?,
+            date_format: provider_config
+                .date_format
+                .clone()
+                .unwrap_or_else(|| DEFAULT_DATE_FORMAT.to_string()),
+        };
+        check_selector(&page.date_selector)?;
+        check_selector(&page.price_selector)?;
+        Ok(page)
+    }
+
+    /// A date in the `date_format`, which may have a time too.
+    fn date(&self, date: &str) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(date, &self.date_format)
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(date, &self.date_format).map(|time| time.date())
+            })
+            .ok()
+    }
+}
+
+/// The selector `template` with `{symbol}` replaced by `symbol`.
+fn selector(template: &str, symbol: &str) -> anyhow::Result<Selector> {
+    Selector::from_str(&template.replace("{symbol}", symbol))
+}
+
+/// Check that the selector `template` parses, whichever symbol is put in
+/// it.
+pub fn check_selector(template: &str) -> anyhow::Result<()> {
+    selector(template, "SYMBOL").map(|_| ())
+}
+
+/// A price in a page, which may have a currency sign, thousands
+/// separators or other text, e.g. `$1,234.5678`, or be left empty (or
+/// e.g. `n/a`) on a day without one.
+fn price(price: &str) -> Option<Option<Decimal>> {
+    let number: String = price
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '.' || *c == '-')
+        .collect();
+    if !number.contains(|c: char| c.is_ascii_digit()) {
+        return Some(None);
+    }
+    Decimal::from_str(&number).ok().map(Some)
+}
+
+/// Scrape the prices of `commodity` (the `symbol` in the `page`'s url and
+/// selectors) from `start` to `end`, pairing each date the page's date
+/// selector selects with the price its price selector selects. The
+/// prices are taken to be in `base`.
+pub async fn get_closes(
+    client: &Client,
+    page: &Page,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Closes> {
+    let url = json::url(&page.url, symbol, base, start, end, None);
+    // Some sites turn away requests without a user agent.
+    let html = send_text(client.get(&url).header(
+        USER_AGENT,
+        concat!("beancount-price-fetcher/", env!("CARGO_PKG_VERSION")),
+    ))
+    .await?;
+    let document = Document::parse(&html);
+    let dates = document.select(&selector(&page.date_selector, symbol)?);
+    let prices = document.select(&selector(&page.price_selector, symbol)?);
+    if dates.len() != prices.len() {
+        return Err(anyhow!(
+            "the {} source's date_selector selected {} dates for {} but its price_selector {} \
+            prices",
+            PROVIDER_NAME,
+            dates.len(),
+            symbol,
+            prices.len()
+        ));
+    }
+
+    let mut closes = BTreeMap::new();
+    for (date, price_text) in dates.iter().zip(&prices) {
+        let date = page.date(date).ok_or_else(|| {
+            anyhow!(
+                "Unable to parse the date {:?} for {} with the date_format {:?}",
+                date,
+                symbol,
+                page.date_format
+            )
+        })?;
+        let price_value = match price(price_text) {
+            Some(Some(price)) => price,
+            Some(None) => continue,
+            None => {
+                return Err(anyhow!(
+                    "Unable to parse the price {:?} for {}",
+                    price_text,
+                    symbol
+                ))
+            }
+        };
+        if (start..=end).contains(&&date) {
+            closes.insert(date, price_value);
+        }
+    }
+
+    Ok(Closes {
+        commodity,
+        currency: base,
+        closes,
+    })
+}
+
+/// The web pages set up by `page` as a [PriceSource], with the symbols in
+/// its `config` and the prices in `base`.
+pub struct Html<'a> {
+    client: &'a Client,
+    page: Page,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> Html<'a> {
+    pub fn new(
+        client: &'a Client,
+        page: Page,
+        config: &'a ProviderConfig,
+        base: CommodityTypeID,
+    ) -> Html<'a> {
+        Html {
+            client,
+            page,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for Html<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            self.client,
+            &self.page,
+            commodity,
+            &self.config.symbol(&commodity),
+            self.base,
+            start,
+            end,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_entities, Document, Selector};
+    use std::str::FromStr;
+
+    fn select(html: &str, selector: &str) -> Vec<String> {
+        Document::parse(html).select(&Selector::from_str(selector).unwrap())
+    }
+
+    #[test]
+    fn implied_end_tags() {
+        let html = "<table><tr><td>a<td>b<tr><td>c<td>d</table>\
+            <ul><li>x<li>y</ul><p>one<p>two<div>three</div></span>";
+        assert_eq!(select(html, "td"), vec!["a", "b", "c", "d"]);
+        assert_eq!(select(html, "tr"), vec!["ab", "cd"]);
+        assert_eq!(select(html, "table > tr > td:nth-child(2)"), vec!["b", "d"]);
+        assert_eq!(select(html, "li"), vec!["x", "y"]);
+        assert_eq!(select(html, "p"), vec!["one", "two"]);
+        assert_eq!(select(html, "div"), vec!["three"]);
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(
+            decode_entities("&amp;&lt;&gt;&quot;&apos;&#36;&#x24;&bogus; & alone"),
+            "&<>\"'$$&bogus; & alone"
+        );
+        // A no-break space is collapsed along with the rest of the
+        // whitespace.
+        assert_eq!(select("<td>\n  1&nbsp;234\n  </td>", "td"), vec!["1 234"]);
+    }
+
+    #[test]
+    fn raw_text_elements() {
+        let html = "<title>A &amp; B</title><script>var s = \"<td>1</td>\";</script>\
+            <style>td { color: red }</style><textarea><b>&amp;</b></textarea><td>2</td>";
+        assert_eq!(select(html, "td"), vec!["2"]);
+        assert_eq!(select(html, "title"), vec!["A & B"]);
+        assert_eq!(select(html, "script"), vec![""]);
+        assert_eq!(select(html, "textarea"), vec!["<b>&</b>"]);
+    }
+
+    #[test]
+    fn attribute_quoting() {
+        let html = "<td data-a=\"x y\" data-b='q\"r' data-c=plain data-d data-e=\"a&amp;b\" \
+            class=\"price  latest\">v</td><td>w</td>";
+        for selector in [
+            "[data-a=\"x y\"]",
+            "[data-b='q\"r']",
+            "[data-c=plain]",
+            "[data-d]",
+            "[data-e=\"a&b\"]",
+            "td.price.latest",
+        ] {
+            assert_eq!(select(html, selector), vec!["v"], "{}", selector);
+        }
+        assert!(select(html, "[data-a=x]").is_empty());
+    }
+
+    #[test]
+    fn selectors() {
+        let html = "<div id=\"prices\"><table>\
+            <tr><td>d1</td><td><span>1</span></td></tr>\
+            <tr><td>d2</td><td><span>2</span></td></tr>\
+            </table></div><span>3</span>";
+        assert_eq!(select(html, "#prices span"), vec!["1", "2"]);
+        assert_eq!(select(html, "span"), vec!["1", "2", "3"]);
+        assert_eq!(select(html, "td > span"), vec!["1", "2"]);
+        assert!(select(html, "tr > span").is_empty());
+        assert_eq!(select(html, "td:nth-child(2)"), vec!["1", "2"]);
+        assert_eq!(select(html, "td:first-child"), vec!["d1", "d2"]);
+        assert_eq!(select(html, "tr:last-child > td:last-child"), vec!["2"]);
+        assert_eq!(
+            select(html, "div > * > tr > *:nth-child(1)"),
+            vec!["d1", "d2"]
+        );
+
+        for selector in [
+            "",
+            "td >",
+            "td:nth-child(0)",
+            "td:hover",
+            "td..price",
+            "a + b",
+        ] {
+            assert!(Selector::from_str(selector).is_err(), "{:?}", selector);
+        }
+    }
+}
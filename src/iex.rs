@@ -0,0 +1,201 @@
+use crate::{
+    config::ProviderConfig,
+    network::request_json,
+    pricesource::{self, PriceSource},
+    series::{Closes, Snapshot, TimeSeries},
+};
+use anyhow::anyhow;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use serde::Deserialize;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "iex";
+
+/// The environment variable the API token can be given in.
+pub const API_KEY_ENV: &str = "IEX_TOKEN";
+
+/// The currency IEX quotes US equities in.
+pub const CURRENCY: &str = "USD";
+
+const API_URL: &str = "https://cloud.iexapis.com/stable";
+
+/// The most symbols a batch request may have.
+const BATCH_SIZE: usize = 100;
+
+/// The chart ranges, and how many days back each goes (less a few, so
+/// the range always covers the start).
+const RANGES: &[(&str, i64)] = &[
+    ("5d", 4),
+    ("1m", 28),
+    ("3m", 88),
+    ("6m", 180),
+    ("1y", 362),
+    ("2y", 727),
+    ("5y", 1822),
+];
+
+/// A symbol's part of a batch response.
+#[derive(Deserialize, Debug)]
+struct Batch {
+    #[serde(default)]
+    chart: Vec<Day>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Day {
+    date: NaiveDate,
+    /// The unadjusted close.
+    close: Option<f64>,
+}
+
+/// The number of batch requests fetching `symbols` symbols takes.
+pub fn requests(symbols: usize) -> usize {
+    symbols.div_ceil(BATCH_SIZE)
+}
+
+/// The shortest chart range going back to `start`.
+fn range(start: &NaiveDate) -> &'static str {
+    let days = Utc::now()
+        .date_naive()
+        .signed_duration_since(*start)
+        .num_days();
+    RANGES
+        .iter()
+        .find(|(_, range_days)| days <= *range_days)
+        .map(|(range, _)| *range)
+        .unwrap_or("max")
+}
+
+/// Fetch the end of day closes of the equities `symbols` (each commodity
+/// and its IEX symbol) from `start` to `end`, in batches of up to 100
+/// symbols a request.
+pub async fn get_closes(
+    client: &Client,
+    token: &str,
+    symbols: &[(CommodityTypeID, String)],
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Vec<Closes>> {
+    let currency = CommodityTypeID::from_str(CURRENCY).expect("expected a valid commodity");
+    let range = range(start);
+    let mut all = Vec::new();
+    for batch in symbols.chunks(BATCH_SIZE) {
+        let list: Vec<String> = batch
+            .iter()
+            .map(|(_, symbol)| {
+                url::form_urlencoded::byte_serialize(symbol.as_bytes()).collect::<String>()
+            })
+            .collect();
+        let url = format!(
+            "{api_url}/stock/market/batch?symbols={symbols}&types=chart&range={range}\
+            &chartCloseOnly=true&token={token}",
+            api_url = API_URL,
+            symbols = list.join(","),
+            range = range,
+            token = token,
+        );
+        // Keyed by the symbol, symbols IEX doesn't have are left out.
+        let mut response: BTreeMap<String, Batch> = request_json(client, &url).await?;
+        for (commodity, symbol) in batch {
+            let chart = response
+                .remove(symbol)
+                .map(|batch| batch.chart)
+                .unwrap_or_default();
+            let closes = chart
+                .into_iter()
+                .filter(|day| (start..=end).contains(&&day.date))
+                .filter_map(|day| Some((day.date, day.close.and_then(Decimal::from_f64)?)))
+                .collect();
+            all.push(Closes {
+                commodity: *commodity,
+                currency,
+                closes,
+            });
+        }
+    }
+    Ok(all)
+}
+
+/// IEX Cloud as a [PriceSource], with the symbols in its `config` and the
+/// prices in `base`. A series is quoted as a table, with a request for
+/// every commodity.
+pub struct Iex<'a> {
+    client: &'a Client,
+    api_key: &'a str,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> Iex<'a> {
+    pub fn new(
+        client: &'a Client,
+        api_key: &'a str,
+        config: &'a ProviderConfig,
+        base: CommodityTypeID,
+    ) -> Iex<'a> {
+        Iex {
+            client,
+            api_key,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for Iex<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn series(
+        &self,
+        start: &NaiveDate,
+        end: &NaiveDate,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<Vec<Snapshot>> {
+        let closes = get_closes(
+            self.client,
+            self.api_key,
+            &self
+                .config
+                .symbols_for(&pricesource::priced(self.base, include)),
+            start,
+            end,
+        )
+        .await?;
+        Ok(pricesource::snapshots(TimeSeries::from_closes(
+            self.base, &closes,
+        )?))
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        let symbol = self.config.symbol(&commodity);
+        get_closes(
+            self.client,
+            self.api_key,
+            &[(commodity, symbol.clone())],
+            start,
+            end,
+        )
+        .await?
+        .pop()
+        .ok_or_else(|| anyhow!("{} has no prices for {}", PROVIDER_NAME, symbol))
+    }
+}
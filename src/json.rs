@@ -0,0 +1,395 @@
+use crate::{
+    config::ProviderConfig,
+    network::request_json,
+    pricesource::{self, PriceSource},
+    series::Closes,
+};
+use anyhow::anyhow;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use serde_json::Value;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "json";
+
+/// The environment variable the API key (for the `{key}` in the url) can
+/// be given in.
+pub const API_KEY_ENV: &str = "JSON_SOURCE_API_KEY";
+
+/// The format of the dates when the provider doesn't set `date_format`.
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// A step of a [Path].
+#[derive(Debug, Clone)]
+enum Step {
+    /// A member of an object, `.name` or `['name']`.
+    Member(String),
+    /// An element of an array, `[0]`.
+    Index(usize),
+    /// Every member of an object or element of an array, `.*` or `[*]`.
+    Wildcard,
+}
+
+/// A JSONPath, of the subset with the root `$` followed by members
+/// (`.name` or `['name']`), indexes (`[0]`) and wildcards (`.*` or `[*]`),
+/// e.g. `$.prices[*].close`.
+#[derive(Debug, Clone)]
+pub struct Path {
+    steps: Vec<Step>,
+}
+
+impl FromStr for Path {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |message: &str| anyhow!("Unable to parse the JSONPath {:?}: {}", s, message);
+        let mut rest = s
+            .trim()
+            .strip_prefix('$')
+            .ok_or_else(|| invalid("expected it to start with $"))?;
+        let mut steps = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                let name = &after[..end];
+                steps.push(match name {
+                    "" => return Err(invalid("expected a member name after .")),
+                    "*" => Step::Wildcard,
+                    name => Step::Member(name.to_string()),
+                });
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let end = after
+                    .find(']')
+                    .ok_or_else(|| invalid("expected a ] after ["))?;
+                let inside = after[..end].trim();
+                steps.push(if inside == "*" {
+                    Step::Wildcard
+                } else if let Some(name) = inside
+                    .strip_prefix('\'')
+                    .and_then(|inside| inside.strip_suffix('\''))
+                    .or_else(|| {
+                        inside
+                            .strip_prefix('"')
+                            .and_then(|inside| inside.strip_suffix('"'))
+                    })
+                {
+                    Step::Member(name.to_string())
+                } else {
+                    Step::Index(
+                        usize::from_str(inside)
+                            .map_err(|_| invalid("expected an index, a quoted name or *"))?,
+                    )
+                });
+                rest = &after[end + 1..];
+            } else {
+                return Err(invalid("expected a . or [ after each step"));
+            }
+        }
+        Ok(Path { steps })
+    }
+}
+
+impl Path {
+    /// The values in `value` the path selects, in order.
+    fn select<'a>(&self, value: &'a Value) -> Vec<&'a Value> {
+        let mut selected = vec![value];
+        for step in &self.steps {
+            selected = selected
+                .into_iter()
+                .flat_map(|value| -> Vec<&'a Value> {
+                    match (step, value) {
+                        (Step::Member(name), Value::Object(object)) => {
+                            object.get(name).into_iter().collect()
+                        }
+                        (Step::Index(index), Value::Array(array)) => {
+                            array.get(*index).into_iter().collect()
+                        }
+                        (Step::Wildcard, Value::Object(object)) => object.values().collect(),
+                        (Step::Wildcard, Value::Array(array)) => array.iter().collect(),
+                        _ => Vec::new(),
+                    }
+                })
+                .collect();
+        }
+        selected
+    }
+}
+
+/// How the `json` source fetches prices, from its provider's config.
+#[derive(Debug)]
+pub struct Template {
+    /// The url to request for each commodity, see [url()].
+    url: String,
+    price_path: Path,
+    date_path: Path,
+    date_format: String,
+}
+
+impl Template {
+    /// The template in the `provider_config` of the `json` source, which
+    /// needs a `url`, `price_path` and `date_path`.
+    pub fn from_config(provider_config: &ProviderConfig) -> anyhow::Result<Template> {
+        let setting = |value: &Option<String>, key: &str| {
+            value.clone().ok_or_else(|| {
+                anyhow!(
+                    "the {} source needs a {} in providers.{} in the config",
+                    PROVIDER_NAME,
+                    key,
+                    PROVIDER_NAME
+                )
+            })
+        };
+        Ok(Template {
+            url: setting(&provider_config.url, "url")?,
+            price_path: Path::from_str(&setting(&provider_config.price_path, "price_path")?)?,
+            date_path: Path::from_str(&setting(&provider_config.date_path, "date_path")?)?,
+            date_format: provider_config
+                .date_format
+                .clone()
+                .unwrap_or_else(|| DEFAULT_DATE_FORMAT.to_string()),
+        })
+    }
+
+    /// A date the date path selected: a string in the `date_format` (or
+    /// RFC 3339), or a UNIX time in seconds (or milliseconds).
+    fn date(&self, value: &Value) -> Option<NaiveDate> {
+        match value {
+            Value::String(date) => NaiveDate::parse_from_str(date, &self.date_format)
+                .or_else(|_| {
+                    NaiveDateTime::parse_from_str(date, &self.date_format).map(|time| time.date())
+                })
+                .or_else(|_| DateTime::parse_from_rfc3339(date).map(|time| time.date_naive()))
+                .ok(),
+            Value::Number(time) => {
+                let time = time.as_i64()?;
+                if time.abs() > 100_000_000_000 {
+                    NaiveDateTime::from_timestamp_millis(time)
+                } else {
+                    NaiveDateTime::from_timestamp_opt(time, 0)
+                }
+                .map(|time| time.date())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The url `template` for `symbol` in `base` from `start` to `end`, with
+/// `{symbol}`, `{base}`, `{start}`, `{end}` (as `YYYY-mm-dd`) and `{key}`
+/// replaced.
+pub fn url(
+    template: &str,
+    symbol: &str,
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+    api_key: Option<&str>,
+) -> String {
+    let encode =
+        |value: &str| url::form_urlencoded::byte_serialize(value.as_bytes()).collect::<String>();
+    template
+        .replace("{symbol}", &encode(symbol))
+        .replace("{base}", &base.to_string())
+        .replace("{start}", &start.format("%Y-%m-%d").to_string())
+        .replace("{end}", &end.format("%Y-%m-%d").to_string())
+        .replace("{key}", &encode(api_key.unwrap_or_default()))
+}
+
+/// A price the price path selected, a number or a string of one. Numbers
+/// are parsed as they are written (e.g. `0.1`), rather than through a
+/// float, which would give e.g. `0.1000000000000000055511151231`.
+fn price(value: &Value) -> Option<Decimal> {
+    let price = match value {
+        Value::Number(price) => price.to_string(),
+        Value::String(price) => price.trim().to_string(),
+        _ => return None,
+    };
+    Decimal::from_str(&price)
+        .or_else(|_| Decimal::from_scientific(&price))
+        .ok()
+}
+
+/// Fetch the prices of `commodity` (the `symbol` in the `template`'s url)
+/// in `base` from `start` to `end`, pairing each date the template's date
+/// path selects in the response with the price its price path selects.
+#[allow(clippy::too_many_arguments)]
+pub async fn get_closes(
+    client: &Client,
+    template: &Template,
+    api_key: Option<&str>,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Closes> {
+    let url = url(&template.url, symbol, base, start, end, api_key);
+    let response: Value = request_json(client, &url).await?;
+    let dates = template.date_path.select(&response);
+    let prices = template.price_path.select(&response);
+    if dates.len() != prices.len() {
+        return Err(anyhow!(
+            "the {} source's date_path selected {} dates for {} but its price_path {} prices",
+            PROVIDER_NAME,
+            dates.len(),
+            symbol,
+            prices.len()
+        ));
+    }
+
+    let mut closes = BTreeMap::new();
+    for (date, price_value) in dates.into_iter().zip(prices) {
+        let date = template
+            .date(date)
+            .ok_or_else(|| anyhow!("Unable to parse the date {} for {}", date, symbol))?;
+        let price_value = match price(price_value) {
+            Some(price) => price,
+            // Missing prices are null.
+            None if price_value.is_null() => continue,
+            None => {
+                return Err(anyhow!(
+                    "Unable to parse the price {} for {}",
+                    price_value,
+                    symbol
+                ))
+            }
+        };
+        if (start..=end).contains(&&date) {
+            closes.insert(date, price_value);
+        }
+    }
+
+    Ok(Closes {
+        commodity,
+        currency: base,
+        closes,
+    })
+}
+
+/// The JSON API set up by `template` as a [PriceSource], with the symbols
+/// in its `config` and the prices in `base`.
+pub struct Json<'a> {
+    client: &'a Client,
+    template: Template,
+    api_key: Option<&'a str>,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> Json<'a> {
+    pub fn new(
+        client: &'a Client,
+        template: Template,
+        api_key: Option<&'a str>,
+        config: &'a ProviderConfig,
+        base: CommodityTypeID,
+    ) -> Json<'a> {
+        Json {
+            client,
+            template,
+            api_key,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for Json<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            self.client,
+            &self.template,
+            self.api_key,
+            commodity,
+            &self.config.symbol(&commodity),
+            self.base,
+            start,
+            end,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{price, Path};
+    use rust_decimal::Decimal;
+    use serde_json::{json, Value};
+    use std::str::FromStr;
+
+    fn select(path: &str, value: &Value) -> Vec<Value> {
+        Path::from_str(path)
+            .unwrap()
+            .select(value)
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn paths() {
+        let response = json!({
+            "data": {
+                "prices": [
+                    {"date": "2021-03-01", "close": 1.5},
+                    {"date": "2021-03-02", "close": "1.6"},
+                ],
+                "meta": {"a": 1, "b": 2},
+            },
+            "odd key": [10, 20],
+        });
+        assert_eq!(select("$", &response), vec![response.clone()]);
+        assert_eq!(
+            select("$.data.prices[*].date", &response),
+            vec![json!("2021-03-01"), json!("2021-03-02")]
+        );
+        assert_eq!(
+            select("$['data'][\"prices\"][1].close", &response),
+            vec![json!("1.6")]
+        );
+        assert_eq!(select("$.data.meta.*", &response), vec![json!(1), json!(2)]);
+        assert_eq!(select("$['odd key'][ 0 ]", &response), vec![json!(10)]);
+        // Steps which don't apply select nothing.
+        assert!(select("$.data.prices[2]", &response).is_empty());
+        assert!(select("$.data.missing[*]", &response).is_empty());
+        assert!(select("$.data.prices.date", &response).is_empty());
+        assert!(select("$.data[0]", &response).is_empty());
+
+        for path in ["", "data", "$.", "$..a", "$[0", "$[a]", "$a", "$[-1]"] {
+            assert!(Path::from_str(path).is_err(), "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn prices() {
+        let decimal = |s: &str| Some(Decimal::from_str(s).unwrap());
+        assert_eq!(price(&json!(0.1)), decimal("0.1"));
+        assert_eq!(price(&json!(1234.5678)), decimal("1234.5678"));
+        assert_eq!(price(&json!(42)), decimal("42"));
+        assert_eq!(price(&json!(1e-7)), decimal("0.0000001"));
+        assert_eq!(price(&json!(" 1.25 ")), decimal("1.25"));
+        assert_eq!(price(&json!("n/a")), None);
+        assert_eq!(price(&Value::Null), None);
+    }
+}
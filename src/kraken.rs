@@ -0,0 +1,180 @@
+use crate::{
+    config::ProviderConfig,
+    network::request_json,
+    pricesource::{self, PriceSource},
+    series::Closes,
+};
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "kraken";
+
+const API_URL: &str = "https://api.kraken.com/0/public/OHLC";
+
+/// Daily candles, in minutes.
+const INTERVAL: u32 = 1440;
+
+/// The most candles the OHLC endpoint returns, the latest ones, so it
+/// only reaches back this many days.
+pub const MAX_DAYS: i64 = 720;
+
+/// Kraken's codes for the assets whose ticker differs.
+const ASSET_CODES: &[(&str, &str)] = &[("BTC", "XBT"), ("DOGE", "XDG")];
+
+/// Data from the OHLC endpoint.
+#[derive(Deserialize, Debug)]
+struct Response {
+    error: Vec<String>,
+    /// The candles keyed by Kraken's name for the pair (e.g. `XXBTZEUR`),
+    /// and `last`.
+    result: Option<BTreeMap<String, serde_json::Value>>,
+}
+
+/// Kraken's code for the asset `code`.
+fn asset_code(code: &str) -> &str {
+    ASSET_CODES
+        .iter()
+        .find(|(ticker, _)| *ticker == code)
+        .map(|(_, kraken)| *kraken)
+        .unwrap_or(code)
+}
+
+/// The pair for `symbol` against `base`: `symbol` itself if it is a pair
+/// (e.g. `XBT/EUR`), otherwise the asset and the base.
+fn pair(symbol: &str, base: CommodityTypeID) -> String {
+    match symbol.split_once('/') {
+        Some((asset, quote)) => format!("{}{}", asset, quote),
+        None => format!("{}{}", asset_code(symbol), asset_code(&base.to_string())),
+    }
+}
+
+/// Fetch the daily closes of the pair of `symbol` (see [pair()]) and
+/// `base` from `start` to `end`, as the prices of `commodity`, from the
+/// daily candles (which are dated in UTC). Only the latest [MAX_DAYS]
+/// days are available.
+pub async fn get_closes(
+    client: &Client,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Closes> {
+    let pair = pair(symbol, base);
+    let since = (*start - Duration::days(1))
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time")
+        .and_utc()
+        .timestamp();
+    let url = format!(
+        "{}?pair={}&interval={}&since={}",
+        API_URL,
+        url::form_urlencoded::byte_serialize(pair.as_bytes()).collect::<String>(),
+        INTERVAL,
+        since
+    );
+    let response: Response = request_json(client, &url).await?;
+    if !response.error.is_empty() {
+        return Err(anyhow!(
+            "{} has no prices for {}: {}",
+            PROVIDER_NAME,
+            pair,
+            response.error.join(", ")
+        ));
+    }
+    let candles = response
+        .result
+        .and_then(|result| result.into_iter().find(|(key, _)| key != "last"))
+        .map(|(_, candles)| candles)
+        .ok_or_else(|| anyhow!("{} has no prices for {}", PROVIDER_NAME, pair))?;
+    // Each candle is the time it starts, open, high, low, close, volume
+    // weighted average price, volume and trade count.
+    let candles: Vec<Vec<serde_json::Value>> = serde_json::from_value(candles)
+        .map_err(|err| anyhow!("Unable to parse the candles for {}: {}", pair, err))?;
+
+    let mut closes = BTreeMap::new();
+    for candle in candles {
+        let (time, close) = match (
+            candle.first().and_then(|time| time.as_i64()),
+            candle.get(4).and_then(|close| close.as_str()),
+        ) {
+            (Some(time), Some(close)) => (time, close),
+            _ => return Err(anyhow!("Unable to parse a candle for {}", pair)),
+        };
+        let date = match NaiveDateTime::from_timestamp_opt(time, 0) {
+            Some(time) => time.date(),
+            None => continue,
+        };
+        if !(start..=end).contains(&&date) {
+            continue;
+        }
+        let close = Decimal::from_str(close)
+            .map_err(|err| anyhow!("Unable to parse the close {:?}: {}", close, err))?;
+        closes.insert(date, close);
+    }
+
+    Ok(Closes {
+        commodity,
+        currency: base,
+        closes,
+    })
+}
+
+/// Kraken as a [PriceSource], with the pairs in its `config` and the prices
+/// in `base`.
+pub struct Kraken<'a> {
+    client: &'a Client,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> Kraken<'a> {
+    pub fn new(
+        client: &'a Client,
+        config: &'a ProviderConfig,
+        base: CommodityTypeID,
+    ) -> Kraken<'a> {
+        Kraken {
+            client,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for Kraken<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            self.client,
+            commodity,
+            &self.config.symbol(&commodity),
+            self.base,
+            start,
+            end,
+        )
+        .await
+    }
+}
@@ -1 +1,52 @@
+pub mod alert;
+pub mod alphavantage;
+pub mod bankofcanada;
+pub mod beanprice;
+pub mod cache;
+pub mod chart;
+pub mod coinbase;
+pub mod coingecko;
+pub mod coinmarketcap;
+pub mod config;
+pub mod credentials;
+pub mod crossrate;
+pub mod csv;
+pub mod ecb;
+pub mod exchangeratehost;
+pub mod export;
+pub mod federalreserve;
+pub mod fetch;
+pub mod finnhub;
+pub mod fmp;
+pub mod frankfurter;
+pub mod holdings;
+pub mod html;
+pub mod iex;
+pub mod json;
+pub mod kraken;
+pub mod lock;
+pub mod marketstack;
+pub mod metadata;
+pub mod metalsapi;
+pub mod metrics;
+pub mod mock;
+pub mod morningstar;
+pub mod nasdaqdatalink;
+pub mod network;
+pub mod notify;
+pub mod oanda;
 pub mod openexchangerate;
+pub mod polygon;
+pub mod prices;
+pub mod pricesource;
+pub mod ratelimit;
+pub mod rba;
+pub mod schedule;
+pub mod series;
+pub mod source;
+pub mod stooq;
+pub mod tiingo;
+pub mod tui;
+pub mod warnings;
+pub mod wise;
+pub mod yahoo;
@@ -0,0 +1,93 @@
+use anyhow::anyhow;
+use std::{
+    fmt,
+    fs::{File, OpenOptions, TryLockError},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Exit code when a run is skipped because another run already holds its
+/// lock (`EX_TEMPFAIL` from sysexits.h), so cron wrappers can tell it apart
+/// from a failure.
+pub const EXIT_LOCKED: i32 = 75;
+
+/// How often to check whether the lock has been released while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// An exclusive lock on a file, held until it is dropped (or the process
+/// exits), which stops overlapping runs of the same job from fetching and
+/// appending the same prices twice.
+#[derive(Debug)]
+pub struct RunLock {
+    _file: File,
+}
+
+/// The error when the locks at `paths` are held by other processes.
+#[derive(Debug)]
+pub struct Locked {
+    pub paths: Vec<PathBuf>,
+}
+
+impl fmt::Display for Locked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let paths: Vec<String> = self
+            .paths
+            .iter()
+            .map(|path| format!("{:?}", path))
+            .collect();
+        write!(f, "another run holds the lock {}", paths.join(", "))
+    }
+}
+
+impl std::error::Error for Locked {}
+
+/// The path of the lock for the output file at `output`: the same path
+/// with `.lock` appended.
+pub fn lock_path(output: &Path) -> PathBuf {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".lock");
+    PathBuf::from(path)
+}
+
+impl RunLock {
+    /// Take the lock at `path`, waiting up to `wait` for another process to
+    /// release it. Fails with [Locked] if it is still held after that.
+    pub async fn acquire(path: &Path, wait: Duration) -> anyhow::Result<RunLock> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .map_err(|err| anyhow!("Unable to create directory {:?}: {}", dir, err))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .map_err(|err| anyhow!("Unable to open lock file {:?}: {}", path, err))?;
+
+        let started = Instant::now();
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) if started.elapsed() < wait => {
+                    tokio::time::delay_for(POLL_INTERVAL).await;
+                }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(Locked {
+                        paths: vec![path.to_path_buf()],
+                    }
+                    .into())
+                }
+                Err(TryLockError::Error(err)) => {
+                    return Err(anyhow!("Unable to lock {:?}: {}", path, err))
+                }
+            }
+        }
+
+        // Only informational, for whoever finds the lock held.
+        file.set_len(0)
+            .and_then(|_| writeln!(file, "{}", std::process::id()))
+            .map_err(|err| anyhow!("Unable to write lock file {:?}: {}", path, err))?;
+        Ok(RunLock { _file: file })
+    }
+}
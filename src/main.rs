@@ -1,412 +1,4931 @@
 use anyhow::anyhow;
-use beancount_price_fetcher::openexchangerate::{OpenExchangeRate, Usage};
-use chrono::{Duration, NaiveDate};
-use clap::{App, Arg};
-use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
-use futures::{stream, StreamExt};
+use beancount_price_fetcher::{
+    alert, alphavantage, bankofcanada, beanprice,
+    cache::Cache,
+    chart::sparkline,
+    coinbase, coingecko, coinmarketcap,
+    config::{self, Config, ProviderConfig},
+    credentials,
+    crossrate::{derive_rate, Derivation},
+    csv, ecb, exchangeratehost,
+    export::{self, Engine},
+    federalreserve,
+    fetch::{self, FetchOptions},
+    finnhub, fmp, frankfurter,
+    holdings::{self, Holdings},
+    html, iex, json, kraken,
+    lock::{self, Locked, RunLock},
+    marketstack,
+    metadata::{self, CurrencyMetadata},
+    metalsapi,
+    metrics::{self, REQUEST_METRICS, SERVICE_METRICS},
+    mock, morningstar, nasdaqdatalink,
+    network::request_json,
+    notify::{self, Event},
+    oanda,
+    openexchangerate::{self, AppID, Currencies, OpenExchangeRates},
+    polygon,
+    prices::{self, Aggregate, MissingRates, MissingRatesError, OutOfBounds, PriceSeries},
+    pricesource::PriceSource,
+    ratelimit::RateLimiter,
+    rba,
+    schedule::Schedule,
+    series::{DateMismatch, MergePolicy, Sampling, SnapshotTime, TimeSeries},
+    source::{Setup, Source},
+    stooq, tiingo,
+    tui::{self, Action},
+    warnings::{warn, Kind, WARNINGS},
+    wise, yahoo,
+};
+use chrono::{Days, Duration, Local, NaiveDate, Utc};
+use clap::{App, Arg, ArgMatches, ArgSettings};
+use commodity::{CommodityTypeID, COMMODITY_TYPE_ID_LENGTH};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
 use reqwest::Client;
-use serde::de::DeserializeOwned;
+use rust_decimal::Decimal;
+use serde::Serialize;
 use std::{
-    collections::{BTreeMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    convert::Infallible,
+    io::{IsTerminal, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
     str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
-pub type AppID = String;
+/// Used when neither `--parallel-requests` nor the config specify a value.
+const DEFAULT_PARALLEL_REQUESTS: usize = 2;
 
-const API_URL: &'static str = "https://openexchangerates.org/api";
+/// Used when the config doesn't specify how many requests a run may make
+/// before asking for confirmation.
+const DEFAULT_CONFIRM_REQUESTS: u32 = 500;
 
-#[derive(Debug)]
-pub struct TimeSeries {
-    map: BTreeMap<NaiveDate, ExchangeRate>,
-}
+/// The longest `--rolling-average` window, about a century.
+const MAX_ROLLING_AVERAGE_DAYS: i64 = 36500;
 
-fn symbols_argument(includes: Vec<CommodityTypeID>) -> Option<String> {
-    if !includes.is_empty() {
-        let mut symbols = String::from("&symbols=");
+/// Set by the long running commands (`serve` and `daemon`), which must
+/// never wait for an answer on stdin.
+static UNATTENDED: AtomicBool = AtomicBool::new(false);
 
-        let includes_list = includes
-            .iter()
-            .map(|currency| currency.to_string())
-            .collect::<Vec<String>>()
-            .join(",");
-        symbols.push_str(includes_list.as_str());
-        Some(symbols)
+/// How long the provider's list of currencies is cached for.
+const CURRENCIES_MAX_AGE: std::time::Duration = std::time::Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The provider's list of currencies (with the `alternative` ones too, see
+/// [OpenExchangeRates::with_alternative()]), from the cache if it was
+/// fetched recently enough.
+async fn get_currencies(
+    client: &Client,
+    cache: Option<&Cache>,
+    alternative: bool,
+) -> anyhow::Result<Currencies> {
+    let (cache_file, arguments) = if alternative {
+        ("currencies-alternative.json", "?show_alternative=1")
     } else {
-        None
+        ("currencies.json", "")
+    };
+    if let Some(cache) = cache {
+        if let Some(currencies) = cache.get_file(cache_file, CURRENCIES_MAX_AGE)? {
+            return Ok(currencies);
+        }
     }
-}
-
-async fn request_json<T: DeserializeOwned>(client: &Client, url: &str) -> anyhow::Result<T> {
-    let result: T = client.get(url).send().await?.json::<T>().await?;
-    Ok(result)
-}
 
-pub async fn get_usage(client: &Client, app_id: &AppID) -> anyhow::Result<Usage> {
     let url = format!(
-        "{api_url}/usage.json?app_id={app_id}&prettyprint=false",
-        api_url = API_URL,
-        app_id = app_id,
+        "{api_url}/currencies.json{arguments}",
+        api_url = openexchangerate::API_URL,
+        arguments = arguments
     );
-
-    request_json(client, &url).await
+    let currencies: Currencies = request_json(client, &url).await?;
+    if let Some(cache) = cache {
+        cache.put_file(cache_file, &currencies)?;
+    }
+    Ok(currencies)
 }
 
-// TODO: refactor this to use a hashmap for arguments, and a generic request api.
-async fn get_day_json(
-    client: &Client,
-    app_id: &AppID,
-    include: Option<Vec<CommodityTypeID>>,
-    json: &str,
-) -> anyhow::Result<ExchangeRate> {
-    let mut url = format!(
-        "{api_url}/{json}?app_id={app_id}&prettyprint=false",
-        api_url = API_URL,
-        app_id = app_id,
-        json = json,
-    );
-    if let Some(includes) = include {
-        if let Some(arg) = symbols_argument(includes) {
-            url.push_str(arg.as_str());
+/// The [metadata] of the provider's currencies, from the cache if it was
+/// fetched recently enough. Takes two requests, for the official and the
+/// alternative currencies.
+async fn get_metadata(client: &Client, cache: Option<&Cache>) -> anyhow::Result<CurrencyMetadata> {
+    const CACHE_FILE: &str = "metadata.json";
+    if let Some(cache) = cache {
+        if let Some(metadata) = cache.get_file(CACHE_FILE, CURRENCIES_MAX_AGE)? {
+            return Ok(metadata);
         }
     }
 
-    request_json::<OpenExchangeRate>(client, &url)
-        .await
-        .map(|rate| rate.into())
+    let official = get_currencies(client, cache, false).await?;
+    let all = get_currencies(client, cache, true).await?;
+    let metadata = metadata::from_currencies(&official, &all);
+    if let Some(cache) = cache {
+        cache.put_file(CACHE_FILE, &metadata)?;
+    }
+    Ok(metadata)
 }
 
-pub async fn get_latest(
+/// Check that the provider has every one of the `commodities` (by their
+/// symbol in the `provider_config`), so a typo
+/// fails before any quota is spent rather than leaving the commodity out of
+/// every rate. With `alternative` the alternative currencies count too. If
+/// the list of currencies can't be fetched this only warns, it is left to
+/// the fetch to fail.
+async fn check_commodities(
     client: &Client,
-    app_id: &AppID,
-    include: Option<Vec<CommodityTypeID>>,
-) -> anyhow::Result<ExchangeRate> {
-    get_day_json(client, app_id, include, "latest.json").await
-}
+    cache: Option<&Cache>,
+    provider_config: &ProviderConfig,
+    commodities: &[CommodityTypeID],
+    alternative: bool,
+) -> anyhow::Result<()> {
+    let currencies = match get_currencies(client, cache, alternative).await {
+        Ok(currencies) => currencies,
+        Err(error) => {
+            warn(
+                Kind::CommodityCheck,
+                format!(
+                    "unable to check the commodities are supported by {}: {}",
+                    openexchangerate::PROVIDER_NAME,
+                    error
+                ),
+            );
+            return Ok(());
+        }
+    };
 
-pub async fn get_historical(
-    client: &Client,
-    app_id: &AppID,
-    date: &NaiveDate,
-    include: Option<Vec<CommodityTypeID>>,
-) -> anyhow::Result<ExchangeRate> {
-    let date = format!("historical/{}.json", date.format("%Y-%m-%d").to_string());
-    get_day_json(client, app_id, include, date.as_str()).await
+    let mut unknown: Vec<String> = commodities
+        .iter()
+        .map(|commodity| provider_config.symbol(commodity))
+        .filter(|commodity| !currencies.contains_key(commodity))
+        .collect();
+    unknown.sort();
+    let unknown: Vec<String> = unknown
+        .into_iter()
+        .map(
+            |commodity| match suggest_currency(&commodity, &currencies) {
+                Some(suggestion) => format!("'{}' (did you mean '{}'?)", commodity, suggestion),
+                None => format!("'{}'", commodity),
+            },
+        )
+        .collect();
+    if !unknown.is_empty() {
+        return Err(anyhow!(
+            "{} doesn't have {}, see --no-commodity-check to skip this check",
+            openexchangerate::PROVIDER_NAME,
+            unknown.join(", ")
+        ));
+    }
+    Ok(())
 }
 
-pub async fn get_time_series_with_historical(
-    client: &Client,
-    app_id: &AppID,
-    parallel_requests: usize,
-    start: &NaiveDate,
-    end: &NaiveDate,
-    include: Option<Vec<CommodityTypeID>>,
-) -> anyhow::Result<TimeSeries> {
-    let mut series: BTreeMap<NaiveDate, ExchangeRate> = BTreeMap::new();
-    let mut dates: Vec<NaiveDate> = Vec::new();
-
-    let mut dt = start.clone();
-
-    while &dt <= end {
-        dates.push(dt);
-        dt = dt + Duration::days(1);
-    }
-
-    let buffer = stream::iter(dates)
-        .map(|date| {
-            let include = include.clone();
-            async move { get_historical(client, app_id, &date, include).await }
+/// `text` in lower case, with the accents taken off the letters which
+/// commonly have them in currency names (e.g. Króna, Złoty), so they can be
+/// searched for without typing them.
+fn fold(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'á' | 'à' | 'â' | 'ä' | 'ã' | 'å' | 'ā' | 'ă' | 'ą' => 'a',
+            'ç' | 'č' | 'ć' => 'c',
+            'đ' | 'ď' => 'd',
+            'é' | 'è' | 'ê' | 'ë' | 'ē' | 'ę' | 'ě' => 'e',
+            'ğ' => 'g',
+            'í' | 'ì' | 'î' | 'ï' | 'ī' | 'ı' => 'i',
+            'ł' => 'l',
+            'ñ' | 'ń' | 'ň' => 'n',
+            'ó' | 'ò' | 'ô' | 'ö' | 'õ' | 'ø' | 'ō' | 'ő' => 'o',
+            'ř' => 'r',
+            'ś' | 'š' | 'ş' | 'ș' => 's',
+            'ť' | 'ţ' | 'ț' => 't',
+            'ú' | 'ù' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' => 'u',
+            'ý' | 'ÿ' => 'y',
+            'ź' | 'ż' | 'ž' => 'z',
+            c => c,
         })
-        .buffer_unordered(parallel_requests);
+        .collect()
+}
 
-    let results: Vec<anyhow::Result<ExchangeRate>> = buffer.collect().await;
+/// How similar a word must be to the query (by Jaro-Winkler similarity)
+/// for [search_currencies()] to count it as a match.
+const SEARCH_SIMILARITY: f64 = 0.85;
 
-    for result in results {
-        match result {
-            Ok(exchange_rate) => {
-                series.insert(
-                    exchange_rate.date.expect("expected date to be present"),
-                    exchange_rate,
-                );
+/// The currencies in `currencies` matching `query`, best first: the one
+/// with that code, then those whose code starts with it or whose name
+/// contains it, then those with a word in their name (or a code) similar
+/// to it, e.g. `krona` finds SEK (Swedish Krona), ISK (Icelandic Króna)
+/// and NOK (Norwegian Krone).
+fn search_currencies<'a>(query: &str, currencies: &'a Currencies) -> Vec<(&'a str, &'a str)> {
+    let query = fold(query.trim());
+    let mut matches: Vec<(u8, f64, &str, &str)> = currencies
+        .iter()
+        .filter_map(|(code, name)| {
+            let folded_code = code.to_lowercase();
+            let folded_name = fold(name);
+            if folded_code == query {
+                return Some((0, 1.0, code.as_str(), name.as_str()));
             }
-            Err(error) => return Err(error),
-        }
+            if folded_code.starts_with(&query) || folded_name.contains(&query) {
+                return Some((1, 1.0, code.as_str(), name.as_str()));
+            }
+            let similarity = folded_name
+                .split(|c: char| !c.is_alphanumeric())
+                .chain(std::iter::once(folded_code.as_str()))
+                .filter(|word| !word.is_empty())
+                .map(|word| strsim::jaro_winkler(word, &query))
+                .fold(0.0, f64::max);
+            if similarity >= SEARCH_SIMILARITY {
+                return Some((2, similarity, code.as_str(), name.as_str()));
+            }
+            None
+        })
+        .collect();
+    matches.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.total_cmp(&a.1)).then(a.2.cmp(b.2)));
+    matches
+        .into_iter()
+        .map(|(_, _, code, name)| (code, name))
+        .collect()
+}
+
+/// Print the code of each of the provider's currencies, in order.
+async fn symbols_command(settings: &Settings<'_>) -> anyhow::Result<()> {
+    let currencies = get_currencies(
+        &Client::new(),
+        cache(settings).as_ref(),
+        settings.is_present("alternative"),
+    )
+    .await?;
+    for code in currencies.keys() {
+        println!("{}", code);
+    }
+    Ok(())
+}
+
+/// Print the currencies matching the query for the `search` subcommand,
+/// see [search_currencies()].
+async fn search_command(settings: &Settings<'_>) -> anyhow::Result<()> {
+    let query = settings
+        .value_of("query")
+        .expect("expected query to be specified");
+    let limit: usize = settings
+        .value_of("limit")
+        .expect("expected limit to be specified")
+        .parse()
+        .map_err(|err| anyhow!("Unable to parse limit: {}", err))?;
+
+    let currencies = get_currencies(&Client::new(), cache(settings).as_ref(), false).await?;
+    let matches = search_currencies(query, &currencies);
+    if matches.is_empty() {
+        return Err(anyhow!(
+            "{} has no currency matching {:?}",
+            openexchangerate::PROVIDER_NAME,
+            query
+        ));
     }
 
-    Ok(TimeSeries { map: series })
+    let mut table = vec![vec![String::from("CODE"), String::from("NAME")]];
+    table.extend(
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(code, name)| vec![code.to_string(), name.to_string()]),
+    );
+    print_table(&table);
+    Ok(())
 }
 
-// TODO: disabled because requires pro series plan, so I can't test right now.
-// #[derive(Deserialize, Debug)]
-// struct OpenExchangeTimeSeries {
-//     start_date: NaiveDate,
-//     end_date: NaiveDate,
-// }
+/// Print the commodity directives for the `commodities` subcommand.
+async fn commodities_command(settings: &Settings<'_>) -> anyhow::Result<()> {
+    let date = parse_date(settings, "date")?;
+    let mut commodities = Vec::new();
+    for commodity in settings.required_values("commodities")? {
+        commodities.push(parse_commodity("commodities", commodity)?);
+    }
+
+    let metadata = get_metadata(&Client::new(), cache(settings).as_ref()).await?;
+    let names: Currencies = metadata
+        .iter()
+        .map(|(code, metadata)| (code.clone(), metadata.name.clone()))
+        .collect();
+    // All looked up before any are printed, so an unknown one doesn't leave
+    // half the directives.
+    let mut directives = Vec::new();
+    for commodity in &commodities {
+        let code = commodity.to_string();
+        let metadata =
+            metadata
+                .get(&code)
+                .ok_or_else(|| match suggest_currency(&code, &names) {
+                    Some(suggestion) => anyhow!(
+                        "{} doesn't have '{}' (did you mean '{}'?)",
+                        openexchangerate::PROVIDER_NAME,
+                        code,
+                        suggestion
+                    ),
+                    None => anyhow!(
+                        "{} doesn't have '{}'",
+                        openexchangerate::PROVIDER_NAME,
+                        code
+                    ),
+                })?;
+        directives.push((code, metadata));
+    }
+
+    for (code, metadata) in directives {
+        println!("{} commodity {}", date.format("%Y-%m-%d"), code);
+        println!(
+            "  name: \"{}\"",
+            metadata.name.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+        if let Some(decimal_places) = metadata.decimal_places {
+            println!("  precision: {}", decimal_places);
+        }
+        println!(
+            "  alternative: {}",
+            if metadata.alternative {
+                "TRUE"
+            } else {
+                "FALSE"
+            }
+        );
+        println!();
+    }
+    Ok(())
+}
 
-// async fn get_time_series(
-//     app_id: &AppID,
-//     start: &NaiveDate,
-//     end: &NaiveDate,
-//     include: Option<Vec<CommodityTypeID>>,
-// ) -> anyhow::Result<OpenExchangeTimeSeries> {
-//     let mut url = format!(
-//         "{api_url}/time-series.json?app_id={app_id}&start={start}&end={end}",
-//         api_url = API_URL,
-//         app_id = app_id,
-//         start = start.format("%Y-%m-%d").to_string(),
-//         end = end.format("%Y-%m-%d").to_string()
-//     );
+/// The currency in `currencies` most like `commodity`: the same code in
+/// another case, a code one letter off, or one whose name contains it.
+fn suggest_currency<'a>(commodity: &str, currencies: &'a Currencies) -> Option<&'a str> {
+    let upper = commodity.to_uppercase();
+    let lower = commodity.to_lowercase();
+    currencies
+        .keys()
+        .find(|code| **code == upper)
+        .or_else(|| {
+            currencies
+                .keys()
+                .filter(|code| strsim::levenshtein(code, &upper) == 1)
+                .min_by_key(|code| strsim::damerau_levenshtein(code, &upper))
+        })
+        .or_else(|| {
+            currencies
+                .iter()
+                .find(|(_, name)| lower.len() > 3 && name.to_lowercase().contains(&lower))
+                .map(|(code, _)| code)
+        })
+        .map(String::as_str)
+}
 
-//     if let Some(includes) = include {
-//         if let Some(arg) = symbols_argument(includes) {
-//             url.push_str(arg.as_str());
-//         }
-//     }
+/// The `--app-id`, `--app-id-file` and `--api-key` arguments, shared by
+/// every subcommand which makes requests.
+fn app_id_args() -> Vec<Arg<'static>> {
+    vec![app_id_arg(), app_id_file_arg(), api_key_arg()]
+}
 
-//     let series: OpenExchangeTimeSeries = reqwest::get(&url)
-//         .await?
-//         .json::<OpenExchangeTimeSeries>()
-//         .await?;
+fn api_key_arg() -> Arg<'static> {
+    Arg::new("api-key")
+        .long("api-key")
+        .value_name("KEY")
+        .help("API key for sources which need one, e.g. alphavantage")
+        .long_help(
+            "API key for sources which need one, e.g. alphavantage, or keyring: to read it \
+            from the OS keychain. Overrides key in the source's section of the config and \
+            the source's environment variable (e.g. ALPHAVANTAGE_API_KEY)",
+        )
+        .takes_value(true)
+}
 
-//     Ok(series)
-// }
+/// Arguments selecting where the rates come from: a provider, or the
+/// mock source for trying things out without an app id.
+fn source_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("source")
+            .long("source")
+            .value_name("SOURCE")
+            .help("Where the rates come from")
+            .long_help(
+                "Where the rates come from, with the key each needs (see --api-key) and what it \
+                prices:\n\
+                \n\
+                openexchangerates  app id        currency exchange rates (see --app-id)\n\
+                yahoo              no key        closes of stocks, ETFs and funds, by ticker\n\
+                alphavantage       api key       daily closes of currencies, stocks and crypto\n\
+                coingecko          optional key  cryptocurrencies, by CoinGecko coin ID\n\
+                coinmarketcap      Pro api key   cryptocurrencies\n\
+                ecb                no key        the European Central Bank's EUR reference rates\n\
+                frankfurter        no key        the ECB's reference rates, in any base\n\
+                exchangeratehost   api key       time series of currency exchange rates\n\
+                iex                api key       end of day prices of US equities\n\
+                finnhub            api key       candles of stocks and forex\n\
+                tiingo             api key       end of day prices of stocks and funds\n\
+                kraken             no key        daily closes of the crypto pairs traded on Kraken\n\
+                coinbase           no key        daily candles of Coinbase Exchange's products\n\
+                polygon            api key       daily aggregates of US stocks and forex\n\
+                nasdaqdatalink     optional key  a column of any Nasdaq Data Link dataset\n\
+                stooq              no key        daily closes of stocks, indices and forex\n\
+                bankofcanada       no key        the Bank of Canada's daily CAD exchange rates\n\
+                rba                no key        the Reserve Bank of Australia's daily AUD rates\n\
+                federalreserve     no key        the Federal Reserve's H.10 USD exchange rates\n\
+                metalsapi          api key       precious metals per troy ounce (or gram)\n\
+                oanda              api key       OANDA's daily average rates (see --quote)\n\
+                wise               no key        Wise's daily mid-market rates\n\
+                marketstack        api key       end of day prices of equities worldwide\n\
+                fmp                api key       closes of stocks, ETFs and mutual funds\n\
+                morningstar        no key        unit prices of managed funds, by Morningstar ID\n\
+                json               optional key  any JSON API, set up with a url and JSONPaths\n\
+                html               no key        web pages, scraped with CSS selectors\n\
+                csv                no key        a local CSV file, such as a broker's statement\n\
+                beanprice          no key        bean-price's Python source modules\n\
+                mock               no key        made up (but deterministic) rates, no network\n\
+                \n\
+                Tickers and IDs which aren't the commodity's code, and the json, html, csv and \
+                beanprice sources, are set up in the provider's config.",
+            )
+            .possible_values(config::SOURCES)
+            .takes_value(true)
+            .default_value(openexchangerate::PROVIDER_NAME),
+        Arg::new("seed")
+            .long("seed")
+            .value_name("SEED")
+            .help("Seed for the mock source's rates, the same seed always gives the same rates")
+            .takes_value(true)
+            .default_value("0"),
+        Arg::new("quote")
+            .long("quote")
+            .value_name("QUOTE")
+            .help("Which of the oanda source's quotes to use for the price: bid, ask or midpoint")
+            .possible_values(oanda::QUOTES)
+            .takes_value(true)
+            .default_value("midpoint"),
+    ]
+}
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    env_logger::init();
+/// The [Source] chosen with the [source_args()].
+fn source(settings: &Settings<'_>) -> anyhow::Result<Source> {
+    source_named(settings, settings.value_of("source"))
+}
 
-    let app_id_arg = Arg::new("app-id")
+/// The [Source] called `name`, with its parameters (e.g. `--seed`) from
+/// the [source_args()].
+fn source_named(settings: &Settings<'_>, name: Option<&str>) -> anyhow::Result<Source> {
+    match name {
+        Some(mock::PROVIDER_NAME) => {
+            let seed = settings
+                .value_of("seed")
+                .expect("expected seed to be specified")
+                .parse()
+                .map_err(|err| anyhow!("Unable to parse seed: {}", err))?;
+            Ok(Source::Mock { seed })
+        }
+        Some(yahoo::PROVIDER_NAME) => Ok(Source::Yahoo),
+        Some(alphavantage::PROVIDER_NAME) => Ok(Source::AlphaVantage),
+        Some(coingecko::PROVIDER_NAME) => Ok(Source::CoinGecko),
+        Some(coinmarketcap::PROVIDER_NAME) => Ok(Source::CoinMarketCap),
+        Some(ecb::PROVIDER_NAME) => Ok(Source::Ecb),
+        Some(frankfurter::PROVIDER_NAME) => Ok(Source::Frankfurter),
+        Some(exchangeratehost::PROVIDER_NAME) => Ok(Source::ExchangerateHost),
+        Some(iex::PROVIDER_NAME) => Ok(Source::Iex),
+        Some(finnhub::PROVIDER_NAME) => Ok(Source::Finnhub),
+        Some(tiingo::PROVIDER_NAME) => Ok(Source::Tiingo),
+        Some(kraken::PROVIDER_NAME) => Ok(Source::Kraken),
+        Some(coinbase::PROVIDER_NAME) => Ok(Source::Coinbase),
+        Some(polygon::PROVIDER_NAME) => Ok(Source::Polygon),
+        Some(nasdaqdatalink::PROVIDER_NAME) => Ok(Source::NasdaqDataLink),
+        Some(stooq::PROVIDER_NAME) => Ok(Source::Stooq),
+        Some(bankofcanada::PROVIDER_NAME) => Ok(Source::BankOfCanada),
+        Some(rba::PROVIDER_NAME) => Ok(Source::Rba),
+        Some(federalreserve::PROVIDER_NAME) => Ok(Source::FederalReserve),
+        Some(metalsapi::PROVIDER_NAME) => Ok(Source::MetalsApi),
+        Some(oanda::PROVIDER_NAME) => {
+            let quote = oanda::Quote::from_str(
+                settings
+                    .value_of("quote")
+                    .expect("expected quote to be specified"),
+            )?;
+            Ok(Source::Oanda { quote })
+        }
+        Some(wise::PROVIDER_NAME) => Ok(Source::Wise),
+        Some(marketstack::PROVIDER_NAME) => Ok(Source::Marketstack),
+        Some(fmp::PROVIDER_NAME) => Ok(Source::Fmp),
+        Some(morningstar::PROVIDER_NAME) => Ok(Source::Morningstar),
+        Some(json::PROVIDER_NAME) => Ok(Source::Json),
+        Some(csv::PROVIDER_NAME) => Ok(Source::Csv),
+        Some(html::PROVIDER_NAME) => Ok(Source::Html),
+        Some(beanprice::PROVIDER_NAME) => Ok(Source::BeanPrice),
+        Some(openexchangerate::PROVIDER_NAME) | None => Ok(Source::OpenExchangeRates),
+        Some(source) => Err(anyhow!("unknown source {:?}", source)),
+    }
+}
+
+fn app_id_arg() -> Arg<'static> {
+    Arg::new("app-id")
         .long("app-id")
         .short('i')
         .value_name("ID")
         .help("OpenExchangeRates App ID ( see https://openexchangerates.org/account/app-ids )")
+        .long_help(
+            "OpenExchangeRates App ID ( see https://openexchangerates.org/account/app-ids ), \
+            or keyring: to read it from the OS keychain (see store-credential). Overrides \
+            app_id in the config",
+        )
+        .env(openexchangerate::APP_ID_ENV)
+        .hide_env_values(true)
+        .takes_value(true)
+}
+
+fn app_id_file_arg() -> Arg<'static> {
+    Arg::new("app-id-file")
+        .long("app-id-file")
+        .value_name("FILE")
+        .help("File to read the OpenExchangeRates App ID from, e.g. /run/secrets/oxr")
+        .long_help(
+            "File to read the OpenExchangeRates App ID from, e.g. /run/secrets/oxr (a trailing \
+            newline is ignored). Takes precedence over --app-id and OXR_APP_ID",
+        )
+        .takes_value(true)
+}
+
+/// The `--rounding` argument.
+fn rounding_arg() -> Arg<'static> {
+    Arg::new("rounding")
+        .long("rounding")
+        .short('r')
+        .value_name("DP")
+        .help("Number of decimal places to round to, or auto")
+        .long_help(
+            "Number of decimal places to round to, or auto for 4 more than the base \
+            commodity usually has (its ISO 4217 minor units, or 8 for cryptocurrencies)",
+        )
+        .takes_value(true)
+}
+
+/// The `--wait` argument of the subcommands which run jobs.
+fn wait_arg() -> Arg<'static> {
+    Arg::new("wait")
+        .long("wait")
+        .value_name("INTERVAL")
+        .help("How long to wait for another run of a job to finish, e.g. 90s or 10m")
+        .long_help(
+            "How long to wait for another run of a job (holding the lock next to its output \
+            file) to finish, e.g. 90s or 10m. If it is still running after that, the job is \
+            skipped, and the command exits with code 75 unless another job failed",
+        )
+        .takes_value(true)
+}
+
+/// The `--out-of-bounds` argument.
+fn bounds_arg() -> Arg<'static> {
+    Arg::new("out-of-bounds")
+        .long("out-of-bounds")
+        .value_name("BEHAVIOUR")
+        .help("What to do with prices outside the bounds in the config: skip, keep or fail")
+        .long_help(
+            "What to do with prices outside the plausible bounds configured for their pair \
+            (the bounds section of the config): skip them, keep them (annotated with a \
+            comment), or fail. Each one is reported with a warning",
+        )
+        .possible_values(["skip", "keep", "fail"])
+        .default_value("skip")
         .takes_value(true)
-        .required(true);
+}
+
+/// The `--chart` argument.
+fn chart_arg() -> Arg<'static> {
+    Arg::new("chart")
+        .long("chart")
+        .help("Print a sparkline chart of the prices for each commodity (to stderr)")
+}
+
+/// The `--config`, `--no-config` and `--cache-dir` arguments, which are
+/// global so they can be given before or after any subcommand.
+fn global_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("config")
+            .long("config")
+            .value_name("FILE")
+            .help("Config file (TOML), instead of the one found in the config directory")
+            .env("BEANCOUNT_PRICE_FETCHER_CONFIG")
+            .long_help(
+                "Config file (TOML). If not given, the first config found in the platform \
+                config directory (e.g. ~/.config/beancount-price-fetcher/config.toml) is used, \
+                see the README for the full search order",
+            )
+            .takes_value(true),
+        Arg::new("no-config")
+            .long("no-config")
+            .help("Don't use a config file, even if one is found in the config directory")
+            .conflicts_with("config"),
+        Arg::new("cache-dir")
+            .long("cache-dir")
+            .value_name("DIR")
+            .help("Directory to cache fetched exchange rates in")
+            .long_help(
+                "Directory to cache fetched exchange rates in. Dates already in the \
+                cache (with all the requested commodities) are not fetched again. \
+                Overrides cache_dir in the config",
+            )
+            .takes_value(true),
+    ]
+    .into_iter()
+    .map(|arg| arg.global(true))
+    .collect()
+}
+
+/// Arguments choosing the commodities to price, and what to price them in.
+fn commodity_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("commodities")
+            .long("commodities")
+            .short('c')
+            .value_name("COMMODITIES")
+            .multiple_occurrences(true)
+            .multiple_values(true)
+            .help("Commodities to request exchange rates for (e.g AUD USD)")
+            .takes_value(true),
+        Arg::new("base")
+            .long("base")
+            .short('b')
+            .value_name("COMMODITY")
+            .help("Commodity to use as the reference/base in the beancount price listing")
+            .takes_value(true),
+        Arg::new("pivot")
+            .long("pivot")
+            .value_name("COMMODITY")
+            .help("Commodity to triangulate prices through (e.g. USD)")
+            .long_help(
+                "Commodity to triangulate prices through, e.g. derive NZD/AUD as NZD/USD \
+                multiplied by USD/AUD. Derived prices are annotated with the pivot. \
+                Overrides pivot in the config",
+            )
+            .takes_value(true),
+        Arg::new("include-base")
+            .long("include-base")
+            .help("Emit a price of 1 for the base commodity if it is one of the commodities")
+            .long_help(
+                "Emit a price of 1 for the base commodity if it is one of the commodities, \
+                otherwise it is left out with a warning",
+            ),
+        no_commodity_check_arg(),
+        alternative_arg(),
+    ]
+}
+
+fn alternative_arg() -> Arg<'static> {
+    Arg::new("alternative")
+        .long("alternative")
+        .help("Include the provider's alternative rates, e.g. black market rates")
+        .long_help(
+            "Include openexchangerates' alternative rates: the unofficial and black market \
+            rates of currencies whose official rate isn't what they trade at (e.g. VES), and \
+            cryptocurrencies, see https://docs.openexchangerates.org/docs/alternative-currencies",
+        )
+}
 
-    let app = App::new("beancount-price-fetcher")
+fn no_commodity_check_arg() -> Arg<'static> {
+    Arg::new("no-commodity-check")
+        .long("no-commodity-check")
+        .help("Don't check the provider has the commodities before fetching")
+        .long_help(
+            "Don't check the provider has the commodities before fetching (the list of \
+            currencies is cached for a week in the --cache-dir, otherwise it is an extra \
+            request on each run)",
+        )
+}
+
+/// Arguments controlling which exchange rates are fetched, and how, shared
+/// by the subcommands which fetch a series.
+fn fetch_args() -> Vec<Arg<'static>> {
+    let mut args = app_id_args();
+    args.extend(source_args());
+    args.extend(vec![
+        Arg::new("start")
+            .long("start")
+            .short('s')
+            .value_name("DATE")
+            .help("Start date in format YYYY-mm-dd, e.g. 2020-05-25, or relative, e.g. -7d")
+            .long_help(
+                "Start date in format YYYY-mm-dd, e.g. 2020-05-25, or relative to today's (UTC) \
+                date: today, yesterday, or a number of days or weeks ago, e.g. -7d or -2w",
+            )
+            .allow_hyphen_values(true)
+            .takes_value(true),
+        Arg::new("end")
+            .long("end")
+            .short('e')
+            .value_name("DATE")
+            .help("End date in format YYYY-mm-dd, e.g. 2020-05-25, or relative, e.g. -7d")
+            .long_help(
+                "End date in format YYYY-mm-dd, e.g. 2020-05-25, or relative to today's (UTC) \
+                date: today, yesterday, or a number of days or weeks ago, e.g. -7d or -2w",
+            )
+            .allow_hyphen_values(true)
+            .takes_value(true),
+        Arg::new("no-quota-check")
+            .long("no-quota-check")
+            .short('q')
+            .help("Don't check the quota limits before performing the requests")
+            .long_help(
+                "Don't check the quota limits before performing the requests \
+                (makes the command faster by avoiding the extra request, but you may \
+                exceed your quota)",
+            ),
+        Arg::new("max-requests")
+            .long("max-requests")
+            .short('m')
+            .value_name("N")
+            .help("Maximum number of requests this run may make")
+            .long_help(
+                "Maximum number of requests this run may make. Dates that don't fit \
+                in the budget are left unfetched, and the date to resume from is \
+                reported. Overrides the provider's max_requests setting in the config",
+            )
+            .takes_value(true),
+        Arg::new("parallel-requests")
+            .long("parallel-requests")
+            .short('p')
+            .value_name("N")
+            .help("Number of parallel network requests to use (when possible)")
+            .long_help(
+                "Number of parallel network requests to use (when possible). \
+                Overrides the provider's parallel_requests setting in the config \
+                [default: 2]",
+            )
+            .takes_value(true),
+        Arg::new("retries")
+            .long("retries")
+            .value_name("N")
+            .help("Number of times to retry a request which failed with a transient error")
+            .takes_value(true)
+            .default_value("2"),
+        Arg::new("stale-ok")
+            .long("stale-ok")
+            .help("Use the nearest earlier cached rates for dates which can't be fetched")
+            .long_help(
+                "If a date can't be fetched (after retries), use the cached rates for \
+                that date, or the nearest date before it (up to --max-stale-days) with all \
+                the commodities, with a warning, instead of failing. Requires a cache (see \
+                --cache-dir)",
+            ),
+        Arg::new("max-stale-days")
+            .long("max-stale-days")
+            .value_name("DAYS")
+            .help("Maximum number of days old the cached rates used by --stale-ok can be")
+            .takes_value(true)
+            .default_value("7"),
+        Arg::new("yes")
+            .long("yes")
+            .short('y')
+            .help("Don't ask for confirmation before making a large number of requests")
+            .long_help(
+                "Don't ask for confirmation before making more requests than the \
+                provider's confirm_requests setting in the config [default: 500]. \
+                Without a terminal to ask on, such a run fails unless this is given",
+            ),
+        Arg::new("missing-rates")
+            .long("missing-rates")
+            .value_name("BEHAVIOUR")
+            .help("What to do with dates a commodity's rate is missing for: skip, forward-fill or fail")
+            .long_help(
+                "What to do with the dates a price can't be calculated for, because a \
+                requested commodity's rate is missing from that date's response: skip \
+                them, forward-fill them with the price for the nearest earlier date, or \
+                fail listing them",
+            )
+            .possible_values(["skip", "forward-fill", "fail"])
+            .default_value("skip")
+            .takes_value(true),
+        Arg::new("strict")
+            .long("strict")
+            .help("Fail if a commodity's rate is missing for any date, the same as --missing-rates fail")
+            .conflicts_with("missing-rates"),
+        Arg::new("full-table")
+            .long("full-table")
+            .help("Fetch the full rate table rather than only the requested commodities")
+            .long_help(
+                "Fetch the full rate table (every commodity the provider has) rather \
+                than only the requested commodities, to warm the cache so later runs \
+                for other commodities don't need to make any requests. Requires a \
+                cache (see --cache-dir)",
+            ),
+        Arg::new("sample")
+            .long("sample")
+            .value_name("INTERVAL")
+            .help("Which dates to fetch: daily, weekly or monthly")
+            .long_help(
+                "Which dates to fetch: daily (every date), weekly (every 7th date from \
+                the start) or monthly (the first of every month). The start and end \
+                dates are always fetched",
+            )
+            .possible_values(["daily", "weekly", "monthly"])
+            .takes_value(true)
+            .default_value("daily"),
+        Arg::new("forward-fill")
+            .long("forward-fill")
+            .help("Fill dates with no data using the most recent prior rate")
+            .long_help(
+                "Fill dates with no data by carrying forward the most recent prior \
+                rate (up to --max-fill-days). Filled prices are annotated with a \
+                comment naming the date they were carried forward from",
+            ),
+        Arg::new("max-fill-days")
+            .long("max-fill-days")
+            .value_name("DAYS")
+            .help("Maximum number of days a rate is carried forward by --forward-fill")
+            .takes_value(true)
+            .default_value("5"),
+        Arg::new("snapshot-time")
+            .long("snapshot-time")
+            .value_name("TIME")
+            .help("Time of day which defines the daily price, e.g. 16:00-05:00 or 00:00Z")
+            .long_help(
+                "Time of day (HH:MM and a UTC offset) which defines the daily price, e.g. \
+                16:00-05:00 for the New York close or 00:00Z. A rate published after this time \
+                counts towards the following date, and the latest rate published before it \
+                is used. Only for sources with intraday prices (finnhub), the others (such \
+                as OpenExchangeRates) only publish one rate per date",
+            )
+            .takes_value(true),
+        Arg::new("date-mismatch")
+            .long("date-mismatch")
+            .value_name("POLICY")
+            .help("Which date to use for a response dated differently to the requested date: requested, response or fail")
+            .long_help(
+                "Which date to use for a response the provider dated differently to the \
+                date it was requested for (a time zone quirk, or a bug in the provider's \
+                API): the requested date or the response's date, with a warning, or fail. \
+                Not used with --snapshot-time, which decides the dates itself",
+            )
+            .possible_values(["requested", "response", "fail"])
+            .default_value("requested")
+            .takes_value(true),
+        Arg::new("interpolate")
+            .long("interpolate")
+            .help("Fill the dates between fetched dates by linear interpolation")
+            .long_help(
+                "Fill the dates between fetched dates (e.g. when using --sample) by \
+                linearly interpolating the rates, without making any extra requests. \
+                Interpolated prices are annotated with a comment",
+            ),
+        Arg::new("summary").long("summary").help(
+            "Print a summary of the requests made (count, failures and latency) when finished",
+        ),
+    ]);
+    args.extend(commodity_args());
+    args
+}
+
+/// The command line interface.
+fn app() -> App<'static> {
+    App::new("beancount-price-fetcher")
         .version("0.1")
         .author("Luke Frisken <l.frisken@gmail.com>")
         .about("Fetches beancount price listings for commodities")
+        .args(global_args())
         .subcommand(
             App::new("usage")
                 .about("Prints your api usage stats")
-                .arg(app_id_arg.clone()),
+                .args(app_id_args()),
         )
         .subcommand(
             App::new("series")
                 .about("Fetches a series of beancount price listings for commodities")
-                .arg(app_id_arg.clone())
+                .args(fetch_args())
                 .arg(
-                    Arg::new("start-date")
-                        .long("start")
-                        .short('s')
-                        .value_name("DATE")
-                        .help("Start date in format YYYY-mm-dd, e.g. 2020-05-25")
+                    Arg::new("desc")
+                        .long("desc")
+                        .short('d')
+                        .help("Order the listings in descending order (by date)"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Output format: beancount, json (with the warnings) or influx")
+                        .long_help(
+                            "Output format: beancount price directives, json for the prices \
+                            along with the warnings raised while fetching and computing them \
+                            (which are otherwise printed to stderr at the end), or influx for \
+                            InfluxDB line protocol",
+                        )
+                        .possible_values(["beancount", "json", "influx"])
                         .takes_value(true)
-                        .required(true),
+                        .default_value("beancount"),
                 )
                 .arg(
-                    Arg::new("end-date")
-                        .long("end")
-                        .short('e')
-                        .value_name("DATE")
-                        .help("End date in format YYYY-mm-dd, e.g. 2020-05-25")
+                    Arg::new("outlier-threshold")
+                        .long("outlier-threshold")
+                        .value_name("PERCENT")
+                        .help("Warn about prices which move more than PERCENT from the previous day")
                         .takes_value(true)
-                        .required(true),
+                        .default_value("20"),
                 )
                 .arg(
-                    Arg::new("order-descending")
-                        .long("desc")
-                        .short('d')
-                        .help("Order the listings in descending order (by date)"),
+                    Arg::new("strict-outliers")
+                        .long("strict-outliers")
+                        .help("Fail instead of warning when a possible outlier is found"),
                 )
+                .arg(bounds_arg())
                 .arg(
-                    Arg::new("no-quota-check")
-                        .long("no-quota-check")
-                        .short('q')
-                        .help("Don't check the quota limits before performing the requests")
+                    Arg::new("check-roundtrip")
+                        .long("check-roundtrip")
+                        .help("Warn about pairs where the rounding makes the inverse rate asymmetric")
                         .long_help(
-                            "Don't check the quota limits before performing the requests \
-                            (makes the command faster by avoiding the extra request, but you may \
-                            exceed your quota)",
+                            "Check that each price, times its inverse (both rounded to \
+                            --rounding), is within --roundtrip-tolerance of 1, and warn \
+                            about the pairs where the rounding loses enough precision that \
+                            converting to the base commodity and back doesn't round trip",
                         ),
                 )
                 .arg(
-                    Arg::new("commodities")
-                        .long("commodities")
-                        .short('c')
-                        .value_name("COMMODITIES")
-                        .multiple(true)
-                        .help("Commodities to request exchange rates for (e.g AUD USD)")
+                    Arg::new("roundtrip-tolerance")
+                        .long("roundtrip-tolerance")
+                        .value_name("PERCENT")
+                        .help("How far from 1 a price times its inverse may be, for --check-roundtrip")
+                        .takes_value(true)
+                        .default_value("0.01"),
+                )
+                .arg(
+                    Arg::new("rolling-average")
+                        .long("rolling-average")
+                        .value_name("DAYS")
+                        .help("Smooth the prices with a rolling average over the trailing DAYS days")
+                        .long_help(
+                            "Smooth the prices with a rolling average over the trailing DAYS days \
+                            (at most 36500), applied before --aggregate",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("aggregate")
+                        .long("aggregate")
+                        .value_name("AGGREGATE")
+                        .help("Emit one price per period, aggregated from the daily prices")
+                        .long_help(
+                            "Emit one price per period, aggregated from the daily prices, dated \
+                            on the first date in the period (weeks start on Monday)",
+                        )
+                        .possible_values([
+                            "weekly-mean",
+                            "weekly-median",
+                            "monthly-mean",
+                            "monthly-median",
+                        ])
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("only-changes")
+                        .long("only-changes")
+                        .help("Only emit prices which changed from the previous price")
+                        .long_help(
+                            "Only emit prices which changed from the previous price (for the \
+                            same commodity), after any smoothing and aggregation. Beancount uses \
+                            the most recent earlier price, so lookups are unaffected",
+                        ),
+                )
+                .arg(
+                    Arg::new("business-days")
+                        .long("business-days")
+                        .help("Drop the prices for weekends")
+                        .long_help(
+                            "Drop the prices for Saturdays and Sundays (which many providers only \
+                            repeat from the Friday), keeping price files lean. Beancount uses the \
+                            most recent earlier price, so weekend lookups still find the Friday \
+                            price",
+                        ),
+                )
+                .arg(chart_arg())
+                .arg(rounding_arg()),
+        )
+        .subcommand(
+            App::new("latest")
+                .about("Fetches beancount price listings for the latest rates of commodities")
+                .args(app_id_args())
+                .args(source_args())
+                .args(commodity_args())
+                .arg(
+                    Arg::new("as-of")
+                        .long("as-of")
+                        .value_name("DATE")
+                        .help("Date to put on the price listings, in format YYYY-mm-dd, or relative, e.g. yesterday")
+                        .long_help(
+                            "Date to put on the price listings, in format YYYY-mm-dd, instead of \
+                            the (UTC) date of the provider's timestamp. Useful near midnight, or \
+                            when entering prices for an earlier date. Listings whose date differs \
+                            from the timestamp are annotated with it",
+                        )
+                        .allow_hyphen_values(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("watch")
+                        .long("watch")
+                        .help("Keep running, fetching the latest rates every --interval and emitting only the prices which changed"),
+                )
+                .arg(
+                    Arg::new("interval")
+                        .long("interval")
+                        .value_name("INTERVAL")
+                        .help("How often to fetch the latest rates with --watch, e.g. 90s, 10m or 1h")
+                        .takes_value(true)
+                        .default_value("1h"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .value_name("FILE")
+                        .help("Append the changed prices to FILE with --watch, instead of printing them")
+                        .requires("watch")
+                        .takes_value(true),
+                )
+                .arg(rounding_arg())
+                .arg(bounds_arg()),
+        )
+        .subcommand(
+            App::new("convert")
+                .about("Converts an amount from one commodity to another with the latest rates")
+                .long_about(
+                    "Converts an amount from one commodity to another with the latest rates, \
+                    using openexchangerates' convert.json api (for the plans which include it) \
+                    rather than fetching a table of rates, or the cross rate of the latest \
+                    prices of the other sources",
+                )
+                .args(app_id_args())
+                .args(source_args())
+                .arg(
+                    Arg::new("amount")
+                        .value_name("AMOUNT")
+                        .help("Amount to convert, e.g. 100")
                         .takes_value(true)
                         .required(true),
                 )
                 .arg(
-                    Arg::new("base")
-                        .long("base")
-                        .short('b')
+                    Arg::new("from")
+                        .long("from")
+                        .short('f')
                         .value_name("COMMODITY")
-                        .help(
-                            "Commodity to use as the reference/base in the beancount price listing",
-                        )
+                        .help("Commodity the amount is in, e.g. USD")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .short('t')
+                        .value_name("COMMODITY")
+                        .help("Commodity to convert the amount to, e.g. AUD")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(no_commodity_check_arg()),
+        )
+        .subcommand(
+            App::new("symbols")
+                .about("Prints the code of every currency the provider has rates for")
+                .long_about(
+                    "Prints the code of every currency the provider has rates for, one on each \
+                    line. The list of currencies is cached for a week in the --cache-dir",
+                )
+                .arg(alternative_arg()),
+        )
+        .subcommand(
+            App::new("search")
+                .about("Searches the provider's currencies by code or name")
+                .long_about(
+                    "Searches the provider's currencies by code or name, allowing for typos and \
+                    accents, e.g. krona finds SEK, ISK and NOK. The list of currencies is cached \
+                    for a week in the --cache-dir",
+                )
+                .arg(
+                    Arg::new("query")
+                        .value_name("QUERY")
+                        .help("Code or (part of a) name to search for, e.g. krona")
                         .takes_value(true)
                         .required(true),
                 )
                 .arg(
-                    Arg::new("parallel-requests")
-                        .long("parallel-requests")
-                        .short('p')
+                    Arg::new("limit")
+                        .long("limit")
+                        .short('n')
                         .value_name("N")
-                        .help("Number of parallel network requests to use (when possible)")
+                        .help("Show at most N currencies")
                         .takes_value(true)
-                        .default_value("2"),
+                        .default_value("10"),
+                ),
+        )
+        .subcommand(
+            App::new("commodities")
+                .about("Prints beancount commodity directives, with metadata from the provider")
+                .long_about(
+                    "Prints beancount commodity directives for commodities, with their name, the \
+                    number of decimal places amounts are usually given to, and whether they are \
+                    alternative currencies (cryptocurrencies, or unofficial rates). The metadata \
+                    is cached for a week in the --cache-dir",
                 )
                 .arg(
-                    Arg::new("rounding")
-                        .long("rounding")
-                        .short('r')
-                        .value_name("DP")
-                        .help("Number of decimal places to round to")
+                    Arg::new("commodities")
+                        .long("commodities")
+                        .short('c')
+                        .value_name("COMMODITIES")
+                        .multiple_occurrences(true)
+                        .multiple_values(true)
+                        .help("Commodities to print the directives for (e.g AUD USD)")
                         .takes_value(true),
+                )
+                .arg(
+                    Arg::new("date")
+                        .long("date")
+                        .short('d')
+                        .value_name("DATE")
+                        .help("Date to put on the directives, in format YYYY-mm-dd, or relative, e.g. -7d")
+                        .allow_hyphen_values(true)
+                        .takes_value(true)
+                        .default_value("1970-01-01"),
                 ),
-        );
-
-    let matches = app.get_matches();
-
-    if let Some(matches) = matches.subcommand_matches("usage") {
-        let app_id = matches
-            .value_of("app-id")
-            .expect("expected app-id to be specified")
-            .to_string();
-
-        let client = Client::new();
-
-        let usage = get_usage(&client, &app_id).await?;
-        println!("{}", serde_yaml::to_string(&usage)?);
-    }
-
-    // Series Command
-    if let Some(matches) = matches.subcommand_matches("series") {
-        let app_id = matches
-            .value_of("app-id")
-            .expect("expected app-id to be specified")
-            .to_string();
-        let parallel_requests: usize = matches
-            .value_of("parallel-requests")
-            .expect("expected parallel-requests to be specified")
-            .parse()
-            .map_err(|err| anyhow!("unable to parse parallel-requests argument: {}", err))?;
-        let start_date = NaiveDate::parse_from_str(
-            matches
-                .value_of("start-date")
-                .expect("expected  start-date to be specified"),
-            "%Y-%m-%d",
-        )
-        .map_err(|err| anyhow!("Unable to parse start-date: {}", err))?;
-        let end_date = NaiveDate::parse_from_str(
-            matches
-                .value_of("end-date")
-                .expect("expected start-date to be specified"),
-            "%Y-%m-%d",
-        )
-        .map_err(|err| anyhow!("Unable to parse end-date: {}", err))?;
-        let commodities: Vec<CommodityTypeID> = matches
-            .values_of("commodities")
-            .expect("expected commodities to be specified")
-            .map(|commodity_str| {
-                CommodityTypeID::from_str(commodity_str).expect("Unable to parse commodity id")
-            })
-            .collect();
-        let base_commodity = CommodityTypeID::from_str(
-            matches
-                .value_of("base")
-                .expect("expected base to be specified"),
         )
-        .map_err(|err| anyhow!("Unable to parse base commodity id: {}", err))?;
-
-        let mut request_commodities: HashSet<CommodityTypeID> = HashSet::new();
-
-        for commodity in &commodities {
-            request_commodities.insert(*commodity);
-        }
-
-        request_commodities.insert(base_commodity);
-
-        let no_quota_check: bool = matches.is_present("no-quota-check");
-
-        let client = Client::new();
-
-        if !no_quota_check {
-            let usage = get_usage(&client, &app_id).await?;
-
-            let dates_diff = end_date.signed_duration_since(start_date);
-            let expected_requests = dates_diff.num_days();
-            let requests_remaining = usage.data.usage.requests_remaining;
-
-            if expected_requests > requests_remaining as i64 {
-                return Err(anyhow!(
-                    "The expected number of requests ({}) for this command \
-                will exceed your remaining quota ({})",
-                    expected_requests,
-                    requests_remaining
-                ));
-            }
-        }
-
-        let series = get_time_series_with_historical(
-            &client,
-            &app_id,
-            parallel_requests,
-            &start_date,
-            &end_date,
-            Some(request_commodities.into_iter().collect()),
+        .subcommand(
+            App::new("compare")
+                .about("Compares the change in prices over a period with a previous period (e.g. this year vs last year)")
+                .args(fetch_args())
+                .arg(
+                    Arg::new("previous-start")
+                        .long("previous-start")
+                        .value_name("DATE")
+                        .help("Start date of the period to compare against, in format YYYY-mm-dd, or relative, e.g. -2w")
+                        .allow_hyphen_values(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("previous-end")
+                        .long("previous-end")
+                        .value_name("DATE")
+                        .help("End date of the period to compare against, in format YYYY-mm-dd, or relative, e.g. -2w")
+                        .allow_hyphen_values(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Output format")
+                        .possible_values(["table", "csv"])
+                        .takes_value(true)
+                        .default_value("table"),
+                )
+                .arg(rounding_arg()),
         )
-        .await?;
-
-        for commodity in &commodities {
-            let keys = series.map.keys();
-
-            let keys: Box<dyn Iterator<Item = &NaiveDate>> =
-                if matches.is_present("order-descending") {
-                    Box::new(keys.rev())
-                } else {
-                    Box::new(keys.into_iter())
-                };
-
-            for key in keys {
-                let exchange_rate = series
-                    .map
-                    .get(key)
-                    .ok_or_else(|| format!("Exchange rate for date {} not present in the map", key))
-                    .unwrap();
-                let mut rate_between = exchange_rate
-                    .rate_between(commodity, &base_commodity)
-                    .map_err(|err| {
-                        anyhow!(
-                            "Unable to calculate the exchange rate between {} and {} because: {}",
-                            commodity,
-                            base_commodity,
-                            err
-                        )
-                    })?
-                    .expect("unable to calculate the exchange rate between commodities");
-
-                if let Some(rounding) = matches.value_of("rounding") {
-                    let dp: u32 = rounding
-                        .parse()
-                        .map_err(|err| anyhow!("Unable to parse rounding: {}", err))?;
-                    rate_between = rate_between.round_dp(dp);
-                }
-
-                println!(
-                    "{date} price {commodity} {rate} {base}",
-                    date = exchange_rate.date.unwrap().format("%Y-%m-%d"),
-                    commodity = commodity,
-                    rate = rate_between,
-                    base = base_commodity,
+        .subcommand(
+            App::new("store-credential")
+                .about("Stores a provider credential (read from stdin) in the OS keychain")
+                .long_about(
+                    "Stores a provider credential (read from stdin) in the OS keychain, so it can \
+                    be given as keyring: (or keyring:ENTRY) instead of in plain text. Requires \
+                    a build with the keyring feature",
                 )
-            }
-        }
-    }
-
-    Ok(())
+                .arg(
+                    Arg::new("provider")
+                        .long("provider")
+                        .value_name("PROVIDER")
+                        .help("Provider the credential is for")
+                        .possible_values([
+                            openexchangerate::PROVIDER_NAME,
+                            alphavantage::PROVIDER_NAME,
+                            coingecko::PROVIDER_NAME,
+                            coinmarketcap::PROVIDER_NAME,
+                            exchangeratehost::PROVIDER_NAME,
+                            iex::PROVIDER_NAME,
+                            finnhub::PROVIDER_NAME,
+                            tiingo::PROVIDER_NAME,
+                            polygon::PROVIDER_NAME,
+                            nasdaqdatalink::PROVIDER_NAME,
+                            metalsapi::PROVIDER_NAME,
+                            oanda::PROVIDER_NAME,
+                            marketstack::PROVIDER_NAME,
+                            fmp::PROVIDER_NAME,
+                            json::PROVIDER_NAME,
+                        ])
+                        .takes_value(true)
+                        .default_value(openexchangerate::PROVIDER_NAME),
+                )
+                .arg(
+                    Arg::new("entry")
+                        .long("entry")
+                        .value_name("ENTRY")
+                        .help("Name of the keychain entry, if not the provider name")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("config")
+                .about("Manages the config file")
+                .subcommand(
+                    App::new("check")
+                        .about("Checks the config for problems, before a scheduled run fails because of them"),
+                )
+                .subcommand(
+                    App::new("init")
+                        .about("Writes a commented example config, prompting for the App ID")
+                        .arg(
+                            Arg::new("path")
+                                .long("path")
+                                .value_name("FILE")
+                                .help("Where to write the config, instead of --config or the platform config directory")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("force")
+                                .long("force")
+                                .help("Overwrite the config if it already exists"),
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("run")
+                .about("Runs the jobs defined in the config, appending new prices to their output files")
+                .args(app_id_args())
+                .arg(wait_arg())
+                .arg(
+                    Arg::new("jobs")
+                        .value_name("JOB")
+                        .help("Names of the jobs to run, all of them if none are given")
+                        .multiple_values(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("cache")
+                .about("Queries the cache of fetched exchange rates")
+                .subcommand(
+                    App::new("query")
+                        .about("Prints (as JSON) the price of a commodity from the cached rates nearest to a date")
+                        .long_about(
+                            "Prints (as JSON) the price of a commodity from the cached rates nearest \
+                            to a date, within --tolerance days either side (the earlier date when two \
+                            are equally near). Nothing is fetched, it fails if there is no such price",
+                        )
+                        .arg(
+                            Arg::new("commodity")
+                                .long("commodity")
+                                .short('c')
+                                .value_name("COMMODITY")
+                                .help("Commodity to price (e.g. NZD)")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("base")
+                                .long("base")
+                                .short('b')
+                                .value_name("COMMODITY")
+                                .help("Commodity to price it in (e.g. AUD)")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("pivot")
+                                .long("pivot")
+                                .value_name("COMMODITY")
+                                .help("Commodity to triangulate the price through (e.g. USD)")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("date")
+                                .long("date")
+                                .short('d')
+                                .value_name("DATE")
+                                .help("Date in format YYYY-mm-dd, or relative, e.g. -7d")
+                                .allow_hyphen_values(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("tolerance")
+                                .long("tolerance")
+                                .value_name("DAYS")
+                                .help("How many days from the date the cached rates may be")
+                                .takes_value(true)
+                                .default_value(DEFAULT_CACHE_TOLERANCE),
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("daemon")
+                .about("Keeps running, running the jobs defined in the config on a schedule")
+                .long_about(
+                    "Keeps running, running the jobs defined in the config (or the JOBs given) \
+                    whenever the cron schedule fires, appending new prices to their output \
+                    files. The config is re-read before each run. A failed run is reported and \
+                    retried at the next scheduled time",
+                )
+                .args(app_id_args())
+                .arg(wait_arg())
+                .arg(
+                    Arg::new("schedule")
+                        .long("schedule")
+                        .value_name("CRON")
+                        .help("When to run the jobs, as a cron expression in local time, e.g. \"0 18 * * mon-fri\"")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("metrics-listen")
+                        .long("metrics-listen")
+                        .value_name("ADDRESS")
+                        .help("Serve Prometheus metrics on ADDRESS (e.g. 127.0.0.1:9090) at /metrics")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("jobs")
+                        .value_name("JOB")
+                        .help("Names of the jobs to run, all of them if none are given")
+                        .multiple_values(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("serve")
+                .about("Serves prices over a small HTTP API, for other local tools")
+                .long_about(
+                    "Serves prices over a small HTTP API, backed by the cache and the provider: \
+                    GET /prices?commodity=AUD&base=USD&date=2020-01-01 (the latest rates if \
+                    there is no date) and GET /series?commodities=AUD,NZD&base=USD&start=...&end=... \
+                    Other query parameters are series arguments, e.g. rounding=4. Responses are JSON. \
+                    Prometheus metrics are served at GET /metrics",
+                )
+                .args(app_id_args())
+                .arg(
+                    Arg::new("listen")
+                        .long("listen")
+                        .value_name("ADDRESS")
+                        .help("Address to listen on")
+                        .takes_value(true)
+                        .default_value("127.0.0.1:8080"),
+                ),
+        )
+        .subcommand(
+            App::new("stats")
+                .about("Prints statistics for a series of prices, for sanity checking before committing them")
+                .args(fetch_args())
+                .arg(chart_arg())
+                .arg(rounding_arg()),
+        )
+        .subcommand(
+            App::new("value")
+                .about("Prints the value of holdings in the base commodity, on a date or over a range")
+                .long_about(
+                    "Prints the value of holdings in the base commodity, read from the postings \
+                    in a beancount journal (--journal) or a CSV of commodity/quantity pairs \
+                    (--holdings). For a single date (--start without --end) each holding is \
+                    listed, for a range the value of each on every sampled date. --commodities \
+                    only values those commodities",
+                )
+                .args(fetch_args())
+                .arg(
+                    Arg::new("journal")
+                        .long("journal")
+                        .value_name("PATH")
+                        .help("Beancount journal to read the holdings from")
+                        .takes_value(true)
+                        .conflicts_with("holdings"),
+                )
+                .arg(
+                    Arg::new("holdings")
+                        .long("holdings")
+                        .value_name("PATH")
+                        .help("CSV of the holdings, a commodity and a quantity on each line")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("account")
+                        .long("account")
+                        .value_name("ACCOUNT")
+                        .help("Accounts (and their sub-accounts) of the journal whose postings are held")
+                        .multiple_occurrences(true)
+                        .multiple_values(true)
+                        .takes_value(true)
+                        .default_values(holdings::DEFAULT_ACCOUNTS),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Output format")
+                        .possible_values(["table", "csv"])
+                        .takes_value(true)
+                        .default_value("table"),
+                )
+                .arg(rounding_arg()),
+        )
+        .subcommand(
+            App::new("export")
+                .about("Exports a series of prices into an SQLite or DuckDB database table")
+                .long_about(
+                    "Exports a series of prices into an SQLite or DuckDB database table (see the \
+                    README for its schema), creating it if it doesn't exist and replacing any \
+                    prices already there for the same date and pair. Runs the sqlite3 or duckdb \
+                    shell, which must be on the PATH",
+                )
+                .args(fetch_args())
+                .arg(
+                    Arg::new("database")
+                        .long("database")
+                        .value_name("PATH")
+                        .help("Database file to export into, or - to print the SQL instead")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("engine")
+                        .long("engine")
+                        .value_name("ENGINE")
+                        .help("Kind of database")
+                        .possible_values(["sqlite", "duckdb"])
+                        .takes_value(true)
+                        .default_value("sqlite"),
+                )
+                .arg(
+                    Arg::new("table")
+                        .long("table")
+                        .value_name("TABLE")
+                        .help("Table to export into")
+                        .takes_value(true)
+                        .default_value("prices"),
+                )
+                .arg(bounds_arg())
+                .arg(rounding_arg()),
+        )
+        .subcommand(
+            App::new("tui")
+                .about("Browses the cached rates in the terminal")
+                .long_about(
+                    "Browses the cached rates in the terminal: pick a pair, scroll through its \
+                    dates and prices with a chart, and mark ranges to re-fetch (replacing the \
+                    cached rates) or export (printed as price directives on stdout) once it is \
+                    closed. Requires a cache (see --cache-dir), and a build with the tui feature",
+                )
+                .args(app_id_args())
+                .arg(
+                    Arg::new("base")
+                        .long("base")
+                        .short('b')
+                        .value_name("COMMODITY")
+                        .help("Base commodity to price the pairs in")
+                        .takes_value(true)
+                        .default_value("USD"),
+                )
+                .arg(
+                    Arg::new("yes")
+                        .long("yes")
+                        .short('y')
+                        .help("Don't ask for confirmation before re-fetching a large number of dates"),
+                )
+                .arg(rounding_arg()),
+        )
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    let matches = app().get_matches();
+
+    // Reported together once the command is done, see --format json for
+    // them along with the prices.
+    WARNINGS.defer(true);
+    let result = command(&matches).await;
+    WARNINGS.print();
+
+    if let Err(error) = &result {
+        if matches.subcommand_matches("run").is_some() && error.is::<Locked>() {
+            eprintln!("Error: {}", error);
+            std::process::exit(lock::EXIT_LOCKED);
+        }
+    }
+    result
+}
+
+async fn command(matches: &ArgMatches) -> anyhow::Result<()> {
+    if let Some(matches) = matches.subcommand_matches("usage") {
+        let app_id = app_id(&Settings::load(matches)?)?;
+
+        let client = Client::new();
+
+        let usage = OpenExchangeRates::new(&client, &app_id).usage().await?;
+        println!("{}", serde_yaml::to_string(&usage)?);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("series") {
+        series_command(&Settings::load(matches)?, &mut std::io::stdout()).await?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("latest") {
+        latest_command(&Settings::load(matches)?).await?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("convert") {
+        convert_command(&Settings::load(matches)?).await?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("symbols") {
+        symbols_command(&Settings::load(matches)?).await?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("search") {
+        search_command(&Settings::load(matches)?).await?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("commodities") {
+        commodities_command(&Settings::load(matches)?).await?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("compare") {
+        compare_command(&Settings::load(matches)?).await?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("value") {
+        value_command(&Settings::load(matches)?).await?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("store-credential") {
+        let provider = matches
+            .value_of("provider")
+            .expect("expected provider to be specified");
+        let mut secret = String::new();
+        std::io::stdin()
+            .read_line(&mut secret)
+            .map_err(|err| anyhow!("Unable to read the credential from stdin: {}", err))?;
+        let secret = secret.trim();
+        if secret.is_empty() {
+            return Err(anyhow!("No credential was given on stdin"));
+        }
+        credentials::store(matches.value_of("entry").unwrap_or(""), provider, secret)?;
+        eprintln!("Stored the {} credential in the keychain", provider);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("config") {
+        if let Some(matches) = matches.subcommand_matches("init") {
+            config_init_command(matches)?;
+        }
+        if let Some(matches) = matches.subcommand_matches("check") {
+            config_check_command(matches)?;
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("run") {
+        run_command(matches).await?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("cache") {
+        if let Some(matches) = matches.subcommand_matches("query") {
+            cache_query_command(&Settings::load(matches)?)?;
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("daemon") {
+        daemon_command(matches).await?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("serve") {
+        serve_command(matches).await?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("stats") {
+        stats_command(&Settings::load(matches)?).await?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("export") {
+        export_command(&Settings::load(matches)?).await?;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("tui") {
+        tui_command(&Settings::load(matches)?).await?;
+    }
+
+    Ok(())
+}
+
+fn config_check_command(matches: &ArgMatches) -> anyhow::Result<()> {
+    let path = match config_path(matches) {
+        Some(path) => path,
+        None if matches.is_present("no-config") => {
+            return Err(anyhow!("There is no config to check with --no-config"))
+        }
+        None => {
+            return Err(anyhow!(
+                "No config was found, looked in: {}",
+                config::search_paths()
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ))
+        }
+    };
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|err| anyhow!("Unable to read config file {:?}: {}", path, err))?;
+
+    let app = app();
+    let mut arguments: Vec<&str> = app
+        .get_arguments()
+        .chain(
+            app.get_subcommands()
+                .flat_map(|subcommand| subcommand.get_arguments()),
+        )
+        .filter_map(|arg| arg.get_long())
+        .collect();
+    arguments.sort_unstable();
+    arguments.dedup();
+
+    let problems = config::check(&contents, &arguments);
+    for problem in &problems {
+        eprintln!("{}:{}", path.display(), problem);
+    }
+
+    let errors = problems
+        .iter()
+        .filter(|problem| problem.severity == config::Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(anyhow!(
+            "Found {} error(s) in the config {:?}",
+            errors,
+            path
+        ));
+    }
+    eprintln!(
+        "The config {:?} is OK ({} warning(s))",
+        path,
+        problems.len()
+    );
+    Ok(())
+}
+
+fn config_init_command(matches: &ArgMatches) -> anyhow::Result<()> {
+    let path = match matches
+        .value_of("path")
+        .or_else(|| matches.value_of("config"))
+    {
+        Some(path) => PathBuf::from(path),
+        None => config::default_path()
+            .ok_or_else(|| anyhow!("Unable to find the config directory, use --path"))?,
+    };
+    if path.exists() && !matches.is_present("force") {
+        return Err(anyhow!(
+            "The config {:?} already exists, use --force to overwrite it",
+            path
+        ));
+    }
+
+    eprint!("OpenExchangeRates App ID (or keyring:, leave empty to fill in later): ");
+    std::io::stderr().flush()?;
+    let mut app_id = String::new();
+    std::io::stdin()
+        .read_line(&mut app_id)
+        .map_err(|err| anyhow!("Unable to read the App ID from stdin: {}", err))?;
+    let app_id = app_id.trim();
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|err| anyhow!("Unable to create config directory {:?}: {}", dir, err))?;
+    }
+    let contents = config::example(if app_id.is_empty() {
+        None
+    } else {
+        Some(app_id)
+    });
+    std::fs::write(&path, contents)
+        .map_err(|err| anyhow!("Unable to write config file {:?}: {}", path, err))?;
+    // The config may contain the App ID, so only the owner may read it.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+            .map_err(|err| anyhow!("Unable to set permissions of {:?}: {}", path, err))?;
+    }
+    eprintln!("Wrote the config to {:?}", path);
+    Ok(())
+}
+
+/// The App ID given with the [app_id_args()], or failing that in the
+/// config, read from a file or the keychain if necessary.
+fn app_id(settings: &Settings<'_>) -> anyhow::Result<AppID> {
+    if let Some(path) = settings.value_of("app-id-file") {
+        return credentials::read_file(path);
+    }
+
+    let provider_config = settings.config.provider(openexchangerate::PROVIDER_NAME);
+    let app_id = settings
+        .value_of("app-id")
+        .or_else(|| provider_config.credential())
+        .ok_or_else(|| {
+            anyhow!(
+                "No App ID was given, use --app-id, --app-id-file, the {} environment variable, \
+                or app_id in the [providers.{}] section of the config",
+                openexchangerate::APP_ID_ENV,
+                openexchangerate::PROVIDER_NAME,
+            )
+        })?;
+    let age_identity = credentials::age_identity(settings.config.age_identity.as_deref());
+    credentials::resolve(
+        app_id,
+        openexchangerate::PROVIDER_NAME,
+        age_identity.as_deref(),
+    )
+}
+
+/// The API key for `source`, from `--api-key`, the source's section of
+/// the config or its environment variable, for sources which take one.
+/// Fails if there isn't one and the source requires it.
+fn api_key(settings: &Settings<'_>, source: Source) -> anyhow::Result<Option<String>> {
+    let env = match source.api_key_env() {
+        Some(env) => env,
+        None => return Ok(None),
+    };
+    let provider_config = settings.config.provider(source.name());
+    let from_env = std::env::var(env).ok();
+    let api_key = match settings
+        .value_of("api-key")
+        .or_else(|| provider_config.credential())
+        .or(from_env.as_deref())
+    {
+        Some(api_key) => api_key,
+        None if !source.api_key_required() => return Ok(None),
+        None => {
+            return Err(anyhow!(
+                "No API key was given for {}, use --api-key, the {} environment variable, \
+                or key in the [providers.{}] section of the config",
+                source.name(),
+                env,
+                source.name(),
+            ))
+        }
+    };
+    let age_identity = credentials::age_identity(settings.config.age_identity.as_deref());
+    credentials::resolve(api_key, source.name(), age_identity.as_deref()).map(Some)
+}
+
+/// The config file chosen with the [global_args()]: the one given with
+/// `--config`, otherwise the first found by [config::discover()] unless
+/// `--no-config` is given.
+fn config_path(matches: &ArgMatches) -> Option<PathBuf> {
+    match matches.value_of("config") {
+        Some(path) => Some(PathBuf::from(path)),
+        None if matches.is_present("no-config") => None,
+        None => config::discover(),
+    }
+}
+
+/// Prefix of the environment variables which set arguments, e.g.
+/// `BEANCOUNT_PRICE_FETCHER_BASE` for `--base`.
+const ENV_PREFIX: &str = "BEANCOUNT_PRICE_FETCHER_";
+
+/// The environment variable which sets the argument `id`.
+fn env_var(id: &str) -> String {
+    format!("{}{}", ENV_PREFIX, id.to_uppercase().replace('-', "_"))
+}
+
+/// The ids of the arguments of `app` (and its subcommands) which take
+/// several values, the only ones whose environment variables are split
+/// into values, so that e.g. a path with a space in it is left whole.
+fn list_args(app: &App<'_>) -> HashSet<String> {
+    let mut ids: HashSet<String> = app
+        .get_arguments()
+        .filter(|arg| arg.is_set(ArgSettings::MultipleValues))
+        .map(|arg| arg.get_name().to_string())
+        .collect();
+    for subcommand in app.get_subcommands() {
+        ids.extend(list_args(subcommand));
+    }
+    ids
+}
+
+/// The arguments given to a subcommand, layered over the environment and
+/// the config. Each argument is taken from the first of these which has
+/// it:
+///
+/// 1. The command line.
+/// 2. The environment variable for the argument (see [env_var()]), with
+///    the values of those taking several (see [list_args()]) separated
+///    by commas or spaces, and flags set by
+///    `true`/`1` (or unset with `false`/`0`).
+/// 3. The `[defaults]` section of the config (see [Config::defaults]).
+/// 4. The built-in default.
+struct Settings<'a> {
+    matches: &'a ArgMatches,
+    config: Config,
+    /// Arguments set by environment variables.
+    env: BTreeMap<String, Vec<String>>,
+    /// Arguments set in the config.
+    defaults: BTreeMap<String, Vec<String>>,
+}
+
+impl<'a> Settings<'a> {
+    /// Load the config file chosen with the [global_args()] (if any) for
+    /// the subcommand `matches`.
+    fn load(matches: &'a ArgMatches) -> anyhow::Result<Settings<'a>> {
+        let config = match config_path(matches) {
+            Some(path) => {
+                log::debug!("using config {:?}", path);
+                Config::from_file(path)?
+            }
+            None => Config::default(),
+        };
+
+        let mut defaults = BTreeMap::new();
+        for (name, value) in &config.defaults {
+            let values = match value {
+                toml::Value::Array(values) => values.iter().map(default_value).collect(),
+                value => default_value(value).map(|value| vec![value]),
+            }
+            .map_err(|err| anyhow!("Unable to use the default for {:?}: {}", name, err))?;
+            defaults.insert(name.replace('_', "-"), values);
+        }
+
+        let lists = list_args(&app());
+        let env = std::env::vars()
+            .filter_map(|(name, value)| {
+                let id = name
+                    .strip_prefix(ENV_PREFIX)?
+                    .to_lowercase()
+                    .replace('_', "-");
+                let values = if lists.contains(&id) {
+                    value
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|value| !value.is_empty())
+                        .map(String::from)
+                        .collect()
+                } else {
+                    vec![value]
+                };
+                Some((id, values))
+            })
+            .collect();
+
+        Ok(Settings {
+            matches,
+            config,
+            env,
+            defaults,
+        })
+    }
+
+    /// Whether the argument given on the command line, rather than
+    /// filled in by clap.
+    fn given(&self, id: &str) -> bool {
+        self.matches.occurrences_of(id) > 0
+    }
+
+    /// The values for the argument `id` from the highest layer below the
+    /// command line which has it.
+    fn layered(&self, id: &str) -> Option<&Vec<String>> {
+        if self.given(id) {
+            return None;
+        }
+        self.env.get(id).or_else(|| self.defaults.get(id))
+    }
+
+    fn value_of(&self, id: &str) -> Option<&str> {
+        match self.layered(id) {
+            Some(values) => values.first().map(String::as_str),
+            None => self.matches.value_of(id),
+        }
+    }
+
+    fn values_of(&self, id: &str) -> Option<Vec<&str>> {
+        match self.layered(id) {
+            Some(values) => Some(values.iter().map(String::as_str).collect()),
+            None => self.matches.values_of(id).map(|values| values.collect()),
+        }
+    }
+
+    fn is_present(&self, id: &str) -> bool {
+        match self.layered(id) {
+            Some(values) => values
+                .iter()
+                .any(|value| matches!(value.as_str(), "true" | "1")),
+            None => self.matches.is_present(id),
+        }
+    }
+
+    /// The value of an argument which must be given, on the command line,
+    /// in the environment or in the config.
+    fn required(&self, id: &str) -> anyhow::Result<&str> {
+        self.value_of(id).ok_or_else(|| missing_argument(id))
+    }
+
+    /// The values of an argument which must be given, on the command
+    /// line, in the environment or in the config.
+    fn required_values(&self, id: &str) -> anyhow::Result<Vec<&str>> {
+        self.values_of(id)
+            .filter(|values| !values.is_empty())
+            .ok_or_else(|| missing_argument(id))
+    }
+}
+
+fn missing_argument(id: &str) -> anyhow::Error {
+    anyhow!(
+        "--{} must be given, either on the command line, with the {} environment variable, \
+        or as {} in the [defaults] section of the config",
+        id,
+        env_var(id),
+        id.replace('-', "_")
+    )
+}
+
+/// A config default as an argument value.
+fn default_value(value: &toml::Value) -> anyhow::Result<String> {
+    match value {
+        toml::Value::String(value) => Ok(value.clone()),
+        toml::Value::Integer(value) => Ok(value.to_string()),
+        toml::Value::Float(value) => Ok(value.to_string()),
+        toml::Value::Boolean(value) => Ok(value.to_string()),
+        toml::Value::Datetime(value) => Ok(value.to_string()),
+        _ => Err(anyhow!(
+            "expected a string, number, boolean, date or array of those"
+        )),
+    }
+}
+
+/// Parse `value`, given for the argument `id`, as a commodity code.
+fn parse_commodity(id: &str, value: &str) -> anyhow::Result<CommodityTypeID> {
+    let valid = !value.is_empty()
+        && value.len() <= COMMODITY_TYPE_ID_LENGTH
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-'".contains(c));
+    if !valid {
+        return Err(anyhow!(
+            "Invalid commodity {:?} for --{}, expected a code of 1 to {} letters or digits, e.g. AUD",
+            value,
+            id,
+            COMMODITY_TYPE_ID_LENGTH
+        ));
+    }
+    CommodityTypeID::from_str(value)
+        .map_err(|err| anyhow!("Invalid commodity {:?} for --{}: {}", value, id, err))
+}
+
+/// The commodities chosen with the [commodity_args()].
+struct CommoditySelection {
+    commodities: Vec<CommodityTypeID>,
+    base: CommodityTypeID,
+    pivot: Option<CommodityTypeID>,
+}
+
+impl CommoditySelection {
+    fn from_settings(settings: &Settings<'_>) -> anyhow::Result<CommoditySelection> {
+        CommoditySelection::with_commodities(settings, settings.required_values("commodities")?)
+    }
+
+    /// The selection for `commodities`, rather than those given by
+    /// `--commodities`, with the `--base` and `--pivot` from `settings`.
+    fn with_commodities(
+        settings: &Settings<'_>,
+        requested: Vec<&str>,
+    ) -> anyhow::Result<CommoditySelection> {
+        let base_commodity = parse_commodity("base", settings.required("base")?)?;
+        let mut commodities: Vec<CommodityTypeID> = Vec::new();
+        for commodity in requested {
+            let commodity = parse_commodity("commodities", commodity)?;
+            // Listed twice, its prices are only wanted once.
+            if commodities.contains(&commodity) {
+                continue;
+            }
+            if commodity == base_commodity && !settings.is_present("include-base") {
+                warn(
+                    Kind::Commodity,
+                    format!(
+                        "{} is the base commodity, leaving out its price (always 1), \
+                    see --include-base",
+                        commodity
+                    ),
+                );
+                continue;
+            }
+            commodities.push(commodity);
+        }
+        if commodities.is_empty() {
+            return Err(anyhow!(
+                "There are no commodities to fetch other than the base commodity {}",
+                base_commodity
+            ));
+        }
+        let pivot = match settings.value_of("pivot") {
+            Some(pivot) => Some(parse_commodity("pivot", pivot)?),
+            None => settings.config.pivot,
+        };
+
+        Ok(CommoditySelection {
+            commodities,
+            base: base_commodity,
+            pivot,
+        })
+    }
+
+    /// Every commodity which needs to be in the exchange rate tables.
+    fn request_commodities(&self) -> Vec<CommodityTypeID> {
+        let mut request_commodities: HashSet<CommodityTypeID> = HashSet::new();
+
+        for commodity in &self.commodities {
+            request_commodities.insert(*commodity);
+        }
+
+        request_commodities.insert(self.base);
+        if let Some(pivot) = self.pivot {
+            request_commodities.insert(pivot);
+        }
+
+        request_commodities.into_iter().collect()
+    }
+
+    /// The commodities `source` is asked for: the
+    /// [CommoditySelection::request_commodities()], less the pivot for
+    /// the sources which quote the prices of a commodity at a time (see
+    /// [Source::quotes_prices()]), which there is no table to cross
+    /// through.
+    fn include(&self, source: Source) -> Vec<CommodityTypeID> {
+        let mut include = self.request_commodities();
+        if source.quotes_prices() {
+            include.retain(|commodity| {
+                self.pivot != Some(*commodity) || self.commodities.contains(commodity)
+            });
+        }
+        include
+    }
+}
+
+/// A series fetched according to the [fetch_args()].
+struct Fetched {
+    series: TimeSeries,
+    commodities: Vec<CommodityTypeID>,
+    base: CommodityTypeID,
+    /// Commodity to triangulate prices through.
+    pivot: Option<CommodityTypeID>,
+    /// The last date fetched, which may be before the requested end
+    /// date when the request budget ran out.
+    end_date: NaiveDate,
+    requested_end_date: NaiveDate,
+    max_requests: Option<u32>,
+}
+
+/// Parse the date argument `name`, see [parse_date_value()].
+fn parse_date(settings: &Settings<'_>, name: &str) -> anyhow::Result<NaiveDate> {
+    parse_date_value(settings.required(name)?)
+        .map_err(|err| anyhow!("Unable to parse {}: {}", name, err))
+}
+
+/// Parse a date in format YYYY-mm-dd, or relative to today's (UTC) date:
+/// `today`, `yesterday`, or a number of days or weeks ago such as `-7d` or
+/// `-2w`.
+fn parse_date_value(date: &str) -> anyhow::Result<NaiveDate> {
+    let today = Utc::now().date_naive();
+    match date {
+        "today" => return Ok(today),
+        "yesterday" => return Ok(today - Duration::days(1)),
+        _ => {}
+    }
+
+    if let Some(relative) = date.strip_prefix('-') {
+        let (count, days) = match (relative.strip_suffix('d'), relative.strip_suffix('w')) {
+            (Some(count), _) => (count, 1),
+            (_, Some(count)) => (count, 7),
+            _ => return Err(anyhow!("expected a relative date such as -7d or -2w")),
+        };
+        let count: u64 = count
+            .parse()
+            .map_err(|err| anyhow!("invalid relative date {:?}: {}", date, err))?;
+        return count
+            .checked_mul(days)
+            .and_then(|days| today.checked_sub_days(Days::new(days)))
+            .ok_or_else(|| anyhow!("the relative date {:?} is too far back", date));
+    }
+
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|err| anyhow!("{}", err))
+}
+
+/// Fetch the series described by the [fetch_args()] in `matches`.
+async fn fetch(settings: &Settings<'_>) -> anyhow::Result<Fetched> {
+    let start_date = parse_date(settings, "start")?;
+    let end_date = parse_date(settings, "end")?;
+    fetch_range(settings, start_date, end_date).await
+}
+
+/// Fetch the series described by the [fetch_args()] in `matches`, but
+/// for the dates from `start_date` to `end_date` rather than those given
+/// by `--start` and `--end`.
+async fn fetch_range(
+    settings: &Settings<'_>,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> anyhow::Result<Fetched> {
+    let selection = CommoditySelection::from_settings(settings)?;
+    fetch_selection(settings, selection, start_date, end_date).await
+}
+
+/// [fetch_range()] for the commodities in `selection` rather than those
+/// given by `--commodities` and `--base`. The commodities with
+/// `fallbacks` in the config are each fetched from the first of their
+/// sources which has their prices (see [fetch_fallbacks()]), and the rest
+/// from the `--source`. Every fetch counts towards the one request budget
+/// (see [Budget]).
+async fn fetch_selection(
+    settings: &Settings<'_>,
+    selection: CommoditySelection,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> anyhow::Result<Fetched> {
+    let mut budget = Budget::default();
+    let (chained, rest): (Vec<CommodityTypeID>, Vec<CommodityTypeID>) =
+        selection.commodities.iter().partition(|commodity| {
+            settings
+                .config
+                .fallbacks
+                .contains_key(&commodity.to_string())
+        });
+    if chained.is_empty() {
+        return fetch_from(
+            settings,
+            source(settings)?,
+            selection,
+            start_date,
+            end_date,
+            &mut budget,
+        )
+        .await;
+    }
+
+    // The sources' series may be quoted against different commodities,
+    // so each commodity's rates are quoted against the base before they
+    // are put together.
+    let mut fetched = Fetched {
+        series: TimeSeries::default(),
+        commodities: selection.commodities.clone(),
+        base: selection.base,
+        pivot: selection.pivot,
+        end_date,
+        requested_end_date: end_date,
+        max_requests: None,
+    };
+    let mut parts = Vec::new();
+    if !rest.is_empty() {
+        let part = CommoditySelection {
+            commodities: rest.clone(),
+            base: selection.base,
+            pivot: selection.pivot,
+        };
+        let rest_fetched = fetch_from(
+            settings,
+            source(settings)?,
+            part,
+            start_date,
+            end_date,
+            &mut budget,
+        )
+        .await?;
+        let mut commodities = rest;
+        commodities.extend(selection.pivot);
+        parts.push((commodities, rest_fetched));
+    }
+    parts.extend(
+        fetch_fallbacks(
+            settings,
+            chained,
+            &selection,
+            start_date,
+            end_date,
+            &mut budget,
+        )
+        .await?,
+    );
+    for (commodities, part) in parts {
+        for commodity in &commodities {
+            fetched.series.merge(
+                part.series.rebased(commodity, &selection.base),
+                MergePolicy::ErrorOnMismatch,
+            )?;
+        }
+        fetched.end_date = std::cmp::min(fetched.end_date, part.end_date);
+        fetched.max_requests = fetched.max_requests.or(part.max_requests);
+    }
+    Ok(fetched)
+}
+
+/// Fetch the `chained` commodities (in the `base` of `selection`) from
+/// each of their `fallbacks` in the config in turn, until one of them has
+/// their prices, warning about those which failed or had none. This goes
+/// a round at a time, each fetching the commodities still without prices
+/// from the next source in their chains, once for each of those sources.
+/// Returns each fetch with the commodities it has the prices of.
+async fn fetch_fallbacks(
+    settings: &Settings<'_>,
+    chained: Vec<CommodityTypeID>,
+    selection: &CommoditySelection,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    budget: &mut Budget,
+) -> anyhow::Result<Vec<(Vec<CommodityTypeID>, Fetched)>> {
+    let chain = |commodity: &CommodityTypeID| &settings.config.fallbacks[&commodity.to_string()];
+    // Each commodity still without prices, with the position of the next
+    // source to try in its chain, and why those before it were passed by.
+    let mut pending: Vec<(CommodityTypeID, usize, Vec<String>)> = chained
+        .into_iter()
+        .map(|commodity| (commodity, 0, Vec::new()))
+        .collect();
+    let mut parts = Vec::new();
+    while !pending.is_empty() {
+        let mut groups: BTreeMap<&str, Vec<CommodityTypeID>> = BTreeMap::new();
+        for (commodity, position, _) in &pending {
+            groups
+                .entry(chain(commodity)[*position].as_str())
+                .or_default()
+                .push(*commodity);
+        }
+
+        let mut outcomes: HashMap<CommodityTypeID, Result<(), String>> = HashMap::new();
+        for (name, commodities) in groups {
+            let part = CommoditySelection {
+                commodities: commodities.clone(),
+                base: selection.base,
+                pivot: selection.pivot,
+            };
+            let result = match source_named(settings, Some(name)) {
+                Ok(source) => {
+                    fetch_from(settings, source, part, start_date, end_date, budget).await
+                }
+                Err(err) => Err(err),
+            };
+            match result {
+                Ok(fetched) => {
+                    let (found, missing): (Vec<CommodityTypeID>, Vec<CommodityTypeID>) =
+                        commodities.into_iter().partition(|commodity| {
+                            !fetched
+                                .series
+                                .rebased(commodity, &selection.base)
+                                .map
+                                .is_empty()
+                        });
+                    for commodity in missing {
+                        outcomes.insert(commodity, Err(format!("{} had no prices for it", name)));
+                    }
+                    for commodity in &found {
+                        outcomes.insert(*commodity, Ok(()));
+                    }
+                    if !found.is_empty() {
+                        parts.push((found, fetched));
+                    }
+                }
+                Err(err) => {
+                    let failure = format!("{} failed: {}", name, err);
+                    for commodity in commodities {
+                        outcomes.insert(commodity, Err(failure.clone()));
+                    }
+                }
+            }
+        }
+
+        let mut still_pending = Vec::new();
+        for (commodity, position, mut failures) in pending {
+            let name = &chain(&commodity)[position];
+            match outcomes.remove(&commodity).expect("expected an outcome") {
+                Ok(()) => {
+                    if !failures.is_empty() {
+                        warn(
+                            Kind::Fallback,
+                            format!(
+                                "fetched {} from {} instead ({})",
+                                commodity,
+                                name,
+                                failures.join("; ")
+                            ),
+                        );
+                    }
+                }
+                Err(failure) => {
+                    failures.push(failure);
+                    if position + 1 == chain(&commodity).len() {
+                        return Err(anyhow!(
+                            "Unable to fetch {} from any of its fallbacks: {}",
+                            commodity,
+                            failures.join("; ")
+                        ));
+                    }
+                    still_pending.push((commodity, position + 1, failures));
+                }
+            }
+        }
+        pending = still_pending;
+    }
+    Ok(parts)
+}
+
+/// The requests of the fetches for one command (see [fetch_selection()]),
+/// which all count towards the one request budget (see `--max-requests`),
+/// and are confirmed once (see `--yes`).
+#[derive(Debug, Default)]
+struct Budget {
+    /// The requests the fetches so far are expected to have made.
+    spent: usize,
+    /// Whether the user has already agreed to go past a source's
+    /// `confirm_requests`.
+    confirmed: bool,
+}
+
+/// [fetch_selection()] from `source`, rather than the `--source`.
+async fn fetch_from(
+    settings: &Settings<'_>,
+    source: Source,
+    selection: CommoditySelection,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    budget: &mut Budget,
+) -> anyhow::Result<Fetched> {
+    if start_date > end_date {
+        return Err(anyhow!(
+            "The start date ({}) is after the end date ({}), check --start and --end",
+            start_date,
+            end_date
+        ));
+    }
+    if settings.value_of("snapshot-time").is_some() && !source.has_intraday() {
+        return Err(anyhow!(
+            "--snapshot-time needs a source with intraday prices (e.g. {}), {} only has one \
+            rate per date",
+            finnhub::PROVIDER_NAME,
+            source.name()
+        ));
+    }
+    // Only OpenExchangeRates' coverage is known.
+    let (start_date, end_date) = match source {
+        Source::OpenExchangeRates | Source::Mock { .. } => clamp_to_coverage(start_date, end_date)?,
+        _ => (start_date, end_date),
+    };
+    let config = &settings.config;
+    let app_id = match source {
+        Source::OpenExchangeRates => app_id(settings)?,
+        _ => AppID::default(),
+    };
+    let api_key = api_key(settings, source)?;
+    let provider_config = config.provider(source.name());
+    let parallel_requests: usize = match settings.value_of("parallel-requests") {
+        Some(parallel_requests) => parallel_requests
+            .parse()
+            .map_err(|err| anyhow!("unable to parse parallel-requests argument: {}", err))?,
+        None => provider_config
+            .parallel_requests
+            .unwrap_or(DEFAULT_PARALLEL_REQUESTS),
+    };
+    if parallel_requests == 0 {
+        return Err(anyhow!("parallel-requests must be at least 1"));
+    }
+    let requests_per_minute = provider_config
+        .requests_per_minute
+        .or_else(|| source.requests_per_minute());
+    if requests_per_minute == Some(0) {
+        return Err(anyhow!(
+            "requests_per_minute for {} must be at least 1",
+            source.name()
+        ));
+    }
+    let rate_limiter = requests_per_minute.map(RateLimiter::per_minute);
+    let retries: u32 = settings
+        .value_of("retries")
+        .expect("expected retries to be specified")
+        .parse()
+        .map_err(|err| anyhow!("Unable to parse retries: {}", err))?;
+    let cache = cache(settings);
+    if settings.is_present("stale-ok") && cache.is_none() {
+        return Err(anyhow!("--stale-ok requires a cache (see --cache-dir)"));
+    }
+    let stale_ok = if settings.is_present("stale-ok") {
+        let max_stale_days: i64 = settings
+            .value_of("max-stale-days")
+            .expect("expected max-stale-days to be specified")
+            .parse()
+            .map_err(|err| anyhow!("Unable to parse max-stale-days: {}", err))?;
+        if max_stale_days < 0 {
+            return Err(anyhow!("max-stale-days can't be negative"));
+        }
+        Some(max_stale_days)
+    } else {
+        None
+    };
+    if settings.is_present("full-table") && cache.is_none() {
+        return Err(anyhow!("--full-table requires a cache (see --cache-dir)"));
+    }
+    let fetch_alternative = settings.is_present("alternative");
+    if settings.is_present("full-table") && source != Source::OpenExchangeRates {
+        return Err(anyhow!(
+            "--full-table warms the cache, which the {} source doesn't use",
+            source.name()
+        ));
+    }
+    let max_requests: Option<u32> = match settings.value_of("max-requests") {
+        Some(max_requests) => Some(
+            max_requests
+                .parse()
+                .map_err(|err| anyhow!("Unable to parse max-requests: {}", err))?,
+        ),
+        None => provider_config.max_requests,
+    };
+    if max_requests == Some(0) {
+        return Err(anyhow!("max-requests must be at least 1"));
+    }
+    // What is left of the budget after the command's other fetches.
+    let remaining_requests =
+        max_requests.map(|max_requests| (max_requests as usize).saturating_sub(budget.spent));
+
+    let sampling = Sampling::from_str(
+        settings
+            .value_of("sample")
+            .expect("expected sample to be specified"),
+    )?;
+
+    // One historical request for each sampled date (or time-series.json
+    // request for each range of them, see below), less those already
+    // cached (with --full-table every date is requested again). Only
+    // OpenExchangeRates' rates are cached, and checked against its quota
+    // and currencies.
+    let cache = cache.filter(|_| source == Source::OpenExchangeRates);
+    let mut uncached = sampling.dates(&start_date, &end_date);
+    if let (Some(cache), false) = (&cache, settings.is_present("full-table")) {
+        let commodities = selection.request_commodities();
+        let mut dates = Vec::new();
+        for date in uncached {
+            if cache.get_complete(&date, &commodities)?.is_none() {
+                dates.push(date);
+            }
+        }
+        uncached = dates;
+    }
+    let no_quota_check: bool = settings.is_present("no-quota-check");
+
+    let client = Client::new();
+    let snapshot_time = settings
+        .value_of("snapshot-time")
+        .map(SnapshotTime::from_str)
+        .transpose()?;
+    let provider = source.provider(Setup {
+        client: &client,
+        config: &provider_config,
+        app_id: &app_id,
+        api_key: api_key.as_deref(),
+        base: selection.base,
+        alternative: fetch_alternative,
+        snapshot_time,
+    })?;
+
+    // The plan, for whether the whole range can be fetched with
+    // time-series.json, and for the quota check (which --no-quota-check
+    // skips, but not this). Like the quota check, it is a request too.
+    let usage = match source {
+        Source::OpenExchangeRates if !uncached.is_empty() => {
+            Some(OpenExchangeRates::new(&client, &app_id).usage().await?)
+        }
+        _ => None,
+    };
+    let time_series = usage
+        .as_ref()
+        .is_some_and(|usage| usage.data.plan.features.time_series);
+
+    // Only make as many requests as the budget allows, the dates they
+    // don't reach are left for a later run. Only OpenExchangeRates'
+    // requests (one for each uncached date, or range of them) can be cut
+    // short like that, the budget is checked against the others below.
+    let requested_end_date = end_date;
+    let mut end_date = end_date;
+    if let (Some(remaining_requests), Source::OpenExchangeRates) = (remaining_requests, source) {
+        let first_left_out = if time_series {
+            fetch::time_series_ranges(&uncached)
+                .get(remaining_requests)
+                .map(|(range_start, _)| *range_start)
+        } else {
+            uncached.get(remaining_requests).copied()
+        };
+        if let Some(first_left_out) = first_left_out {
+            if first_left_out == start_date {
+                return Err(anyhow!(
+                    "The request budget ({}, see --max-requests) was used up by the command's \
+                    other fetches before {}, fetch fewer commodities instead",
+                    max_requests.expect("expected a max-requests"),
+                    source.name()
+                ));
+            }
+            end_date = first_left_out - Duration::days(1);
+            uncached.retain(|date| *date <= end_date);
+        }
+    }
+
+    let expected_requests = match source {
+        Source::OpenExchangeRates if time_series => fetch::time_series_ranges(&uncached).len(),
+        Source::OpenExchangeRates => uncached.len(),
+        // Mock rates are made up, and CSV prices read from a file, rather
+        // than requested.
+        Source::Mock { .. } | Source::Csv => 0,
+        Source::ExchangerateHost => exchangeratehost::requests(&start_date, &end_date),
+        Source::Iex => iex::requests(selection.commodities.len()),
+        Source::MetalsApi => metalsapi::requests(&start_date, &end_date),
+        Source::Marketstack => {
+            marketstack::requests(selection.commodities.len(), &start_date, &end_date)
+        }
+        Source::Tiingo => selection.commodities.len() * tiingo::requests(&start_date, &end_date),
+        Source::Oanda { .. } => {
+            selection.commodities.len() * oanda::requests(&start_date, &end_date)
+        }
+        Source::Fmp => selection.commodities.len() * fmp::REQUESTS,
+        source if source.max_days_per_request().is_some() => {
+            selection.commodities.len()
+                * fetch::request_ranges(source, &start_date, &end_date).len()
+        }
+        source if source.is_table() => 1,
+        _ => selection.commodities.len(),
+    };
+
+    if let (Some(max_requests), Some(remaining_requests)) = (max_requests, remaining_requests) {
+        if expected_requests > remaining_requests {
+            return Err(anyhow!(
+                "The expected number of requests ({}, after the {} of the command's other \
+                fetches) for this command exceeds the request budget ({}, see --max-requests), \
+                and {} can't stop part way through the range, fetch fewer commodities or a \
+                shorter range instead",
+                budget.spent + expected_requests,
+                budget.spent,
+                max_requests,
+                source.name()
+            ));
+        }
+    }
+
+    // Asked once for the command, when its fetches together go past the
+    // source's limit.
+    if !budget.confirmed {
+        budget.confirmed = confirm_requests(
+            settings,
+            source,
+            budget.spent + expected_requests,
+            &if budget.spent == 0 {
+                format!("for {} to {}", start_date, end_date)
+            } else {
+                format!(
+                    "for {} to {}, counting the command's other fetches",
+                    start_date, end_date
+                )
+            },
+        )?;
+    }
+    budget.spent += expected_requests;
+
+    if source == Source::OpenExchangeRates && !settings.is_present("no-commodity-check") {
+        check_commodities(
+            &client,
+            cache.as_ref(),
+            &provider_config,
+            &selection.request_commodities(),
+            fetch_alternative,
+        )
+        .await?;
+    }
+
+    // Nothing to check when everything is cached.
+    if !no_quota_check && expected_requests > 0 {
+        match source {
+            Source::OpenExchangeRates => {
+                let usage = usage.as_ref().expect("expected the usage to be checked");
+                let requests_remaining = usage.data.usage.requests_remaining;
+
+                // Reading the usage above is a request too.
+                let expected_requests = expected_requests + 1;
+                if expected_requests > requests_remaining as usize {
+                    return Err(anyhow!(
+                        "The expected number of requests ({}, including the quota check) for \
+                        this command will exceed your remaining quota ({})",
+                        expected_requests,
+                        requests_remaining
+                    ));
+                }
+            }
+            // CoinMarketCap's quota is in credits, which a request uses
+            // more of the longer its range is.
+            Source::CoinMarketCap => {
+                let api_key = api_key.as_deref().expect("expected an API key");
+                if let Some(credits_left) =
+                    coinmarketcap::get_credits_left(&client, api_key).await?
+                {
+                    let expected_credits = selection.commodities.len()
+                        * coinmarketcap::expected_credits(&start_date, &end_date);
+                    if expected_credits as u64 > credits_left {
+                        return Err(anyhow!(
+                            "The expected number of credits ({}) for this command will exceed \
+                            your remaining {} credits ({})",
+                            expected_credits,
+                            coinmarketcap::PROVIDER_NAME,
+                            credits_left
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let fetch_options = FetchOptions {
+        parallel_requests,
+        rate_limiter: rate_limiter.as_ref(),
+        retries,
+        cache: cache.as_ref(),
+        stale_ok,
+        full_table: settings.is_present("full-table"),
+        sampling,
+        snapshot_time,
+        date_mismatch: DateMismatch::from_str(
+            settings
+                .value_of("date-mismatch")
+                .expect("expected date-mismatch to be specified"),
+        )?,
+        source,
+    };
+
+    let include = selection.include(source);
+    let mut series = if source.is_bulk() {
+        fetch::get_bulk_series(
+            provider.as_ref(),
+            &fetch_options,
+            &start_date,
+            &end_date,
+            selection.base,
+            include,
+        )
+        .await?
+    } else if time_series {
+        fetch::get_time_series(
+            provider.as_ref(),
+            &fetch_options,
+            &start_date,
+            &end_date,
+            Some(include),
+        )
+        .await?
+    } else {
+        fetch::get_time_series_with_historical(
+            provider.as_ref(),
+            &fetch_options,
+            &start_date,
+            &end_date,
+            Some(include),
+        )
+        .await?
+    };
+    warn_absent(&series, &selection.request_commodities());
+
+    if settings.is_present("forward-fill") {
+        let max_fill_days: i64 = settings
+            .value_of("max-fill-days")
+            .expect("expected max-fill-days to be specified")
+            .parse()
+            .map_err(|err| anyhow!("Unable to parse max-fill-days: {}", err))?;
+        series.forward_fill(&start_date, &end_date, max_fill_days);
+    }
+
+    if settings.is_present("interpolate") {
+        series.interpolate();
+    }
+
+    if !series.filled.is_empty() {
+        let dates: Vec<String> = series
+            .filled
+            .keys()
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .collect();
+        warn(
+            Kind::FilledGaps,
+            format!(
+                "the provider had no rates for {} date(s), they were filled in: {}",
+                dates.len(),
+                dates.join(", ")
+            ),
+        );
+    }
+
+    Ok(Fetched {
+        series,
+        commodities: selection.commodities,
+        base: selection.base,
+        pivot: selection.pivot,
+        end_date,
+        requested_end_date,
+        max_requests,
+    })
+}
+
+/// Ask before making `requests` requests to `source` (`purpose` says
+/// what for) when that is more than its `confirm_requests`, unless
+/// `--yes` was given. Returns whether going ahead was agreed to, rather
+/// than not needing to be.
+fn confirm_requests(
+    settings: &Settings<'_>,
+    source: Source,
+    requests: usize,
+    purpose: &str,
+) -> anyhow::Result<bool> {
+    if settings.is_present("yes") {
+        return Ok(true);
+    }
+    let limit = settings
+        .config
+        .provider(source.name())
+        .confirm_requests
+        .unwrap_or(DEFAULT_CONFIRM_REQUESTS);
+    if requests > limit as usize {
+        confirm(&format!(
+            "This will make up to {} requests to {} ({}), continue?",
+            requests,
+            source.name(),
+            purpose
+        ))?;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Ask `question` on the terminal, failing unless the answer is yes. Fails
+/// straight away when there is no terminal to ask on.
+fn confirm(question: &str) -> anyhow::Result<()> {
+    if UNATTENDED.load(Ordering::Relaxed)
+        || !std::io::stdin().is_terminal()
+        || !std::io::stderr().is_terminal()
+    {
+        return Err(anyhow!("{} Pass --yes to go ahead", question));
+    }
+
+    eprint!("{} [y/N] ", question);
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .map_err(|err| anyhow!("Unable to read the answer: {}", err))?;
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => Err(anyhow!("Cancelled")),
+    }
+}
+
+/// The part of the range from `start_date` to `end_date` which the
+/// provider has rates for, from its earliest date up to today (UTC), with
+/// a warning if that cuts the range short. Requests for dates outside it
+/// are bound to fail.
+fn clamp_to_coverage(
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> anyhow::Result<(NaiveDate, NaiveDate)> {
+    let earliest = openexchangerate::earliest_date();
+    let today = Utc::now().date_naive();
+    if end_date < earliest || start_date > today {
+        return Err(anyhow!(
+            "There are no rates from {} to {}, {} has rates from {} up to today ({})",
+            start_date,
+            end_date,
+            openexchangerate::PROVIDER_NAME,
+            earliest,
+            today
+        ));
+    }
+
+    if start_date < earliest {
+        warn(
+            Kind::Coverage,
+            format!(
+                "{} has no rates before {}, starting from then instead of {}",
+                openexchangerate::PROVIDER_NAME,
+                earliest,
+                start_date
+            ),
+        );
+    }
+    if end_date > today {
+        warn(
+            Kind::Coverage,
+            format!(
+                "there are no rates after today ({}), ending then instead of {}",
+                today, end_date
+            ),
+        );
+    }
+    Ok((
+        std::cmp::max(start_date, earliest),
+        std::cmp::min(end_date, today),
+    ))
+}
+
+/// The cache chosen with `--cache-dir`, or in the config.
+fn cache(settings: &Settings<'_>) -> Option<Cache> {
+    settings
+        .value_of("cache-dir")
+        .map(PathBuf::from)
+        .or_else(|| settings.config.cache_dir.clone())
+        .map(|cache_dir| Cache::new(cache_dir, openexchangerate::PROVIDER_NAME))
+}
+
+/// A price found by [query_cache()].
+#[derive(Serialize)]
+struct CachedPrice {
+    commodity: String,
+    base: String,
+    /// The date asked for.
+    requested_date: NaiveDate,
+    /// The date of the cached rates the price is from.
+    date: NaiveDate,
+    /// Number of days from `requested_date` to `date`, negative if it is
+    /// before.
+    days_away: i64,
+    price: Decimal,
+    /// How the price was derived, if it was triangulated.
+    derivation: Option<String>,
+}
+
+/// The price of `commodity` in `base` from the cached rates nearest to
+/// `date`, within `tolerance` days, if there is one.
+fn query_cache(
+    cache: &Cache,
+    commodity: CommodityTypeID,
+    base: CommodityTypeID,
+    pivot: Option<&CommodityTypeID>,
+    date: NaiveDate,
+    tolerance: i64,
+) -> anyhow::Result<Option<CachedPrice>> {
+    for nearest in cache.dates_near(&date, tolerance)? {
+        let exchange_rate = match cache.get(&nearest)? {
+            Some(exchange_rate) => exchange_rate,
+            None => continue,
+        };
+        if let Some(derived) = derive_rate(&exchange_rate, &commodity, &base, pivot)? {
+            return Ok(Some(CachedPrice {
+                commodity: commodity.to_string(),
+                base: base.to_string(),
+                requested_date: date,
+                date: nearest,
+                days_away: (nearest - date).num_days(),
+                price: derived.rate,
+                derivation: match derived.derivation {
+                    Derivation::Quoted => None,
+                    derivation => Some(derivation.to_string()),
+                },
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Look up a price with [query_cache()] for `cache query` or the `/cache`
+/// endpoint of `serve`, with the arguments given by `value_of`.
+fn cache_query<'a>(
+    settings: &Settings<'_>,
+    value_of: impl Fn(&str) -> Option<&'a str>,
+) -> anyhow::Result<Option<CachedPrice>> {
+    let cache =
+        cache(settings).ok_or_else(|| anyhow!("There is no cache to query, see --cache-dir"))?;
+    let commodity = |id: &str| -> anyhow::Result<CommodityTypeID> {
+        let value = value_of(id).ok_or_else(|| anyhow!("{} must be given", id))?;
+        parse_commodity(id, value)
+    };
+    let pivot = match value_of("pivot") {
+        Some(_) => Some(commodity("pivot")?),
+        None => settings.config.pivot,
+    };
+    let date = parse_date_value(value_of("date").ok_or_else(|| anyhow!("date must be given"))?)
+        .map_err(|err| anyhow!("Unable to parse date: {}", err))?;
+    let tolerance: i64 = value_of("tolerance")
+        .unwrap_or(DEFAULT_CACHE_TOLERANCE)
+        .parse()
+        .map_err(|err| anyhow!("Unable to parse tolerance: {}", err))?;
+    if tolerance < 0 {
+        return Err(anyhow!("tolerance can't be negative"));
+    }
+
+    query_cache(
+        &cache,
+        commodity("commodity")?,
+        commodity("base")?,
+        pivot.as_ref(),
+        date,
+        tolerance,
+    )
+}
+
+/// The default `--tolerance` of `cache query`, enough to reach over a long
+/// weekend.
+const DEFAULT_CACHE_TOLERANCE: &str = "3";
+
+/// `cache query`: print the price found by [query_cache()] as JSON.
+fn cache_query_command(settings: &Settings<'_>) -> anyhow::Result<()> {
+    let found = cache_query(settings, |id| settings.value_of(id))?.ok_or_else(|| {
+        anyhow!(
+            "No cached price for {} in {} within {} day(s) of {}",
+            settings.value_of("commodity").unwrap_or(""),
+            settings.value_of("base").unwrap_or(""),
+            settings
+                .value_of("tolerance")
+                .unwrap_or(DEFAULT_CACHE_TOLERANCE),
+            settings.value_of("date").unwrap_or(""),
+        )
+    })?;
+    println!("{}", serde_json::to_string(&found)?);
+    Ok(())
+}
+
+/// Report on the requests made by [fetch()], once the output is finished.
+fn report_fetch(settings: &Settings<'_>, fetched: &Fetched) {
+    if settings.is_present("summary") {
+        eprintln!("Requests: {}", REQUEST_METRICS.summary());
+    }
+
+    if fetched.end_date < fetched.requested_end_date {
+        let resume_date = fetched.end_date + Duration::days(1);
+        eprintln!(
+            "Stopped after reaching the request budget ({} requests). \
+            Dates {} to {} remain to be fetched, resume with --start {}",
+            fetched
+                .max_requests
+                .expect("expected max-requests to be specified"),
+            resume_date.format("%Y-%m-%d"),
+            fetched.requested_end_date.format("%Y-%m-%d"),
+            resume_date.format("%Y-%m-%d"),
+        );
+    }
+}
+
+/// The `--rounding`, where `auto` is [metadata::EXTRA_PRICE_DECIMAL_PLACES]
+/// more than the number of decimal places the base commodity usually has.
+async fn parse_rounding(settings: &Settings<'_>) -> anyhow::Result<Option<u32>> {
+    let rounding = match settings.value_of("rounding") {
+        Some(rounding) => rounding,
+        None => return Ok(None),
+    };
+    if rounding != "auto" {
+        return rounding
+            .parse()
+            .map(Some)
+            .map_err(|err| anyhow!("Unable to parse rounding: {}", err));
+    }
+
+    let base = settings.required("base")?;
+    let decimal_places = match metadata::iso_decimal_places(base) {
+        Some(decimal_places) => Some(decimal_places),
+        None => get_metadata(&Client::new(), cache(settings).as_ref())
+            .await?
+            .get(base)
+            .and_then(|metadata| metadata.decimal_places),
+    };
+    match decimal_places {
+        Some(decimal_places) => Ok(Some(decimal_places + metadata::EXTRA_PRICE_DECIMAL_PLACES)),
+        None => Err(anyhow!(
+            "Unable to choose the rounding for {} automatically, give --rounding a number of decimal places",
+            base
+        )),
+    }
+}
+
+/// Write the price directives for the `series` subcommand to `out`,
+/// returning the prices written.
+async fn series_command(
+    settings: &Settings<'_>,
+    out: &mut dyn Write,
+) -> anyhow::Result<Vec<PriceSeries>> {
+    let rounding = parse_rounding(settings).await?;
+    let (fetched, series) = series_prices(settings).await?;
+
+    if settings.is_present("chart") {
+        for prices in &series {
+            print_chart(prices, rounding);
+        }
+    }
+
+    if settings.is_present("check-roundtrip") {
+        check_roundtrip(settings, &series, rounding)?;
+    }
+
+    let descending = settings.is_present("desc");
+    match settings.value_of("format") {
+        Some("json") => {
+            let output = serde_json::json!({
+                "prices": price_responses(&series, rounding, descending),
+                "warnings": WARNINGS.take(),
+            });
+            writeln!(out, "{:#}", output)
+        }
+        Some("influx") => {
+            let source = settings
+                .value_of("source")
+                .unwrap_or(openexchangerate::PROVIDER_NAME);
+            write_influx(out, &series, source, rounding, descending)
+        }
+        _ => write_prices(out, &series, rounding, descending),
+    }
+    .map_err(|err| anyhow!("Unable to write the prices: {}", err))?;
+
+    report_fetch(settings, &fetched);
+    Ok(series)
+}
+
+/// `--check-roundtrip`: warn about each pair in `series` with prices
+/// which don't round trip at the `rounding` they are written with.
+fn check_roundtrip(
+    settings: &Settings<'_>,
+    series: &[PriceSeries],
+    rounding: Option<u32>,
+) -> anyhow::Result<()> {
+    let tolerance = Decimal::from_str(
+        settings
+            .value_of("roundtrip-tolerance")
+            .expect("expected roundtrip-tolerance to be specified"),
+    )
+    .map_err(|err| anyhow!("Unable to parse roundtrip-tolerance: {}", err))?;
+    let precision = match rounding {
+        Some(dp) => format!("rounded to {} decimal places", dp),
+        None => String::from("unrounded"),
+    };
+
+    for prices in series {
+        let asymmetries = prices.asymmetries(rounding, tolerance);
+        let worst = match asymmetries
+            .iter()
+            .max_by_key(|asymmetry| asymmetry.deviation_percent)
+        {
+            Some(worst) => worst,
+            None => continue,
+        };
+        let detail = match worst.inverse {
+            Some(inverse) => format!(
+                "{} {} per {} but {} {} per {}, off by {}%",
+                worst.rate,
+                prices.base,
+                prices.commodity,
+                inverse,
+                prices.commodity,
+                prices.base,
+                worst.deviation_percent.round_dp(4)
+            ),
+            None => format!("the rate rounds to {}", worst.rate),
+        };
+        warn(
+            Kind::Roundtrip,
+            format!(
+                "{}/{} {} doesn't round trip within {}% on {} date(s), worst {}: {}",
+                prices.commodity,
+                prices.base,
+                precision,
+                tolerance,
+                asymmetries.len(),
+                worst.date.format("%Y-%m-%d"),
+                detail
+            ),
+        );
+    }
+    Ok(())
+}
+
+/// Fetch and compute the prices for the `series` subcommand, one series
+/// for each commodity.
+async fn series_prices(settings: &Settings<'_>) -> anyhow::Result<(Fetched, Vec<PriceSeries>)> {
+    let aggregate = settings
+        .value_of("aggregate")
+        .map(Aggregate::from_str)
+        .transpose()?;
+    let rolling_average: Option<i64> = settings
+        .value_of("rolling-average")
+        .map(|days| {
+            days.parse()
+                .map_err(|err| anyhow!("Unable to parse rolling-average: {}", err))
+        })
+        .transpose()?;
+    if matches!(rolling_average, Some(days) if days < 1) {
+        return Err(anyhow!("rolling-average must be at least 1 day"));
+    }
+    if matches!(rolling_average, Some(days) if days > MAX_ROLLING_AVERAGE_DAYS) {
+        return Err(anyhow!(
+            "rolling-average can be at most {} days",
+            MAX_ROLLING_AVERAGE_DAYS
+        ));
+    }
+    let outlier_threshold = Decimal::from_str(
+        settings
+            .value_of("outlier-threshold")
+            .expect("expected outlier-threshold to be specified"),
+    )
+    .map_err(|err| anyhow!("Unable to parse outlier-threshold: {}", err))?;
+
+    let mut fetched = fetch(settings).await?;
+    let base_commodity = fetched.base;
+
+    if settings.is_present("business-days") {
+        fetched.series.retain_business_days();
+    }
+
+    let mut series = Vec::new();
+    for commodity in &fetched.commodities {
+        let mut prices = PriceSeries::from_time_series(
+            &fetched.series,
+            *commodity,
+            base_commodity,
+            fetched.pivot.as_ref(),
+        )?;
+        check_missing(&mut prices, missing_rates(settings)?)?;
+        check_bounds(settings, &mut prices)?;
+
+        let outliers = prices.outliers(outlier_threshold);
+        for outlier in &outliers {
+            warn(Kind::Outlier, format!("possible outlier: {}", outlier));
+        }
+        if !outliers.is_empty() && settings.is_present("strict-outliers") {
+            return Err(anyhow!(
+                "{} possible outlier(s) for {} found (see --outlier-threshold)",
+                outliers.len(),
+                commodity
+            ));
+        }
+
+        if let Some(days) = rolling_average {
+            prices = prices.rolling_average(days);
+        }
+
+        if let Some(aggregate) = &aggregate {
+            prices = prices.aggregate(aggregate);
+        }
+
+        if settings.is_present("only-changes") {
+            prices.compress();
+        }
+
+        series.push(prices);
+    }
+
+    Ok((fetched, series))
+}
+
+/// Warn about each of `commodities` which is missing from the rates for
+/// some of the dates in `series`, with the dates affected.
+fn warn_absent(series: &TimeSeries, commodities: &[CommodityTypeID]) {
+    let mut commodities = commodities.to_vec();
+    commodities.sort();
+    for commodity in &commodities {
+        let runs = series.absent(commodity);
+        if runs.is_empty() {
+            continue;
+        }
+        let count: usize = runs.iter().map(|(_, _, count)| count).sum();
+        let ranges: Vec<String> = runs
+            .iter()
+            .map(|(first, last, _)| {
+                if first == last {
+                    first.format("%Y-%m-%d").to_string()
+                } else {
+                    format!(
+                        "{} to {}",
+                        first.format("%Y-%m-%d"),
+                        last.format("%Y-%m-%d")
+                    )
+                }
+            })
+            .collect();
+        warn(
+            Kind::MissingSymbol,
+            format!(
+                "the provider has no rate for {} on {} of {} date(s) ({}), \
+            see --missing-rates for what happens to the prices which need it",
+                commodity,
+                count,
+                series.map.len(),
+                ranges.join(", ")
+            ),
+        );
+    }
+}
+
+/// The [MissingRates] behaviour chosen with `--missing-rates` (or
+/// `--strict`).
+fn missing_rates(settings: &Settings<'_>) -> anyhow::Result<MissingRates> {
+    if settings.is_present("strict") {
+        return Ok(MissingRates::Fail);
+    }
+    MissingRates::from_str(
+        settings
+            .value_of("missing-rates")
+            .expect("expected missing-rates to be specified"),
+    )
+}
+
+/// Deal with the dates left out of `prices` because a rate was missing
+/// (already warned about by [warn_absent()]) according to `behaviour`.
+fn check_missing(prices: &mut PriceSeries, behaviour: MissingRates) -> anyhow::Result<()> {
+    if prices.missing.is_empty() {
+        return Ok(());
+    }
+    match behaviour {
+        MissingRates::Skip => Ok(()),
+        MissingRates::ForwardFill => {
+            prices.fill_missing();
+            if !prices.missing.is_empty() {
+                warn(
+                    Kind::SkippedDates,
+                    format!(
+                        "there is no earlier price of {}/{} to carry forward to {} \
+                    date(s), skipping them",
+                        prices.commodity,
+                        prices.base,
+                        prices.missing.len()
+                    ),
+                );
+            }
+            Ok(())
+        }
+        MissingRates::Fail => Err(MissingRatesError {
+            commodity: prices.commodity,
+            base: prices.base,
+            dates: prices.missing.clone(),
+        }
+        .into()),
+    }
+}
+
+/// Check `prices` against the bounds configured for its pair, dealing
+/// with those outside them as chosen with `--out-of-bounds`.
+fn check_bounds(settings: &Settings<'_>, prices: &mut PriceSeries) -> anyhow::Result<()> {
+    let behaviour = OutOfBounds::from_str(
+        settings
+            .value_of("out-of-bounds")
+            .expect("expected out-of-bounds to be specified"),
+    )?;
+    for bounds in &settings.config.bounds {
+        if bounds.commodity != prices.commodity || bounds.base != prices.base {
+            continue;
+        }
+        let outside: Vec<NaiveDate> = prices
+            .prices
+            .iter()
+            .filter(|(_, price)| !bounds.contains(price.rate))
+            .map(|(date, _)| *date)
+            .collect();
+        for date in &outside {
+            warn(
+                Kind::OutOfBounds,
+                format!(
+                    "{} {}/{} is {}, outside its bounds ({})",
+                    date.format("%Y-%m-%d"),
+                    prices.commodity,
+                    prices.base,
+                    prices.prices[date].rate.round_dp(6),
+                    bounds
+                ),
+            );
+        }
+        if outside.is_empty() {
+            continue;
+        }
+
+        match behaviour {
+            OutOfBounds::Skip => {
+                for date in &outside {
+                    prices.prices.remove(date);
+                }
+            }
+            OutOfBounds::Keep => {
+                for date in &outside {
+                    if let Some(price) = prices.prices.get_mut(date) {
+                        let note = format!("outside the bounds {}", bounds);
+                        price.annotation = Some(match price.annotation.take() {
+                            Some(annotation) => format!("{}, {}", annotation, note),
+                            None => note,
+                        });
+                    }
+                }
+            }
+            OutOfBounds::Fail => {
+                return Err(anyhow!(
+                    "{} price(s) of {}/{} are outside its bounds ({}), see --out-of-bounds",
+                    outside.len(),
+                    prices.commodity,
+                    prices.base,
+                    bounds
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run the jobs from the config named in `matches` (or all of them),
+/// carrying on with the other jobs if one fails.
+async fn run_command(matches: &ArgMatches) -> anyhow::Result<()> {
+    let settings = Settings::load(matches)?;
+    let jobs: Vec<&str> = match matches.values_of("jobs") {
+        Some(names) => names.collect(),
+        None => settings.config.jobs.keys().map(String::as_str).collect(),
+    };
+    if jobs.is_empty() {
+        return Err(anyhow!("There are no jobs in the config"));
+    }
+
+    let wait = match settings.value_of("wait") {
+        Some(wait) => {
+            parse_interval(wait).map_err(|err| anyhow!("Unable to parse wait: {}", err))?
+        }
+        None => std::time::Duration::from_secs(0),
+    };
+
+    let mut failed: Vec<&str> = Vec::new();
+    let mut locked: Vec<PathBuf> = Vec::new();
+    for name in jobs {
+        let job = settings
+            .config
+            .jobs
+            .get(name)
+            .ok_or_else(|| anyhow!("There is no job called {:?} in the config", name))?;
+        let event = match run_job(matches, name, job, wait).await {
+            Err(error) if error.is::<Locked>() => {
+                eprintln!("Job {} is already running, skipping it", name);
+                locked.push(lock::lock_path(&job.output));
+                continue;
+            }
+            Ok(run) => {
+                eprintln!("{}", run.message);
+                check_alerts(&settings.config, &run).await;
+                Event::JobSucceeded {
+                    job: name.to_string(),
+                    message: run.message,
+                }
+            }
+            Err(error) => {
+                eprintln!("Error: job {} failed: {}", name, error);
+                failed.push(name);
+                Event::JobFailed {
+                    job: name.to_string(),
+                    error: error.to_string(),
+                }
+            }
+        };
+        notify::send(&settings.config, &event).await;
+    }
+
+    if let (Some(threshold), Some(requests_remaining)) = (
+        settings.config.notify.quota_threshold,
+        SERVICE_METRICS.quota_remaining(),
+    ) {
+        if requests_remaining < threshold {
+            let event = Event::QuotaLow {
+                provider: openexchangerate::PROVIDER_NAME.to_string(),
+                requests_remaining,
+                threshold,
+            };
+            warn(Kind::QuotaLow, event.to_string());
+            notify::send(&settings.config, &event).await;
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(anyhow!(
+            "{} job(s) failed: {}",
+            failed.len(),
+            failed.join(", ")
+        ));
+    }
+    if !locked.is_empty() {
+        return Err(Locked { paths: locked }.into());
+    }
+    Ok(())
+}
+
+/// Run the jobs (see [run_command()]) every time the `--schedule` fires,
+/// until the process is stopped.
+async fn daemon_command(matches: &ArgMatches) -> anyhow::Result<()> {
+    UNATTENDED.store(true, Ordering::Relaxed);
+    // Running until stopped, so there is no end to report them at.
+    WARNINGS.defer(false);
+    let settings = Settings::load(matches)?;
+    let schedule: Schedule = settings.required("schedule")?.parse()?;
+    if let Some(address) = settings.value_of("metrics-listen") {
+        let address: SocketAddr = address
+            .parse()
+            .map_err(|err| anyhow!("Unable to parse metrics-listen address: {}", err))?;
+        let server = Server::try_bind(&address)
+            .map_err(|err| anyhow!("Unable to listen on {}: {}", address, err))?
+            .serve(make_service_fn(|_connection| async {
+                Ok::<_, Infallible>(service_fn(|request: Request<Body>| async move {
+                    Ok::<_, Infallible>(match request.uri().path() {
+                        "/metrics" => metrics_response(),
+                        _ => Response::builder()
+                            .status(StatusCode::NOT_FOUND)
+                            .body(Body::from("Not found, expected /metrics\n"))
+                            .expect("expected a valid response"),
+                    })
+                }))
+            }));
+        eprintln!("Serving metrics on http://{}/metrics", address);
+        tokio::spawn(async {
+            if let Err(error) = server.await {
+                eprintln!("Error: the metrics server failed: {}", error);
+            }
+        });
+    }
+    // Fail on startup rather than at the first run.
+    if settings.config.jobs.is_empty() {
+        return Err(anyhow!("There are no jobs in the config"));
+    }
+
+    loop {
+        let now = Local::now();
+        let next = schedule
+            .next_after(&now)
+            .ok_or_else(|| anyhow!("The schedule {:?} never fires", schedule.to_string()))?;
+        eprintln!("Next run at {}", next.format("%Y-%m-%d %H:%M %Z"));
+        let wait = (next - now).to_std().unwrap_or_default();
+        tokio::time::delay_for(wait).await;
+
+        let result = run_command(matches).await;
+        SERVICE_METRICS.record_run(result.is_ok());
+        if let Err(error) = result {
+            eprintln!("Error: {}", error);
+        }
+    }
+}
+
+/// The arguments from `matches` (for `run`, `daemon` or `serve`) which are
+/// passed on to the `series` subcommand they run: the config, credentials
+/// and cache.
+fn passthrough_args(matches: &ArgMatches) -> Vec<String> {
+    let mut args = Vec::new();
+    match config_path(matches) {
+        Some(path) => {
+            args.push(String::from("--config"));
+            args.push(path.display().to_string());
+        }
+        None => args.push(String::from("--no-config")),
+    }
+    for id in &["app-id", "app-id-file", "api-key", "cache-dir"] {
+        if let Some(value) = matches.value_of(id) {
+            args.push(format!("--{}", id));
+            args.push(value.to_string());
+        }
+    }
+    args
+}
+
+/// Check the alert rules in `config` against the prices appended by a
+/// job, sending a notification for each alert raised.
+async fn check_alerts(config: &Config, run: &JobRun) {
+    for rule in &config.alerts {
+        for prices in &run.prices {
+            let pair = (prices.commodity.to_string(), prices.base.to_string());
+            let previous = run.previous.get(&pair).copied();
+            for alert in alert::evaluate(rule, prices, previous) {
+                eprintln!("Alert: {}", alert);
+                let event = Event::Alert {
+                    commodity: alert.commodity.clone(),
+                    base: alert.base.clone(),
+                    date: alert.date,
+                    message: alert.to_string(),
+                };
+                notify::send(config, &event).await;
+            }
+        }
+    }
+}
+
+/// The latest date with a price directive in the beancount file at
+/// `path`, if it exists and has any.
+fn latest_price_date(path: &Path) -> anyhow::Result<Option<NaiveDate>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| anyhow!("Unable to read {:?}: {}", path, err))?;
+    Ok(contents
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let date = NaiveDate::parse_from_str(words.next()?, "%Y-%m-%d").ok()?;
+            if words.next()? == "price" {
+                Some(date)
+            } else {
+                None
+            }
+        })
+        .max())
+}
+
+/// Append `contents` to the file at `path`, creating it (and its directory)
+/// if necessary.
+fn append_to(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .map_err(|err| anyhow!("Unable to create directory {:?}: {}", dir, err))?;
+    }
+    let mut output = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|err| anyhow!("Unable to open {:?}: {}", path, err))?;
+    output
+        .write_all(contents)
+        .map_err(|err| anyhow!("Unable to write to {:?}: {}", path, err))
+}
+
+/// The last price of each commodity (and base) in the beancount file at
+/// `path`, if it exists.
+fn last_prices(path: &Path) -> anyhow::Result<HashMap<(String, String), Decimal>> {
+    let mut prices = HashMap::new();
+    if !path.exists() {
+        return Ok(prices);
+    }
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| anyhow!("Unable to read {:?}: {}", path, err))?;
+    for line in contents.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if let [date, "price", commodity, rate, base, ..] = words.as_slice() {
+            if let (Ok(_), Ok(rate)) = (
+                NaiveDate::parse_from_str(date, "%Y-%m-%d"),
+                Decimal::from_str(rate),
+            ) {
+                prices.insert((commodity.to_string(), base.to_string()), rate);
+            }
+        }
+    }
+    Ok(prices)
+}
+
+/// What [run_job()] did.
+struct JobRun {
+    message: String,
+    /// The prices appended to the output.
+    prices: Vec<PriceSeries>,
+    /// The last price of each pair in the output before they were.
+    previous: HashMap<(String, String), Decimal>,
+}
+
+/// Run `job` as the `series` subcommand, appending the new prices to its
+/// output file. The other arguments given to `run` (`matches`) are
+/// passed on to it.
+async fn run_job(
+    matches: &ArgMatches,
+    name: &str,
+    job: &config::Job,
+    wait: std::time::Duration,
+) -> anyhow::Result<JobRun> {
+    if !config::SOURCES.contains(&job.source.as_str()) {
+        return Err(anyhow!("unknown source {:?}", job.source));
+    }
+    if job.format != "beancount" {
+        return Err(anyhow!(
+            "unknown format {:?}, expected beancount",
+            job.format
+        ));
+    }
+
+    // Held until the prices are appended, so an overlapping run sees them.
+    let _lock = RunLock::acquire(&lock::lock_path(&job.output), wait).await?;
+
+    let start = match latest_price_date(&job.output)? {
+        Some(latest) => latest + Duration::days(1),
+        None => parse_date_value(job.start.as_deref().ok_or_else(|| {
+            anyhow!("the output has no prices yet, so the job needs a start date")
+        })?)?,
+    };
+    let end = parse_date_value(job.end.as_deref().unwrap_or("yesterday"))?;
+    if start > end {
+        return Ok(JobRun {
+            message: format!("Job {} is up to date", name),
+            prices: Vec::new(),
+            previous: HashMap::new(),
+        });
+    }
+
+    let mut args: Vec<String> = vec![
+        String::from("beancount-price-fetcher"),
+        String::from("series"),
+        String::from("--source"),
+        job.source.clone(),
+        String::from("--start"),
+        start.format("%Y-%m-%d").to_string(),
+        String::from("--end"),
+        end.format("%Y-%m-%d").to_string(),
+        String::from("--base"),
+        job.base.to_string(),
+        String::from("--sample"),
+        job.frequency.clone(),
+        String::from("--commodities"),
+    ];
+    args.extend(
+        job.commodities
+            .iter()
+            .map(|commodity| commodity.to_string()),
+    );
+    if let Some(rounding) = job.rounding {
+        args.push(String::from("--rounding"));
+        args.push(rounding.to_string());
+    }
+    args.extend(passthrough_args(matches));
+    args.extend(job.args.iter().cloned());
+
+    let series_matches = app()
+        .try_get_matches_from(args)
+        .map_err(|err| anyhow!("invalid arguments: {}", err))?;
+    let series_matches = series_matches
+        .subcommand_matches("series")
+        .expect("expected series subcommand");
+    let settings = Settings::load(series_matches)?;
+
+    let previous = last_prices(&job.output)?;
+    // Written to a buffer first, so a failed fetch doesn't leave part of
+    // the prices in the output.
+    let mut output: Vec<u8> = Vec::new();
+    let prices = series_command(&settings, &mut output).await?;
+
+    append_to(&job.output, &output)?;
+
+    Ok(JobRun {
+        message: format!(
+            "Job {} appended prices from {} to {} to {:?}",
+            name,
+            start.format("%Y-%m-%d"),
+            end.format("%Y-%m-%d"),
+            job.output
+        ),
+        prices,
+        previous,
+    })
+}
+
+/// The only arguments which can be given in a request to the `serve` API:
+/// those choosing which prices are fetched and how they are computed.
+/// Anything else (reading or writing files, skipping the confirmation or
+/// quota check, or changing how many requests are made) stays as the server
+/// was started, as any web page the user visits can send a request to it.
+const SERVE_QUERY_ARGS: &[&str] = &[
+    "commodities",
+    "base",
+    "pivot",
+    "include-base",
+    "alternative",
+    "source",
+    "seed",
+    "start",
+    "end",
+    "as-of",
+    "sample",
+    "business-days",
+    "forward-fill",
+    "max-fill-days",
+    "interpolate",
+    "snapshot-time",
+    "date-mismatch",
+    "missing-rates",
+    "strict",
+    "outlier-threshold",
+    "strict-outliers",
+    "check-roundtrip",
+    "roundtrip-tolerance",
+    "rolling-average",
+    "aggregate",
+    "only-changes",
+    "out-of-bounds",
+    "rounding",
+];
+
+/// A price in a response from the `serve` API, or in the output of
+/// `series --format json`.
+#[derive(Serialize)]
+struct PriceResponse<'a> {
+    date: String,
+    commodity: String,
+    base: String,
+    price: Decimal,
+    annotation: Option<&'a str>,
+}
+
+/// Serve the `serve` API until the process is stopped.
+async fn serve_command(matches: &ArgMatches) -> anyhow::Result<()> {
+    UNATTENDED.store(true, Ordering::Relaxed);
+    // Running until stopped, so there is no end to report them at.
+    WARNINGS.defer(false);
+    let address: SocketAddr = matches
+        .value_of("listen")
+        .expect("expected listen to be specified")
+        .parse()
+        .map_err(|err| anyhow!("Unable to parse listen address: {}", err))?;
+    // Check the config and credentials on startup rather than at the first
+    // request.
+    app_id(&Settings::load(matches)?)?;
+
+    let matches = Arc::new(matches.clone());
+    let make_service = make_service_fn(move |_connection| {
+        let matches = matches.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let matches = matches.clone();
+                async move { Ok::<_, Infallible>(serve_request(&matches, request).await) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&address)
+        .map_err(|err| anyhow!("Unable to listen on {}: {}", address, err))?
+        .serve(make_service);
+    eprintln!("Listening on http://{}", address);
+    server
+        .await
+        .map_err(|err| anyhow!("The server failed: {}", err))
+}
+
+/// Respond to a request to the `serve` API.
+async fn serve_request(matches: &ArgMatches, request: Request<Body>) -> Response<Body> {
+    log::debug!("{} {}", request.method(), request.uri());
+    let query = request.uri().query().unwrap_or("");
+    let result = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/prices") => serve_prices(matches, query).await,
+        (&Method::GET, "/metrics") => return metrics_response(),
+        (&Method::GET, "/series") => serve_series(matches, query).await,
+        (&Method::GET, "/cache") => serve_cache(matches, query),
+        (&Method::GET, _) => Err((
+            StatusCode::NOT_FOUND,
+            anyhow!("Not found, expected /prices, /series or /cache"),
+        )),
+        _ => Err((
+            StatusCode::METHOD_NOT_ALLOWED,
+            anyhow!("Only GET is supported"),
+        )),
+    };
+
+    // Client errors don't mean the price pipeline is broken.
+    match &result {
+        Ok(_) => SERVICE_METRICS.record_run(true),
+        Err((status, _)) if status.is_server_error() => SERVICE_METRICS.record_run(false),
+        Err(_) => {}
+    }
+
+    let (status, body) = match result {
+        Ok(body) => (StatusCode::OK, body),
+        Err((status, error)) => {
+            log::debug!("{} {}: {}", request.method(), request.uri(), error);
+            (status, serde_json::json!({ "error": error.to_string() }))
+        }
+    };
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("expected a valid response")
+}
+
+/// The response to `/metrics`, see [metrics::prometheus()].
+fn metrics_response() -> Response<Body> {
+    Response::builder()
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(Body::from(metrics::prometheus()))
+        .expect("expected a valid response")
+}
+
+type ServeResult = Result<serde_json::Value, (StatusCode, anyhow::Error)>;
+
+/// `/prices`: the price of each `commodity` on `date`, or the latest.
+async fn serve_prices(matches: &ArgMatches, query: &str) -> ServeResult {
+    let mut args = Vec::new();
+    let mut date = None;
+    for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+        match key.as_ref() {
+            "date" => date = Some(value.into_owned()),
+            "commodity" => args.extend(query_arg("commodities", &value)),
+            key => args.extend(query_arg(key, &value)),
+        }
+    }
+
+    match date {
+        Some(date) => {
+            args.push(format!("--start={}", date));
+            args.push(format!("--end={}", date));
+            let series_matches = serve_matches(matches, "series", args)?;
+            let settings = serve_settings(&series_matches, &["commodities", "base"])?;
+            let (_, series) = series_prices(&settings)
+                .await
+                .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))?;
+            prices_response(&settings, &series).await
+        }
+        None => {
+            let latest_matches = serve_matches(matches, "latest", args)?;
+            let settings = serve_settings(&latest_matches, &["commodities", "base"])?;
+            let series = latest_prices(&settings)
+                .await
+                .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))?;
+            prices_response(&settings, &series).await
+        }
+    }
+}
+
+/// `/series`: the series from `start` to `end`, as the `series`
+/// subcommand would fetch it.
+async fn serve_series(matches: &ArgMatches, query: &str) -> ServeResult {
+    let args = url::form_urlencoded::parse(query.as_bytes())
+        .flat_map(|(key, value)| query_arg(&key, &value))
+        .collect();
+    let series_matches = serve_matches(matches, "series", args)?;
+    let settings = serve_settings(&series_matches, &["commodities", "base", "start", "end"])?;
+    let (_, series) = series_prices(&settings)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))?;
+    prices_response(&settings, &series).await
+}
+
+/// `/cache`: the price of `commodity` in `base` from the cached rates
+/// nearest to `date`, see [query_cache()].
+fn serve_cache(matches: &ArgMatches, query: &str) -> ServeResult {
+    let parameters: HashMap<String, String> = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    let settings =
+        Settings::load(matches).map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))?;
+    match cache_query(&settings, |id| parameters.get(id).map(String::as_str)) {
+        Ok(Some(found)) => Ok(serde_json::to_value(&found).expect("expected a serializable price")),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            anyhow!("No cached price within the tolerance of the date"),
+        )),
+        Err(err) => Err((StatusCode::BAD_REQUEST, err)),
+    }
+}
+
+/// The command line arguments for the query parameter `key`: `--key=value`,
+/// one for each value in a comma separated list of commodities, or just
+/// `--key` for a flag given as `true`.
+fn query_arg(key: &str, value: &str) -> Vec<String> {
+    match value {
+        "true" | "" => vec![format!("--{}", key)],
+        "false" => Vec::new(),
+        _ if key == "commodities" => value
+            .split(',')
+            .map(|commodity| format!("--commodities={}", commodity))
+            .collect(),
+        _ => vec![format!("--{}={}", key, value)],
+    }
+}
+
+/// Parse the arguments made from a request to the `serve` API as
+/// `subcommand`, with the [passthrough_args()] of the server.
+fn serve_matches(
+    matches: &ArgMatches,
+    subcommand: &str,
+    query_args: Vec<String>,
+) -> Result<ArgMatches, (StatusCode, anyhow::Error)> {
+    if let Some(arg) = query_args.iter().find(|arg| {
+        let id = arg.trim_start_matches("--").split('=').next().unwrap_or("");
+        !SERVE_QUERY_ARGS.contains(&id)
+    }) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            anyhow!("{} can't be given in a request", arg),
+        ));
+    }
+
+    let mut args = vec![
+        String::from("beancount-price-fetcher"),
+        subcommand.to_string(),
+    ];
+    args.extend(passthrough_args(matches));
+    args.extend(query_args);
+    let matches = app().try_get_matches_from(args).map_err(|err| {
+        // Only the first line, without the usage.
+        let message = err.to_string();
+        let message = message
+            .lines()
+            .next()
+            .unwrap_or("")
+            .trim_start_matches("error: ");
+        (StatusCode::BAD_REQUEST, anyhow!("{}", message))
+    })?;
+    Ok(matches
+        .subcommand_matches(subcommand)
+        .expect("expected subcommand")
+        .clone())
+}
+
+/// Load the settings for a request to the `serve` API, checking the
+/// arguments in `required` are given so a request missing them is a bad
+/// request rather than a failed fetch.
+fn serve_settings<'a>(
+    matches: &'a ArgMatches,
+    required: &[&str],
+) -> Result<Settings<'a>, (StatusCode, anyhow::Error)> {
+    let settings = Settings::load(matches).map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    for id in required {
+        settings
+            .required(id)
+            .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    }
+    Ok(settings)
+}
+
+async fn prices_response(settings: &Settings<'_>, series: &[PriceSeries]) -> ServeResult {
+    let rounding = parse_rounding(settings)
+        .await
+        .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    let prices = price_responses(series, rounding, false);
+    Ok(serde_json::json!({ "prices": prices }))
+}
+
+/// The prices in `series` as [PriceResponse]s, in the same order as
+/// [write_prices()].
+fn price_responses(
+    series: &[PriceSeries],
+    rounding: Option<u32>,
+    descending: bool,
+) -> Vec<PriceResponse<'_>> {
+    prices::ordered(series, descending)
+        .into_iter()
+        .map(|(prices, date, price)| PriceResponse {
+            date: date.format("%Y-%m-%d").to_string(),
+            commodity: prices.commodity.to_string(),
+            base: prices.base.to_string(),
+            price: match rounding {
+                Some(dp) => price.rate.round_dp(dp),
+                None => price.rate,
+            },
+            annotation: price.annotation.as_deref(),
+        })
+        .collect()
+}
+
+async fn latest_command(settings: &Settings<'_>) -> anyhow::Result<()> {
+    let rounding = parse_rounding(settings).await?;
+    if settings.is_present("watch") {
+        return watch_latest(settings, rounding).await;
+    }
+    print_prices(&latest_prices(settings).await?, rounding, false);
+    Ok(())
+}
+
+/// Print the `amount` converted `from` one commodity `to` another, with
+/// the rate it was converted at.
+async fn convert_command(settings: &Settings<'_>) -> anyhow::Result<()> {
+    let amount = Decimal::from_str(settings.required("amount")?)
+        .map_err(|err| anyhow!("Unable to parse amount: {}", err))?;
+    let from = parse_commodity("from", settings.required("from")?)?;
+    let to = parse_commodity("to", settings.required("to")?)?;
+
+    let source = source(settings)?;
+    let client = Client::new();
+    let provider_config = settings.config.provider(source.name());
+    let app_id = match source {
+        Source::OpenExchangeRates => app_id(settings)?,
+        _ => AppID::default(),
+    };
+    if source == Source::OpenExchangeRates {
+        if !settings.is_present("no-commodity-check") {
+            check_commodities(
+                &client,
+                cache(settings).as_ref(),
+                &provider_config,
+                &[from, to],
+                false,
+            )
+            .await?;
+        }
+        // Reading the plan is a request too, like the quota check.
+        let usage = OpenExchangeRates::new(&client, &app_id).usage().await?;
+        if !usage.data.plan.features.convert {
+            return Err(anyhow!(
+                "Your {} plan doesn't include the convert api, use the latest command \
+                for the rates instead",
+                usage.data.plan.name
+            ));
+        }
+    }
+    let api_key = api_key(settings, source)?;
+    let provider = source.provider(Setup {
+        client: &client,
+        config: &provider_config,
+        app_id: &app_id,
+        api_key: api_key.as_deref(),
+        base: to,
+        alternative: false,
+        snapshot_time: None,
+    })?;
+    let conversion = provider.convert(amount, from, to).await?;
+
+    let mut note = format!("at {} {} per {}", conversion.rate, to, from);
+    if let Some(timestamp) = conversion.timestamp {
+        note.push_str(&format!(
+            ", published {}",
+            timestamp.format("%Y-%m-%d %H:%M UTC")
+        ));
+    }
+    println!("{} {} ; {}", conversion.amount, to, note);
+    Ok(())
+}
+
+/// Parse an interval such as `90s`, `10m` or `1h`.
+fn parse_interval(interval: &str) -> anyhow::Result<std::time::Duration> {
+    let expected = || anyhow!("expected an interval such as 90s, 10m or 1h");
+    let (count, seconds) = [("s", 1), ("m", 60), ("h", 60 * 60)]
+        .iter()
+        .find_map(|(unit, seconds)| Some((interval.strip_suffix(unit)?, *seconds)))
+        .ok_or_else(expected)?;
+    let count: u64 = count
+        .parse()
+        .map_err(|err| anyhow!("invalid interval {:?}: {}", interval, err))?;
+    if count == 0 {
+        return Err(anyhow!("the interval must be more than 0"));
+    }
+    count
+        .checked_mul(seconds)
+        .map(std::time::Duration::from_secs)
+        .ok_or_else(expected)
+}
+
+/// `latest --watch`: fetch the latest prices every `--interval`, until the
+/// process is stopped, and print (or append to `--output`) those which
+/// changed since the last price (in the output, if it already has one).
+async fn watch_latest(settings: &Settings<'_>, rounding: Option<u32>) -> anyhow::Result<()> {
+    // Running until stopped, so there is no end to report them at.
+    WARNINGS.defer(false);
+    let interval = parse_interval(settings.required("interval")?)
+        .map_err(|err| anyhow!("Unable to parse interval: {}", err))?;
+    let output = settings.value_of("output").map(PathBuf::from);
+    let mut last = match &output {
+        Some(path) => last_prices(path)?,
+        None => HashMap::new(),
+    };
+
+    loop {
+        match latest_prices(settings).await {
+            Ok(series) => {
+                let mut changed = Vec::new();
+                for mut prices in series {
+                    let pair = (prices.commodity.to_string(), prices.base.to_string());
+                    // Compared as they are written, so changes which round away
+                    // aren't written.
+                    prices.prices.retain(|_, price| {
+                        let rate = match rounding {
+                            Some(dp) => price.rate.round_dp(dp),
+                            None => price.rate,
+                        };
+                        last.insert(pair.clone(), rate) != Some(rate)
+                    });
+                    changed.push(prices);
+                }
+                let mut changed_bytes: Vec<u8> = Vec::new();
+                write_prices(&mut changed_bytes, &changed, rounding, false)
+                    .map_err(|err| anyhow!("Unable to write the prices: {}", err))?;
+                match &output {
+                    Some(path) => append_to(path, &changed_bytes)?,
+                    None => std::io::stdout()
+                        .write_all(&changed_bytes)
+                        .map_err(|err| anyhow!("Unable to write the prices: {}", err))?,
+                }
+            }
+            // Keep watching, the provider may be back by the next interval.
+            Err(error) => eprintln!("Error: {}", error),
+        }
+        tokio::time::delay_for(interval).await;
+    }
+}
+
+/// Fetch the latest prices for the `latest` subcommand, one series (with
+/// a single price) for each commodity.
+async fn latest_prices(settings: &Settings<'_>) -> anyhow::Result<Vec<PriceSeries>> {
+    let selection = CommoditySelection::from_settings(settings)?;
+    let as_of = settings
+        .value_of("as-of")
+        .map(|date| parse_date_value(date).map_err(|err| anyhow!("Unable to parse as-of: {}", err)))
+        .transpose()?;
+
+    let source = source(settings)?;
+    let client = Client::new();
+    let provider_config = settings.config.provider(source.name());
+    let include = selection.include(source);
+    if source == Source::OpenExchangeRates && !settings.is_present("no-commodity-check") {
+        check_commodities(
+            &client,
+            cache(settings).as_ref(),
+            &provider_config,
+            &include,
+            settings.is_present("alternative"),
+        )
+        .await?;
+    }
+    let app_id = match source {
+        Source::OpenExchangeRates => app_id(settings)?,
+        _ => AppID::default(),
+    };
+    let api_key = api_key(settings, source)?;
+    let provider = source.provider(Setup {
+        client: &client,
+        config: &provider_config,
+        app_id: &app_id,
+        api_key: api_key.as_deref(),
+        base: selection.base,
+        alternative: settings.is_present("alternative"),
+        snapshot_time: None,
+    })?;
+    let (mut exchange_rate, timestamp) = provider.latest(Some(include)).await?;
+
+    let provider_date = exchange_rate
+        .date
+        .ok_or_else(|| anyhow!("The latest rates have no date"))?;
+    let date = as_of.unwrap_or(provider_date);
+    exchange_rate.date = Some(date);
+
+    let mut map = BTreeMap::new();
+    map.insert(date, exchange_rate);
+    let series = TimeSeries::new(map);
+    warn_absent(&series, &selection.request_commodities());
+
+    let mut latest = Vec::new();
+    for commodity in &selection.commodities {
+        let mut prices = PriceSeries::from_time_series(
+            &series,
+            *commodity,
+            selection.base,
+            selection.pivot.as_ref(),
+        )?;
+        check_bounds(settings, &mut prices)?;
+
+        if date != provider_date {
+            let published = match timestamp {
+                Some(timestamp) => timestamp.format("%Y-%m-%d %H:%M UTC").to_string(),
+                None => provider_date.format("%Y-%m-%d").to_string(),
+            };
+            for price in prices.prices.values_mut() {
+                let note = format!("latest rate published {}", published);
+                price.annotation = Some(match price.annotation.take() {
+                    Some(annotation) => format!("{}, {}", annotation, note),
+                    None => note,
+                });
+            }
+        }
+
+        latest.push(prices);
+    }
+
+    Ok(latest)
+}
+
+async fn stats_command(settings: &Settings<'_>) -> anyhow::Result<()> {
+    let rounding = parse_rounding(settings).await?;
+    let fetched = fetch(settings).await?;
+
+    let mut table: Vec<Vec<String>> = vec![[
+        "PAIR",
+        "START",
+        "END",
+        "MIN",
+        "MAX",
+        "MEAN",
+        "MEDIAN",
+        "CHANGE",
+        "VOLATILITY",
+        "MAX DRAWDOWN",
+    ]
+    .iter()
+    .map(|heading| heading.to_string())
+    .collect()];
+
+    for commodity in &fetched.commodities {
+        let mut prices = PriceSeries::from_time_series(
+            &fetched.series,
+            *commodity,
+            fetched.base,
+            fetched.pivot.as_ref(),
+        )?;
+        check_missing(&mut prices, missing_rates(settings)?)?;
+        let pair = format!("{}/{}", commodity, fetched.base);
+        let stats = match prices.stats() {
+            Some(stats) => stats,
+            None => {
+                warn(Kind::NoPrices, format!("no prices for {}", pair));
+                continue;
+            }
+        };
+
+        if settings.is_present("chart") {
+            print_chart(&prices, rounding);
+        }
+
+        let round = |rate: Decimal| match rounding {
+            Some(dp) => rate.round_dp(dp).to_string(),
+            None => rate.to_string(),
+        };
+        let change = match stats.change_percent {
+            Some(change_percent) => format!("{:+}%", change_percent.round_dp(2)),
+            None => String::from("-"),
+        };
+
+        table.push(vec![
+            pair,
+            format!(
+                "{} ({})",
+                round(stats.start.1),
+                stats.start.0.format("%Y-%m-%d")
+            ),
+            format!(
+                "{} ({})",
+                round(stats.end.1),
+                stats.end.0.format("%Y-%m-%d")
+            ),
+            round(stats.min),
+            round(stats.max),
+            round(stats.mean),
+            round(stats.median),
+            change,
+            match stats.volatility_percent {
+                Some(volatility_percent) => format!("{:.2}%", volatility_percent),
+                None => String::from("-"),
+            },
+            format!("{}%", stats.max_drawdown_percent.round_dp(2)),
+        ]);
+    }
+
+    print_table(&table);
+    report_fetch(settings, &fetched);
+    Ok(())
+}
+
+/// Export the prices for the `export` subcommand into a database table,
+/// see [export::sql()].
+async fn export_command(settings: &Settings<'_>) -> anyhow::Result<()> {
+    let rounding = parse_rounding(settings).await?;
+    let engine = Engine::from_str(
+        settings
+            .value_of("engine")
+            .expect("expected engine to be specified"),
+    )?;
+    let table = settings
+        .value_of("table")
+        .expect("expected table to be specified");
+    export::check_table(table)?;
+    let database = settings
+        .value_of("database")
+        .expect("expected database to be specified");
+
+    let fetched = fetch(settings).await?;
+    let mut series = Vec::new();
+    for commodity in &fetched.commodities {
+        let mut prices = PriceSeries::from_time_series(
+            &fetched.series,
+            *commodity,
+            fetched.base,
+            fetched.pivot.as_ref(),
+        )?;
+        check_missing(&mut prices, missing_rates(settings)?)?;
+        check_bounds(settings, &mut prices)?;
+        series.push(prices);
+    }
+
+    let source = settings
+        .value_of("source")
+        .expect("expected source to be specified");
+    let sql = export::sql(table, &series, source, rounding);
+    if database == "-" {
+        print!("{}", sql);
+    } else {
+        export::execute(engine, Path::new(database), &sql)?;
+        let count: usize = series.iter().map(|prices| prices.prices.len()).sum();
+        eprintln!("Exported {} price(s) into {} in {}", count, table, database);
+    }
+
+    report_fetch(settings, &fetched);
+    Ok(())
+}
+
+/// Browse the cached rates for the `tui` subcommand, then carry out the
+/// actions marked while browsing.
+async fn tui_command(settings: &Settings<'_>) -> anyhow::Result<()> {
+    let rounding = parse_rounding(settings).await?;
+    let base = parse_commodity(
+        "base",
+        settings
+            .value_of("base")
+            .expect("expected base to be specified"),
+    )?;
+    let cache =
+        cache(settings).ok_or_else(|| anyhow!("There is no cache to browse, see --cache-dir"))?;
+
+    let mut map = BTreeMap::new();
+    for date in cache.dates()? {
+        if let Some(exchange_rate) = cache.get(&date)? {
+            map.insert(date, exchange_rate);
+        }
+    }
+    let series = TimeSeries::new(map);
+
+    let mut exports: BTreeMap<(CommodityTypeID, CommodityTypeID), PriceSeries> = BTreeMap::new();
+    let mut refetch: BTreeSet<NaiveDate> = BTreeSet::new();
+    for action in tui::browse(&series, base)? {
+        match action {
+            Action::Export {
+                commodity,
+                base,
+                start,
+                end,
+            } => {
+                let mut prices = PriceSeries::from_time_series(&series, commodity, base, None)?;
+                prices.prices.retain(|date, _| (start..=end).contains(date));
+                match exports.get_mut(&(commodity, base)) {
+                    Some(exported) => exported.prices.append(&mut prices.prices),
+                    None => {
+                        exports.insert((commodity, base), prices);
+                    }
+                }
+            }
+            Action::Refetch { start, end } => {
+                refetch.extend(series.map.range(start..=end).map(|(date, _)| *date))
+            }
+        }
+    }
+
+    let exports: Vec<PriceSeries> = exports.into_values().collect();
+    print_prices(&exports, rounding, false);
+
+    if !refetch.is_empty() {
+        let app_id = app_id(settings)?;
+        confirm_requests(
+            settings,
+            Source::OpenExchangeRates,
+            refetch.len(),
+            &format!("to re-fetch {} date(s)", refetch.len()),
+        )?;
+        let client = Client::new();
+        let provider = OpenExchangeRates::new(&client, &app_id).with_symbols(
+            settings
+                .config
+                .provider(openexchangerate::PROVIDER_NAME)
+                .symbols,
+        );
+        for date in &refetch {
+            let snapshot = provider.historical(date, None).await.map_err(|err| {
+                anyhow!("Unable to re-fetch {}: {}", date.format("%Y-%m-%d"), err)
+            })?;
+            cache.put(&snapshot.exchange_rate)?;
+        }
+        eprintln!("Re-fetched the rates for {} date(s)", refetch.len());
+    }
+
+    Ok(())
+}
+
+/// Print `series` as beancount price directives, see [write_prices()].
+fn print_prices(series: &[PriceSeries], rounding: Option<u32>, descending: bool) {
+    write_prices(&mut std::io::stdout(), series, rounding, descending)
+        .expect("unable to write to stdout");
+}
+
+/// Write the prices in `series` to `out` as beancount price directives,
+/// in ascending date order unless `descending` (see [prices::ordered()]).
+fn write_prices(
+    out: &mut dyn Write,
+    series: &[PriceSeries],
+    rounding: Option<u32>,
+    descending: bool,
+) -> std::io::Result<()> {
+    for (prices, date, price) in prices::ordered(series, descending) {
+        let mut rate = price.rate;
+        if let Some(dp) = rounding {
+            rate = rate.round_dp(dp);
+        }
+
+        let annotation = match &price.annotation {
+            Some(annotation) => format!(" ; {}", annotation),
+            None => String::new(),
+        };
+
+        writeln!(
+            out,
+            "{date} price {commodity} {rate} {base}{annotation}",
+            date = date.format("%Y-%m-%d"),
+            commodity = prices.commodity,
+            rate = rate,
+            base = prices.base,
+            annotation = annotation,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Write the prices in `series` as InfluxDB line protocol, one `price`
+/// point per price with `commodity`, `base` and `source` tags and a `rate`
+/// field, timestamped (in nanoseconds) at midnight UTC on its date.
+fn write_influx(
+    out: &mut dyn Write,
+    series: &[PriceSeries],
+    source: &str,
+    rounding: Option<u32>,
+    descending: bool,
+) -> std::io::Result<()> {
+    for (prices, date, price) in prices::ordered(series, descending) {
+        let mut rate = price.rate;
+        if let Some(dp) = rounding {
+            rate = rate.round_dp(dp);
+        }
+        let timestamp = date
+            .and_hms_opt(0, 0, 0)
+            .expect("midnight is a valid time")
+            .and_utc()
+            .timestamp()
+            * 1_000_000_000;
+
+        // Commodity codes are letters, digits and `._-'`, none of which need
+        // escaping in a tag value.
+        writeln!(
+            out,
+            "price,commodity={commodity},base={base},source={source} rate={rate} {timestamp}",
+            commodity = prices.commodity,
+            base = prices.base,
+            source = source,
+            rate = rate,
+            timestamp = timestamp,
+        )?;
+    }
+
+    Ok(())
+}
+
+async fn compare_command(settings: &Settings<'_>) -> anyhow::Result<()> {
+    let rounding = parse_rounding(settings).await?;
+    let previous_start = parse_date(settings, "previous-start")?;
+    let previous_end = parse_date(settings, "previous-end")?;
+    let previous = fetch_range(settings, previous_start, previous_end).await?;
+    report_fetch(settings, &previous);
+    let current = fetch(settings).await?;
+
+    let mut table: Vec<Vec<String>> = vec![[
+        "PAIR",
+        "START DATE",
+        "START",
+        "END DATE",
+        "END",
+        "CHANGE",
+        "VS PREVIOUS",
+    ]
+    .iter()
+    .map(|heading| heading.to_string())
+    .collect()];
+
+    let round = |rate: Decimal| match rounding {
+        Some(dp) => rate.round_dp(dp).to_string(),
+        None => rate.to_string(),
+    };
+    let percent = |percent: Option<Decimal>| match percent {
+        Some(percent) => format!("{:+}%", percent.round_dp(2)),
+        None => String::from("-"),
+    };
+
+    for commodity in &current.commodities {
+        let pair = format!("{}/{}", commodity, current.base);
+        let mut previous_end_rate: Option<Decimal> = None;
+
+        for fetched in &[&previous, &current] {
+            let mut prices = PriceSeries::from_time_series(
+                &fetched.series,
+                *commodity,
+                fetched.base,
+                fetched.pivot.as_ref(),
+            )?;
+            check_missing(&mut prices, missing_rates(settings)?)?;
+            let stats = match prices.stats() {
+                Some(stats) => stats,
+                None => {
+                    warn(Kind::NoPrices, format!("no prices for {}", pair));
+                    continue;
+                }
+            };
+
+            // Change of the end price relative to the previous period's.
+            let vs_previous = previous_end_rate
+                .filter(|rate| !rate.is_zero())
+                .map(|rate| (stats.end.1 - rate) / rate * Decimal::from(100));
+            previous_end_rate = Some(stats.end.1);
+
+            table.push(vec![
+                pair.clone(),
+                stats.start.0.format("%Y-%m-%d").to_string(),
+                round(stats.start.1),
+                stats.end.0.format("%Y-%m-%d").to_string(),
+                round(stats.end.1),
+                percent(stats.change_percent),
+                percent(vs_previous),
+            ]);
+        }
+    }
+
+    match settings.value_of("format") {
+        Some("csv") => {
+            for row in &table {
+                println!("{}", row.join(","));
+            }
+        }
+        _ => print_table(&table),
+    }
+    report_fetch(settings, &current);
+    Ok(())
+}
+
+async fn value_command(settings: &Settings<'_>) -> anyhow::Result<()> {
+    let rounding = parse_rounding(settings).await?;
+    let holdings = match (settings.value_of("journal"), settings.value_of("holdings")) {
+        (Some(journal), _) => {
+            let accounts = settings
+                .values_of("account")
+                .expect("expected account to be specified");
+            Holdings::from_journal(Path::new(journal), &accounts)?
+        }
+        (None, Some(path)) => Holdings::from_csv(Path::new(path))?,
+        (None, None) => {
+            return Err(anyhow!(
+                "There are no holdings to value, give a journal with --journal or a CSV with \
+                --holdings"
+            ))
+        }
+    };
+    let start_date = parse_date(settings, "start")?;
+    let end_date = match settings.value_of("end") {
+        Some(_) => parse_date(settings, "end")?,
+        None => start_date,
+    };
+    let base = parse_commodity("base", settings.required("base")?)?.to_string();
+
+    // Only the base and the commodities the provider has rates for can be
+    // valued.
+    let mut held = holdings.commodities();
+    if let Some(commodities) = settings.values_of("commodities") {
+        held.retain(|commodity| commodities.contains(&commodity.as_str()));
+    }
+    let source = source(settings)?;
+    let mut currencies = None;
+    if source == Source::OpenExchangeRates && !settings.is_present("no-commodity-check") {
+        currencies = get_currencies(
+            &Client::new(),
+            cache(settings).as_ref(),
+            settings.is_present("alternative"),
+        )
+        .await
+        .ok();
+    }
+    let provider_config = settings.config.provider(source.name());
+    // Why `commodity` can't be valued, if it can't.
+    let left_out = |commodity: &String| -> Option<String> {
+        if *commodity == base {
+            return None;
+        }
+        let id = match CommodityTypeID::from_str(commodity) {
+            Ok(id) => id,
+            Err(_) if commodity.len() > COMMODITY_TYPE_ID_LENGTH => {
+                return Some(format!(
+                    "{} (longer than the {} characters a commodity can have)",
+                    commodity, COMMODITY_TYPE_ID_LENGTH
+                ))
+            }
+            Err(_) => return Some(format!("{} (not a valid commodity code)", commodity)),
+        };
+        match &currencies {
+            Some(currencies) if !currencies.contains_key(&provider_config.symbol(&id)) => {
+                Some(format!(
+                    "{} (not one of the currencies {} has)",
+                    commodity,
+                    source.name()
+                ))
+            }
+            _ => None,
+        }
+    };
+    let mut valued = BTreeSet::new();
+    let mut unvalued = Vec::new();
+    for commodity in held {
+        match left_out(&commodity) {
+            Some(reason) => unvalued.push(reason),
+            None => {
+                valued.insert(commodity);
+            }
+        }
+    }
+    if !unvalued.is_empty() {
+        warn(
+            Kind::Commodity,
+            format!(
+                "{} doesn't have rates for some of the holdings, leaving them out of the \
+                value: {}",
+                source.name(),
+                unvalued.join(", ")
+            ),
+        );
+    }
+    let commodities: Vec<&str> = valued
+        .iter()
+        .map(String::as_str)
+        .filter(|commodity| *commodity != base)
+        .collect();
+
+    let selection = CommoditySelection::with_commodities(settings, commodities)?;
+    let fetched = fetch_selection(settings, selection, start_date, end_date).await?;
+    let mut prices: BTreeMap<String, PriceSeries> = BTreeMap::new();
+    for commodity in &fetched.commodities {
+        let mut series = PriceSeries::from_time_series(
+            &fetched.series,
+            *commodity,
+            fetched.base,
+            fetched.pivot.as_ref(),
+        )?;
+        check_missing(&mut series, missing_rates(settings)?)?;
+        prices.insert(commodity.to_string(), series);
+    }
+
+    // The rate of `commodity` on `date`, if it can be valued.
+    let rate = |commodity: &str, date: &NaiveDate| -> Option<Decimal> {
+        if commodity == base {
+            return Some(Decimal::ONE);
+        }
+        prices
+            .get(commodity)?
+            .prices
+            .get(date)
+            .map(|price| price.rate)
+    };
+    // Values are given to the base commodity's smallest unit.
+    let decimal_places = metadata::iso_decimal_places(&fetched.base.to_string()).unwrap_or(2);
+    let amount = |value: Decimal| format!("{:.*}", decimal_places as usize, value);
+    let dash = || String::from("-");
+
+    let dates: Vec<NaiveDate> = fetched.series.map.keys().copied().collect();
+    let mut table: Vec<Vec<String>>;
+    if let [date] = dates.as_slice() {
+        table = vec![vec![
+            String::from("COMMODITY"),
+            String::from("QUANTITY"),
+            String::from("RATE"),
+            format!("VALUE ({})", fetched.base),
+        ]];
+        // Only a total of every holding is given, not a partial one (like
+        // the totals of a range below).
+        let mut total = Some(Decimal::ZERO);
+        for (commodity, quantity) in holdings.on(date) {
+            if !valued.contains(&commodity) {
+                continue;
+            }
+            let rate = rate(&commodity, date);
+            let value = rate.map(|rate| quantity * rate);
+            total = total.zip(value).map(|(total, value)| total + value);
+            table.push(vec![
+                commodity,
+                quantity.to_string(),
+                rate.map_or_else(dash, |rate| match rounding {
+                    Some(dp) => rate.round_dp(dp).to_string(),
+                    None => rate.to_string(),
+                }),
+                value.map_or_else(dash, amount),
+            ]);
+        }
+        table.push(vec![
+            String::from("TOTAL"),
+            String::new(),
+            String::new(),
+            total.map_or_else(dash, amount),
+        ]);
+    } else {
+        // A column for each commodity held at some point in the range.
+        let columns: BTreeSet<String> = dates
+            .iter()
+            .flat_map(|date| holdings.on(date).into_keys())
+            .filter(|commodity| valued.contains(commodity))
+            .collect();
+        let mut heading = vec![String::from("DATE")];
+        heading.extend(columns.iter().cloned());
+        heading.push(format!("TOTAL ({})", fetched.base));
+        table = vec![heading];
+        for date in &dates {
+            let quantities = holdings.on(date);
+            let mut row = vec![date.format("%Y-%m-%d").to_string()];
+            let mut total = Some(Decimal::ZERO);
+            for commodity in &columns {
+                let value = match quantities.get(commodity) {
+                    Some(quantity) => rate(commodity, date).map(|rate| *quantity * rate),
+                    None => Some(Decimal::ZERO),
+                };
+                total = total.zip(value).map(|(total, value)| total + value);
+                row.push(value.map_or_else(dash, amount));
+            }
+            row.push(total.map_or_else(dash, amount));
+            table.push(row);
+        }
+    }
+
+    match settings.value_of("format") {
+        Some("csv") => {
+            for row in &table {
+                println!("{}", row.join(","));
+            }
+        }
+        _ => print_table(&table),
+    }
+    report_fetch(settings, &fetched);
+    Ok(())
+}
+
+/// Width (in characters) of the sparklines printed by `--chart`.
+const CHART_WIDTH: usize = 60;
+
+/// Print a sparkline of `prices` (to stderr, so it doesn't end up in the
+/// price listings), labelled with the range of prices.
+fn print_chart(prices: &PriceSeries, rounding: Option<u32>) {
+    let rates: Vec<Decimal> = prices.prices.values().map(|price| price.rate).collect();
+    let (min, max) = match (rates.iter().min(), rates.iter().max()) {
+        (Some(min), Some(max)) => (*min, *max),
+        _ => return,
+    };
+    let round = |rate: Decimal| rate.round_dp(rounding.unwrap_or(4));
+
+    eprintln!(
+        "{}/{} {} {} {}",
+        prices.commodity,
+        prices.base,
+        round(min),
+        sparkline(&rates, CHART_WIDTH),
+        round(max),
+    );
+}
+
+/// Print `rows` as columns aligned on the widest cell, separated by
+/// two spaces.
+fn print_table(rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            if i >= widths.len() {
+                widths.push(0);
+            }
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+
+    for row in rows {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<String>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use beancount_price_fetcher::prices::Price;
+
+    fn series(commodity: &str, base: &str, prices: &[(u32, &str)]) -> PriceSeries {
+        PriceSeries {
+            commodity: CommodityTypeID::from_str(commodity).unwrap(),
+            base: CommodityTypeID::from_str(base).unwrap(),
+            prices: prices
+                .iter()
+                .map(|(day, rate)| {
+                    let price = Price {
+                        rate: Decimal::from_str(rate).unwrap(),
+                        annotation: None,
+                    };
+                    (NaiveDate::from_ymd_opt(2021, 3, *day).unwrap(), price)
+                })
+                .collect(),
+            missing: Vec::new(),
+        }
+    }
+
+    fn written(series: &[PriceSeries], descending: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_prices(&mut out, series, Some(4), descending).unwrap();
+        out
+    }
+
+    #[test]
+    fn settings_precedence() {
+        let dir = std::env::temp_dir().join(format!(
+            "beancount-price-fetcher-settings-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("config.toml");
+        std::fs::write(
+            &config,
+            "[defaults]\n\
+            commodities = [\"NZD\", \"USD\"]\n\
+            base = \"EUR\"\n\
+            end = \"2021-03-10\"\n\
+            rounding = 4\n\
+            desc = true\n\
+            forward_fill = true\n\
+            interpolate = true\n",
+        )
+        .unwrap();
+        for (name, value) in [
+            ("BASE", "AUD"),
+            ("END", "2021-03-05"),
+            ("DESC", "false"),
+            ("FORWARD_FILL", "false"),
+            ("CACHE_DIR", "/x/My Cache"),
+        ] {
+            std::env::set_var(format!("{}{}", ENV_PREFIX, name), value);
+        }
+
+        let matches = app()
+            .try_get_matches_from([
+                "beancount-price-fetcher",
+                "series",
+                "--config",
+                config.to_str().unwrap(),
+                "--base",
+                "USD",
+                "--forward-fill",
+            ])
+            .unwrap();
+        let settings = Settings::load(matches.subcommand_matches("series").unwrap()).unwrap();
+
+        // The command line over the environment over the config.
+        assert_eq!(settings.value_of("base"), Some("USD"));
+        assert!(settings.is_present("forward-fill"));
+        // The environment over the config, including a flag turned off.
+        assert_eq!(settings.value_of("end"), Some("2021-03-05"));
+        assert!(!settings.is_present("desc"));
+        // Only in the config.
+        assert_eq!(settings.value_of("rounding"), Some("4"));
+        assert_eq!(settings.values_of("commodities"), Some(vec!["NZD", "USD"]));
+        assert!(settings.is_present("interpolate"));
+        // In none of them.
+        assert_eq!(settings.value_of("start"), None);
+        assert!(!settings.is_present("strict"));
+
+        // Only the values of lists are split up.
+        std::env::set_var(format!("{}COMMODITIES", ENV_PREFIX), "GBP, JPY");
+        let settings = Settings::load(matches.subcommand_matches("series").unwrap()).unwrap();
+        assert_eq!(settings.values_of("commodities"), Some(vec!["GBP", "JPY"]));
+        assert_eq!(settings.value_of("cache-dir"), Some("/x/My Cache"));
+
+        for name in [
+            "BASE",
+            "END",
+            "DESC",
+            "FORWARD_FILL",
+            "OUTPUT",
+            "COMMODITIES",
+        ] {
+            std::env::remove_var(format!("{}{}", ENV_PREFIX, name));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn relative_dates() {
+        let today = Utc::now().date_naive();
+        assert_eq!(parse_date_value("-7d").unwrap(), today - Duration::days(7));
+        assert_eq!(parse_date_value("-2w").unwrap(), today - Duration::days(14));
+        assert!(parse_date_value("-7\u{e9}").is_err());
+        assert!(parse_date_value("-\u{e9}").is_err());
+        assert!(parse_date_value("-99999999999999d").is_err());
+        assert!(parse_date_value("-99999999999999999w").is_err());
+    }
+
+    #[test]
+    fn intervals() {
+        assert_eq!(
+            parse_interval("90s").unwrap(),
+            std::time::Duration::from_secs(90)
+        );
+        assert_eq!(
+            parse_interval("2h").unwrap(),
+            std::time::Duration::from_secs(2 * 60 * 60)
+        );
+        assert!(parse_interval("1\u{e9}").is_err());
+        assert!(parse_interval("0m").is_err());
+        assert!(parse_interval("18446744073709551615h").is_err());
+    }
+
+    #[test]
+    fn write_prices_is_deterministic() {
+        let series = vec![
+            series("EUR", "USD", &[(2, "1.19"), (1, "1.2")]),
+            series("AUD", "USD", &[(1, "0.77"), (2, "0.7712345")]),
+        ];
+        let reversed: Vec<PriceSeries> = series.iter().rev().cloned().collect();
+
+        let ascending = written(&series, false);
+        assert_eq!(
+            String::from_utf8(ascending.clone()).unwrap(),
+            "2021-03-01 price AUD 0.77 USD\n\
+            2021-03-01 price EUR 1.2 USD\n\
+            2021-03-02 price AUD 0.7712 USD\n\
+            2021-03-02 price EUR 1.19 USD\n"
+        );
+        assert_eq!(written(&series, false), ascending);
+        assert_eq!(written(&reversed, false), ascending);
+
+        let descending = written(&series, true);
+        assert_eq!(
+            String::from_utf8(descending.clone()).unwrap(),
+            "2021-03-02 price AUD 0.7712 USD\n\
+            2021-03-02 price EUR 1.19 USD\n\
+            2021-03-01 price AUD 0.77 USD\n\
+            2021-03-01 price EUR 1.2 USD\n"
+        );
+        assert_eq!(written(&reversed, true), descending);
+    }
 }
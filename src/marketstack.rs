@@ -0,0 +1,234 @@
+use crate::{
+    config::ProviderConfig,
+    network::request_json,
+    pricesource::{self, PriceSource},
+    series::{Closes, Snapshot, TimeSeries},
+};
+use anyhow::anyhow;
+use chrono::{NaiveDate, NaiveDateTime};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "marketstack";
+
+/// The environment variable the access key can be given in.
+pub const API_KEY_ENV: &str = "MARKETSTACK_ACCESS_KEY";
+
+const API_URL: &str = "https://api.marketstack.com/v1/eod";
+
+/// The most symbols a request may have.
+const BATCH_SIZE: usize = 100;
+
+/// The most days (over all the symbols) a page of a response has.
+const PAGE_SIZE: usize = 1000;
+
+#[derive(Deserialize, Debug)]
+struct ApiError {
+    code: String,
+    message: String,
+}
+
+/// Data from the `/eod` endpoint, a page of the days of all the symbols.
+#[derive(Deserialize, Debug)]
+struct Response {
+    pagination: Option<Pagination>,
+    #[serde(default)]
+    data: Vec<Day>,
+    error: Option<ApiError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Pagination {
+    offset: usize,
+    count: usize,
+    total: usize,
+}
+
+#[derive(Deserialize, Debug)]
+struct Day {
+    /// e.g. `2020-01-02T00:00:00+0000`.
+    date: String,
+    /// The exchange qualified ticker, as requested (but upper case).
+    symbol: String,
+    /// The unadjusted close.
+    close: Option<f64>,
+}
+
+/// The number of requests fetching `symbols` symbols from `start` to `end`
+/// takes, if every day is a trading day.
+pub fn requests(symbols: usize, start: &NaiveDate, end: &NaiveDate) -> usize {
+    let days = (end.signed_duration_since(*start).num_days() + 1).max(1) as usize;
+    (0..symbols)
+        .step_by(BATCH_SIZE)
+        .map(|first| {
+            let batch = std::cmp::min(BATCH_SIZE, symbols - first);
+            (batch * days).div_ceil(PAGE_SIZE)
+        })
+        .sum()
+}
+
+/// Fetch the end of day closes of the equities `symbols` (each commodity
+/// and its Marketstack ticker, e.g. `BHP.XASX`) from `start` to `end`, in
+/// batches of up to 100 symbols a request and pages of 1000 days.
+/// Marketstack doesn't say which currency a ticker is quoted in, so its
+/// prices are taken to be in `base`.
+pub async fn get_closes(
+    client: &Client,
+    access_key: &str,
+    symbols: &[(CommodityTypeID, String)],
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Vec<Closes>> {
+    let mut all = Vec::new();
+    for batch in symbols.chunks(BATCH_SIZE) {
+        let list: Vec<String> = batch
+            .iter()
+            .map(|(_, symbol)| {
+                url::form_urlencoded::byte_serialize(symbol.as_bytes()).collect::<String>()
+            })
+            .collect();
+        let mut days: BTreeMap<String, BTreeMap<NaiveDate, Decimal>> = BTreeMap::new();
+        let mut offset = 0;
+        loop {
+            let url = format!(
+                "{api_url}?access_key={access_key}&symbols={symbols}&date_from={start}\
+                &date_to={end}&limit={limit}&offset={offset}",
+                api_url = API_URL,
+                access_key = access_key,
+                symbols = list.join(","),
+                start = start.format("%Y-%m-%d"),
+                end = end.format("%Y-%m-%d"),
+                limit = PAGE_SIZE,
+                offset = offset,
+            );
+            let response: Response = request_json(client, &url).await?;
+            if let Some(error) = response.error {
+                return Err(anyhow!(
+                    "{} responded with error {}: {}",
+                    PROVIDER_NAME,
+                    error.code,
+                    error.message
+                ));
+            }
+            for day in response.data {
+                let date = day
+                    .date
+                    .get(..10)
+                    .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+                    .ok_or_else(|| anyhow!("Unable to parse the date {:?}", day.date))?;
+                if let (true, Some(close)) = (
+                    (start..=end).contains(&&date),
+                    day.close.and_then(Decimal::from_f64),
+                ) {
+                    days.entry(day.symbol.to_uppercase())
+                        .or_default()
+                        .insert(date, close);
+                }
+            }
+            match response.pagination {
+                Some(pagination)
+                    if pagination.count > 0
+                        && pagination.offset + pagination.count < pagination.total =>
+                {
+                    offset = pagination.offset + pagination.count
+                }
+                _ => break,
+            }
+        }
+        for (commodity, symbol) in batch {
+            all.push(Closes {
+                commodity: *commodity,
+                currency: base,
+                closes: days.remove(&symbol.to_uppercase()).unwrap_or_default(),
+            });
+        }
+    }
+    Ok(all)
+}
+
+/// Marketstack as a [PriceSource], with the symbols in its `config` and the
+/// prices in `base`. A series is quoted as a table, with a request for
+/// every commodity.
+pub struct Marketstack<'a> {
+    client: &'a Client,
+    api_key: &'a str,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> Marketstack<'a> {
+    pub fn new(
+        client: &'a Client,
+        api_key: &'a str,
+        config: &'a ProviderConfig,
+        base: CommodityTypeID,
+    ) -> Marketstack<'a> {
+        Marketstack {
+            client,
+            api_key,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for Marketstack<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn series(
+        &self,
+        start: &NaiveDate,
+        end: &NaiveDate,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<Vec<Snapshot>> {
+        let closes = get_closes(
+            self.client,
+            self.api_key,
+            &self
+                .config
+                .symbols_for(&pricesource::priced(self.base, include)),
+            self.base,
+            start,
+            end,
+        )
+        .await?;
+        Ok(pricesource::snapshots(TimeSeries::from_closes(
+            self.base, &closes,
+        )?))
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        let symbol = self.config.symbol(&commodity);
+        get_closes(
+            self.client,
+            self.api_key,
+            &[(commodity, symbol.clone())],
+            self.base,
+            start,
+            end,
+        )
+        .await?
+        .pop()
+        .ok_or_else(|| anyhow!("{} has no prices for {}", PROVIDER_NAME, symbol))
+    }
+}
@@ -0,0 +1,54 @@
+use crate::openexchangerate::Currencies;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, convert::TryFrom};
+
+/// Decimal places assumed for the alternative currencies which aren't in
+/// ISO 4217, which are mostly cryptocurrencies.
+const ALTERNATIVE_DECIMAL_PLACES: u32 = 8;
+
+/// How many more decimal places than its base commodity usually has a
+/// price is given to with `--rounding auto`, so converting amounts in the
+/// thousands is still accurate to the base commodity's smallest unit.
+pub const EXTRA_PRICE_DECIMAL_PLACES: u32 = 4;
+
+/// What is known about a currency.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    /// Full name, e.g. New Zealand Dollar.
+    pub name: String,
+    /// Number of decimal places amounts are usually given to (the ISO 4217
+    /// minor units), if known.
+    pub decimal_places: Option<u32>,
+    /// Whether it is an alternative currency (a cryptocurrency, or an
+    /// unofficial rate) rather than an official one.
+    pub alternative: bool,
+}
+
+/// The [Metadata] of the provider's currencies, keyed by their symbol.
+pub type CurrencyMetadata = BTreeMap<String, Metadata>;
+
+/// The metadata for the currencies in `all` (the provider's list including
+/// the alternative currencies), any not in `official` being alternative.
+pub fn from_currencies(official: &Currencies, all: &Currencies) -> CurrencyMetadata {
+    all.iter()
+        .chain(official)
+        .map(|(code, name)| {
+            let alternative = !official.contains_key(code);
+            let metadata = Metadata {
+                name: name.clone(),
+                decimal_places: iso_decimal_places(code).or(if alternative {
+                    Some(ALTERNATIVE_DECIMAL_PLACES)
+                } else {
+                    None
+                }),
+                alternative,
+            };
+            (code.clone(), metadata)
+        })
+        .collect()
+}
+
+/// The ISO 4217 minor units of the currency `code`, if it is in ISO 4217.
+pub fn iso_decimal_places(code: &str) -> Option<u32> {
+    iso4217::alpha3(code).and_then(|currency| u32::try_from(currency.exp).ok())
+}
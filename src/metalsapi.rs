@@ -0,0 +1,276 @@
+use crate::{
+    config::ProviderConfig,
+    network::request_json,
+    pricesource::{self, PriceSource},
+    series::{Snapshot, TimeSeries},
+};
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "metalsapi";
+
+/// The environment variable the access key can be given in.
+pub const API_KEY_ENV: &str = "METALS_API_KEY";
+
+const API_URL: &str = "https://metals-api.com/api";
+
+/// The longest range (in days) a `/timeseries` request may cover.
+const MAX_DAYS: i64 = 30;
+
+/// The units a metal's price can be given per, and how many of them are in
+/// the troy ounce metals are quoted per.
+const UNITS: &[(&str, &str)] = &[("oz", "1"), ("g", "31.1034768"), ("kg", "0.0311034768")];
+
+#[derive(Deserialize, Debug)]
+struct ApiError {
+    code: i64,
+    info: Option<String>,
+}
+
+/// Data from the `/timeseries` endpoint (with `rates` keyed by date) or
+/// the `/latest` endpoint (with `rates` for the time in `timestamp`). Each
+/// rate is the troy ounces of a metal (e.g. `XAU`) a unit of `base` buys.
+#[derive(Deserialize, Debug)]
+struct Response<R> {
+    success: bool,
+    error: Option<ApiError>,
+    timestamp: Option<i64>,
+    rates: Option<R>,
+}
+
+impl<R> Response<R> {
+    fn check(self) -> anyhow::Result<(Option<i64>, R)> {
+        if !self.success {
+            let error = self.error.as_ref();
+            return Err(anyhow!(
+                "{} responded with error {}: {}",
+                PROVIDER_NAME,
+                error.map(|error| error.code).unwrap_or_default(),
+                error
+                    .and_then(|error| error.info.as_deref())
+                    .unwrap_or("unknown error")
+            ));
+        }
+        let timestamp = self.timestamp;
+        self.rates
+            .map(|rates| (timestamp, rates))
+            .ok_or_else(|| anyhow!("{} responded without rates", PROVIDER_NAME))
+    }
+}
+
+/// A commodity's metal, and how many of its units are in a troy ounce,
+/// from a symbol of the form `XAU` or `XAU:g` (see [UNITS]).
+#[derive(Debug)]
+struct Metal {
+    commodity: CommodityTypeID,
+    code: String,
+    units_per_ounce: Decimal,
+}
+
+impl Metal {
+    fn parse(commodity: CommodityTypeID, symbol: &str) -> anyhow::Result<Metal> {
+        let (code, unit) = symbol.split_once(':').unwrap_or((symbol, "oz"));
+        let units_per_ounce = UNITS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(unit))
+            .map(|(_, units)| Decimal::from_str(units).expect("expected a valid number"))
+            .ok_or_else(|| {
+                anyhow!(
+                    "{:?} isn't a unit {} can price {} per, expected one of {}",
+                    unit,
+                    PROVIDER_NAME,
+                    commodity,
+                    UNITS
+                        .iter()
+                        .map(|(name, _)| *name)
+                        .collect::<Vec<&str>>()
+                        .join(", ")
+                )
+            })?;
+        Ok(Metal {
+            commodity,
+            code: code.to_uppercase(),
+            units_per_ounce,
+        })
+    }
+}
+
+/// The number of `/timeseries` requests fetching `start` to `end` takes.
+pub fn requests(start: &NaiveDate, end: &NaiveDate) -> usize {
+    let days = (end.signed_duration_since(*start).num_days() + 1).max(1) as usize;
+    days.div_ceil(MAX_DAYS as usize)
+}
+
+fn parse_metals(symbols: &[(CommodityTypeID, String)]) -> anyhow::Result<Vec<Metal>> {
+    symbols
+        .iter()
+        .map(|(commodity, symbol)| Metal::parse(*commodity, symbol))
+        .collect()
+}
+
+fn codes(metals: &[Metal]) -> String {
+    let mut codes: Vec<&str> = metals.iter().map(|metal| metal.code.as_str()).collect();
+    codes.sort_unstable();
+    codes.dedup();
+    codes.join(",")
+}
+
+/// The exchange rate for `date` from the troy ounce `rates` of each metal
+/// against `base`, in each commodity's unit.
+fn exchange_rate(
+    date: NaiveDate,
+    base: CommodityTypeID,
+    metals: &[Metal],
+    rates: &BTreeMap<String, Decimal>,
+) -> ExchangeRate {
+    ExchangeRate {
+        date: Some(date),
+        obtained_datetime: Some(Utc::now()),
+        base: Some(base),
+        rates: metals
+            .iter()
+            .filter_map(|metal| {
+                let ounces = rates.get(&metal.code)?;
+                Some((metal.commodity, *ounces * metal.units_per_ounce))
+            })
+            .collect(),
+    }
+}
+
+/// Fetch the prices of the metals of `symbols` (each commodity and its
+/// metal, see [Metal::parse()]) in `base` from `start` to `end` with the
+/// `/timeseries` endpoint, a request for each 30 days of the range. Metals
+/// are quoted per troy ounce, a commodity's unit can be grams or kilograms
+/// instead.
+pub async fn get_series(
+    client: &Client,
+    access_key: &str,
+    base: CommodityTypeID,
+    symbols: &[(CommodityTypeID, String)],
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<TimeSeries> {
+    let metals = parse_metals(symbols)?;
+    let mut map = BTreeMap::new();
+    let mut chunk_start = *start;
+    while chunk_start <= *end {
+        let chunk_end = std::cmp::min(*end, chunk_start + Duration::days(MAX_DAYS - 1));
+        let url = format!(
+            "{api_url}/timeseries?access_key={access_key}&start_date={start}&end_date={end}\
+            &base={base}&symbols={symbols}",
+            api_url = API_URL,
+            access_key = access_key,
+            start = chunk_start.format("%Y-%m-%d"),
+            end = chunk_end.format("%Y-%m-%d"),
+            base = base,
+            symbols = codes(&metals),
+        );
+        let response: Response<BTreeMap<NaiveDate, BTreeMap<String, Decimal>>> =
+            request_json(client, &url).await?;
+        let (_, rates) = response.check()?;
+        for (date, rates) in rates {
+            map.insert(date, exchange_rate(date, base, &metals, &rates));
+        }
+        chunk_start = chunk_end + Duration::days(1);
+    }
+    Ok(TimeSeries::new(map))
+}
+
+/// Fetch the latest prices of the metals of `symbols` in `base` with the
+/// `/latest` endpoint, and when they are from.
+pub async fn get_latest(
+    client: &Client,
+    access_key: &str,
+    base: CommodityTypeID,
+    symbols: &[(CommodityTypeID, String)],
+) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+    let metals = parse_metals(symbols)?;
+    let url = format!(
+        "{}/latest?access_key={}&base={}&symbols={}",
+        API_URL,
+        access_key,
+        base,
+        codes(&metals)
+    );
+    let response: Response<BTreeMap<String, Decimal>> = request_json(client, &url).await?;
+    let (timestamp, rates) = response.check()?;
+    let timestamp = timestamp.and_then(|timestamp| NaiveDateTime::from_timestamp_opt(timestamp, 0));
+    let date = timestamp
+        .map(|timestamp| timestamp.date())
+        .unwrap_or_else(|| Utc::now().date_naive());
+    Ok((exchange_rate(date, base, &metals, &rates), timestamp))
+}
+
+/// metals-api as a [PriceSource], with the symbols in its `config` and the
+/// rates against `base` in a table for the whole range.
+pub struct MetalsApi<'a> {
+    client: &'a Client,
+    api_key: &'a str,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> MetalsApi<'a> {
+    pub fn new(
+        client: &'a Client,
+        api_key: &'a str,
+        config: &'a ProviderConfig,
+        base: CommodityTypeID,
+    ) -> MetalsApi<'a> {
+        MetalsApi {
+            client,
+            api_key,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for MetalsApi<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        get_latest(
+            self.client,
+            self.api_key,
+            self.base,
+            &self
+                .config
+                .symbols_for(&pricesource::priced(self.base, include)),
+        )
+        .await
+    }
+
+    async fn series(
+        &self,
+        start: &NaiveDate,
+        end: &NaiveDate,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<Vec<Snapshot>> {
+        Ok(pricesource::snapshots(
+            get_series(
+                self.client,
+                self.api_key,
+                self.base,
+                &self
+                    .config
+                    .symbols_for(&pricesource::priced(self.base, include)),
+                start,
+                end,
+            )
+            .await?,
+        ))
+    }
+}
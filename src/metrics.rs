@@ -0,0 +1,331 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    fmt::Write,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+/// Metrics for all the requests made to providers by this process.
+pub static REQUEST_METRICS: RequestMetrics = RequestMetrics::new();
+
+/// Metrics for the cache, quota and runs of a long running process (the
+/// `daemon` and `serve` subcommands).
+pub static SERVICE_METRICS: ServiceMetrics = ServiceMetrics::new();
+
+/// How many of the latest requests' latencies are kept for the
+/// percentiles, so a long running process doesn't keep every one.
+pub const RECENT_LATENCIES: usize = 1024;
+
+/// Records the latency and outcome of requests.
+#[derive(Debug)]
+pub struct RequestMetrics {
+    records: Mutex<Records>,
+}
+
+#[derive(Debug)]
+struct Records {
+    /// The latencies of the last [RECENT_LATENCIES] requests, oldest
+    /// first.
+    recent: VecDeque<Duration>,
+    requests: usize,
+    failures: usize,
+    /// The total latency of every request.
+    total: Duration,
+    max: Option<Duration>,
+}
+
+impl RequestMetrics {
+    pub const fn new() -> RequestMetrics {
+        RequestMetrics {
+            records: Mutex::new(Records {
+                recent: VecDeque::new(),
+                requests: 0,
+                failures: 0,
+                total: Duration::ZERO,
+                max: None,
+            }),
+        }
+    }
+
+    /// Record a request which took `latency` to complete (or fail).
+    pub fn record(&self, latency: Duration, success: bool) {
+        let mut records = self.records.lock().expect("metrics lock poisoned");
+        if records.recent.len() == RECENT_LATENCIES {
+            records.recent.pop_front();
+        }
+        records.recent.push_back(latency);
+        records.requests += 1;
+        records.total += latency;
+        records.max = records.max.max(Some(latency));
+        if !success {
+            records.failures += 1;
+        }
+    }
+
+    /// Aggregate statistics over the requests recorded so far, see
+    /// [LatencySummary].
+    pub fn summary(&self) -> LatencySummary {
+        let records = self.records.lock().expect("metrics lock poisoned");
+        let mut latencies: Vec<Duration> = records.recent.iter().copied().collect();
+        latencies.sort();
+
+        LatencySummary {
+            requests: records.requests,
+            failures: records.failures,
+            p50: percentile(&latencies, 50),
+            p95: percentile(&latencies, 95),
+            total: records.total,
+            max: records.max,
+        }
+    }
+}
+
+impl Default for RequestMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Nearest-rank percentile of already sorted values.
+fn percentile(sorted: &[Duration], percent: usize) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percent * sorted.len()).div_ceil(100);
+    Some(sorted[rank.max(1) - 1])
+}
+
+/// Aggregate statistics produced by [RequestMetrics::summary()]. The
+/// percentiles are of the last [RECENT_LATENCIES] requests, the rest of
+/// every request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencySummary {
+    pub requests: usize,
+    pub failures: usize,
+    pub p50: Option<Duration>,
+    pub p95: Option<Duration>,
+    pub total: Duration,
+    pub max: Option<Duration>,
+}
+
+impl fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} requests ({} failed)", self.requests, self.failures)?;
+        if let (Some(p50), Some(p95), Some(max)) = (self.p50, self.p95, self.max) {
+            write!(
+                f,
+                ", latency p50 {}ms, p95 {}ms, max {}ms",
+                p50.as_millis(),
+                p95.as_millis(),
+                max.as_millis()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Records how a long running process is doing, exposed alongside the
+/// [REQUEST_METRICS] by [prometheus()].
+#[derive(Debug)]
+pub struct ServiceMetrics {
+    records: Mutex<ServiceRecords>,
+}
+
+#[derive(Debug)]
+struct ServiceRecords {
+    cache_hits: usize,
+    cache_misses: usize,
+    quota_remaining: Option<u32>,
+    runs: usize,
+    run_failures: usize,
+    last_success: Option<SystemTime>,
+}
+
+impl ServiceMetrics {
+    pub const fn new() -> ServiceMetrics {
+        ServiceMetrics {
+            records: Mutex::new(ServiceRecords {
+                cache_hits: 0,
+                cache_misses: 0,
+                quota_remaining: None,
+                runs: 0,
+                run_failures: 0,
+                last_success: None,
+            }),
+        }
+    }
+
+    fn records(&self) -> std::sync::MutexGuard<'_, ServiceRecords> {
+        self.records.lock().expect("metrics lock poisoned")
+    }
+
+    /// Record a lookup in the cache, which was a hit if it found the rates.
+    pub fn record_cache(&self, hit: bool) {
+        let mut records = self.records();
+        if hit {
+            records.cache_hits += 1;
+        } else {
+            records.cache_misses += 1;
+        }
+    }
+
+    /// Record the provider's quota, as last reported.
+    pub fn record_quota(&self, requests_remaining: u32) {
+        self.records().quota_remaining = Some(requests_remaining);
+    }
+
+    /// The provider's quota, as last reported.
+    pub fn quota_remaining(&self) -> Option<u32> {
+        self.records().quota_remaining
+    }
+
+    /// Record a run (of the jobs, or of a request to the server).
+    pub fn record_run(&self, success: bool) {
+        let mut records = self.records();
+        records.runs += 1;
+        if success {
+            records.last_success = Some(SystemTime::now());
+        } else {
+            records.run_failures += 1;
+        }
+    }
+}
+
+impl Default for ServiceMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The [REQUEST_METRICS] and [SERVICE_METRICS] in the Prometheus text
+/// exposition format.
+pub fn prometheus() -> String {
+    let requests = REQUEST_METRICS.summary();
+    let service = SERVICE_METRICS.records();
+    let mut out = String::new();
+    // Each sample is the suffix of the metric's name (with any labels) and
+    // its value.
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(&str, String)]| {
+        let name = format!("beancount_price_fetcher_{}", name);
+        // Writing to a String can't fail.
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for (suffix, value) in samples {
+            let _ = writeln!(out, "{}{} {}", name, suffix, value);
+        }
+    };
+
+    metric(
+        "requests_total",
+        "counter",
+        "Requests made to the provider.",
+        &[("", requests.requests.to_string())],
+    );
+    metric(
+        "request_failures_total",
+        "counter",
+        "Requests to the provider which failed.",
+        &[("", requests.failures.to_string())],
+    );
+    if let (Some(p50), Some(p95)) = (requests.p50, requests.p95) {
+        metric(
+            "request_latency_seconds",
+            "summary",
+            "Latency of the requests to the provider, the quantiles of the last ones.",
+            &[
+                ("{quantile=\"0.5\"}", p50.as_secs_f64().to_string()),
+                ("{quantile=\"0.95\"}", p95.as_secs_f64().to_string()),
+                ("_sum", requests.total.as_secs_f64().to_string()),
+                ("_count", requests.requests.to_string()),
+            ],
+        );
+    }
+    metric(
+        "cache_hits_total",
+        "counter",
+        "Dates found in the cache.",
+        &[("", service.cache_hits.to_string())],
+    );
+    metric(
+        "cache_misses_total",
+        "counter",
+        "Dates which weren't in the cache, and were fetched.",
+        &[("", service.cache_misses.to_string())],
+    );
+    if let Some(quota_remaining) = service.quota_remaining {
+        metric(
+            "quota_requests_remaining",
+            "gauge",
+            "Requests remaining in the provider's quota, as last reported.",
+            &[("", quota_remaining.to_string())],
+        );
+    }
+    metric(
+        "runs_total",
+        "counter",
+        "Runs of the jobs, or requests to the server.",
+        &[("", service.runs.to_string())],
+    );
+    metric(
+        "run_failures_total",
+        "counter",
+        "Runs of the jobs, or requests to the server, which failed.",
+        &[("", service.run_failures.to_string())],
+    );
+    if let Some(seconds) = service
+        .last_success
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+    {
+        metric(
+            "last_success_timestamp_seconds",
+            "gauge",
+            "When the last successful run finished, in seconds since the Unix epoch.",
+            &[("", seconds.as_secs().to_string())],
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{prometheus, RequestMetrics, RECENT_LATENCIES, REQUEST_METRICS};
+    use std::time::Duration;
+
+    #[test]
+    fn percentiles_of_the_recent_requests() {
+        let metrics = RequestMetrics::new();
+        // A slow start, which falls out of the recent latencies.
+        for _ in 0..RECENT_LATENCIES {
+            metrics.record(Duration::from_millis(2000), true);
+        }
+        for millis in 1..=RECENT_LATENCIES as u64 {
+            metrics.record(Duration::from_millis(millis), millis % 2 == 0);
+        }
+
+        let summary = metrics.summary();
+        assert_eq!(summary.requests, 2 * RECENT_LATENCIES);
+        assert_eq!(summary.failures, RECENT_LATENCIES / 2);
+        assert_eq!(summary.p50, Some(Duration::from_millis(512)));
+        assert_eq!(summary.p95, Some(Duration::from_millis(973)));
+        assert_eq!(summary.max, Some(Duration::from_millis(2000)));
+    }
+
+    #[test]
+    fn latency_summary_in_prometheus() {
+        REQUEST_METRICS.record(Duration::from_millis(250), true);
+
+        let metrics = prometheus();
+        assert!(
+            metrics.contains("# TYPE beancount_price_fetcher_request_latency_seconds summary\n")
+        );
+        for sample in [
+            "beancount_price_fetcher_request_latency_seconds{quantile=\"0.5\"} 0.25\n",
+            "beancount_price_fetcher_request_latency_seconds{quantile=\"0.95\"} 0.25\n",
+            "beancount_price_fetcher_request_latency_seconds_sum 0.25\n",
+            "beancount_price_fetcher_request_latency_seconds_count 1\n",
+        ] {
+            assert!(metrics.contains(sample), "{} not in {}", sample, metrics);
+        }
+    }
+}
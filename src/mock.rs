@@ -0,0 +1,102 @@
+use crate::{pricesource::PriceSource, series::Snapshot};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use std::str::FromStr;
+
+/// Name of the mock provider, for `--source` and the `source` of a job.
+pub const PROVIDER_NAME: &str = "mock";
+
+/// The commodity the mock rates are quoted against, like
+/// openexchangerates.
+pub const BASE: &str = "USD";
+
+/// Number of decimal places the mock rates are given to, like
+/// openexchangerates.
+const DECIMAL_PLACES: u32 = 6;
+
+/// A number in `0..1` which depends only on `seed` and `parts`, the same
+/// in every run and on every platform (unlike a `HashMap`'s hasher).
+fn unit(seed: u64, parts: &[&str]) -> f64 {
+    // FNV-1a, with the parts separated so ["ab", "c"] differs from
+    // ["a", "bc"].
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325 ^ seed;
+    for byte in parts.join("\0").bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    // The splitmix64 finalizer, to spread FNV's weak low bits.
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^= hash >> 31;
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// The mock rate of `commodity` (per [BASE]) on `date`: a level between
+/// 0.5 and 150 chosen by the seed, moving ±10% over a year, with up to
+/// ±0.5% of noise from day to day. Each date's rate depends only on the
+/// seed, the commodity and the date, so overlapping ranges agree.
+fn rate(seed: u64, commodity: &str, date: &NaiveDate) -> Decimal {
+    if commodity == BASE {
+        return Decimal::ONE;
+    }
+    let level = (0.5f64.ln() + unit(seed, &[commodity, "level"]) * (150f64 / 0.5).ln()).exp();
+    let phase = unit(seed, &[commodity, "phase"]) * std::f64::consts::TAU;
+    let day = date
+        .signed_duration_since(NaiveDate::from_ymd_opt(1970, 1, 1).expect("expected a valid date"))
+        .num_days() as f64;
+    let cycle = 0.1 * (day / 365.25 * std::f64::consts::TAU + phase).sin();
+    let date = date.format("%Y-%m-%d").to_string();
+    let noise = 0.005 * (2.0 * unit(seed, &[commodity, &date]) - 1.0);
+    Decimal::from_f64(level * (1.0 + cycle) * (1.0 + noise))
+        .expect("expected a finite rate")
+        .round_dp(DECIMAL_PLACES)
+}
+
+/// The mock exchange rate for `date`, with the rates of `commodities`
+/// against [BASE] for the seed `seed`.
+pub fn exchange_rate(seed: u64, date: &NaiveDate, commodities: &[CommodityTypeID]) -> ExchangeRate {
+    ExchangeRate {
+        date: Some(*date),
+        obtained_datetime: Some(Utc::now()),
+        base: Some(CommodityTypeID::from_str(BASE).expect("expected a valid commodity")),
+        rates: commodities
+            .iter()
+            .map(|commodity| (*commodity, rate(seed, &commodity.to_string(), date)))
+            .collect(),
+    }
+}
+
+/// The mock provider as a [PriceSource], with the rates of `seed`. It
+/// makes no requests, so asking for every commodity (`include` of `None`)
+/// gives no rates.
+pub struct Mock {
+    pub seed: u64,
+}
+
+impl PriceSource for Mock {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        let now = Utc::now().naive_utc();
+        let exchange_rate = exchange_rate(self.seed, &now.date(), &include.unwrap_or_default());
+        Ok((exchange_rate, Some(now)))
+    }
+
+    async fn historical(
+        &self,
+        date: &NaiveDate,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<Snapshot> {
+        Ok(Snapshot {
+            requested_date: *date,
+            timestamp: Some(date.and_hms_opt(23, 59, 59).expect("expected a valid time")),
+            exchange_rate: exchange_rate(self.seed, date, &include.unwrap_or_default()),
+        })
+    }
+}
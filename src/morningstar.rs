@@ -0,0 +1,132 @@
+use crate::{
+    config::ProviderConfig,
+    network::request_json,
+    pricesource::{self, PriceSource},
+    series::Closes,
+};
+use anyhow::anyhow;
+use chrono::{NaiveDate, NaiveDateTime};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use std::collections::BTreeMap;
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "morningstar";
+
+/// The price series behind Morningstar's fund charts, which needs no
+/// account.
+const API_URL: &str = "https://tools.morningstar.co.uk/api/rest.svc/timeseries_price/t92wz0sj7c";
+
+/// The security id for the fund `symbol`: `symbol` itself if it is one
+/// (e.g. `F00000OPX3]2]0]FOALL$$ALL`), otherwise the fund's Morningstar
+/// ID (e.g. `F00000OPX3`) in any market.
+fn security_id(symbol: &str) -> String {
+    if symbol.contains(']') {
+        symbol.to_string()
+    } else {
+        format!("{}]2]0]FOALL$$ALL", symbol)
+    }
+}
+
+/// Fetch the daily unit prices (NAVs) of the fund `symbol` (see
+/// [security_id()]) in `base` from `start` to `end`, as the prices of
+/// `commodity`. Morningstar converts the prices of a fund quoted in
+/// another currency.
+pub async fn get_closes(
+    client: &Client,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Closes> {
+    let url = format!(
+        "{api_url}?id={id}&currencyId={base}&idtype=Morningstar&frequency=daily\
+        &startDate={start}&endDate={end}&outputType=COMPACTJSON",
+        api_url = API_URL,
+        id = url::form_urlencoded::byte_serialize(security_id(symbol).as_bytes())
+            .collect::<String>(),
+        base = base,
+        start = start.format("%Y-%m-%d"),
+        end = end.format("%Y-%m-%d"),
+    );
+    // Each price is the date it is for (UNIX time in milliseconds) and
+    // the NAV. A fund Morningstar doesn't have is an empty response.
+    let body: serde_json::Value = request_json(client, &url).await?;
+    let prices: Vec<(i64, f64)> = match body {
+        serde_json::Value::Array(_) => serde_json::from_value(body)
+            .map_err(|err| anyhow!("Unable to parse the prices of {}: {}", symbol, err))?,
+        _ => return Err(anyhow!("{} has no fund {:?}", PROVIDER_NAME, symbol)),
+    };
+
+    let mut closes = BTreeMap::new();
+    for (time, price) in prices {
+        let date = match NaiveDateTime::from_timestamp_millis(time) {
+            Some(time) => time.date(),
+            None => continue,
+        };
+        if let (true, Some(price)) = ((start..=end).contains(&&date), Decimal::from_f64(price)) {
+            closes.insert(date, price);
+        }
+    }
+
+    Ok(Closes {
+        commodity,
+        currency: base,
+        closes,
+    })
+}
+
+/// Morningstar as a [PriceSource], with the fund IDs in its `config` and
+/// the prices in `base`.
+pub struct Morningstar<'a> {
+    client: &'a Client,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> Morningstar<'a> {
+    pub fn new(
+        client: &'a Client,
+        config: &'a ProviderConfig,
+        base: CommodityTypeID,
+    ) -> Morningstar<'a> {
+        Morningstar {
+            client,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for Morningstar<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            self.client,
+            commodity,
+            &self.config.symbol(&commodity),
+            self.base,
+            start,
+            end,
+        )
+        .await
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{
+    config::ProviderConfig,
+    network::{request_json, RequestError},
+    pricesource::{self, PriceSource},
+    series::Closes,
+};
+use anyhow::anyhow;
+use chrono::{NaiveDate, NaiveDateTime};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::{Client, StatusCode};
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use serde::Deserialize;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "nasdaqdatalink";
+
+/// The environment variable the API key can be given in.
+pub const API_KEY_ENV: &str = "NASDAQ_DATA_LINK_API_KEY";
+
+const API_URL: &str = "https://data.nasdaq.com/api/v3/datasets";
+
+/// The columns used for the price when a symbol doesn't choose one, in
+/// order of preference.
+const PRICE_COLUMNS: &[&str] = &["Close", "Settle", "Value", "Rate", "Price"];
+
+/// Data from the `/datasets/{code}/data.json` endpoint.
+#[derive(Deserialize, Debug)]
+struct Response {
+    dataset_data: DatasetData,
+}
+
+#[derive(Deserialize, Debug)]
+struct DatasetData {
+    /// The name of each column, the first being the date.
+    column_names: Vec<String>,
+    /// A row of values for each date, in the order of the columns.
+    data: Vec<Vec<serde_json::Value>>,
+}
+
+/// A dataset and the column of it to use, from a symbol of the form
+/// `DATABASE/DATASET` or `DATABASE/DATASET:COLUMN`, the column being its
+/// name or its number (starting at 1 after the date).
+#[derive(Debug)]
+struct Dataset<'a> {
+    code: &'a str,
+    column: Option<&'a str>,
+}
+
+impl<'a> Dataset<'a> {
+    fn parse(symbol: &'a str) -> anyhow::Result<Dataset<'a>> {
+        let (code, column) = match symbol.split_once(':') {
+            Some((code, column)) => (code, Some(column)),
+            None => (symbol, None),
+        };
+        if !code.contains('/') {
+            return Err(anyhow!(
+                "{:?} isn't a {} dataset code, expected DATABASE/DATASET (e.g. LBMA/GOLD), \
+                see symbols in the provider's config",
+                symbol,
+                PROVIDER_NAME
+            ));
+        }
+        Ok(Dataset { code, column })
+    }
+
+    /// The index of the column to use in a row with `column_names`.
+    fn column_index(&self, column_names: &[String]) -> anyhow::Result<usize> {
+        let index = match self.column {
+            Some(column) => match usize::from_str(column) {
+                Ok(number) => Some(number).filter(|number| *number > 0),
+                Err(_) => column_names
+                    .iter()
+                    .position(|name| name.eq_ignore_ascii_case(column)),
+            },
+            None => PRICE_COLUMNS
+                .iter()
+                .find_map(|price| {
+                    column_names
+                        .iter()
+                        .position(|name| name.eq_ignore_ascii_case(price))
+                })
+                .or(Some(1)),
+        };
+        index
+            .filter(|index| *index < column_names.len())
+            .ok_or_else(|| {
+                anyhow!(
+                    "{} has no column {} in dataset {}, its columns are {}",
+                    PROVIDER_NAME,
+                    self.column.unwrap_or("to price with"),
+                    self.code,
+                    column_names.join(", ")
+                )
+            })
+    }
+}
+
+/// Fetch the values of `symbol`'s column of its dataset (see
+/// [Dataset::parse()]), e.g. `LBMA/GOLD:USD (PM)`, from `start` to `end`,
+/// as the prices of `commodity`. Without a column it is the dataset's
+/// close (or settle, value, rate or price), or else its first column.
+/// Datasets don't say which currency they are in, so their prices are
+/// taken to be in `base`. Without an `api_key` only a few requests a day
+/// are allowed.
+pub async fn get_closes(
+    client: &Client,
+    api_key: Option<&str>,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Closes> {
+    let dataset = Dataset::parse(symbol)?;
+    let mut url = format!(
+        "{}/{}/data.json?start_date={}&end_date={}&order=asc",
+        API_URL,
+        dataset.code,
+        start.format("%Y-%m-%d"),
+        end.format("%Y-%m-%d"),
+    );
+    if let Some(api_key) = api_key {
+        url.push_str(&format!("&api_key={}", api_key));
+    }
+    let response: Response = request_json(client, &url)
+        .await
+        .map_err(|error| match error.downcast_ref::<RequestError>() {
+            Some(RequestError::Status(StatusCode::NOT_FOUND)) => {
+                anyhow!("{} has no dataset {:?}", PROVIDER_NAME, dataset.code)
+            }
+            _ => error,
+        })?;
+    let data = response.dataset_data;
+    let index = dataset.column_index(&data.column_names)?;
+
+    let mut closes = BTreeMap::new();
+    for row in data.data {
+        let date = match row
+            .first()
+            .and_then(|date| date.as_str())
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+        {
+            Some(date) => date,
+            None => return Err(anyhow!("Unable to parse a row of {}", dataset.code)),
+        };
+        // Missing values are null.
+        let value = row
+            .get(index)
+            .and_then(|value| value.as_f64())
+            .and_then(Decimal::from_f64);
+        if let (true, Some(value)) = ((start..=end).contains(&&date), value) {
+            closes.insert(date, value);
+        }
+    }
+
+    Ok(Closes {
+        commodity,
+        currency: base,
+        closes,
+    })
+}
+
+/// Nasdaq Data Link as a [PriceSource], with the dataset codes in its
+/// `config` and the prices in `base`. The `api_key` is optional, for the
+/// premium datasets and higher limits.
+pub struct NasdaqDataLink<'a> {
+    client: &'a Client,
+    api_key: Option<&'a str>,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> NasdaqDataLink<'a> {
+    pub fn new(
+        client: &'a Client,
+        api_key: Option<&'a str>,
+        config: &'a ProviderConfig,
+        base: CommodityTypeID,
+    ) -> NasdaqDataLink<'a> {
+        NasdaqDataLink {
+            client,
+            api_key,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for NasdaqDataLink<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            self.client,
+            self.api_key,
+            commodity,
+            &self.config.symbol(&commodity),
+            self.base,
+            start,
+            end,
+        )
+        .await
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{metrics::REQUEST_METRICS, openexchangerate::ErrorResponse};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use std::{error::Error, fmt, time::Instant};
+
+/// A failed request to a provider, classified by what the user can do
+/// about it.
+#[derive(Debug)]
+pub enum RequestError {
+    /// The provider's host name could not be resolved.
+    Dns,
+    /// The secure connection to the provider could not be established.
+    Tls,
+    /// The provider took too long to respond.
+    Timeout,
+    /// The provider could not be reached for some other reason.
+    Unreachable,
+    /// The provider rejected the credentials (HTTP 401).
+    Unauthorized,
+    /// The credentials are valid but don't allow this request (HTTP 403).
+    Forbidden,
+    /// Too many requests, or the quota has been used up (HTTP 429).
+    TooManyRequests,
+    /// An unsuccessful HTTP status, with the provider's explanation of the
+    /// error (e.g. `invalid_app_id`).
+    Provider {
+        status: StatusCode,
+        code: String,
+        description: String,
+    },
+    /// Any other unsuccessful HTTP status.
+    Status(StatusCode),
+    /// The response could not be read or decoded.
+    InvalidResponse(String),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Dns => write!(
+                f,
+                "Unable to resolve the provider's address, check your internet connection and DNS settings"
+            ),
+            RequestError::Tls => write!(
+                f,
+                "Unable to establish a secure connection to the provider, check your proxy settings and system certificates"
+            ),
+            RequestError::Timeout => write!(
+                f,
+                "The request to the provider timed out, the provider may be overloaded or your connection is slow"
+            ),
+            RequestError::Unreachable => write!(
+                f,
+                "The provider is unreachable, check your internet connection and proxy settings"
+            ),
+            RequestError::Unauthorized => write!(
+                f,
+                "The provider rejected the credentials, check that your app-id is valid"
+            ),
+            RequestError::Forbidden => write!(
+                f,
+                "Your plan with the provider doesn't allow this request"
+            ),
+            RequestError::TooManyRequests => write!(
+                f,
+                "Too many requests, you have reached the provider's rate limit or used up your quota"
+            ),
+            RequestError::Provider {
+                status,
+                code,
+                description,
+            } => write!(
+                f,
+                "The provider responded with an error ({}, {}): {}",
+                code, status, description
+            ),
+            RequestError::Status(status) => {
+                write!(f, "The provider responded with an error ({})", status)
+            }
+            RequestError::InvalidResponse(message) => write!(
+                f,
+                "Unable to read the provider's response: {}",
+                message
+            ),
+        }
+    }
+}
+
+impl Error for RequestError {}
+
+impl RequestError {
+    /// Whether the request might succeed if it is tried again.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            RequestError::Timeout | RequestError::Unreachable | RequestError::TooManyRequests => {
+                true
+            }
+            RequestError::Provider { status, .. } => {
+                *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            }
+            RequestError::Status(status) => status.is_server_error(),
+            _ => false,
+        }
+    }
+
+    /// Whether the provider refused the request because too many have been
+    /// made.
+    pub fn is_rate_limited(&self) -> bool {
+        match self {
+            RequestError::TooManyRequests => true,
+            RequestError::Provider { status, .. } => *status == StatusCode::TOO_MANY_REQUESTS,
+            _ => false,
+        }
+    }
+}
+
+impl From<StatusCode> for RequestError {
+    fn from(status: StatusCode) -> RequestError {
+        match status {
+            StatusCode::UNAUTHORIZED => RequestError::Unauthorized,
+            StatusCode::FORBIDDEN => RequestError::Forbidden,
+            StatusCode::TOO_MANY_REQUESTS => RequestError::TooManyRequests,
+            status => RequestError::Status(status),
+        }
+    }
+}
+
+impl From<reqwest::Error> for RequestError {
+    fn from(error: reqwest::Error) -> RequestError {
+        // The raw error chain is still available for debugging.
+        log::debug!("request error: {:?}", error);
+
+        if let Some(status) = error.status() {
+            return status.into();
+        }
+
+        if error.is_timeout() {
+            return RequestError::Timeout;
+        }
+
+        // hyper and native-tls don't expose the kind of connection
+        // failure, so it has to be recognized from the messages. The
+        // top level message is skipped because it contains the url
+        // (and with it the credentials).
+        let mut chain = String::new();
+        let mut source: Option<&dyn Error> = error.source();
+        while let Some(err) = source {
+            chain.push_str(&err.to_string().to_lowercase());
+            chain.push('\n');
+            source = err.source();
+        }
+
+        if chain.contains("dns error") || chain.contains("failed to lookup address") {
+            RequestError::Dns
+        } else if ["tls", "ssl", "certificate", "handshake"]
+            .iter()
+            .any(|pattern| chain.contains(pattern))
+        {
+            RequestError::Tls
+        } else if error.is_connect() {
+            RequestError::Unreachable
+        } else {
+            RequestError::InvalidResponse(
+                error
+                    .source()
+                    .map(|source| source.to_string())
+                    .unwrap_or_else(|| String::from("unknown error")),
+            )
+        }
+    }
+}
+
+/// GET `url` and decode its JSON response, see [send_json()].
+pub async fn request_json<T: DeserializeOwned>(client: &Client, url: &str) -> anyhow::Result<T> {
+    send_json(client.get(url)).await
+}
+
+/// Send `request` and decode its JSON response, recording how long it
+/// took (and whether it failed) in the [REQUEST_METRICS].
+pub async fn send_json<T: DeserializeOwned>(request: RequestBuilder) -> anyhow::Result<T> {
+    let started = Instant::now();
+    let result = async {
+        let body = read_body(request).await?;
+        serde_json::from_slice(&body)
+            .map_err(|err| anyhow::Error::from(RequestError::InvalidResponse(err.to_string())))
+    }
+    .await;
+    REQUEST_METRICS.record(started.elapsed(), result.is_ok());
+    result
+}
+
+/// Send `request` and read its response as text (e.g. XML or CSV),
+/// recording it in the [REQUEST_METRICS] like [send_json()].
+pub async fn send_text(request: RequestBuilder) -> anyhow::Result<String> {
+    let started = Instant::now();
+    let result = async {
+        let body = read_body(request).await?;
+        String::from_utf8(body)
+            .map_err(|err| anyhow::Error::from(RequestError::InvalidResponse(err.to_string())))
+    }
+    .await;
+    REQUEST_METRICS.record(started.elapsed(), result.is_ok());
+    result
+}
+
+/// Send `request` and read the body of its response, failing with a
+/// [RequestError] if it was unsuccessful.
+async fn read_body(request: RequestBuilder) -> anyhow::Result<Vec<u8>> {
+    let response = request.send().await.map_err(RequestError::from)?;
+
+    let status = response.status();
+    let body = response.bytes().await.map_err(RequestError::from)?;
+    if !status.is_success() {
+        // The provider explains most errors in the body, fall back to the
+        // status if it doesn't.
+        let error = match serde_json::from_slice::<ErrorResponse>(&body) {
+            Ok(error) => RequestError::Provider {
+                status,
+                code: error.message,
+                description: error.description,
+            },
+            Err(_) => RequestError::from(status),
+        };
+        return Err(error.into());
+    }
+    Ok(body.to_vec())
+}
@@ -0,0 +1,261 @@
+use crate::{
+    config::{Config, SlackConfig, TelegramConfig},
+    credentials,
+    warnings::{warn, Kind},
+};
+use anyhow::anyhow;
+use chrono::NaiveDate;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Something that happened during a run that may be worth notifying about.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A job finished, `message` describes what it did.
+    JobSucceeded { job: String, message: String },
+    /// A job failed with `error`.
+    JobFailed { job: String, error: String },
+    /// An alert rule was triggered, `message` describes how.
+    Alert {
+        commodity: String,
+        base: String,
+        date: NaiveDate,
+        message: String,
+    },
+    /// The provider's remaining quota fell below the threshold.
+    QuotaLow {
+        provider: String,
+        requests_remaining: u32,
+        threshold: u32,
+    },
+}
+
+impl Event {
+    /// Whether the event is worth notifying about even without
+    /// `on_success`.
+    fn is_notable(&self) -> bool {
+        !matches!(self, Event::JobSucceeded { .. })
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Event::JobSucceeded { .. } => "Prices updated",
+            Event::JobFailed { .. } => "Price job failed",
+            Event::Alert { .. } => "Price alert",
+            Event::QuotaLow { .. } => "Price provider quota low",
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::JobSucceeded { message, .. } => f.write_str(message),
+            Event::JobFailed { job, error } => write!(f, "Job {} failed: {}", job, error),
+            Event::Alert { message, .. } => f.write_str(message),
+            Event::QuotaLow {
+                provider,
+                requests_remaining,
+                threshold,
+            } => write!(
+                f,
+                "Only {} requests remain in the {} quota (threshold {})",
+                requests_remaining, provider, threshold
+            ),
+        }
+    }
+}
+
+/// The JSON body POSTed to the webhook: the event's fields, and a
+/// human readable `text`.
+#[derive(Serialize)]
+struct WebhookBody<'a> {
+    #[serde(flatten)]
+    event: &'a Event,
+    text: String,
+}
+
+/// Send `event` to the destinations in the `notify` section of `config`,
+/// if it is one they want (successes only with `on_success`).
+/// Notifications are best effort, a failure to deliver one is reported as
+/// a warning rather than failing the run.
+pub async fn send(config: &Config, event: &Event) {
+    let notify = &config.notify;
+    if !event.is_notable() && !notify.on_success {
+        return;
+    }
+
+    if let Some(url) = &notify.webhook {
+        if let Err(error) = webhook(url, event).await {
+            warn(
+                Kind::Notification,
+                format!("unable to send notification to the webhook: {}", error),
+            );
+        }
+    }
+
+    if let Some(slack_config) = &notify.slack {
+        if let Err(error) = slack(config, slack_config, event).await {
+            warn(
+                Kind::Notification,
+                format!("unable to send notification to Slack: {}", error),
+            );
+        }
+    }
+
+    if let Some(telegram_config) = &notify.telegram {
+        if let Err(error) = telegram(config, telegram_config, event).await {
+            warn(
+                Kind::Notification,
+                format!("unable to send notification to Telegram: {}", error),
+            );
+        }
+    }
+
+    if notify.desktop {
+        if let Err(error) = desktop(event.title(), &event.to_string()) {
+            warn(
+                Kind::Notification,
+                format!("unable to show desktop notification: {}", error),
+            );
+        }
+    }
+}
+
+async fn webhook(url: &str, event: &Event) -> anyhow::Result<()> {
+    let body = WebhookBody {
+        event,
+        text: event.to_string(),
+    };
+    let response = Client::new()
+        .post(url)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|err| anyhow!("{}", err))?;
+    if !response.status().is_success() {
+        return Err(anyhow!("it responded with {}", response.status()));
+    }
+    Ok(())
+}
+
+/// The parts of a Slack or Telegram API response which say whether the
+/// message was sent: Slack gives an `error` code, Telegram a
+/// `description`.
+#[derive(Deserialize)]
+struct ApiResponse {
+    ok: bool,
+    error: Option<String>,
+    description: Option<String>,
+}
+
+impl ApiResponse {
+    fn check(self) -> anyhow::Result<()> {
+        if self.ok {
+            return Ok(());
+        }
+        Err(anyhow!(
+            "it responded with {}",
+            self.error
+                .or(self.description)
+                .unwrap_or_else(|| String::from("an error"))
+        ))
+    }
+}
+
+/// Resolve the bot token of the `destination` (`slack` or `telegram`).
+fn token(config: &Config, destination: &str, token: &str) -> anyhow::Result<String> {
+    credentials::resolve(
+        token,
+        destination,
+        credentials::age_identity(config.age_identity.as_deref()).as_deref(),
+    )
+}
+
+/// Post `event` to a Slack channel with `chat.postMessage`.
+async fn slack(config: &Config, slack: &SlackConfig, event: &Event) -> anyhow::Result<()> {
+    let token = token(config, "slack", &slack.token)?;
+    // Slack only needs these escaped, the rest of the text is left as is.
+    let escape = |text: &str| {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    };
+    let body = serde_json::json!({
+        "channel": slack.channel,
+        "text": format!("*{}*\n{}", escape(event.title()), escape(&event.to_string())),
+    });
+    Client::new()
+        .post("https://slack.com/api/chat.postMessage")
+        .bearer_auth(token)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|err| anyhow!("{}", err))?
+        .json::<ApiResponse>()
+        .await
+        .map_err(|err| anyhow!("unable to read its response: {}", err))?
+        .check()
+}
+
+/// Send `event` to a Telegram chat with `sendMessage`.
+async fn telegram(config: &Config, telegram: &TelegramConfig, event: &Event) -> anyhow::Result<()> {
+    let token = token(config, "telegram", &telegram.token)?;
+    let body = serde_json::json!({
+        "chat_id": telegram.chat_id,
+        "text": format!("{}\n{}", event.title(), event),
+    });
+    Client::new()
+        .post(&format!(
+            "https://api.telegram.org/bot{}/sendMessage",
+            token
+        ))
+        .json(&body)
+        .send()
+        .await
+        // The URL has the token in it, so it is left out of the error.
+        .map_err(|err| anyhow!("{}", err.to_string().replace(&token, "<token>")))?
+        .json::<ApiResponse>()
+        .await
+        .map_err(|err| {
+            anyhow!(
+                "unable to read its response: {}",
+                err.to_string().replace(&token, "<token>")
+            )
+        })?
+        .check()
+}
+
+/// Show a desktop notification with `notify-send` on Linux and the BSDs,
+/// or `osascript` on macOS. Does nothing when there is no desktop session
+/// (e.g. on a server, or in a container).
+fn desktop(title: &str, body: &str) -> anyhow::Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        let script = format!("display notification {:?} with title {:?}", body, title);
+        let mut command = std::process::Command::new("osascript");
+        command.arg("-e").arg(script);
+        command
+    } else if cfg!(unix) {
+        if std::env::var_os("DISPLAY").is_none() && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+            log::debug!("no desktop session, skipping notification {:?}", title);
+            return Ok(());
+        }
+        let mut command = std::process::Command::new("notify-send");
+        command.arg(title).arg(body);
+        command
+    } else {
+        return Err(anyhow!(
+            "desktop notifications aren't supported on this platform"
+        ));
+    };
+
+    let status = command
+        .status()
+        .map_err(|err| anyhow!("unable to run {:?}: {}", command, err))?;
+    if !status.success() {
+        return Err(anyhow!("{:?} exited with {}", command, status));
+    }
+    Ok(())
+}
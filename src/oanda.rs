@@ -0,0 +1,209 @@
+use crate::{
+    config::ProviderConfig,
+    network::send_json,
+    pricesource::{self, PriceSource},
+    series::Closes,
+};
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::{header::AUTHORIZATION, Client};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "oanda";
+
+/// The environment variable the API key can be given in.
+pub const API_KEY_ENV: &str = "OANDA_API_KEY";
+
+const API_URL: &str = "https://exchange-rates-api.oanda.com/v2/rates/candles.json";
+
+/// Number of days of candles requested at a time, so a long range comes
+/// back in responses of a reasonable size.
+const PAGE_DAYS: i64 = 365;
+
+/// Which of OANDA's quotes is used for the price, chosen with `--quote`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quote {
+    Bid,
+    Ask,
+    Midpoint,
+}
+
+/// The names the [Quote]s are chosen by.
+pub const QUOTES: &[&str] = &["bid", "ask", "midpoint"];
+
+impl FromStr for Quote {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bid" => Ok(Quote::Bid),
+            "ask" => Ok(Quote::Ask),
+            "midpoint" => Ok(Quote::Midpoint),
+            _ => Err(anyhow!(
+                "{:?} isn't a quote, expected one of {}",
+                s,
+                QUOTES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Data from the candles endpoint.
+#[derive(Deserialize, Debug)]
+struct Response {
+    #[serde(default)]
+    quotes: Vec<Candle>,
+}
+
+/// A day's candle, whose averages are decimal strings.
+#[derive(Deserialize, Debug)]
+struct Candle {
+    /// When the candle starts, e.g. `2020-01-02T00:00:00+0000`.
+    start_time: String,
+    average_bid: Option<String>,
+    average_ask: Option<String>,
+    average_midpoint: Option<String>,
+}
+
+impl Candle {
+    fn average(&self, quote: Quote) -> Option<&str> {
+        match quote {
+            Quote::Bid => self.average_bid.as_deref(),
+            Quote::Ask => self.average_ask.as_deref(),
+            Quote::Midpoint => self.average_midpoint.as_deref(),
+        }
+    }
+}
+
+/// The number of requests fetching a commodity from `start` to `end`
+/// takes.
+pub fn requests(start: &NaiveDate, end: &NaiveDate) -> usize {
+    let days = (end.signed_duration_since(*start).num_days() + 1).max(1) as usize;
+    days.div_ceil(PAGE_DAYS as usize)
+}
+
+/// Fetch the daily average `quote` of the currency `symbol` in `base` from
+/// `start` to `end`, as the prices of `commodity`, a page of up to a year
+/// a request.
+#[allow(clippy::too_many_arguments)]
+pub async fn get_closes(
+    client: &Client,
+    api_key: &str,
+    quote: Quote,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Closes> {
+    let mut closes = BTreeMap::new();
+    let mut page_start = *start;
+    while page_start <= *end {
+        let page_end = std::cmp::min(*end, page_start + Duration::days(PAGE_DAYS - 1));
+        let url = format!(
+            "{}?base={}&quote={}&start_time={}&end_time={}&data_set=OANDA",
+            API_URL,
+            url::form_urlencoded::byte_serialize(symbol.as_bytes()).collect::<String>(),
+            base,
+            page_start.format("%Y-%m-%d"),
+            page_end.format("%Y-%m-%d"),
+        );
+        let response: Response = send_json(
+            client
+                .get(&url)
+                .header(AUTHORIZATION, format!("Bearer {}", api_key)),
+        )
+        .await?;
+        for candle in &response.quotes {
+            let date = candle
+                .start_time
+                .get(..10)
+                .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Unable to parse the start time {:?} of a candle",
+                        candle.start_time
+                    )
+                })?;
+            let average = match candle.average(quote) {
+                Some(average) if (start..=end).contains(&&date) => average,
+                _ => continue,
+            };
+            let average = Decimal::from_str(average)
+                .map_err(|err| anyhow!("Unable to parse the quote {:?}: {}", average, err))?;
+            closes.insert(date, average);
+        }
+        page_start = page_end + Duration::days(1);
+    }
+
+    Ok(Closes {
+        commodity,
+        currency: base,
+        closes,
+    })
+}
+
+/// OANDA as a [PriceSource], with the symbols in its `config` and its
+/// `quote` of the rates in `base`.
+pub struct Oanda<'a> {
+    client: &'a Client,
+    api_key: &'a str,
+    quote: Quote,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> Oanda<'a> {
+    pub fn new(
+        client: &'a Client,
+        api_key: &'a str,
+        quote: Quote,
+        config: &'a ProviderConfig,
+        base: CommodityTypeID,
+    ) -> Oanda<'a> {
+        Oanda {
+            client,
+            api_key,
+            quote,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for Oanda<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            self.client,
+            self.api_key,
+            self.quote,
+            commodity,
+            &self.config.symbol(&commodity),
+            self.base,
+            start,
+            end,
+        )
+        .await
+    }
+}
@@ -1,31 +1,144 @@
-use chrono::{NaiveDateTime, Utc};
+use crate::{
+    metrics::SERVICE_METRICS,
+    network::request_json,
+    pricesource::{self, PriceSource},
+    series::Snapshot,
+};
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
 use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this provider, as used in the `providers` section of the config.
+pub const PROVIDER_NAME: &str = "openexchangerates";
+
+/// Environment variable the App ID is read from when it isn't given on
+/// the command line.
+pub const APP_ID_ENV: &str = "OXR_APP_ID";
+
+pub type AppID = String;
+
+pub const API_URL: &str = "https://openexchangerates.org/api";
+
+/// The first date the provider has historical rates for.
+pub fn earliest_date() -> NaiveDate {
+    NaiveDate::from_ymd_opt(1999, 1, 1).expect("expected a valid date")
+}
 
 /// Data from https://docs.openexchangerates.org/docs/latest-json and
-/// https://docs.openexchangerates.org/docs/historical-json apis.
+/// https://docs.openexchangerates.org/docs/historical-json apis. The rates
+/// are keyed by the provider's codes, some of which (e.g. the alternative
+/// `VEF_BLKMKT`) are too long to be a commodity, see [commodity_rates()].
 #[derive(Deserialize, Debug)]
 pub struct OpenExchangeRate {
     timestamp: u32,
     base: CommodityTypeID,
-    rates: BTreeMap<CommodityTypeID, Decimal>,
+    rates: BTreeMap<String, Decimal>,
 }
 
-impl Into<ExchangeRate> for OpenExchangeRate {
-    fn into(self) -> ExchangeRate {
-        let date = Some(NaiveDateTime::from_timestamp(self.timestamp as i64, 0).date());
+impl OpenExchangeRate {
+    /// When the rates were published (UTC).
+    pub fn timestamp(&self) -> Option<NaiveDateTime> {
+        NaiveDateTime::from_timestamp_opt(self.timestamp as i64, 0)
+    }
+
+    /// The rates as an exchange rate of commodities, with the provider's
+    /// codes given in the `symbols` replaced (see [commodity_rates()]).
+    pub fn exchange_rate(self, symbols: &BTreeMap<String, String>) -> ExchangeRate {
+        let date = self.timestamp().map(|dt| dt.date());
 
         ExchangeRate {
             date,
             obtained_datetime: Some(Utc::now()),
             base: Some(self.base),
-            rates: self.rates,
+            rates: commodity_rates(self.rates, symbols),
         }
     }
 }
 
+/// The `rates` keyed by the provider's codes, keyed by commodity instead:
+/// the commodity whose symbol the code is in `symbols` (the provider's
+/// [crate::config::ProviderConfig::symbols], e.g. `VEFBLK = "VEF_BLKMKT"`),
+/// or otherwise the code itself. Codes which aren't valid commodities and
+/// have no symbol, like most alternative currencies in a full table, are
+/// left out.
+pub fn commodity_rates(
+    rates: BTreeMap<String, Decimal>,
+    symbols: &BTreeMap<String, String>,
+) -> BTreeMap<CommodityTypeID, Decimal> {
+    rates
+        .into_iter()
+        .filter_map(|(code, rate)| {
+            let commodity = symbols
+                .iter()
+                .find(|(_, symbol)| **symbol == code)
+                .map(|(commodity, _)| commodity.as_str())
+                .unwrap_or(&code);
+            match CommodityTypeID::from_str(commodity) {
+                Ok(commodity) => Some((commodity, rate)),
+                Err(err) => {
+                    log::debug!("Leaving out the rate of {}: {}", code, err);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// The most days a time-series.json request asks for, a longer range is
+/// split into several requests.
+pub const TIME_SERIES_MAX_DAYS: i64 = 31;
+
+/// Data from https://docs.openexchangerates.org/docs/time-series-json: the
+/// rates of each date in the range, keyed by the date.
+#[derive(Deserialize, Debug)]
+pub struct OpenExchangeTimeSeries {
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    base: CommodityTypeID,
+    /// Keyed by the provider's codes, like [OpenExchangeRate].
+    rates: BTreeMap<NaiveDate, BTreeMap<String, Decimal>>,
+}
+
+/// Data from https://docs.openexchangerates.org/docs/errors: the body of
+/// an unsuccessful response.
+#[derive(Deserialize, Debug)]
+pub struct ErrorResponse {
+    pub status: u16,
+    /// Short code for the error, e.g. `invalid_app_id`.
+    pub message: String,
+    /// Explanation of the error, and what to do about it.
+    pub description: String,
+}
+
+/// Data from https://docs.openexchangerates.org/docs/currencies-json: the
+/// name of every symbol the provider has rates for, keyed by the symbol.
+pub type Currencies = BTreeMap<String, String>;
+
+/// Data from https://docs.openexchangerates.org/docs/convert: a single
+/// amount converted at the latest rates.
+#[derive(Deserialize, Debug)]
+pub struct Conversion {
+    pub meta: ConversionMeta,
+    /// The converted amount.
+    pub response: Decimal,
+}
+
+/// The `meta` field from https://docs.openexchangerates.org/docs/convert
+#[derive(Deserialize, Debug)]
+pub struct ConversionMeta {
+    timestamp: u32,
+    pub rate: Decimal,
+}
+
+impl ConversionMeta {
+    pub fn timestamp(&self) -> Option<NaiveDateTime> {
+        NaiveDateTime::from_timestamp_opt(self.timestamp as i64, 0)
+    }
+}
+
 /// Data from https://docs.openexchangerates.org/docs/usage-json
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Usage {
@@ -75,3 +188,289 @@ pub struct UsageDataUsage {
     pub days_remaining: u32,
     pub daily_average: u32,
 }
+
+/// The `&symbols=` argument requesting the rates of `includes`, by their
+/// provider's codes in `symbols` if they have one.
+fn symbols_argument(
+    mut includes: Vec<CommodityTypeID>,
+    symbols: &BTreeMap<String, String>,
+) -> Option<String> {
+    if !includes.is_empty() {
+        let mut argument = String::from("&symbols=");
+
+        // Sorted so the same request always has the same url.
+        includes.sort();
+        includes.dedup();
+        let includes_list = includes
+            .iter()
+            .map(|currency| symbol(currency, symbols))
+            .collect::<Vec<String>>()
+            .join(",");
+        argument.push_str(includes_list.as_str());
+        Some(argument)
+    } else {
+        None
+    }
+}
+
+/// The provider's code for `commodity`: its symbol in `symbols`, or the
+/// commodity itself.
+fn symbol(commodity: &CommodityTypeID, symbols: &BTreeMap<String, String>) -> String {
+    let commodity = commodity.to_string();
+    symbols.get(&commodity).cloned().unwrap_or(commodity)
+}
+
+/// The openexchangerates api, with the App ID its requests are made with.
+pub struct OpenExchangeRates<'a> {
+    client: &'a Client,
+    app_id: &'a str,
+    /// The provider's codes for the commodities whose code differs, see
+    /// [OpenExchangeRates::with_symbols()].
+    symbols: BTreeMap<String, String>,
+    /// Whether the alternative rates are included, see
+    /// [OpenExchangeRates::with_alternative()].
+    alternative: bool,
+}
+
+impl<'a> OpenExchangeRates<'a> {
+    pub fn new(client: &'a Client, app_id: &'a str) -> OpenExchangeRates<'a> {
+        OpenExchangeRates {
+            client,
+            app_id,
+            symbols: BTreeMap::new(),
+            alternative: false,
+        }
+    }
+
+    /// Request the commodities in `symbols` (the provider's
+    /// [crate::config::ProviderConfig::symbols]) by the provider's codes
+    /// given for them, e.g. `VEFBLK = "VEF_BLKMKT"` for an alternative rate
+    /// whose code is too long to be a commodity.
+    pub fn with_symbols(mut self, symbols: BTreeMap<String, String>) -> OpenExchangeRates<'a> {
+        self.symbols = symbols;
+        self
+    }
+
+    /// Include the alternative rates (`show_alternative=1`): the
+    /// unofficial and black market rates of currencies whose official rate
+    /// isn't what they trade at (e.g. `VES`), and cryptocurrencies, see
+    /// https://docs.openexchangerates.org/docs/alternative-currencies.
+    pub fn with_alternative(mut self, alternative: bool) -> OpenExchangeRates<'a> {
+        self.alternative = alternative;
+        self
+    }
+
+    /// The `&show_alternative=1` argument, if the alternative rates are
+    /// included.
+    fn alternative_argument(&self) -> &'static str {
+        if self.alternative {
+            "&show_alternative=1"
+        } else {
+            ""
+        }
+    }
+
+    /// The App ID's plan and how much of its quota is left, which is
+    /// recorded in the [SERVICE_METRICS].
+    pub async fn usage(&self) -> anyhow::Result<Usage> {
+        let url = format!(
+            "{api_url}/usage.json?app_id={app_id}&prettyprint=false",
+            api_url = API_URL,
+            app_id = self.app_id,
+        );
+
+        let usage: Usage = request_json(self.client, &url).await?;
+        SERVICE_METRICS.record_quota(usage.data.usage.requests_remaining);
+        Ok(usage)
+    }
+
+    // TODO: refactor this to use a hashmap for arguments, and a generic request api.
+    async fn get_day_json(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+        json: &str,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        let mut url = format!(
+            "{api_url}/{json}?app_id={app_id}&prettyprint=false",
+            api_url = API_URL,
+            app_id = self.app_id,
+            json = json,
+        );
+        if let Some(includes) = include {
+            if let Some(arg) = symbols_argument(includes, &self.symbols) {
+                url.push_str(arg.as_str());
+            }
+        }
+        url.push_str(self.alternative_argument());
+
+        request_json::<OpenExchangeRate>(self.client, &url)
+            .await
+            .map(|rate| {
+                let timestamp = rate.timestamp();
+                (rate.exchange_rate(&self.symbols), timestamp)
+            })
+    }
+}
+
+impl PriceSource for OpenExchangeRates<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        self.get_day_json(include, "latest.json").await
+    }
+
+    async fn historical(
+        &self,
+        date: &NaiveDate,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<Snapshot> {
+        let json = format!("historical/{}.json", date.format("%Y-%m-%d"));
+        let (exchange_rate, timestamp) = self.get_day_json(include, json.as_str()).await?;
+        Ok(Snapshot {
+            requested_date: *date,
+            timestamp,
+            exchange_rate,
+        })
+    }
+
+    /// With the time-series.json api, a request for each
+    /// [TIME_SERIES_MAX_DAYS] of the range, which only the plans with the
+    /// `time_series` feature (see [PlanFeatures]) can use.
+    async fn series(
+        &self,
+        start: &NaiveDate,
+        end: &NaiveDate,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<Vec<Snapshot>> {
+        let symbols = include
+            .and_then(|include| symbols_argument(include, &self.symbols))
+            .unwrap_or_default();
+        let mut snapshots = Vec::new();
+        let mut range_start = *start;
+        while range_start <= *end {
+            let range_end =
+                std::cmp::min(*end, range_start + Duration::days(TIME_SERIES_MAX_DAYS - 1));
+            let url = format!(
+                "{api_url}/time-series.json?app_id={app_id}&start={start}&end={end}\
+                &prettyprint=false{symbols}{alternative}",
+                api_url = API_URL,
+                app_id = self.app_id,
+                start = range_start.format("%Y-%m-%d"),
+                end = range_end.format("%Y-%m-%d"),
+                symbols = symbols,
+                alternative = self.alternative_argument(),
+            );
+
+            let series: OpenExchangeTimeSeries = request_json(self.client, &url).await?;
+            let base = series.base;
+            snapshots.extend(series.rates.into_iter().map(|(date, rates)| Snapshot {
+                requested_date: date,
+                timestamp: None,
+                exchange_rate: ExchangeRate {
+                    date: Some(date),
+                    obtained_datetime: Some(Utc::now()),
+                    base: Some(base),
+                    rates: commodity_rates(rates, &self.symbols),
+                },
+            }));
+            range_start = range_end + Duration::days(1);
+        }
+        Ok(snapshots)
+    }
+
+    /// With the convert.json api, which only the plans with the `convert`
+    /// feature (see [PlanFeatures]) can use.
+    async fn convert(
+        &self,
+        amount: Decimal,
+        from: CommodityTypeID,
+        to: CommodityTypeID,
+    ) -> anyhow::Result<pricesource::Conversion> {
+        let url = format!(
+            "{api_url}/convert/{amount}/{from}/{to}?app_id={app_id}&prettyprint=false",
+            api_url = API_URL,
+            amount = amount,
+            from = symbol(&from, &self.symbols),
+            to = symbol(&to, &self.symbols),
+            app_id = self.app_id,
+        );
+
+        let conversion: Conversion = request_json(self.client, &url).await?;
+        Ok(pricesource::Conversion {
+            amount: conversion.response,
+            rate: conversion.meta.rate,
+            timestamp: conversion.meta.timestamp(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A trimmed latest.json?show_alternative=1 response, with official,
+    /// alternative and digital currencies.
+    const ALTERNATIVE_LATEST: &str = r#"{
+        "disclaimer": "Usage subject to terms: https://openexchangerates.org/terms",
+        "license": "https://openexchangerates.org/license",
+        "timestamp": 1577923200,
+        "base": "USD",
+        "rates": {
+            "AUD": 1.425362,
+            "BTC": 0.000136574263,
+            "CNH": 6.96555,
+            "EUR": 0.891186,
+            "VEF_BLKMKT": 73243.3,
+            "VEF_DICOM": 47101.8,
+            "VEF_DIPRO": 10,
+            "VES": 46712.16
+        }
+    }"#;
+
+    fn commodity(code: &str) -> CommodityTypeID {
+        CommodityTypeID::from_str(code).expect("expected a valid commodity")
+    }
+
+    #[test]
+    fn alternative_rates() {
+        let rate: OpenExchangeRate =
+            serde_json::from_str(ALTERNATIVE_LATEST).expect("expected the response to parse");
+        let symbols: BTreeMap<String, String> =
+            std::iter::once(("VEFBLK".to_string(), "VEF_BLKMKT".to_string())).collect();
+        let exchange_rate = rate.exchange_rate(&symbols);
+
+        assert_eq!(exchange_rate.date, NaiveDate::from_ymd_opt(2020, 1, 2));
+        assert_eq!(
+            exchange_rate.rates.get(&commodity("VEFBLK")),
+            Some(&Decimal::from_str("73243.3").unwrap())
+        );
+        assert_eq!(
+            exchange_rate.rates.get(&commodity("CNH")),
+            Some(&Decimal::from_str("6.96555").unwrap())
+        );
+        assert!(exchange_rate.rates.contains_key(&commodity("BTC")));
+        assert!(exchange_rate.rates.contains_key(&commodity("VES")));
+        // VEF_DICOM and VEF_DIPRO are too long to be commodities, and have
+        // no symbol.
+        assert_eq!(exchange_rate.rates.len(), 6);
+    }
+
+    #[test]
+    fn symbols_argument_uses_the_provider_codes() {
+        let symbols: BTreeMap<String, String> =
+            std::iter::once(("VEFBLK".to_string(), "VEF_BLKMKT".to_string())).collect();
+        assert_eq!(
+            symbols_argument(
+                vec![commodity("VES"), commodity("VEFBLK"), commodity("VES")],
+                &symbols
+            ),
+            Some(String::from("&symbols=VEF_BLKMKT,VES"))
+        );
+        assert_eq!(symbols_argument(Vec::new(), &symbols), None);
+    }
+}
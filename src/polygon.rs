@@ -0,0 +1,187 @@
+use crate::{
+    config::ProviderConfig,
+    metadata,
+    network::request_json,
+    pricesource::{self, PriceSource},
+    series::Closes,
+};
+use anyhow::anyhow;
+use chrono::{NaiveDate, NaiveDateTime};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use serde::Deserialize;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "polygon";
+
+/// The environment variable the API key can be given in.
+pub const API_KEY_ENV: &str = "POLYGON_API_KEY";
+
+/// The currency Polygon quotes US stocks in.
+pub const CURRENCY: &str = "USD";
+
+/// The free plan's limit, a paid plan's can be set with the provider's
+/// `requests_per_minute`.
+pub const REQUESTS_PER_MINUTE: u32 = 5;
+
+const API_URL: &str = "https://api.polygon.io/v2/aggs/ticker";
+
+/// The most aggregates a response may have, far more days than a range
+/// will have.
+const LIMIT: u32 = 50000;
+
+/// Data from the aggregates endpoint.
+#[derive(Deserialize, Debug)]
+struct Aggregates {
+    /// `OK` or `DELAYED`, otherwise the request failed.
+    status: String,
+    #[serde(default)]
+    results: Vec<Aggregate>,
+    /// Why the request failed.
+    error: Option<String>,
+    message: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Aggregate {
+    /// When the aggregate starts (UNIX time in milliseconds).
+    t: i64,
+    /// The close.
+    c: f64,
+}
+
+/// The ticker for `symbol` and the currency it is quoted in: `symbol`
+/// itself if it has a market prefix (e.g. `C:EURUSD` or `X:BTCUSD`, quoted
+/// in `base`), the forex pair of an ISO 4217 currency and `base`, or
+/// otherwise a US stock, quoted in USD.
+fn ticker(symbol: &str, base: CommodityTypeID) -> (String, CommodityTypeID) {
+    if symbol.contains(':') {
+        (symbol.to_string(), base)
+    } else if metadata::iso_decimal_places(symbol).is_some() {
+        (format!("C:{}{}", symbol, base), base)
+    } else {
+        (
+            symbol.to_uppercase(),
+            CommodityTypeID::from_str(CURRENCY).expect("expected a valid commodity"),
+        )
+    }
+}
+
+/// Fetch the unadjusted daily closes of `symbol` (see [ticker()]) from
+/// `start` to `end`, as the prices of `commodity`, from the daily
+/// aggregates.
+pub async fn get_closes(
+    client: &Client,
+    api_key: &str,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Closes> {
+    let (ticker, currency) = ticker(symbol, base);
+    let url = format!(
+        "{api_url}/{ticker}/range/1/day/{start}/{end}?adjusted=false&sort=asc&limit={limit}\
+        &apiKey={api_key}",
+        api_url = API_URL,
+        ticker = url::form_urlencoded::byte_serialize(ticker.as_bytes()).collect::<String>(),
+        start = start.format("%Y-%m-%d"),
+        end = end.format("%Y-%m-%d"),
+        limit = LIMIT,
+        api_key = api_key,
+    );
+    let aggregates: Aggregates = request_json(client, &url).await?;
+    if aggregates.status != "OK" && aggregates.status != "DELAYED" {
+        return Err(anyhow!(
+            "{} responded with status {:?} for {}: {}",
+            PROVIDER_NAME,
+            aggregates.status,
+            ticker,
+            aggregates
+                .error
+                .or(aggregates.message)
+                .unwrap_or_else(|| String::from("no explanation"))
+        ));
+    }
+
+    // Stock aggregates start at midnight in New York, which is still the
+    // same date in UTC.
+    let mut closes = BTreeMap::new();
+    for aggregate in aggregates.results {
+        let date = match NaiveDateTime::from_timestamp_millis(aggregate.t) {
+            Some(time) => time.date(),
+            None => continue,
+        };
+        if let (true, Some(close)) = (
+            (start..=end).contains(&&date),
+            Decimal::from_f64(aggregate.c),
+        ) {
+            closes.insert(date, close);
+        }
+    }
+
+    Ok(Closes {
+        commodity,
+        currency,
+        closes,
+    })
+}
+
+/// Polygon.io as a [PriceSource], with the symbols in its `config` and the
+/// prices in `base`.
+pub struct Polygon<'a> {
+    client: &'a Client,
+    api_key: &'a str,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> Polygon<'a> {
+    pub fn new(
+        client: &'a Client,
+        api_key: &'a str,
+        config: &'a ProviderConfig,
+        base: CommodityTypeID,
+    ) -> Polygon<'a> {
+        Polygon {
+            client,
+            api_key,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for Polygon<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            self.client,
+            self.api_key,
+            commodity,
+            &self.config.symbol(&commodity),
+            self.base,
+            start,
+            end,
+        )
+        .await
+    }
+}
@@ -0,0 +1,700 @@
+use crate::{
+    crossrate::{derive_rate, Derivation},
+    series::TimeSeries,
+};
+use anyhow::anyhow;
+use chrono::{Datelike, Duration, NaiveDate};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+/// The price of a commodity (in terms of a base commodity) on a date.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Price {
+    pub rate: Decimal,
+    /// Note about how the price was derived, emitted as a comment.
+    pub annotation: Option<String>,
+}
+
+/// The prices of one commodity in terms of a base commodity, by date.
+#[derive(Debug, Clone)]
+pub struct PriceSeries {
+    pub commodity: CommodityTypeID,
+    pub base: CommodityTypeID,
+    pub prices: BTreeMap<NaiveDate, Price>,
+    /// Dates in the source series for which the price couldn't be
+    /// calculated, because a rate it needs was missing from that date's
+    /// response.
+    pub missing: Vec<NaiveDate>,
+}
+
+impl PriceSeries {
+    /// The prices of `commodity` in terms of `base` for every date in
+    /// `series`, computed locally as cross rates when the series is quoted
+    /// against a different base, or triangulated through `pivot` if given
+    /// (see [crate::crossrate]). Dates without the rates needed are left
+    /// out, and listed in [PriceSeries::missing].
+    pub fn from_time_series(
+        series: &TimeSeries,
+        commodity: CommodityTypeID,
+        base: CommodityTypeID,
+        pivot: Option<&CommodityTypeID>,
+    ) -> anyhow::Result<PriceSeries> {
+        let mut prices = BTreeMap::new();
+        let mut missing = Vec::new();
+
+        for (date, exchange_rate) in &series.map {
+            let derived = match derive_rate(exchange_rate, &commodity, &base, pivot)? {
+                Some(derived) => derived,
+                None => {
+                    missing.push(*date);
+                    continue;
+                }
+            };
+
+            let mut notes: Vec<String> = Vec::new();
+            if let Some(filled) = series.filled.get(date) {
+                notes.push(filled.to_string());
+            }
+            if let Derivation::Pivot { .. } = derived.derivation {
+                notes.push(derived.derivation.to_string());
+            }
+
+            prices.insert(
+                *date,
+                Price {
+                    rate: derived.rate,
+                    annotation: if notes.is_empty() {
+                        None
+                    } else {
+                        Some(notes.join(", "))
+                    },
+                },
+            );
+        }
+
+        Ok(PriceSeries {
+            commodity,
+            base,
+            prices,
+            missing,
+        })
+    }
+
+    /// Fill in each of the [PriceSeries::missing] dates with the price for
+    /// the nearest earlier date, leaving only those with no earlier price
+    /// missing.
+    pub fn fill_missing(&mut self) {
+        let mut unfilled = Vec::new();
+        for date in std::mem::take(&mut self.missing) {
+            let filled = self
+                .prices
+                .range(..date)
+                .next_back()
+                .map(|(from, price)| Price {
+                    rate: price.rate,
+                    annotation: Some(format!(
+                        "rate missing, carried forward from {}",
+                        from.format("%Y-%m-%d")
+                    )),
+                });
+            match filled {
+                Some(price) => {
+                    self.prices.insert(date, price);
+                }
+                None => unfilled.push(date),
+            }
+        }
+        self.missing = unfilled;
+    }
+
+    /// The prices for which `rate × 1/rate`, each rounded to `rounding`
+    /// decimal places (if given), is further than `tolerance_percent` from
+    /// 1, i.e. where the rounding loses enough precision that converting to
+    /// the base commodity and back doesn't give the starting amount.
+    pub fn asymmetries(&self, rounding: Option<u32>, tolerance_percent: Decimal) -> Vec<Asymmetry> {
+        let round = |rate: Decimal| match rounding {
+            Some(dp) => rate.round_dp(dp),
+            None => rate,
+        };
+        self.prices
+            .iter()
+            .filter_map(|(date, price)| {
+                if price.rate.is_zero() {
+                    return None;
+                }
+                let rate = round(price.rate);
+                let inverse = Decimal::ONE
+                    .checked_div(price.rate)
+                    .map(round)
+                    .filter(|inverse| !rate.is_zero() && !inverse.is_zero());
+                let deviation_percent = match inverse {
+                    Some(inverse) => ((rate * inverse - Decimal::ONE) * Decimal::from(100)).abs(),
+                    None => Decimal::from(100),
+                };
+                if deviation_percent > tolerance_percent {
+                    Some(Asymmetry {
+                        date: *date,
+                        rate,
+                        inverse,
+                        deviation_percent,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Remove each price which is the same as the price for the previous
+    /// date in the series, leaving only the dates where the price changed
+    /// (and the first), with [TimeSeries::compress()].
+    pub fn compress(&mut self) {
+        let mut series = TimeSeries::default();
+        for (date, price) in &self.prices {
+            series.map.insert(
+                *date,
+                ExchangeRate {
+                    date: Some(*date),
+                    obtained_datetime: None,
+                    base: Some(self.base),
+                    rates: std::iter::once((self.commodity, price.rate)).collect(),
+                },
+            );
+        }
+        series.compress();
+        self.prices.retain(|date, _| series.map.contains_key(date));
+    }
+
+    /// Summary statistics over all the prices, or `None` if there are
+    /// no prices.
+    pub fn stats(&self) -> Option<Stats> {
+        let (start_date, start) = self.prices.iter().next()?;
+        let (end_date, end) = self.prices.iter().next_back()?;
+        let rates: Vec<Decimal> = self.prices.values().map(|price| price.rate).collect();
+
+        let change_percent = if start.rate.is_zero() {
+            None
+        } else {
+            Some((end.rate - start.rate) / start.rate * Decimal::from(100))
+        };
+
+        Some(Stats {
+            volatility_percent: self.annualized_volatility_percent(),
+            max_drawdown_percent: self.max_drawdown_percent(),
+            start: (*start_date, start.rate),
+            end: (*end_date, end.rate),
+            min: rates.iter().copied().min()?,
+            max: rates.iter().copied().max()?,
+            mean: Statistic::Mean.compute(&rates),
+            median: Statistic::Median.compute(&rates),
+            change_percent,
+        })
+    }
+
+    /// Annualized volatility: the standard deviation of the log returns
+    /// between consecutive prices, scaled by the square root of the
+    /// number of such intervals in a year (based on the mean spacing of
+    /// the prices, so it works for sampled series too). `None` if there
+    /// are fewer than 3 prices.
+    fn annualized_volatility_percent(&self) -> Option<f64> {
+        let points: Vec<(NaiveDate, f64)> = self
+            .prices
+            .iter()
+            .filter_map(|(date, price)| Some((*date, price.rate.to_f64()?)))
+            .filter(|(_, rate)| *rate > 0.0)
+            .collect();
+        if points.len() < 3 {
+            return None;
+        }
+
+        let returns: Vec<f64> = points
+            .windows(2)
+            .map(|pair| (pair[1].1 / pair[0].1).ln())
+            .collect();
+        let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+        let variance = returns
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f64>()
+            / (returns.len() - 1) as f64;
+
+        let days = points[points.len() - 1]
+            .0
+            .signed_duration_since(points[0].0)
+            .num_days() as f64;
+        let intervals_per_year = 365.25 / (days / returns.len() as f64);
+
+        Some(variance.sqrt() * intervals_per_year.sqrt() * 100.0)
+    }
+
+    /// The largest percentage fall from a peak price to a later trough
+    /// (zero or negative).
+    fn max_drawdown_percent(&self) -> Decimal {
+        let mut peak: Option<Decimal> = None;
+        let mut max_drawdown = Decimal::ZERO;
+
+        for price in self.prices.values() {
+            let peak = peak.get_or_insert(price.rate);
+            if price.rate > *peak {
+                *peak = price.rate;
+            } else if !peak.is_zero() {
+                let drawdown = (price.rate - *peak) / *peak * Decimal::from(100);
+                max_drawdown = max_drawdown.min(drawdown);
+            }
+        }
+
+        max_drawdown
+    }
+
+    /// Prices which moved by more than `threshold_percent` percent from
+    /// the previous price in the series.
+    pub fn outliers(&self, threshold_percent: Decimal) -> Vec<Outlier> {
+        self.prices
+            .iter()
+            .zip(self.prices.iter().skip(1))
+            .filter_map(|((previous_date, previous), (date, price))| {
+                if previous.rate.is_zero() {
+                    return None;
+                }
+                let change_percent =
+                    (price.rate - previous.rate) / previous.rate * Decimal::from(100);
+                if change_percent.abs() > threshold_percent {
+                    Some(Outlier {
+                        commodity: self.commodity,
+                        base: self.base,
+                        date: *date,
+                        rate: price.rate,
+                        previous_date: *previous_date,
+                        previous_rate: previous.rate,
+                        change_percent,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Each price replaced with the mean of the prices in the trailing
+    /// `days` day window ending on (and including) its date.
+    pub fn rolling_average(&self, days: i64) -> PriceSeries {
+        let prices = self
+            .prices
+            .iter()
+            .map(|(date, price)| {
+                let window_start = *date - Duration::days(days - 1);
+                let rates: Vec<Decimal> = self
+                    .prices
+                    .range(window_start..=*date)
+                    .map(|(_, price)| price.rate)
+                    .collect();
+
+                let rolling = format!("{}-day rolling average of {} prices", days, rates.len());
+                let annotation = match &price.annotation {
+                    Some(annotation) => format!("{}, {}", annotation, rolling),
+                    None => rolling,
+                };
+
+                let price = Price {
+                    rate: Statistic::Mean.compute(&rates),
+                    annotation: Some(annotation),
+                };
+                (*date, price)
+            })
+            .collect();
+
+        PriceSeries {
+            commodity: self.commodity,
+            base: self.base,
+            prices,
+            missing: self.missing.clone(),
+        }
+    }
+
+    /// One price per period, computed with `aggregate.statistic` from
+    /// the prices within that period. Each price is dated on the first
+    /// date in its period which has a price, so a partial period at the
+    /// start of the series isn't dated before the series starts.
+    pub fn aggregate(&self, aggregate: &Aggregate) -> PriceSeries {
+        let mut periods: BTreeMap<NaiveDate, (NaiveDate, Vec<Decimal>)> = BTreeMap::new();
+        for (date, price) in &self.prices {
+            periods
+                .entry(aggregate.period.start(date))
+                .or_insert_with(|| (*date, Vec::new()))
+                .1
+                .push(price.rate);
+        }
+
+        let prices = periods
+            .into_values()
+            .map(|(first, rates)| {
+                let price = Price {
+                    rate: aggregate.statistic.compute(&rates),
+                    annotation: Some(format!("{} of {} days", aggregate, rates.len())),
+                };
+                (first, price)
+            })
+            .collect();
+
+        PriceSeries {
+            commodity: self.commodity,
+            base: self.base,
+            prices,
+            missing: self.missing.clone(),
+        }
+    }
+}
+
+/// Every price in `series`, ordered by date (latest first if
+/// `descending`), then commodity, then base commodity. A total order, so
+/// output never depends on the order the series were computed in, and
+/// repeated runs write identical files.
+pub fn ordered(series: &[PriceSeries], descending: bool) -> Vec<(&PriceSeries, NaiveDate, &Price)> {
+    let mut entries: Vec<(&PriceSeries, NaiveDate, &Price)> = series
+        .iter()
+        .flat_map(|prices| {
+            prices
+                .prices
+                .iter()
+                .map(move |(date, price)| (prices, *date, price))
+        })
+        .collect();
+    entries.sort_by(|(a, a_date, _), (b, b_date, _)| {
+        let by_date = a_date.cmp(b_date);
+        let by_date = if descending {
+            by_date.reverse()
+        } else {
+            by_date
+        };
+        by_date
+            .then_with(|| a.commodity.cmp(&b.commodity))
+            .then_with(|| a.base.cmp(&b.base))
+    });
+    entries
+}
+
+/// What to do with the dates a price couldn't be calculated for, because a
+/// rate was missing from the response (see [PriceSeries::missing]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingRates {
+    /// Leave the dates out.
+    Skip,
+    /// Use the price for the nearest earlier date, see
+    /// [PriceSeries::fill_missing()].
+    ForwardFill,
+    /// Fail with [MissingRatesError].
+    Fail,
+}
+
+impl FromStr for MissingRates {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(MissingRates::Skip),
+            "forward-fill" => Ok(MissingRates::ForwardFill),
+            "fail" => Ok(MissingRates::Fail),
+            _ => Err(anyhow!(
+                "unknown missing rates behaviour {:?}, expected one of: skip, forward-fill, fail",
+                s
+            )),
+        }
+    }
+}
+
+/// What to do with prices outside the bounds configured for their pair
+/// (see [crate::config::Bounds]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutOfBounds {
+    /// Leave them out.
+    Skip,
+    /// Keep them, annotated.
+    Keep,
+    /// Fail.
+    Fail,
+}
+
+impl FromStr for OutOfBounds {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(OutOfBounds::Skip),
+            "keep" => Ok(OutOfBounds::Keep),
+            "fail" => Ok(OutOfBounds::Fail),
+            _ => Err(anyhow!(
+                "unknown out of bounds behaviour {:?}, expected one of: skip, keep, fail",
+                s
+            )),
+        }
+    }
+}
+
+/// The error when the prices of `commodity` in `base` couldn't be
+/// calculated for `dates`.
+#[derive(Debug)]
+pub struct MissingRatesError {
+    pub commodity: CommodityTypeID,
+    pub base: CommodityTypeID,
+    pub dates: Vec<NaiveDate>,
+}
+
+impl fmt::Display for MissingRatesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dates: Vec<String> = self
+            .dates
+            .iter()
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .collect();
+        write!(
+            f,
+            "The rates for {}/{} are missing on {} date(s): {}",
+            self.commodity,
+            self.base,
+            dates.len(),
+            dates.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for MissingRatesError {}
+
+/// Summary statistics for a [PriceSeries], see [PriceSeries::stats()].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// The first date and price.
+    pub start: (NaiveDate, Decimal),
+    /// The last date and price.
+    pub end: (NaiveDate, Decimal),
+    pub min: Decimal,
+    pub max: Decimal,
+    pub mean: Decimal,
+    pub median: Decimal,
+    /// Percentage change from the first price to the last (`None` if the
+    /// first price is zero).
+    pub change_percent: Option<Decimal>,
+    /// Annualized volatility of the returns, as a percentage (`None` if
+    /// there are too few prices).
+    pub volatility_percent: Option<f64>,
+    /// Largest fall from a peak to a later trough, as a (negative)
+    /// percentage.
+    pub max_drawdown_percent: Decimal,
+}
+
+/// A price which moved suspiciously far from the previous price, see
+/// [PriceSeries::outliers()].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outlier {
+    pub commodity: CommodityTypeID,
+    pub base: CommodityTypeID,
+    pub date: NaiveDate,
+    pub rate: Decimal,
+    pub previous_date: NaiveDate,
+    pub previous_rate: Decimal,
+    pub change_percent: Decimal,
+}
+
+impl fmt::Display for Outlier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}/{} moved {:+}% to {} from {} on {}",
+            self.date.format("%Y-%m-%d"),
+            self.commodity,
+            self.base,
+            self.change_percent.round_dp(1),
+            self.rate.round_dp(6),
+            self.previous_rate.round_dp(6),
+            self.previous_date.format("%Y-%m-%d"),
+        )
+    }
+}
+
+/// A price whose rounded rate and rounded inverse rate don't multiply to
+/// 1, see [PriceSeries::asymmetries()].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Asymmetry {
+    pub date: NaiveDate,
+    /// The rate as emitted, in the base commodity.
+    pub rate: Decimal,
+    /// The inverse rate at the same precision, in the commodity. `None`
+    /// if the rate rounds to 0.
+    pub inverse: Option<Decimal>,
+    /// How far `rate × inverse` is from 1, as a percentage.
+    pub deviation_percent: Decimal,
+}
+
+/// A period of time which prices can be aggregated over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    /// Monday to Sunday.
+    Weekly,
+    /// Calendar month.
+    Monthly,
+}
+
+impl Period {
+    /// The first day of the period containing `date`.
+    pub fn start(&self, date: &NaiveDate) -> NaiveDate {
+        match self {
+            Period::Weekly => *date - Duration::days(date.weekday().num_days_from_monday() as i64),
+            Period::Monthly => date
+                .with_day(1)
+                .expect("expected first of month to be valid"),
+        }
+    }
+}
+
+/// A statistic used to aggregate a set of prices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Statistic {
+    Mean,
+    Median,
+}
+
+impl Statistic {
+    /// Compute the statistic over `rates`, which must not be empty.
+    pub fn compute(&self, rates: &[Decimal]) -> Decimal {
+        match self {
+            Statistic::Mean => {
+                rates.iter().copied().sum::<Decimal>() / Decimal::from(rates.len() as i64)
+            }
+            Statistic::Median => {
+                let mut sorted = rates.to_vec();
+                sorted.sort();
+                let middle = sorted.len() / 2;
+                if sorted.len().is_multiple_of(2) {
+                    (sorted[middle - 1] + sorted[middle]) / Decimal::from(2)
+                } else {
+                    sorted[middle]
+                }
+            }
+        }
+    }
+}
+
+/// Downsampling of a price series, e.g. `monthly-mean`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Aggregate {
+    pub period: Period,
+    pub statistic: Statistic,
+}
+
+impl fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let period = match self.period {
+            Period::Weekly => "weekly",
+            Period::Monthly => "monthly",
+        };
+        let statistic = match self.statistic {
+            Statistic::Mean => "mean",
+            Statistic::Median => "median",
+        };
+        write!(f, "{} {}", period, statistic)
+    }
+}
+
+impl FromStr for Aggregate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (period, statistic) = match s {
+            "weekly-mean" => (Period::Weekly, Statistic::Mean),
+            "weekly-median" => (Period::Weekly, Statistic::Median),
+            "monthly-mean" => (Period::Monthly, Statistic::Mean),
+            "monthly-median" => (Period::Monthly, Statistic::Median),
+            _ => {
+                return Err(anyhow!(
+                    "unknown aggregate {:?}, expected one of: weekly-mean, weekly-median, \
+                    monthly-mean, monthly-median",
+                    s
+                ))
+            }
+        };
+        Ok(Aggregate { period, statistic })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ordered, Price, PriceSeries};
+    use chrono::NaiveDate;
+    use commodity::CommodityTypeID;
+    use rust_decimal::Decimal;
+    use std::str::FromStr;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2021, 3, day).unwrap()
+    }
+
+    fn series(commodity: &str, base: &str, days: &[u32]) -> PriceSeries {
+        PriceSeries {
+            commodity: CommodityTypeID::from_str(commodity).unwrap(),
+            base: CommodityTypeID::from_str(base).unwrap(),
+            prices: days
+                .iter()
+                .map(|day| {
+                    let price = Price {
+                        rate: Decimal::from(*day),
+                        annotation: None,
+                    };
+                    (date(*day), price)
+                })
+                .collect(),
+            missing: Vec::new(),
+        }
+    }
+
+    fn keys(series: &[PriceSeries], descending: bool) -> Vec<(NaiveDate, String, String)> {
+        ordered(series, descending)
+            .into_iter()
+            .map(|(prices, date, _)| (date, prices.commodity.to_string(), prices.base.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn ordered_by_date_then_commodity_then_base() {
+        // Out of order commodities, each sharing dates with the others.
+        let series = vec![
+            series("EUR", "USD", &[2, 1]),
+            series("AUD", "USD", &[1, 3]),
+            series("AUD", "EUR", &[1, 2]),
+        ];
+        let key = |day, commodity: &str, base: &str| (date(day), commodity.into(), base.into());
+        let ascending = vec![
+            key(1, "AUD", "EUR"),
+            key(1, "AUD", "USD"),
+            key(1, "EUR", "USD"),
+            key(2, "AUD", "EUR"),
+            key(2, "EUR", "USD"),
+            key(3, "AUD", "USD"),
+        ];
+        assert_eq!(keys(&series, false), ascending);
+
+        let descending = vec![
+            key(3, "AUD", "USD"),
+            key(2, "AUD", "EUR"),
+            key(2, "EUR", "USD"),
+            key(1, "AUD", "EUR"),
+            key(1, "AUD", "USD"),
+            key(1, "EUR", "USD"),
+        ];
+        assert_eq!(keys(&series, true), descending);
+
+        // The order the series are in makes no difference.
+        let reversed: Vec<PriceSeries> = series.iter().rev().cloned().collect();
+        assert_eq!(keys(&reversed, false), ascending);
+        assert_eq!(keys(&reversed, true), descending);
+    }
+
+    #[test]
+    fn compress_keeps_the_changes() {
+        // Each rate is its day, so the 2nd is a repeat of the 1st.
+        let mut prices = series("EUR", "USD", &[1, 2, 3]);
+        prices.prices.get_mut(&date(2)).unwrap().rate = Decimal::from(1);
+        prices.compress();
+        let dates: Vec<NaiveDate> = prices.prices.keys().copied().collect();
+        assert_eq!(dates, vec![date(1), date(3)]);
+    }
+}
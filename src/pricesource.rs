@@ -0,0 +1,280 @@
+use crate::series::{Closes, Snapshot, TimeSeries};
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use futures::future::BoxFuture;
+use rust_decimal::Decimal;
+use std::future::Future;
+
+/// An amount converted from one commodity to another with the latest
+/// rates.
+#[derive(Debug, Clone)]
+pub struct Conversion {
+    /// The amount in the commodity it was converted to.
+    pub amount: Decimal,
+    /// The rate it was converted at, in the commodity converted to per
+    /// unit of the commodity converted from.
+    pub rate: Decimal,
+    /// When the rate was published (UTC), if the provider says.
+    pub timestamp: Option<NaiveDateTime>,
+}
+
+/// A provider of prices: of exchange rate tables for a day at a time,
+/// such as OpenExchangeRates (see
+/// [crate::openexchangerate::OpenExchangeRates]) or the made up rates of
+/// [crate::mock::Mock], of tables for a whole range (see
+/// [crate::source::Source::is_table()]), or of the closing prices of one
+/// commodity at a time (see [PriceSource::closes()]). Each
+/// [crate::source::Source] is one, see [crate::source::Source::provider()].
+///
+/// `include` limits the rates to those commodities, `None` being every
+/// commodity the provider has.
+pub trait PriceSource: Sync {
+    /// The name it is chosen by, see [crate::source::Source::name()].
+    fn name(&self) -> &'static str;
+
+    /// The latest rates, and when they were published (UTC) if the
+    /// provider says.
+    fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> impl Future<Output = anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)>> + Send;
+
+    /// The rates at the end of `date`, by default an error for the
+    /// sources which only quote ranges.
+    fn historical(
+        &self,
+        date: &NaiveDate,
+        _include: Option<Vec<CommodityTypeID>>,
+    ) -> impl Future<Output = anyhow::Result<Snapshot>> + Send {
+        async move {
+            Err(anyhow!(
+                "the {} source doesn't quote the rates of a single date ({}), fetch a range \
+                instead",
+                self.name(),
+                date
+            ))
+        }
+    }
+
+    /// The rates of each date from `start` to `end`, by default with a
+    /// [PriceSource::historical()] request for each, one after another.
+    fn series(
+        &self,
+        start: &NaiveDate,
+        end: &NaiveDate,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> impl Future<Output = anyhow::Result<Vec<Snapshot>>> + Send {
+        async move {
+            let mut snapshots = Vec::new();
+            let mut date = *start;
+            while date <= *end {
+                snapshots.push(self.historical(&date, include.clone()).await?);
+                date += Duration::days(1);
+            }
+            Ok(snapshots)
+        }
+    }
+
+    /// The closing prices of `commodity` from `start` to `end`, for the
+    /// sources which quote a commodity at a time, by default an error.
+    fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        _start: &NaiveDate,
+        _end: &NaiveDate,
+    ) -> impl Future<Output = anyhow::Result<Closes>> + Send {
+        async move {
+            Err(anyhow!(
+                "the {} source doesn't quote closing prices (of {})",
+                self.name(),
+                commodity
+            ))
+        }
+    }
+
+    /// Convert `amount` of `from` to `to` with the latest rates, by
+    /// default with a [PriceSource::latest()] request for just the two
+    /// commodities, at their cross rate rounded to 6 decimal places (like
+    /// openexchangerates' rates).
+    fn convert(
+        &self,
+        amount: Decimal,
+        from: CommodityTypeID,
+        to: CommodityTypeID,
+    ) -> impl Future<Output = anyhow::Result<Conversion>> + Send {
+        async move {
+            let (exchange_rate, timestamp) = self.latest(Some(vec![from, to])).await?;
+            let rate = |commodity: CommodityTypeID| {
+                if exchange_rate.base == Some(commodity) {
+                    return Ok(Decimal::ONE);
+                }
+                exchange_rate
+                    .rates
+                    .get(&commodity)
+                    .copied()
+                    .filter(|rate| !rate.is_zero())
+                    .ok_or_else(|| anyhow!("The latest rates have no rate for {}", commodity))
+            };
+            let rate = (rate(to)? / rate(from)?).round_dp(6);
+            Ok(Conversion {
+                amount: amount * rate,
+                rate,
+                timestamp,
+            })
+        }
+    }
+}
+
+/// A [PriceSource] as a trait object, so the source can be chosen at run
+/// time (see [crate::source::Source::provider()]). Every [PriceSource] is
+/// one, with the same methods.
+pub trait DynPriceSource: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> BoxFuture<'_, anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)>>;
+
+    fn historical<'a>(
+        &'a self,
+        date: &'a NaiveDate,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> BoxFuture<'a, anyhow::Result<Snapshot>>;
+
+    fn series<'a>(
+        &'a self,
+        start: &'a NaiveDate,
+        end: &'a NaiveDate,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<Snapshot>>>;
+
+    fn closes<'a>(
+        &'a self,
+        commodity: CommodityTypeID,
+        start: &'a NaiveDate,
+        end: &'a NaiveDate,
+    ) -> BoxFuture<'a, anyhow::Result<Closes>>;
+
+    fn convert(
+        &self,
+        amount: Decimal,
+        from: CommodityTypeID,
+        to: CommodityTypeID,
+    ) -> BoxFuture<'_, anyhow::Result<Conversion>>;
+}
+
+impl<P: PriceSource + Send> DynPriceSource for P {
+    fn name(&self) -> &'static str {
+        PriceSource::name(self)
+    }
+
+    fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> BoxFuture<'_, anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)>> {
+        Box::pin(PriceSource::latest(self, include))
+    }
+
+    fn historical<'a>(
+        &'a self,
+        date: &'a NaiveDate,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> BoxFuture<'a, anyhow::Result<Snapshot>> {
+        Box::pin(PriceSource::historical(self, date, include))
+    }
+
+    fn series<'a>(
+        &'a self,
+        start: &'a NaiveDate,
+        end: &'a NaiveDate,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<Snapshot>>> {
+        Box::pin(PriceSource::series(self, start, end, include))
+    }
+
+    fn closes<'a>(
+        &'a self,
+        commodity: CommodityTypeID,
+        start: &'a NaiveDate,
+        end: &'a NaiveDate,
+    ) -> BoxFuture<'a, anyhow::Result<Closes>> {
+        Box::pin(PriceSource::closes(self, commodity, start, end))
+    }
+
+    fn convert(
+        &self,
+        amount: Decimal,
+        from: CommodityTypeID,
+        to: CommodityTypeID,
+    ) -> BoxFuture<'_, anyhow::Result<Conversion>> {
+        Box::pin(PriceSource::convert(self, amount, from, to))
+    }
+}
+
+/// The `include` commodities other than `base`, those a source which
+/// quotes prices in `base` is asked for. With `None` (every commodity)
+/// there are none, these sources can't list what they quote.
+pub fn priced(
+    base: CommodityTypeID,
+    include: Option<Vec<CommodityTypeID>>,
+) -> Vec<CommodityTypeID> {
+    include
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|commodity| *commodity != base)
+        .collect()
+}
+
+/// The latest closing prices in `base` of the `include` commodities from
+/// `source` (see [PriceSource::closes()]), all dated on the most recent
+/// close: each commodity's latest close within the last week, markets may
+/// be shut for days. The [PriceSource::latest()] of the sources which
+/// quote closing prices.
+pub async fn latest_closes(
+    source: &impl PriceSource,
+    base: CommodityTypeID,
+    include: Option<Vec<CommodityTypeID>>,
+) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+    let end = Utc::now().date_naive();
+    let start = end - Duration::days(7);
+    let mut closes = Vec::new();
+    for commodity in priced(base, include) {
+        closes.push(source.closes(commodity, &start, &end).await?);
+    }
+
+    let no_closes = || anyhow!("{} has no closes in the last week", source.name());
+    let date = closes
+        .iter()
+        .filter_map(|closes| closes.closes.keys().next_back())
+        .max()
+        .copied()
+        .ok_or_else(no_closes)?;
+    for closes in &mut closes {
+        closes.closes = closes
+            .closes
+            .values()
+            .next_back()
+            .map(|close| (date, *close))
+            .into_iter()
+            .collect();
+    }
+    let series = TimeSeries::from_closes(base, &closes)?;
+    let exchange_rate = series.map.get(&date).cloned().ok_or_else(no_closes)?;
+    Ok((exchange_rate, None))
+}
+
+/// The snapshots of each date of a `series` quoted for a whole range, the
+/// [PriceSource::series()] of the sources which quote tables.
+pub fn snapshots(series: TimeSeries) -> Vec<Snapshot> {
+    series
+        .map
+        .into_iter()
+        .map(|(date, exchange_rate)| Snapshot {
+            requested_date: date,
+            timestamp: None,
+            exchange_rate,
+        })
+        .collect()
+}
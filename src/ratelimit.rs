@@ -0,0 +1,46 @@
+use std::{sync::Mutex, time::Duration};
+use tokio::time::Instant;
+
+/// Spaces out requests so that no more than a set number are started
+/// per minute. Shared between all the requests made to a provider.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    /// Panics if `requests_per_minute` is 0.
+    pub fn per_minute(requests_per_minute: u32) -> RateLimiter {
+        RateLimiter {
+            interval: Duration::from_secs(60) / requests_per_minute,
+            next: Mutex::new(None),
+        }
+    }
+
+    /// Wait until the next request is allowed to start.
+    pub async fn wait(&self) {
+        let slot = {
+            let mut next = self.next.lock().expect("rate limiter lock poisoned");
+            let now = Instant::now();
+            let slot = match *next {
+                Some(next) if next > now => next,
+                _ => now,
+            };
+            *next = Some(slot + self.interval);
+            slot
+        };
+
+        tokio::time::delay_until(slot).await;
+    }
+
+    /// Hold back every request for `duration` from now, after the
+    /// provider said too many have been made (e.g. within its minute).
+    pub fn pause(&self, duration: Duration) {
+        let mut next = self.next.lock().expect("rate limiter lock poisoned");
+        let resume = Instant::now() + duration;
+        if next.map(|next| next < resume).unwrap_or(true) {
+            *next = Some(resume);
+        }
+    }
+}
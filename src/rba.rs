@@ -0,0 +1,151 @@
+use crate::{
+    csv::fields,
+    network::{send_text, RequestError},
+    pricesource::{self, PriceSource},
+    series::{Snapshot, TimeSeries},
+};
+use anyhow::anyhow;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "rba";
+
+/// The commodity the rates are quoted against.
+pub const BASE: &str = "AUD";
+
+/// Table F11.1, the daily exchange rates of the current period (earlier
+/// years are only published as spreadsheets).
+const TABLE_URL: &str = "https://www.rba.gov.au/statistics/tables/csv/f11.1-data.csv";
+
+/// Fetch the daily exchange rates from `start` to `end` from table F11.1.
+/// The RBA only publishes rates for business days, at 4pm Sydney time.
+pub async fn get_series(
+    client: &Client,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<TimeSeries> {
+    let mut map = parse(&send_text(client.get(TABLE_URL)).await?)?;
+    if let Some(first) = map.keys().next() {
+        if start < first {
+            return Err(anyhow!(
+                "{}'s table of exchange rates starts on {}, the earlier years are only \
+                published as spreadsheets",
+                PROVIDER_NAME,
+                first
+            ));
+        }
+    }
+    map.retain(|date, _| (start..=end).contains(&date));
+    Ok(TimeSeries::new(map))
+}
+
+/// Fetch the latest day's exchange rates.
+pub async fn get_latest(client: &Client) -> anyhow::Result<ExchangeRate> {
+    parse(&send_text(client.get(TABLE_URL)).await?)?
+        .into_iter()
+        .next_back()
+        .map(|(_, exchange_rate)| exchange_rate)
+        .ok_or_else(|| anyhow!("{} has no latest rates", PROVIDER_NAME))
+}
+
+/// Parse the rates in table F11.1, whose header lines describe each
+/// column, the `Units` line having the currency of a column (e.g. `USD`,
+/// or `Index` for the trade weighted index), followed by a line for each
+/// day such as `02-Jan-2023,0.6828,60.90,...`, the value of A$1 in each
+/// column's currency.
+fn parse(csv: &str) -> anyhow::Result<BTreeMap<NaiveDate, ExchangeRate>> {
+    let invalid = |message: String| anyhow::Error::from(RequestError::InvalidResponse(message));
+    let base = CommodityTypeID::from_str(BASE).expect("expected a valid commodity");
+    let mut currencies: Vec<Option<CommodityTypeID>> = Vec::new();
+    let mut map = BTreeMap::new();
+
+    for line in csv.trim_start_matches('\u{feff}').lines() {
+        let fields = fields(line);
+        let (first, values) = match fields.split_first() {
+            Some((first, values)) => (first, values),
+            None => continue,
+        };
+        if first == "Units" {
+            // Only the currencies, not the index.
+            currencies = values
+                .iter()
+                .map(|unit| {
+                    Some(unit)
+                        .filter(|unit| unit.len() == 3)
+                        .and_then(|unit| CommodityTypeID::from_str(unit).ok())
+                })
+                .collect();
+            continue;
+        }
+        let date = match NaiveDate::parse_from_str(first, "%d-%b-%Y") {
+            Ok(date) => date,
+            // The rest of the header.
+            Err(_) => continue,
+        };
+        if currencies.is_empty() {
+            return Err(invalid(String::from(
+                "the exchange rates have no Units line",
+            )));
+        }
+        let mut rates = BTreeMap::new();
+        for (currency, value) in currencies.iter().zip(values) {
+            // A day a rate wasn't published is empty.
+            if let (Some(currency), false) = (currency, value.is_empty()) {
+                let rate = Decimal::from_str(value).map_err(|err| {
+                    invalid(format!("unable to parse the rate {:?}: {}", value, err))
+                })?;
+                rates.insert(*currency, rate);
+            }
+        }
+        map.insert(
+            date,
+            ExchangeRate {
+                date: Some(date),
+                obtained_datetime: Some(Utc::now()),
+                base: Some(base),
+                rates,
+            },
+        );
+    }
+    Ok(map)
+}
+
+/// The RBA's rates as a [PriceSource], a table for the whole range.
+pub struct Rba<'a> {
+    client: &'a Client,
+}
+
+impl<'a> Rba<'a> {
+    pub fn new(client: &'a Client) -> Rba<'a> {
+        Rba { client }
+    }
+}
+
+impl PriceSource for Rba<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        _include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        Ok((get_latest(self.client).await?, None))
+    }
+
+    async fn series(
+        &self,
+        start: &NaiveDate,
+        end: &NaiveDate,
+        _include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<Vec<Snapshot>> {
+        Ok(pricesource::snapshots(
+            get_series(self.client, start, end).await?,
+        ))
+    }
+}
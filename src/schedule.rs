@@ -0,0 +1,307 @@
+use anyhow::anyhow;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Timelike};
+use std::{fmt, str::FromStr};
+
+/// A cron schedule, in the usual five field format `MINUTE HOUR
+/// DAY-OF-MONTH MONTH DAY-OF-WEEK`, e.g. `0 18 * * mon-fri`. Fields may be
+/// `*`, a value, a range (`1-5`), a list (`1,15`) or a step (`*/15`,
+/// `0-30/10`), months and days of the week may be given by name, and
+/// `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` are accepted.
+///
+/// As with cron, when both the day of the month and the day of the week
+/// are restricted, a date matching either of them matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    expression: String,
+    minutes: Field,
+    hours: Field,
+    days_of_month: Field,
+    months: Field,
+    days_of_week: Field,
+}
+
+/// The values a field matches, as a bit set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Field {
+    bits: u64,
+    /// Whether the field was `*` (or a step over it).
+    any: bool,
+}
+
+impl Field {
+    fn contains(&self, value: u32) -> bool {
+        self.bits & (1 << value) != 0
+    }
+
+    fn values(self, min: u32, max: u32) -> impl Iterator<Item = u32> {
+        (min..=max).filter(move |value| self.contains(*value))
+    }
+}
+
+const MONTHS: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAYS_OF_WEEK: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Parse a single value of a field, either a number or one of `names`
+/// (numbered from `min`).
+fn parse_value(value: &str, min: u32, max: u32, names: &[&str]) -> anyhow::Result<u32> {
+    let lower = value.to_lowercase();
+    let parsed = match names.iter().position(|name| *name == lower) {
+        Some(index) => index as u32 + min,
+        None => value
+            .parse::<u32>()
+            .map_err(|_| anyhow!("{:?} is not a number", value))?,
+    };
+    if parsed < min || parsed > max {
+        return Err(anyhow!(
+            "{} is out of range, expected {} to {}",
+            parsed,
+            min,
+            max
+        ));
+    }
+    Ok(parsed)
+}
+
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> anyhow::Result<Field> {
+    let mut bits = 0;
+    let mut any = false;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step = step
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| anyhow!("invalid step {:?}", step))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            any = true;
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (
+                parse_value(start, min, max, names)?,
+                parse_value(end, min, max, names)?,
+            )
+        } else {
+            let value = parse_value(range, min, max, names)?;
+            // `5/10` means from 5 onwards, in steps of 10.
+            (value, if step > 1 { max } else { value })
+        };
+        if start > end {
+            return Err(anyhow!("range {:?} is backwards", range));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(Field { bits, any })
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expression = match s.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            expression => expression,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(anyhow!(
+                "Invalid schedule {:?}, expected 5 fields (minute hour day-of-month month \
+                day-of-week), e.g. \"0 18 * * mon-fri\"",
+                s
+            ));
+        }
+        let invalid = |name: &str, err: anyhow::Error| {
+            anyhow!("Invalid {} field in schedule {:?}: {}", name, s, err)
+        };
+
+        let mut days_of_week = parse_field(fields[4], 0, 7, DAYS_OF_WEEK)
+            .map_err(|err| invalid("day-of-week", err))?;
+        // Both 0 and 7 are Sunday.
+        if days_of_week.contains(7) {
+            days_of_week.bits |= 1;
+        }
+
+        Ok(Schedule {
+            expression: s.trim().to_string(),
+            minutes: parse_field(fields[0], 0, 59, &[]).map_err(|err| invalid("minute", err))?,
+            hours: parse_field(fields[1], 0, 23, &[]).map_err(|err| invalid("hour", err))?,
+            days_of_month: parse_field(fields[2], 1, 31, &[])
+                .map_err(|err| invalid("day-of-month", err))?,
+            months: parse_field(fields[3], 1, 12, MONTHS).map_err(|err| invalid("month", err))?,
+            days_of_week,
+        })
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+impl Schedule {
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if !self.months.contains(date.month()) {
+            return false;
+        }
+        let day_of_month = self.days_of_month.contains(date.day());
+        let day_of_week = self
+            .days_of_week
+            .contains(date.weekday().num_days_from_sunday());
+        match (self.days_of_month.any, self.days_of_week.any) {
+            (true, true) => true,
+            (true, false) => day_of_week,
+            (false, true) => day_of_month,
+            (false, false) => day_of_month || day_of_week,
+        }
+    }
+
+    /// The first time after `after` (in its time zone) that the schedule
+    /// fires, or `None` if it never does (e.g. `0 0 31 2 *`). Local times
+    /// skipped by a daylight saving change are skipped.
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let timezone = after.timezone();
+        let start = after.naive_local().with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+
+        // The calendar repeats every 28 years, so if nothing matches by
+        // then nothing ever will.
+        let mut date = start.date();
+        let last = date + Duration::days(366 * 28);
+        while date <= last {
+            if self.matches_date(date) {
+                for hour in self.hours.values(0, 23) {
+                    for minute in self.minutes.values(0, 59) {
+                        let time = date.and_hms_opt(hour, minute, 0)?;
+                        if time < start {
+                            continue;
+                        }
+                        if let Some(next) = timezone.from_local_datetime(&time).earliest() {
+                            return Some(next);
+                        }
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Schedule;
+    use chrono::{DateTime, FixedOffset, NaiveDateTime};
+    use std::str::FromStr;
+
+    /// When `schedule` fires next after `after` (`YYYY-mm-dd HH:MM:SS`, in
+    /// UTC), in the same format.
+    fn next(schedule: &str, after: &str) -> Option<String> {
+        let after = NaiveDateTime::parse_from_str(after, "%Y-%m-%d %H:%M:%S")
+            .unwrap()
+            .and_utc();
+        Schedule::from_str(schedule)
+            .unwrap()
+            .next_after(&after)
+            .map(|next| next.format("%Y-%m-%d %H:%M:%S").to_string())
+    }
+
+    #[test]
+    fn parse() {
+        let schedule = Schedule::from_str(" 0 18 * * mon-fri ").unwrap();
+        assert_eq!(schedule.to_string(), "0 18 * * mon-fri");
+        assert_eq!(
+            Schedule::from_str("@daily").unwrap().days_of_week,
+            Schedule::from_str("0 0 * * *").unwrap().days_of_week
+        );
+        // Both 0 and 7 are Sunday.
+        assert_eq!(
+            Schedule::from_str("0 0 * * 7").unwrap().days_of_week.bits & 1,
+            1
+        );
+
+        for schedule in [
+            "",
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "* * * foo *",
+            "@fortnightly",
+        ] {
+            assert!(Schedule::from_str(schedule).is_err(), "{:?}", schedule);
+        }
+    }
+
+    #[test]
+    fn next_after() {
+        // 2021-03-01 is a Monday.
+        assert_eq!(
+            next("0 18 * * mon-fri", "2021-03-05 18:00:00").as_deref(),
+            Some("2021-03-08 18:00:00")
+        );
+        assert_eq!(
+            next("0 18 * * mon-fri", "2021-03-05 17:59:59").as_deref(),
+            Some("2021-03-05 18:00:00")
+        );
+        assert_eq!(
+            next("*/15 * * * *", "2021-03-01 10:07:00").as_deref(),
+            Some("2021-03-01 10:15:00")
+        );
+        assert_eq!(
+            next("5/20 * * * *", "2021-03-01 10:26:00").as_deref(),
+            Some("2021-03-01 10:45:00")
+        );
+        assert_eq!(
+            next("30 9 1,15 jan,mar *", "2021-03-02 00:00:00").as_deref(),
+            Some("2021-03-15 09:30:00")
+        );
+        assert_eq!(
+            next("@monthly", "2021-12-31 23:59:00").as_deref(),
+            Some("2022-01-01 00:00:00")
+        );
+        assert_eq!(
+            next("0 0 * * 7", "2021-03-01 00:00:00").as_deref(),
+            Some("2021-03-07 00:00:00")
+        );
+        // With both a day of the month and of the week, either matches.
+        assert_eq!(
+            next("0 0 13 * fri", "2021-03-01 00:00:00").as_deref(),
+            Some("2021-03-05 00:00:00")
+        );
+        assert_eq!(
+            next("0 0 13 * *", "2021-03-01 00:00:00").as_deref(),
+            Some("2021-03-13 00:00:00")
+        );
+        assert_eq!(
+            next("0 0 29 2 *", "2021-03-01 00:00:00").as_deref(),
+            Some("2024-02-29 00:00:00")
+        );
+        assert_eq!(next("0 0 31 2 *", "2021-03-01 00:00:00"), None);
+
+        // In the time zone of the time it's after.
+        let after: DateTime<FixedOffset> =
+            DateTime::parse_from_rfc3339("2021-03-01T20:00:00+10:00").unwrap();
+        let next = Schedule::from_str("0 18 * * *")
+            .unwrap()
+            .next_after(&after)
+            .unwrap();
+        assert_eq!(next.to_rfc3339(), "2021-03-02T18:00:00+10:00");
+    }
+}
@@ -0,0 +1,928 @@
+use anyhow::anyhow;
+use chrono::{
+    Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Weekday,
+};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use rust_decimal::Decimal;
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    fmt,
+    str::FromStr,
+};
+
+/// Which dates in a range are fetched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sampling {
+    /// Every date.
+    Daily,
+    /// Every 7th date from the start, and the end date.
+    Weekly,
+    /// The start date, the first of every month, and the end date.
+    Monthly,
+}
+
+impl Sampling {
+    /// The dates between `start` and `end` (inclusive) to fetch, in
+    /// ascending order.
+    pub fn dates(&self, start: &NaiveDate, end: &NaiveDate) -> Vec<NaiveDate> {
+        let mut dates: Vec<NaiveDate> = Vec::new();
+        let mut date = *start;
+
+        while &date <= end {
+            let sampled = match self {
+                Sampling::Daily => true,
+                Sampling::Weekly => date.signed_duration_since(*start).num_days() % 7 == 0,
+                Sampling::Monthly => &date == start || date.day() == 1,
+            };
+            if sampled || &date == end {
+                dates.push(date);
+            }
+            date += Duration::days(1);
+        }
+
+        dates
+    }
+}
+
+impl FromStr for Sampling {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "daily" => Ok(Sampling::Daily),
+            "weekly" => Ok(Sampling::Weekly),
+            "monthly" => Ok(Sampling::Monthly),
+            _ => Err(anyhow!(
+                "unknown sampling {:?}, expected one of: daily, weekly, monthly",
+                s
+            )),
+        }
+    }
+}
+
+/// An exchange rate fetched for a requested date.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// The date the exchange rate was requested for.
+    pub requested_date: NaiveDate,
+    /// When the provider says the rates were published (UTC), if known.
+    pub timestamp: Option<NaiveDateTime>,
+    pub exchange_rate: ExchangeRate,
+}
+
+/// The time of day which defines the daily price, for providers with
+/// intraday data, e.g. `16:00-05:00` (New York close) or `00:00Z`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotTime {
+    pub time: NaiveTime,
+    /// Offset from UTC of the time zone `time` is in.
+    pub offset: FixedOffset,
+}
+
+impl SnapshotTime {
+    /// The date whose daily price a snapshot published at `timestamp`
+    /// (UTC) counts towards: its date in the snapshot time zone, or the
+    /// following date if it was published after the snapshot time.
+    pub fn date_of(&self, timestamp: &NaiveDateTime) -> NaiveDate {
+        let local = *timestamp + Duration::seconds(self.offset.local_minus_utc() as i64);
+        if local.time() <= self.time {
+            local.date()
+        } else {
+            local.date() + Duration::days(1)
+        }
+    }
+}
+
+impl FromStr for SnapshotTime {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || {
+            anyhow!(
+                "unable to parse snapshot time {:?}, expected HH:MM followed by a UTC offset \
+                (e.g. 16:00-05:00 or 00:00Z)",
+                s
+            )
+        };
+
+        if s.len() < 5 || !s.is_char_boundary(5) {
+            return Err(error());
+        }
+        let (time, offset) = s.split_at(5);
+        let time = NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| error())?;
+        let offset = match offset {
+            "" | "Z" => FixedOffset::east_opt(0),
+            offset => {
+                let sign = match offset.chars().next() {
+                    Some('+') => 1,
+                    Some('-') => -1,
+                    _ => return Err(error()),
+                };
+                let offset_time =
+                    NaiveTime::parse_from_str(&offset[1..], "%H:%M").map_err(|_| error())?;
+                let seconds = offset_time.num_seconds_from_midnight() as i32;
+                FixedOffset::east_opt(sign * seconds)
+            }
+        }
+        .ok_or_else(error)?;
+
+        Ok(SnapshotTime { time, offset })
+    }
+}
+
+/// Which date a snapshot is attributed to when the provider dates it
+/// differently to the date it was requested for, see
+/// [TimeSeries::from_snapshots()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateMismatch {
+    /// The date it was requested for.
+    Requested,
+    /// The date the provider gave.
+    Response,
+    /// Neither, the series can't be built.
+    Fail,
+}
+
+impl FromStr for DateMismatch {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "requested" => Ok(DateMismatch::Requested),
+            "response" => Ok(DateMismatch::Response),
+            "fail" => Ok(DateMismatch::Fail),
+            _ => Err(anyhow!(
+                "unknown date mismatch policy {:?}, expected one of: requested, response, fail",
+                s
+            )),
+        }
+    }
+}
+
+/// A snapshot the provider dated differently to the date it was requested
+/// for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mismatch {
+    pub requested: NaiveDate,
+    pub response: NaiveDate,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the response requested for {} is dated {}",
+            self.requested.format("%Y-%m-%d"),
+            self.response.format("%Y-%m-%d")
+        )
+    }
+}
+
+/// Two or more snapshots which resolved to the same date, see
+/// [TimeSeries::from_snapshots()].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+    pub date: NaiveDate,
+    /// Requested date of the snapshot which was kept.
+    pub kept: NaiveDate,
+    /// Requested dates of the snapshots which were discarded.
+    pub discarded: Vec<NaiveDate>,
+}
+
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let discarded: Vec<String> = self
+            .discarded
+            .iter()
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .collect();
+        write!(
+            f,
+            "the responses requested for {} and {} all resolved to {}, keeping the latest \
+            snapshot (requested for {})",
+            discarded.join(", "),
+            self.kept.format("%Y-%m-%d"),
+            self.date.format("%Y-%m-%d"),
+            self.kept.format("%Y-%m-%d"),
+        )
+    }
+}
+
+/// What [TimeSeries::merge()] does with a date which both series have
+/// an exchange rate for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Keep the exchange rate already in the series.
+    KeepExisting,
+    /// Replace it with the exchange rate being merged in.
+    Overwrite,
+    /// Fail if the two exchange rates have a different base, or a
+    /// different rate for any commodity they both have.
+    ErrorOnMismatch,
+}
+
+impl FromStr for MergePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep-existing" => Ok(MergePolicy::KeepExisting),
+            "overwrite" => Ok(MergePolicy::Overwrite),
+            "error-on-mismatch" => Ok(MergePolicy::ErrorOnMismatch),
+            _ => Err(anyhow!(
+                "unknown merge policy {:?}, expected one of: keep-existing, overwrite, \
+                error-on-mismatch",
+                s
+            )),
+        }
+    }
+}
+
+/// Exchange rates for a range of dates.
+#[derive(Debug, Default, Clone)]
+pub struct TimeSeries {
+    pub map: BTreeMap<NaiveDate, ExchangeRate>,
+    /// Dates in `map` whose exchange rate was filled in, rather than
+    /// provided for that date.
+    pub filled: BTreeMap<NaiveDate, Filled>,
+}
+
+/// How the exchange rate for a date was filled in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filled {
+    /// Carried forward from the most recent prior date with data.
+    ForwardFilled { from: NaiveDate },
+    /// Linearly interpolated between the nearest dates with data on
+    /// either side.
+    Interpolated { from: NaiveDate, to: NaiveDate },
+}
+
+impl fmt::Display for Filled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filled::ForwardFilled { from } => {
+                write!(f, "forward-filled from {}", from.format("%Y-%m-%d"))
+            }
+            Filled::Interpolated { from, to } => write!(
+                f,
+                "interpolated between {} and {}",
+                from.format("%Y-%m-%d"),
+                to.format("%Y-%m-%d")
+            ),
+        }
+    }
+}
+
+/// The daily closing prices of a security (or anything else a source
+/// quotes a price for, rather than a table of exchange rates).
+#[derive(Debug, Clone)]
+pub struct Closes {
+    pub commodity: CommodityTypeID,
+    /// The currency the prices are in.
+    pub currency: CommodityTypeID,
+    pub closes: BTreeMap<NaiveDate, Decimal>,
+}
+
+impl TimeSeries {
+    pub fn new(map: BTreeMap<NaiveDate, ExchangeRate>) -> TimeSeries {
+        TimeSeries {
+            map,
+            filled: BTreeMap::new(),
+        }
+    }
+
+    /// Build a series quoted against `base` from the closing prices in
+    /// `closes`, which must all be in `base`. Each price is stored as the
+    /// units of its commodity per unit of `base` (like an exchange rate),
+    /// and comes back out as the price through [crate::crossrate].
+    pub fn from_closes(base: CommodityTypeID, closes: &[Closes]) -> anyhow::Result<TimeSeries> {
+        let mut map: BTreeMap<NaiveDate, ExchangeRate> = BTreeMap::new();
+        for closes in closes {
+            if closes.currency != base {
+                return Err(anyhow!(
+                    "{} is quoted in {}, not {}, fetch it with --base {}",
+                    closes.commodity,
+                    closes.currency,
+                    base,
+                    closes.currency
+                ));
+            }
+            for (date, close) in &closes.closes {
+                if close.is_zero() {
+                    continue;
+                }
+                map.entry(*date)
+                    .or_insert_with(|| ExchangeRate {
+                        date: Some(*date),
+                        obtained_datetime: Some(chrono::Utc::now()),
+                        base: Some(base),
+                        rates: BTreeMap::new(),
+                    })
+                    .rates
+                    .insert(closes.commodity, Decimal::ONE / *close);
+            }
+        }
+        Ok(TimeSeries::new(map))
+    }
+
+    /// The runs of consecutive dates in the series whose exchange rate
+    /// doesn't include `commodity`, as the first and last date of each run
+    /// and the number of dates in it.
+    pub fn absent(&self, commodity: &CommodityTypeID) -> Vec<(NaiveDate, NaiveDate, usize)> {
+        let mut runs: Vec<(NaiveDate, NaiveDate, usize)> = Vec::new();
+        let mut in_run = false;
+        for (date, exchange_rate) in &self.map {
+            let present = exchange_rate.base.as_ref() == Some(commodity)
+                || exchange_rate.rates.contains_key(commodity);
+            match runs.last_mut() {
+                Some((_, last, count)) if in_run && !present => {
+                    *last = *date;
+                    *count += 1;
+                }
+                _ if !present => runs.push((*date, *date, 1)),
+                _ => {}
+            }
+            in_run = !present;
+        }
+        runs
+    }
+
+    /// Build a series from `snapshots`, keyed by the date of each
+    /// exchange rate. When several snapshots resolve to the same date
+    /// (e.g. the latest rates and the historical rates for today, or a
+    /// timestamp on a DST edge) the one with the latest timestamp is kept,
+    /// then the one requested for the latest date, so the result doesn't
+    /// depend on the order the snapshots arrived in. Snapshots without a
+    /// timestamp lose to those with one.
+    ///
+    /// With a `snapshot_time`, snapshots with a timestamp are attributed
+    /// to dates using [SnapshotTime::date_of()] rather than the date the
+    /// provider gave. Otherwise snapshots the provider dated differently
+    /// to the date they were requested for are returned along with the
+    /// series, and attributed according to `mismatch` (failing with
+    /// [DateMismatch::Fail]).
+    pub fn from_snapshots(
+        snapshots: Vec<Snapshot>,
+        snapshot_time: Option<&SnapshotTime>,
+        mismatch: DateMismatch,
+    ) -> anyhow::Result<(TimeSeries, Vec<Collision>, Vec<Mismatch>)> {
+        let mut by_date: BTreeMap<NaiveDate, Vec<Snapshot>> = BTreeMap::new();
+        let mut mismatches = Vec::new();
+        for mut snapshot in snapshots {
+            let date = match (snapshot_time, &snapshot.timestamp) {
+                (Some(snapshot_time), Some(timestamp)) => snapshot_time.date_of(timestamp),
+                _ => match snapshot.exchange_rate.date {
+                    Some(response) if response != snapshot.requested_date => {
+                        let found = Mismatch {
+                            requested: snapshot.requested_date,
+                            response,
+                        };
+                        match mismatch {
+                            DateMismatch::Requested => {
+                                mismatches.push(found);
+                                snapshot.requested_date
+                            }
+                            DateMismatch::Response => {
+                                mismatches.push(found);
+                                response
+                            }
+                            DateMismatch::Fail => {
+                                return Err(anyhow!(
+                                    "The response requested for {} is dated {}, see \
+                                    --date-mismatch",
+                                    found.requested.format("%Y-%m-%d"),
+                                    found.response.format("%Y-%m-%d")
+                                ))
+                            }
+                        }
+                    }
+                    _ => snapshot.requested_date,
+                },
+            };
+            snapshot.exchange_rate.date = Some(date);
+            by_date.entry(date).or_default().push(snapshot);
+        }
+
+        let mut map = BTreeMap::new();
+        let mut collisions = Vec::new();
+        for (date, mut snapshots) in by_date {
+            snapshots.sort_by_key(|snapshot| (snapshot.timestamp, snapshot.requested_date));
+            let kept = snapshots.pop().expect("expected at least one snapshot");
+            if !snapshots.is_empty() {
+                collisions.push(Collision {
+                    date,
+                    kept: kept.requested_date,
+                    discarded: snapshots
+                        .iter()
+                        .map(|snapshot| snapshot.requested_date)
+                        .collect(),
+                });
+            }
+            map.insert(date, kept.exchange_rate);
+        }
+
+        Ok((TimeSeries::new(map), collisions, mismatches))
+    }
+
+    /// Merge `other` (e.g. freshly fetched rates) into this series (e.g.
+    /// a long history loaded from a cache), so a history can be extended
+    /// by only fetching the new dates. Dates which only `other` has are
+    /// added, and `policy` decides what happens to dates both have. With
+    /// [MergePolicy::ErrorOnMismatch] the series is left unchanged if
+    /// there is a mismatch, otherwise the rates for commodities only
+    /// `other` has are added to the date.
+    pub fn merge(&mut self, other: TimeSeries, policy: MergePolicy) -> anyhow::Result<()> {
+        if policy == MergePolicy::ErrorOnMismatch {
+            if let Some(date) = other.map.iter().find_map(|(date, exchange_rate)| {
+                let existing = self.map.get(date)?;
+                let mismatch = existing.base != exchange_rate.base
+                    || exchange_rate.rates.iter().any(|(commodity, rate)| {
+                        matches!(existing.rates.get(commodity), Some(existing) if existing != rate)
+                    });
+                if mismatch {
+                    Some(*date)
+                } else {
+                    None
+                }
+            }) {
+                return Err(anyhow!(
+                    "Unable to merge the exchange rates for {}, they don't match the existing \
+                    rates",
+                    date.format("%Y-%m-%d")
+                ));
+            }
+        }
+
+        let TimeSeries { map, mut filled } = other;
+        for (date, exchange_rate) in map {
+            let other_filled = filled.remove(&date);
+            match self.map.entry(date) {
+                Entry::Vacant(entry) => {
+                    entry.insert(exchange_rate);
+                    if let Some(other_filled) = other_filled {
+                        self.filled.insert(date, other_filled);
+                    }
+                }
+                Entry::Occupied(mut entry) => match policy {
+                    MergePolicy::KeepExisting => {}
+                    MergePolicy::Overwrite => {
+                        entry.insert(exchange_rate);
+                        match other_filled {
+                            Some(other_filled) => self.filled.insert(date, other_filled),
+                            None => self.filled.remove(&date),
+                        };
+                    }
+                    MergePolicy::ErrorOnMismatch => {
+                        let existing = entry.get_mut();
+                        for (commodity, rate) in exchange_rate.rates {
+                            existing.rates.entry(commodity).or_insert(rate);
+                        }
+                    }
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The rates of `commodity` in the series, quoted against `base`
+    /// (which the rates of each date must include, unless it is the
+    /// date's base), so they can be merged into a series from another
+    /// source with another base. Dates without either are left out.
+    pub fn rebased(&self, commodity: &CommodityTypeID, base: &CommodityTypeID) -> TimeSeries {
+        let mut series = TimeSeries::default();
+        for (date, exchange_rate) in &self.map {
+            let quoted = |commodity: &CommodityTypeID| {
+                if exchange_rate.base.as_ref() == Some(commodity) {
+                    Some(Decimal::ONE)
+                } else {
+                    exchange_rate.rates.get(commodity).copied()
+                }
+            };
+            let rate = match (quoted(commodity), quoted(base)) {
+                (Some(rate), Some(base_rate)) if !base_rate.is_zero() => rate / base_rate,
+                _ => continue,
+            };
+            series.map.insert(
+                *date,
+                ExchangeRate {
+                    date: exchange_rate.date,
+                    obtained_datetime: exchange_rate.obtained_datetime,
+                    base: Some(*base),
+                    rates: std::iter::once((*commodity, rate)).collect(),
+                },
+            );
+            if let Some(filled) = self.filled.get(date) {
+                series.filled.insert(*date, *filled);
+            }
+        }
+        series
+    }
+
+    /// Fill in the dates between `start` and `end` (inclusive) which have
+    /// no exchange rate by carrying forward the rate from the most recent
+    /// prior date that has data. No date is filled more than
+    /// `max_fill_days` after the date it was filled from. Dates before
+    /// the first date with data are left empty.
+    pub fn forward_fill(&mut self, start: &NaiveDate, end: &NaiveDate, max_fill_days: i64) {
+        // The date a date's rate came from, which isn't itself if it was
+        // filled.
+        let source = |filled: &BTreeMap<NaiveDate, Filled>, date: &NaiveDate| match filled.get(date)
+        {
+            Some(Filled::ForwardFilled { from }) => *from,
+            _ => *date,
+        };
+        let mut last: Option<(NaiveDate, ExchangeRate)> = self
+            .map
+            .range(..*start)
+            .next_back()
+            .map(|(date, exchange_rate)| (source(&self.filled, date), exchange_rate.clone()));
+
+        let mut date = *start;
+        while &date <= end {
+            match self.map.get(&date) {
+                Some(exchange_rate) => {
+                    // A filled date isn't used as the source of later fills.
+                    last = Some((source(&self.filled, &date), exchange_rate.clone()));
+                }
+                None => {
+                    if let Some((from, exchange_rate)) = &last {
+                        if date.signed_duration_since(*from).num_days() <= max_fill_days {
+                            let mut filled = exchange_rate.clone();
+                            filled.date = Some(date);
+                            self.map.insert(date, filled);
+                            self.filled
+                                .insert(date, Filled::ForwardFilled { from: *from });
+                        }
+                    }
+                }
+            }
+            date += Duration::days(1);
+        }
+    }
+
+    /// Remove each commodity's rate which is unchanged (in the same base)
+    /// from its rate on the previous date which had one, e.g. repeated
+    /// weekend rates or forward-filled dates, and the dates left with no
+    /// rates. Cross rates between the remaining rates can't be derived,
+    /// so to remove the duplicates of a pair which isn't quoted against
+    /// the series' base, compress the series [TimeSeries::rebased()] onto
+    /// the pair's base (as [crate::prices::PriceSeries::compress()] does).
+    pub fn compress(&mut self) {
+        let mut previous: BTreeMap<CommodityTypeID, (Option<CommodityTypeID>, Decimal)> =
+            BTreeMap::new();
+        for exchange_rate in self.map.values_mut() {
+            let base = exchange_rate.base;
+            exchange_rate.rates.retain(|commodity, rate| {
+                previous.insert(*commodity, (base, *rate)) != Some((base, *rate))
+            });
+        }
+
+        let emptied: Vec<NaiveDate> = self
+            .map
+            .iter()
+            .filter(|(_, exchange_rate)| exchange_rate.rates.is_empty())
+            .map(|(date, _)| *date)
+            .collect();
+        for date in emptied {
+            self.map.remove(&date);
+            self.filled.remove(&date);
+        }
+    }
+
+    /// Drop the exchange rates for Saturdays and Sundays, which many
+    /// providers only repeat from the Friday. Lookups for a weekend date
+    /// still find the Friday's price in the journal.
+    pub fn retain_business_days(&mut self) {
+        let weekday = |date: &NaiveDate| !matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+        self.map.retain(|date, _| weekday(date));
+        self.filled.retain(|date, _| weekday(date));
+    }
+
+    /// Fill in the dates which have no exchange rate, between the
+    /// first and last dates with data, by linearly interpolating each
+    /// rate between the nearest dates with data on either side. Only
+    /// rates present (with the same base) on both sides are
+    /// interpolated.
+    pub fn interpolate(&mut self) {
+        let known: Vec<(NaiveDate, ExchangeRate)> = self
+            .map
+            .iter()
+            .filter(|(date, _)| !self.filled.contains_key(date))
+            .map(|(date, exchange_rate)| (*date, exchange_rate.clone()))
+            .collect();
+
+        for pair in known.windows(2) {
+            let (from, from_rate) = &pair[0];
+            let (to, to_rate) = &pair[1];
+            if from_rate.base != to_rate.base {
+                continue;
+            }
+
+            let span = Decimal::from(to.signed_duration_since(*from).num_days());
+            let mut date = *from + Duration::days(1);
+            while &date < to {
+                if let Entry::Vacant(entry) = self.map.entry(date) {
+                    let elapsed = Decimal::from(date.signed_duration_since(*from).num_days());
+                    let rates = from_rate
+                        .rates
+                        .iter()
+                        .filter_map(|(commodity, start)| {
+                            let end = to_rate.rates.get(commodity)?;
+                            Some((*commodity, *start + (*end - *start) * elapsed / span))
+                        })
+                        .collect();
+
+                    entry.insert(ExchangeRate {
+                        date: Some(date),
+                        obtained_datetime: to_rate.obtained_datetime,
+                        base: to_rate.base,
+                        rates,
+                    });
+                    self.filled.insert(
+                        date,
+                        Filled::Interpolated {
+                            from: *from,
+                            to: *to,
+                        },
+                    );
+                }
+                date += Duration::days(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Collision, DateMismatch, Filled, MergePolicy, Mismatch, Snapshot, TimeSeries};
+    use chrono::NaiveDate;
+    use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+    use rust_decimal::Decimal;
+    use std::str::FromStr;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2021, 3, day).unwrap()
+    }
+
+    fn commodity(code: &str) -> CommodityTypeID {
+        CommodityTypeID::from_str(code).unwrap()
+    }
+
+    /// The exchange rate on `day` in `base`, of each of `rates`.
+    fn exchange_rate(day: u32, base: &str, rates: &[(&str, &str)]) -> ExchangeRate {
+        ExchangeRate {
+            date: Some(date(day)),
+            obtained_datetime: None,
+            base: Some(commodity(base)),
+            rates: rates
+                .iter()
+                .map(|(code, rate)| (commodity(code), Decimal::from_str(rate).unwrap()))
+                .collect(),
+        }
+    }
+
+    fn series(exchange_rates: Vec<ExchangeRate>) -> TimeSeries {
+        let mut series = TimeSeries::default();
+        for exchange_rate in exchange_rates {
+            series
+                .map
+                .insert(exchange_rate.date.unwrap(), exchange_rate);
+        }
+        series
+    }
+
+    #[test]
+    fn compress_per_commodity() {
+        let mut compressed = series(vec![
+            exchange_rate(1, "USD", &[("EUR", "0.9"), ("AUD", "1.5")]),
+            exchange_rate(2, "USD", &[("EUR", "0.9"), ("AUD", "1.6")]),
+            exchange_rate(3, "USD", &[("EUR", "0.9"), ("AUD", "1.6")]),
+            exchange_rate(4, "USD", &[("EUR", "0.8"), ("AUD", "1.6")]),
+            // The same rate in another base is a change.
+            exchange_rate(5, "EUR", &[("AUD", "1.6")]),
+        ]);
+        compressed.compress();
+
+        let expected = series(vec![
+            exchange_rate(1, "USD", &[("EUR", "0.9"), ("AUD", "1.5")]),
+            exchange_rate(2, "USD", &[("AUD", "1.6")]),
+            exchange_rate(4, "USD", &[("EUR", "0.8")]),
+            exchange_rate(5, "EUR", &[("AUD", "1.6")]),
+        ]);
+        assert_eq!(compressed.map, expected.map);
+    }
+
+    #[test]
+    fn merge() {
+        let existing = || {
+            let mut existing = series(vec![
+                exchange_rate(1, "USD", &[("EUR", "0.9")]),
+                exchange_rate(2, "USD", &[("EUR", "0.8")]),
+            ]);
+            existing
+                .filled
+                .insert(date(2), Filled::ForwardFilled { from: date(1) });
+            existing
+        };
+        let other = |eur: &str| {
+            let mut other = series(vec![
+                exchange_rate(2, "USD", &[("EUR", eur), ("AUD", "1.5")]),
+                exchange_rate(3, "USD", &[("EUR", "0.6")]),
+            ]);
+            other
+                .filled
+                .insert(date(3), Filled::ForwardFilled { from: date(2) });
+            other
+        };
+        let filled_from = |days: &[(u32, u32)]| {
+            days.iter()
+                .map(|(day, from)| (date(*day), Filled::ForwardFilled { from: date(*from) }))
+                .collect()
+        };
+
+        let mut kept = existing();
+        kept.merge(other("0.7"), MergePolicy::KeepExisting).unwrap();
+        let expected = series(vec![
+            exchange_rate(1, "USD", &[("EUR", "0.9")]),
+            exchange_rate(2, "USD", &[("EUR", "0.8")]),
+            exchange_rate(3, "USD", &[("EUR", "0.6")]),
+        ]);
+        assert_eq!(kept.map, expected.map);
+        assert_eq!(kept.filled, filled_from(&[(2, 1), (3, 2)]));
+
+        let mut overwritten = existing();
+        overwritten
+            .merge(other("0.7"), MergePolicy::Overwrite)
+            .unwrap();
+        let expected = series(vec![
+            exchange_rate(1, "USD", &[("EUR", "0.9")]),
+            exchange_rate(2, "USD", &[("EUR", "0.7"), ("AUD", "1.5")]),
+            exchange_rate(3, "USD", &[("EUR", "0.6")]),
+        ]);
+        assert_eq!(overwritten.map, expected.map);
+        assert_eq!(overwritten.filled, filled_from(&[(3, 2)]));
+
+        // Matching rates are put together, and a mismatch leaves the
+        // series as it was.
+        let mut matched = existing();
+        matched
+            .merge(other("0.8"), MergePolicy::ErrorOnMismatch)
+            .unwrap();
+        let expected = series(vec![
+            exchange_rate(1, "USD", &[("EUR", "0.9")]),
+            exchange_rate(2, "USD", &[("EUR", "0.8"), ("AUD", "1.5")]),
+            exchange_rate(3, "USD", &[("EUR", "0.6")]),
+        ]);
+        assert_eq!(matched.map, expected.map);
+        let mut mismatched = existing();
+        assert!(mismatched
+            .merge(other("0.7"), MergePolicy::ErrorOnMismatch)
+            .is_err());
+        assert_eq!(mismatched.map, existing().map);
+        let other_base = series(vec![exchange_rate(1, "EUR", &[("AUD", "1.6")])]);
+        assert!(existing()
+            .merge(other_base, MergePolicy::ErrorOnMismatch)
+            .is_err());
+    }
+
+    #[test]
+    fn forward_fill() {
+        let mut filled = series(vec![
+            exchange_rate(1, "USD", &[("EUR", "0.9")]),
+            exchange_rate(5, "USD", &[("EUR", "0.8")]),
+        ]);
+        filled.forward_fill(&date(2), &date(7), 2);
+        let expected = series(vec![
+            exchange_rate(1, "USD", &[("EUR", "0.9")]),
+            exchange_rate(2, "USD", &[("EUR", "0.9")]),
+            exchange_rate(3, "USD", &[("EUR", "0.9")]),
+            exchange_rate(5, "USD", &[("EUR", "0.8")]),
+            exchange_rate(6, "USD", &[("EUR", "0.8")]),
+            exchange_rate(7, "USD", &[("EUR", "0.8")]),
+        ]);
+        assert_eq!(filled.map, expected.map);
+        assert_eq!(
+            filled.filled.get(&date(3)),
+            Some(&Filled::ForwardFilled { from: date(1) })
+        );
+
+        // Filling on from a filled date still counts from the date it was
+        // filled from.
+        filled.forward_fill(&date(4), &date(4), 2);
+        assert!(!filled.map.contains_key(&date(4)));
+        filled.forward_fill(&date(4), &date(4), 3);
+        assert_eq!(
+            filled.filled.get(&date(4)),
+            Some(&Filled::ForwardFilled { from: date(1) })
+        );
+
+        // Dates before the first with data are left empty.
+        let mut empty = series(vec![exchange_rate(3, "USD", &[("EUR", "0.9")])]);
+        empty.forward_fill(&date(1), &date(3), 7);
+        assert_eq!(empty.map.keys().collect::<Vec<_>>(), vec![&date(3)]);
+    }
+
+    #[test]
+    fn interpolate() {
+        let mut interpolated = series(vec![
+            exchange_rate(1, "USD", &[("EUR", "1"), ("AUD", "2")]),
+            exchange_rate(3, "USD", &[("EUR", "2")]),
+            // No rates across a change of base.
+            exchange_rate(5, "EUR", &[("AUD", "4")]),
+            exchange_rate(8, "EUR", &[("AUD", "7")]),
+        ]);
+        interpolated.interpolate();
+        let expected = series(vec![
+            exchange_rate(1, "USD", &[("EUR", "1"), ("AUD", "2")]),
+            // Only the rates on both sides.
+            exchange_rate(2, "USD", &[("EUR", "1.5")]),
+            exchange_rate(3, "USD", &[("EUR", "2")]),
+            exchange_rate(5, "EUR", &[("AUD", "4")]),
+            exchange_rate(6, "EUR", &[("AUD", "5")]),
+            exchange_rate(7, "EUR", &[("AUD", "6")]),
+            exchange_rate(8, "EUR", &[("AUD", "7")]),
+        ]);
+        assert_eq!(interpolated.map, expected.map);
+        assert_eq!(
+            interpolated.filled.get(&date(6)),
+            Some(&Filled::Interpolated {
+                from: date(5),
+                to: date(8)
+            })
+        );
+        assert!(!interpolated.filled.contains_key(&date(4)));
+    }
+
+    #[test]
+    fn from_snapshots() {
+        let snapshot = |requested: u32, dated: u32, hour: Option<u32>, eur: &str| Snapshot {
+            requested_date: date(requested),
+            timestamp: hour.map(|hour| date(dated).and_hms_opt(hour, 0, 0).unwrap()),
+            exchange_rate: exchange_rate(dated, "USD", &[("EUR", eur)]),
+        };
+        let snapshots = vec![
+            snapshot(1, 1, None, "0.1"),
+            // Dated the day before it was requested for.
+            snapshot(2, 1, Some(12), "0.2"),
+            snapshot(1, 1, Some(6), "0.3"),
+            // Without timestamps, the one requested for the latest date.
+            snapshot(3, 3, None, "0.4"),
+            snapshot(4, 3, None, "0.5"),
+        ];
+
+        // Not depending on the order they arrived in.
+        for snapshots in [snapshots.clone(), snapshots.into_iter().rev().collect()] {
+            let (series, collisions, mismatches) =
+                TimeSeries::from_snapshots(snapshots, None, DateMismatch::Response).unwrap();
+            let mut expected = TimeSeries::default();
+            expected
+                .map
+                .insert(date(1), exchange_rate(1, "USD", &[("EUR", "0.2")]));
+            expected
+                .map
+                .insert(date(3), exchange_rate(3, "USD", &[("EUR", "0.5")]));
+            assert_eq!(series.map, expected.map);
+            assert_eq!(
+                collisions,
+                vec![
+                    Collision {
+                        date: date(1),
+                        kept: date(2),
+                        discarded: vec![date(1), date(1)],
+                    },
+                    Collision {
+                        date: date(3),
+                        kept: date(4),
+                        discarded: vec![date(3)],
+                    },
+                ]
+            );
+            assert_eq!(mismatches.len(), 2);
+        }
+
+        // Attributed to the requested date instead, or failing.
+        let (series, collisions, mismatches) = TimeSeries::from_snapshots(
+            vec![snapshot(2, 1, None, "0.2")],
+            None,
+            DateMismatch::Requested,
+        )
+        .unwrap();
+        assert_eq!(series.map.keys().collect::<Vec<_>>(), vec![&date(2)]);
+        assert_eq!(series.map[&date(2)].date, Some(date(2)));
+        assert!(collisions.is_empty());
+        assert_eq!(
+            mismatches,
+            vec![Mismatch {
+                requested: date(2),
+                response: date(1)
+            }]
+        );
+        assert!(TimeSeries::from_snapshots(
+            vec![snapshot(2, 1, None, "0.2")],
+            None,
+            DateMismatch::Fail
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,383 @@
+use crate::{
+    alphavantage, bankofcanada, beanprice, coinbase, coingecko, coinmarketcap,
+    config::ProviderConfig,
+    csv, ecb, exchangeratehost, federalreserve, finnhub, fmp, frankfurter, html, iex, json, kraken,
+    marketstack, metalsapi,
+    mock::{self, Mock},
+    morningstar, nasdaqdatalink, oanda,
+    openexchangerate::{self, OpenExchangeRates},
+    polygon,
+    pricesource::DynPriceSource,
+    rba,
+    series::SnapshotTime,
+    stooq, tiingo, wise, yahoo,
+};
+use anyhow::anyhow;
+use commodity::CommodityTypeID;
+use reqwest::Client;
+
+/// Where the rates are fetched from, chosen with `--source` (or the
+/// `source` of a job).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    OpenExchangeRates,
+    /// Made up rates for the seed, see [crate::mock].
+    Mock {
+        seed: u64,
+    },
+    Yahoo,
+    AlphaVantage,
+    CoinGecko,
+    CoinMarketCap,
+    /// The European Central Bank's reference rates, see [crate::ecb].
+    Ecb,
+    /// The ECB's rates through the Frankfurter API, in any base.
+    Frankfurter,
+    ExchangerateHost,
+    /// IEX Cloud's end of day prices of US equities, in batches.
+    Iex,
+    Finnhub,
+    Tiingo,
+    /// Daily candles of the pairs traded on Kraken.
+    Kraken,
+    /// Daily candles of the products traded on Coinbase Exchange.
+    Coinbase,
+    /// Polygon.io's daily aggregates of US stocks and forex.
+    Polygon,
+    /// Datasets from Nasdaq Data Link (formerly Quandl), by dataset code.
+    NasdaqDataLink,
+    /// Stooq's free daily CSVs of stocks, indices and forex.
+    Stooq,
+    /// The Bank of Canada's daily exchange rates against CAD, see [crate::bankofcanada].
+    BankOfCanada,
+    /// The Reserve Bank of Australia's daily exchange rates against AUD, see [crate::rba].
+    Rba,
+    /// The Federal Reserve's H.10 rates against USD, see [crate::federalreserve].
+    FederalReserve,
+    /// Prices of precious metals from metals-api, see [crate::metalsapi].
+    MetalsApi,
+    /// OANDA's daily average rates, see [crate::oanda].
+    Oanda {
+        quote: oanda::Quote,
+    },
+    /// Wise's daily mid-market rates.
+    Wise,
+    /// Marketstack's end of day prices of global equities, in batches.
+    Marketstack,
+    /// Financial Modeling Prep's daily prices of stocks, ETFs and mutual funds.
+    Fmp,
+    /// The unit prices of managed funds on Morningstar, by fund ID.
+    Morningstar,
+    /// Prices from any JSON API, as set up in the provider's config, see [crate::json].
+    Json,
+    /// Prices read from a local CSV, such as a broker's statement, with the
+    /// columns set in the provider's config, see [crate::csv].
+    Csv,
+    /// Prices scraped from a web page with the CSS selectors set in the
+    /// provider's config, see [crate::html].
+    Html,
+    /// Prices from the Python source modules of bean-price (the price
+    /// fetcher which comes with beancount), see [crate::beanprice].
+    BeanPrice,
+}
+
+impl Source {
+    /// The name it is chosen by, which is also the name of its section
+    /// under `providers` in the config.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Source::OpenExchangeRates => openexchangerate::PROVIDER_NAME,
+            Source::Mock { .. } => mock::PROVIDER_NAME,
+            Source::Yahoo => yahoo::PROVIDER_NAME,
+            Source::AlphaVantage => alphavantage::PROVIDER_NAME,
+            Source::CoinGecko => coingecko::PROVIDER_NAME,
+            Source::CoinMarketCap => coinmarketcap::PROVIDER_NAME,
+            Source::Ecb => ecb::PROVIDER_NAME,
+            Source::Frankfurter => frankfurter::PROVIDER_NAME,
+            Source::ExchangerateHost => exchangeratehost::PROVIDER_NAME,
+            Source::Iex => iex::PROVIDER_NAME,
+            Source::Finnhub => finnhub::PROVIDER_NAME,
+            Source::Tiingo => tiingo::PROVIDER_NAME,
+            Source::Kraken => kraken::PROVIDER_NAME,
+            Source::Coinbase => coinbase::PROVIDER_NAME,
+            Source::Polygon => polygon::PROVIDER_NAME,
+            Source::NasdaqDataLink => nasdaqdatalink::PROVIDER_NAME,
+            Source::Stooq => stooq::PROVIDER_NAME,
+            Source::BankOfCanada => bankofcanada::PROVIDER_NAME,
+            Source::Rba => rba::PROVIDER_NAME,
+            Source::FederalReserve => federalreserve::PROVIDER_NAME,
+            Source::MetalsApi => metalsapi::PROVIDER_NAME,
+            Source::Oanda { .. } => oanda::PROVIDER_NAME,
+            Source::Wise => wise::PROVIDER_NAME,
+            Source::Marketstack => marketstack::PROVIDER_NAME,
+            Source::Fmp => fmp::PROVIDER_NAME,
+            Source::Morningstar => morningstar::PROVIDER_NAME,
+            Source::Json => json::PROVIDER_NAME,
+            Source::Csv => csv::PROVIDER_NAME,
+            Source::Html => html::PROVIDER_NAME,
+            Source::BeanPrice => beanprice::PROVIDER_NAME,
+        }
+    }
+
+    /// The environment variable its API key can be given in, for the
+    /// sources which take one (see `--api-key`).
+    pub fn api_key_env(&self) -> Option<&'static str> {
+        match self {
+            Source::AlphaVantage => Some(alphavantage::API_KEY_ENV),
+            Source::CoinGecko => Some(coingecko::API_KEY_ENV),
+            Source::CoinMarketCap => Some(coinmarketcap::API_KEY_ENV),
+            Source::ExchangerateHost => Some(exchangeratehost::API_KEY_ENV),
+            Source::Iex => Some(iex::API_KEY_ENV),
+            Source::Finnhub => Some(finnhub::API_KEY_ENV),
+            Source::Tiingo => Some(tiingo::API_KEY_ENV),
+            Source::Polygon => Some(polygon::API_KEY_ENV),
+            Source::NasdaqDataLink => Some(nasdaqdatalink::API_KEY_ENV),
+            Source::MetalsApi => Some(metalsapi::API_KEY_ENV),
+            Source::Oanda { .. } => Some(oanda::API_KEY_ENV),
+            Source::Marketstack => Some(marketstack::API_KEY_ENV),
+            Source::Fmp => Some(fmp::API_KEY_ENV),
+            Source::Json => Some(json::API_KEY_ENV),
+            _ => None,
+        }
+    }
+
+    /// Whether it can't be used without an API key, rather than only
+    /// being limited more strictly.
+    pub fn api_key_required(&self) -> bool {
+        !matches!(
+            self,
+            Source::CoinGecko | Source::NasdaqDataLink | Source::Json
+        )
+    }
+
+    /// Whether it fetches a whole range at once, rather than a table of
+    /// exchange rates for a day at a time: either the prices of each
+    /// commodity in a request (see [crate::series::Closes]), or every
+    /// table in one (see [Source::is_table()]).
+    pub fn is_bulk(&self) -> bool {
+        matches!(
+            self,
+            Source::Yahoo
+                | Source::AlphaVantage
+                | Source::CoinGecko
+                | Source::CoinMarketCap
+                | Source::Ecb
+                | Source::Frankfurter
+                | Source::ExchangerateHost
+                | Source::Iex
+                | Source::Finnhub
+                | Source::Tiingo
+                | Source::Kraken
+                | Source::Coinbase
+                | Source::Polygon
+                | Source::NasdaqDataLink
+                | Source::Stooq
+                | Source::BankOfCanada
+                | Source::Rba
+                | Source::FederalReserve
+                | Source::MetalsApi
+                | Source::Oanda { .. }
+                | Source::Wise
+                | Source::Marketstack
+                | Source::Fmp
+                | Source::Morningstar
+                | Source::Json
+                | Source::Csv
+                | Source::Html
+                | Source::BeanPrice
+        )
+    }
+
+    /// Whether it is a bulk source which fetches the exchange rate tables
+    /// (or the prices of a batch of commodities) of a whole range in a
+    /// single request, rather than the prices of each commodity.
+    pub fn is_table(&self) -> bool {
+        matches!(
+            self,
+            Source::Ecb
+                | Source::Frankfurter
+                | Source::ExchangerateHost
+                | Source::Iex
+                | Source::BankOfCanada
+                | Source::Rba
+                | Source::FederalReserve
+                | Source::MetalsApi
+                | Source::Marketstack
+                | Source::Csv
+        )
+    }
+
+    /// Whether it quotes the prices of the commodities it is asked for,
+    /// one by one (even when several are fetched in a request), rather
+    /// than tables of exchange rates which cross rates can be derived
+    /// from (see `--pivot`).
+    pub fn quotes_prices(&self) -> bool {
+        self.is_bulk()
+            && !matches!(
+                self,
+                Source::Ecb
+                    | Source::Frankfurter
+                    | Source::ExchangerateHost
+                    | Source::BankOfCanada
+                    | Source::Rba
+                    | Source::FederalReserve
+            )
+    }
+
+    /// The most days of prices of a commodity a request can fetch, for
+    /// the bulk sources which split a longer range into several requests
+    /// of their own (each waiting its turn with the rate limiter).
+    pub fn max_days_per_request(&self) -> Option<i64> {
+        match self {
+            Source::Coinbase => Some(coinbase::MAX_DAYS),
+            _ => None,
+        }
+    }
+
+    /// Whether it has intraday prices, which `--snapshot-time` can pick
+    /// the daily price from. The others only have one price per date.
+    pub fn has_intraday(&self) -> bool {
+        matches!(self, Source::Finnhub)
+    }
+
+    /// The source as a [crate::pricesource::PriceSource], making its
+    /// requests as set up by `setup`. An error if it needs an API key and
+    /// there is none, or its config is incomplete.
+    pub fn provider<'a>(&self, setup: Setup<'a>) -> anyhow::Result<Box<dyn DynPriceSource + 'a>> {
+        let Setup {
+            client,
+            config,
+            app_id,
+            api_key,
+            base,
+            alternative,
+            snapshot_time,
+        } = setup;
+        let required_key = || api_key.ok_or_else(|| anyhow!("{} needs an API key", self.name()));
+        Ok(match *self {
+            Source::OpenExchangeRates => Box::new(
+                OpenExchangeRates::new(client, app_id)
+                    .with_symbols(config.symbols.clone())
+                    .with_alternative(alternative),
+            ),
+            Source::Mock { seed } => Box::new(Mock { seed }),
+            Source::Yahoo => Box::new(yahoo::Yahoo::new(client, config, base)),
+            Source::AlphaVantage => Box::new(alphavantage::AlphaVantage::new(
+                client,
+                required_key()?,
+                config,
+                base,
+            )),
+            Source::CoinGecko => Box::new(coingecko::CoinGecko::new(client, api_key, config, base)),
+            Source::CoinMarketCap => Box::new(coinmarketcap::CoinMarketCap::new(
+                client,
+                required_key()?,
+                config,
+                base,
+            )),
+            Source::Ecb => Box::new(ecb::Ecb::new(client)),
+            Source::Frankfurter => Box::new(frankfurter::Frankfurter::new(client, base)),
+            Source::ExchangerateHost => Box::new(exchangeratehost::ExchangerateHost::new(
+                client,
+                required_key()?,
+                base,
+            )),
+            Source::Iex => Box::new(iex::Iex::new(client, required_key()?, config, base)),
+            Source::Finnhub => Box::new(finnhub::Finnhub::new(
+                client,
+                required_key()?,
+                config,
+                base,
+                snapshot_time,
+            )),
+            Source::Tiingo => Box::new(tiingo::Tiingo::new(client, required_key()?, config, base)),
+            Source::Kraken => Box::new(kraken::Kraken::new(client, config, base)),
+            Source::Coinbase => Box::new(coinbase::Coinbase::new(client, config, base)),
+            Source::Polygon => {
+                Box::new(polygon::Polygon::new(client, required_key()?, config, base))
+            }
+            Source::NasdaqDataLink => Box::new(nasdaqdatalink::NasdaqDataLink::new(
+                client, api_key, config, base,
+            )),
+            Source::Stooq => Box::new(stooq::Stooq::new(client, config, base)),
+            Source::BankOfCanada => Box::new(bankofcanada::BankOfCanada::new(client)),
+            Source::Rba => Box::new(rba::Rba::new(client)),
+            Source::FederalReserve => Box::new(federalreserve::FederalReserve::new(client)),
+            Source::MetalsApi => Box::new(metalsapi::MetalsApi::new(
+                client,
+                required_key()?,
+                config,
+                base,
+            )),
+            Source::Oanda { quote } => Box::new(oanda::Oanda::new(
+                client,
+                required_key()?,
+                quote,
+                config,
+                base,
+            )),
+            Source::Wise => Box::new(wise::Wise::new(client, config, base)),
+            Source::Marketstack => Box::new(marketstack::Marketstack::new(
+                client,
+                required_key()?,
+                config,
+                base,
+            )),
+            Source::Fmp => Box::new(fmp::Fmp::new(client, required_key()?, config, base)),
+            Source::Morningstar => Box::new(morningstar::Morningstar::new(client, config, base)),
+            Source::Json => Box::new(json::Json::new(
+                client,
+                json::Template::from_config(config)?,
+                api_key,
+                config,
+                base,
+            )),
+            Source::Csv => Box::new(csv::Csv::new(
+                csv::Import::from_config(config)?,
+                config,
+                base,
+            )),
+            Source::Html => Box::new(html::Html::new(
+                client,
+                html::Page::from_config(config)?,
+                config,
+                base,
+            )),
+            Source::BeanPrice => Box::new(beanprice::BeanPrice::new(
+                beanprice::Bridge::from_config(config),
+                config,
+                base,
+            )),
+        })
+    }
+
+    /// The rate limit it imposes, used unless the provider's
+    /// `requests_per_minute` is set in the config.
+    pub fn requests_per_minute(&self) -> Option<u32> {
+        match self {
+            Source::Coinbase => Some(coinbase::REQUESTS_PER_MINUTE),
+            Source::Polygon => Some(polygon::REQUESTS_PER_MINUTE),
+            _ => None,
+        }
+    }
+}
+
+/// What [Source::provider()] needs to make the requests to a source.
+pub struct Setup<'a> {
+    pub client: &'a Client,
+    /// The source's section of the config, with its symbols (and the
+    /// settings of the sources whose requests are set up there).
+    pub config: &'a ProviderConfig,
+    /// The openexchangerates app id.
+    pub app_id: &'a str,
+    /// The API key of the other sources which take one.
+    pub api_key: Option<&'a str>,
+    /// The commodity the prices are quoted in (or against), by the
+    /// sources which can quote in any.
+    pub base: CommodityTypeID,
+    /// Include openexchangerates' alternative rates.
+    pub alternative: bool,
+    /// The time of day of the daily price, for the sources with intraday
+    /// prices (see [Source::has_intraday()]).
+    pub snapshot_time: Option<SnapshotTime>,
+}
@@ -0,0 +1,155 @@
+use crate::{
+    config::ProviderConfig,
+    metadata,
+    network::send_text,
+    pricesource::{self, PriceSource},
+    series::Closes,
+};
+use anyhow::anyhow;
+use chrono::{NaiveDate, NaiveDateTime};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "stooq";
+
+const API_URL: &str = "https://stooq.com/q/d/l/";
+
+/// Stooq's symbol for `symbol` against `base`: the forex pair of an ISO
+/// 4217 currency and `base` (e.g. `eurusd`), `symbol` itself if it has a
+/// market (e.g. `vod.uk`) or is an index (e.g. `^spx`), otherwise a US
+/// ticker.
+fn stooq_symbol(symbol: &str, base: CommodityTypeID) -> String {
+    let symbol = if metadata::iso_decimal_places(symbol).is_some() {
+        format!("{}{}", symbol, base)
+    } else if symbol.contains('.') || symbol.starts_with('^') {
+        symbol.to_string()
+    } else {
+        format!("{}.us", symbol)
+    };
+    symbol.to_lowercase()
+}
+
+/// Fetch the daily closes of `symbol` (see [stooq_symbol()]) from `start`
+/// to `end` from Stooq's CSV download, as the prices of `commodity`. Stooq
+/// doesn't say which currency a symbol is quoted in, so its prices are
+/// taken to be in `base`.
+pub async fn get_closes(
+    client: &Client,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Closes> {
+    let symbol = stooq_symbol(symbol, base);
+    let url = format!(
+        "{}?s={}&d1={}&d2={}&i=d",
+        API_URL,
+        url::form_urlencoded::byte_serialize(symbol.as_bytes()).collect::<String>(),
+        start.format("%Y%m%d"),
+        end.format("%Y%m%d"),
+    );
+    let csv = send_text(client.get(&url)).await?;
+    let closes = parse_csv(&csv, start, end)
+        .map_err(|err| anyhow!("Unable to read the prices of {}: {}", symbol, err))?;
+
+    Ok(Closes {
+        commodity,
+        currency: base,
+        closes,
+    })
+}
+
+/// The closes from `start` to `end` in a CSV with a header line naming
+/// its `Date` and `Close` columns, and a line for each day. Stooq
+/// responds with `No data` instead for a symbol it doesn't have.
+fn parse_csv(
+    csv: &str,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<BTreeMap<NaiveDate, Decimal>> {
+    let mut lines = csv.lines().map(str::trim).filter(|line| !line.is_empty());
+    let header: Vec<&str> = match lines.next() {
+        Some(header) if header.contains(',') => header.split(',').map(str::trim).collect(),
+        Some(message) => return Err(anyhow!("{} responded {:?}", PROVIDER_NAME, message)),
+        None => return Ok(BTreeMap::new()),
+    };
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|column| column.eq_ignore_ascii_case(name))
+            .ok_or_else(|| anyhow!("the CSV has no {} column", name))
+    };
+    let (date_column, close_column) = (column("Date")?, column("Close")?);
+
+    let mut closes = BTreeMap::new();
+    for line in lines {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let (date, close) = match (fields.get(date_column), fields.get(close_column)) {
+            (Some(date), Some(close)) => (*date, *close),
+            _ => return Err(anyhow!("expected a date and a close in {:?}", line)),
+        };
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|err| anyhow!("unable to parse the date {:?}: {}", date, err))?;
+        if !(start..=end).contains(&&date) {
+            continue;
+        }
+        let close = Decimal::from_str(close)
+            .or_else(|_| Decimal::from_scientific(close))
+            .map_err(|err| anyhow!("unable to parse the close {:?}: {}", close, err))?;
+        closes.insert(date, close);
+    }
+    Ok(closes)
+}
+
+/// Stooq as a [PriceSource], with the symbols in its `config` and the
+/// prices in `base`.
+pub struct Stooq<'a> {
+    client: &'a Client,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> Stooq<'a> {
+    pub fn new(client: &'a Client, config: &'a ProviderConfig, base: CommodityTypeID) -> Stooq<'a> {
+        Stooq {
+            client,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for Stooq<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            self.client,
+            commodity,
+            &self.config.symbol(&commodity),
+            self.base,
+            start,
+            end,
+        )
+        .await
+    }
+}
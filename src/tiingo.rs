@@ -0,0 +1,154 @@
+use crate::{
+    config::ProviderConfig,
+    network::{send_json, RequestError},
+    pricesource::{self, PriceSource},
+    series::Closes,
+};
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::{header::AUTHORIZATION, Client, StatusCode};
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "tiingo";
+
+/// The environment variable the API token can be given in.
+pub const API_KEY_ENV: &str = "TIINGO_API_TOKEN";
+
+const API_URL: &str = "https://api.tiingo.com/tiingo/daily";
+
+/// Number of days of prices requested at a time, so a long range comes
+/// back in responses of a reasonable size.
+const PAGE_DAYS: i64 = 5 * 365;
+
+/// A day in a `/tiingo/daily/{ticker}/prices` response.
+#[derive(Deserialize, Debug)]
+struct Day {
+    date: DateTime<Utc>,
+    /// The unadjusted close.
+    close: Option<f64>,
+}
+
+/// The number of requests fetching a commodity from `start` to `end`
+/// takes.
+pub fn requests(start: &NaiveDate, end: &NaiveDate) -> usize {
+    let days = (end.signed_duration_since(*start).num_days() + 1).max(1) as usize;
+    days.div_ceil(PAGE_DAYS as usize)
+}
+
+/// Fetch the end of day closes of the stock or fund `symbol` (its Tiingo
+/// ticker) from `start` to `end`, as the prices of `commodity`, a page of
+/// up to 5 years a request. Tiingo doesn't say which currency a ticker is
+/// quoted in, so its prices are taken to be in `base`.
+pub async fn get_closes(
+    client: &Client,
+    api_token: &str,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Closes> {
+    let ticker =
+        url::form_urlencoded::byte_serialize(symbol.to_lowercase().as_bytes()).collect::<String>();
+    let mut closes = BTreeMap::new();
+    let mut page_start = *start;
+    while page_start <= *end {
+        let page_end = std::cmp::min(*end, page_start + Duration::days(PAGE_DAYS - 1));
+        let url = format!(
+            "{}/{}/prices?startDate={}&endDate={}",
+            API_URL,
+            ticker,
+            page_start.format("%Y-%m-%d"),
+            page_end.format("%Y-%m-%d"),
+        );
+        let days: Vec<Day> = send_json(
+            client
+                .get(&url)
+                .header(AUTHORIZATION, format!("Token {}", api_token)),
+        )
+        .await
+        .map_err(|error| match error.downcast_ref::<RequestError>() {
+            Some(RequestError::Status(StatusCode::NOT_FOUND)) => {
+                anyhow!("{} has no ticker {:?}", PROVIDER_NAME, symbol)
+            }
+            _ => error,
+        })?;
+        for day in days {
+            let date = day.date.date_naive();
+            if let (true, Some(close)) = (
+                (start..=end).contains(&&date),
+                day.close.and_then(Decimal::from_f64),
+            ) {
+                closes.insert(date, close);
+            }
+        }
+        page_start = page_end + Duration::days(1);
+    }
+
+    Ok(Closes {
+        commodity,
+        currency: base,
+        closes,
+    })
+}
+
+/// Tiingo as a [PriceSource], with the symbols in its `config` and the
+/// prices in `base`.
+pub struct Tiingo<'a> {
+    client: &'a Client,
+    api_key: &'a str,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> Tiingo<'a> {
+    pub fn new(
+        client: &'a Client,
+        api_key: &'a str,
+        config: &'a ProviderConfig,
+        base: CommodityTypeID,
+    ) -> Tiingo<'a> {
+        Tiingo {
+            client,
+            api_key,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for Tiingo<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            self.client,
+            self.api_key,
+            commodity,
+            &self.config.symbol(&commodity),
+            self.base,
+            start,
+            end,
+        )
+        .await
+    }
+}
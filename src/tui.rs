@@ -0,0 +1,403 @@
+use chrono::NaiveDate;
+use commodity::CommodityTypeID;
+
+/// Something to do with a range of cached dates, marked in the browser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Fetch the rates for the dates again, replacing the cached ones.
+    Refetch { start: NaiveDate, end: NaiveDate },
+    /// Write the prices of `commodity` in `base` for the dates.
+    Export {
+        commodity: CommodityTypeID,
+        base: CommodityTypeID,
+        start: NaiveDate,
+        end: NaiveDate,
+    },
+}
+
+#[cfg(feature = "tui")]
+pub use browser::browse;
+
+/// Browse the prices in `series` (in terms of `base`), returning the
+/// actions marked while browsing. This build doesn't support it.
+#[cfg(not(feature = "tui"))]
+pub fn browse(
+    _series: &crate::series::TimeSeries,
+    _base: CommodityTypeID,
+) -> anyhow::Result<Vec<Action>> {
+    Err(anyhow::anyhow!(
+        "This build doesn't support the TUI, reinstall with `--features tui`"
+    ))
+}
+
+#[cfg(feature = "tui")]
+mod browser {
+    use super::Action;
+    use crate::{prices::PriceSeries, series::TimeSeries};
+    use anyhow::anyhow;
+    use chrono::NaiveDate;
+    use commodity::CommodityTypeID;
+    use ratatui::{
+        backend::CrosstermBackend,
+        crossterm::{
+            event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+            execute,
+            terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+        },
+        layout::{Constraint, Layout},
+        style::{Color, Modifier, Style},
+        symbols::Marker,
+        widgets::{
+            Axis, Block, Chart, Dataset, GraphType, List, ListState, Paragraph, Row, Table,
+            TableState,
+        },
+        Frame, Terminal,
+    };
+    use rust_decimal::prelude::ToPrimitive;
+    use std::{
+        collections::BTreeSet,
+        io::{IsTerminal, Stderr},
+    };
+
+    /// How far Page Up and Page Down move.
+    const PAGE: isize = 20;
+
+    const HELP: &str = "↑↓ move  tab switch pane  m mark  r re-fetch  e export  esc unmark  q quit";
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Focus {
+        Pairs,
+        Dates,
+    }
+
+    struct Browser<'a> {
+        series: &'a TimeSeries,
+        base: CommodityTypeID,
+        commodities: Vec<CommodityTypeID>,
+        pair: ListState,
+        prices: PriceSeries,
+        /// Every date in the series, whether or not the pair has a price.
+        dates: Vec<NaiveDate>,
+        cursor: TableState,
+        /// Where the marked range starts, it ends at the cursor.
+        mark: Option<usize>,
+        focus: Focus,
+        actions: Vec<Action>,
+        /// Replaces the help until the next key press.
+        status: Option<String>,
+    }
+
+    /// Browse the prices in `series` (in terms of `base`) in the terminal
+    /// (drawn on stderr, so stdout stays free for the exported prices),
+    /// returning the actions marked while browsing.
+    pub fn browse(series: &TimeSeries, base: CommodityTypeID) -> anyhow::Result<Vec<Action>> {
+        if !std::io::stderr().is_terminal() {
+            return Err(anyhow!("The TUI needs a terminal"));
+        }
+        let mut browser = Browser::new(series, base)?;
+
+        terminal::enable_raw_mode()
+            .map_err(|err| anyhow!("Unable to set up the terminal: {}", err))?;
+        let result = execute!(std::io::stderr(), EnterAlternateScreen)
+            .and_then(|_| Terminal::new(CrosstermBackend::new(std::io::stderr())))
+            .map_err(|err| anyhow!("Unable to set up the terminal: {}", err))
+            .and_then(|mut terminal| browser.run(&mut terminal));
+        // Restored even when drawing failed, so the shell is left usable.
+        let _ = terminal::disable_raw_mode();
+        let _ = execute!(std::io::stderr(), LeaveAlternateScreen);
+
+        result.map(|()| browser.actions)
+    }
+
+    /// `index` moved by `delta`, within `0..len`.
+    fn moved(index: usize, delta: isize, len: usize) -> usize {
+        (index as isize + delta).clamp(0, len.saturating_sub(1) as isize) as usize
+    }
+
+    impl<'a> Browser<'a> {
+        fn new(series: &'a TimeSeries, base: CommodityTypeID) -> anyhow::Result<Browser<'a>> {
+            let commodities: BTreeSet<CommodityTypeID> = series
+                .map
+                .values()
+                .flat_map(|exchange_rate| {
+                    exchange_rate
+                        .rates
+                        .keys()
+                        .copied()
+                        .chain(exchange_rate.base)
+                })
+                .filter(|commodity| *commodity != base)
+                .collect();
+            let commodities: Vec<CommodityTypeID> = commodities.into_iter().collect();
+            let first = *commodities
+                .first()
+                .ok_or_else(|| anyhow!("The cache has no rates to browse"))?;
+
+            let mut cursor = TableState::default();
+            cursor.select(Some(0));
+            Ok(Browser {
+                series,
+                base,
+                prices: PriceSeries::from_time_series(series, first, base, None)?,
+                commodities,
+                pair: ListState::default().with_selected(Some(0)),
+                dates: series.map.keys().copied().collect(),
+                cursor,
+                mark: None,
+                focus: Focus::Dates,
+                actions: Vec::new(),
+                status: None,
+            })
+        }
+
+        fn run(&mut self, terminal: &mut Terminal<CrosstermBackend<Stderr>>) -> anyhow::Result<()> {
+            loop {
+                terminal
+                    .draw(|frame| self.draw(frame))
+                    .map_err(|err| anyhow!("Unable to draw the TUI: {}", err))?;
+                let event =
+                    event::read().map_err(|err| anyhow!("Unable to read the terminal: {}", err))?;
+                if let Event::Key(key) = event {
+                    if key.kind != KeyEventKind::Press {
+                        continue;
+                    }
+                    let interrupted = key.modifiers.contains(KeyModifiers::CONTROL)
+                        && key.code == KeyCode::Char('c');
+                    if interrupted || self.key(key.code)? {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        fn commodity(&self) -> CommodityTypeID {
+            self.commodities[self.pair.selected().unwrap_or(0)]
+        }
+
+        fn cursor(&self) -> usize {
+            self.cursor.selected().unwrap_or(0)
+        }
+
+        /// The indices of the first and last marked dates, or just the
+        /// cursor when nothing is marked.
+        fn range(&self) -> (usize, usize) {
+            let cursor = self.cursor();
+            let mark = self.mark.unwrap_or(cursor);
+            (mark.min(cursor), mark.max(cursor))
+        }
+
+        /// Handle a key press, returning whether to quit.
+        fn key(&mut self, code: KeyCode) -> anyhow::Result<bool> {
+            self.status = None;
+            match code {
+                KeyCode::Char('q') => return Ok(true),
+                KeyCode::Esc if self.mark.is_none() => return Ok(true),
+                KeyCode::Esc => self.mark = None,
+                KeyCode::Tab | KeyCode::BackTab | KeyCode::Left | KeyCode::Right => {
+                    self.focus = match self.focus {
+                        Focus::Pairs => Focus::Dates,
+                        Focus::Dates => Focus::Pairs,
+                    };
+                }
+                KeyCode::Up | KeyCode::Char('k') => self.step(-1)?,
+                KeyCode::Down | KeyCode::Char('j') => self.step(1)?,
+                KeyCode::PageUp => self.step(-PAGE)?,
+                KeyCode::PageDown => self.step(PAGE)?,
+                KeyCode::Home | KeyCode::Char('g') => self.step(isize::MIN / 2)?,
+                KeyCode::End | KeyCode::Char('G') => self.step(isize::MAX / 2)?,
+                KeyCode::Char('m') | KeyCode::Char(' ') => {
+                    self.mark = match self.mark {
+                        Some(_) => None,
+                        None => Some(self.cursor()),
+                    };
+                }
+                KeyCode::Char('r') => {
+                    let (start, end) = self.range();
+                    let (start, end) = (self.dates[start], self.dates[end]);
+                    self.actions.push(Action::Refetch { start, end });
+                    self.marked(format!(
+                        "Marked {} to {} to re-fetch",
+                        start.format("%Y-%m-%d"),
+                        end.format("%Y-%m-%d")
+                    ));
+                }
+                KeyCode::Char('e') => {
+                    let (start, end) = self.range();
+                    let (start, end) = (self.dates[start], self.dates[end]);
+                    self.actions.push(Action::Export {
+                        commodity: self.commodity(),
+                        base: self.base,
+                        start,
+                        end,
+                    });
+                    self.marked(format!(
+                        "Marked {}/{} from {} to {} to export",
+                        self.commodity(),
+                        self.base,
+                        start.format("%Y-%m-%d"),
+                        end.format("%Y-%m-%d")
+                    ));
+                }
+                _ => {}
+            }
+            Ok(false)
+        }
+
+        fn marked(&mut self, status: String) {
+            self.mark = None;
+            self.status = Some(format!("{}, done when you quit", status));
+        }
+
+        /// Move the selection in the focused pane by `delta`.
+        fn step(&mut self, delta: isize) -> anyhow::Result<()> {
+            match self.focus {
+                Focus::Dates => {
+                    let cursor = moved(self.cursor(), delta, self.dates.len());
+                    self.cursor.select(Some(cursor));
+                }
+                Focus::Pairs => {
+                    let pair = moved(
+                        self.pair.selected().unwrap_or(0),
+                        delta,
+                        self.commodities.len(),
+                    );
+                    self.pair.select(Some(pair));
+                    self.prices = PriceSeries::from_time_series(
+                        self.series,
+                        self.commodity(),
+                        self.base,
+                        None,
+                    )?;
+                }
+            }
+            Ok(())
+        }
+
+        fn block(&self, title: String, focus: Focus) -> Block<'static> {
+            let block = Block::bordered().title(title);
+            if self.focus == focus {
+                block.border_style(Style::default().fg(Color::Yellow))
+            } else {
+                block
+            }
+        }
+
+        fn draw(&mut self, frame: &mut Frame<'_>) {
+            let [main, status] =
+                Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+            let [pairs, right] =
+                Layout::horizontal([Constraint::Length(22), Constraint::Min(0)]).areas(main);
+            let [chart, table] =
+                Layout::vertical([Constraint::Percentage(50), Constraint::Min(0)]).areas(right);
+            let pair = format!("{}/{}", self.commodity(), self.base);
+            let (start, end) = self.range();
+            let has_mark = self.mark.is_some();
+            let marked = move |index: usize| has_mark && (start..=end).contains(&index);
+
+            let list = List::new(
+                self.commodities
+                    .iter()
+                    .map(|commodity| format!("{}/{}", commodity, self.base)),
+            )
+            .block(self.block(String::from("Pairs"), Focus::Pairs))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            frame.render_stateful_widget(list, pairs, &mut self.pair);
+
+            // Plotted against the number of days since the first date.
+            let first = self.dates[0];
+            let point = |date: &NaiveDate| {
+                let rate = self.prices.prices.get(date)?.rate.to_f64()?;
+                Some(((*date - first).num_days() as f64, rate))
+            };
+            let mut all: Vec<(f64, f64)> = Vec::new();
+            let mut highlighted: Vec<(f64, f64)> = Vec::new();
+            for (index, date) in self.dates.iter().enumerate() {
+                if let Some(point) = point(date) {
+                    if marked(index) {
+                        highlighted.push(point);
+                    } else {
+                        all.push(point);
+                    }
+                }
+            }
+            let cursor: Vec<(f64, f64)> = point(&self.dates[self.cursor()]).into_iter().collect();
+
+            let rates = all.iter().chain(&highlighted).map(|(_, rate)| *rate);
+            let min = rates.clone().fold(f64::INFINITY, f64::min);
+            let max = rates.fold(f64::NEG_INFINITY, f64::max);
+            let (min, max) = if min < max {
+                (min, max)
+            } else if min.is_finite() {
+                (min - 1.0, max + 1.0)
+            } else {
+                (0.0, 1.0)
+            };
+            let days = (self.dates[self.dates.len() - 1] - first).num_days().max(1) as f64;
+
+            let chart_widget = Chart::new(vec![
+                Dataset::default()
+                    .marker(Marker::Braille)
+                    .graph_type(GraphType::Scatter)
+                    .style(Style::default().fg(Color::Cyan))
+                    .data(&all),
+                Dataset::default()
+                    .marker(Marker::Braille)
+                    .graph_type(GraphType::Scatter)
+                    .style(Style::default().fg(Color::Magenta))
+                    .data(&highlighted),
+                Dataset::default()
+                    .marker(Marker::Block)
+                    .graph_type(GraphType::Scatter)
+                    .style(Style::default().fg(Color::Yellow))
+                    .data(&cursor),
+            ])
+            .block(Block::bordered().title(pair.clone()))
+            .x_axis(Axis::default().bounds([0.0, days]).labels(vec![
+                first.format("%Y-%m-%d").to_string(),
+                self.dates[self.dates.len() - 1]
+                    .format("%Y-%m-%d")
+                    .to_string(),
+            ]))
+            .y_axis(
+                Axis::default()
+                    .bounds([min, max])
+                    .labels(vec![format!("{:.4}", min), format!("{:.4}", max)]),
+            );
+            frame.render_widget(chart_widget, chart);
+
+            let rows = self.dates.iter().enumerate().map(|(index, date)| {
+                let (price, note) = match self.prices.prices.get(date) {
+                    Some(price) => (
+                        price.rate.round_dp(6).to_string(),
+                        price.annotation.clone().unwrap_or_default(),
+                    ),
+                    None => (String::from("-"), String::from("missing")),
+                };
+                let row = Row::new(vec![date.format("%Y-%m-%d").to_string(), price, note]);
+                if marked(index) {
+                    row.style(Style::default().fg(Color::Magenta))
+                } else {
+                    row
+                }
+            });
+            let table_widget = Table::new(
+                rows,
+                [
+                    Constraint::Length(10),
+                    Constraint::Length(18),
+                    Constraint::Min(0),
+                ],
+            )
+            .header(
+                Row::new(vec!["DATE", "PRICE", "NOTE"])
+                    .style(Style::default().add_modifier(Modifier::BOLD)),
+            )
+            .block(self.block(format!("{} dates", self.dates.len()), Focus::Dates))
+            .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            frame.render_stateful_widget(table_widget, table, &mut self.cursor);
+
+            let text = self.status.as_deref().unwrap_or(HELP);
+            frame.render_widget(Paragraph::new(text), status);
+        }
+    }
+}
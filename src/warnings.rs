@@ -0,0 +1,140 @@
+use serde::Serialize;
+use std::{fmt, sync::Mutex};
+
+/// The warnings raised by this process, see [warn()].
+pub static WARNINGS: Warnings = Warnings::new();
+
+/// What a [Warning] is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    /// The commodities couldn't be checked against the provider's list.
+    CommodityCheck,
+    /// A commodity was left out of the selection, e.g. the base commodity.
+    Commodity,
+    /// The range was cut short to the dates the provider has rates for.
+    Coverage,
+    /// Cached rates were used for a date which couldn't be fetched.
+    StaleRates,
+    /// A response was dated differently to the date it was requested for.
+    DateMismatch,
+    /// Several responses resolved to the same date.
+    Collision,
+    /// A requested commodity was missing from some responses.
+    MissingSymbol,
+    /// Dates were left out of the prices.
+    SkippedDates,
+    /// Dates without rates were filled in (forward-filled or
+    /// interpolated).
+    FilledGaps,
+    /// A price moved suspiciously far from the previous one.
+    Outlier,
+    /// A price was outside the bounds configured for its pair.
+    OutOfBounds,
+    /// The rounding makes the inverse of a price asymmetric.
+    Roundtrip,
+    /// There were no prices for a pair.
+    NoPrices,
+    /// The provider's remaining quota is low.
+    QuotaLow,
+    /// A notification couldn't be delivered.
+    Notification,
+    /// The holdings to value couldn't be read exactly, e.g. a journal
+    /// has directives which aren't applied.
+    Holdings,
+    /// A commodity's prices came from a fallback source, because an
+    /// earlier one in its chain failed or had none.
+    Fallback,
+    /// A cache file couldn't be parsed (e.g. a write to it was
+    /// interrupted), so it was treated as missing.
+    Cache,
+}
+
+/// A non-fatal issue met while fetching or computing prices.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Warning {
+    pub kind: Kind,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Warning: {}", self.message)
+    }
+}
+
+/// Collects [Warning]s, so they can be reported together at the end of a
+/// command (see [Warnings::defer()]) rather than interleaved with its
+/// progress. Otherwise each is printed to stderr as it is raised.
+#[derive(Debug)]
+pub struct Warnings {
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    deferred: bool,
+    warnings: Vec<Warning>,
+}
+
+impl Warnings {
+    pub const fn new() -> Warnings {
+        Warnings {
+            state: Mutex::new(State {
+                deferred: false,
+                warnings: Vec::new(),
+            }),
+        }
+    }
+
+    /// Whether to hold the warnings until [Warnings::take()] rather than
+    /// printing them straight away. A long running command (which never
+    /// reaches the end) should print them straight away. Any warnings held
+    /// when that is turned off are printed.
+    pub fn defer(&self, deferred: bool) {
+        let mut state = self.state.lock().expect("warnings lock poisoned");
+        state.deferred = deferred;
+        if !deferred {
+            for warning in state.warnings.drain(..) {
+                eprintln!("{}", warning);
+            }
+        }
+    }
+
+    /// Raise a warning of `kind`.
+    pub fn warn(&self, kind: Kind, message: impl Into<String>) {
+        let warning = Warning {
+            kind,
+            message: message.into(),
+        };
+        let mut state = self.state.lock().expect("warnings lock poisoned");
+        if state.deferred {
+            state.warnings.push(warning);
+        } else {
+            eprintln!("{}", warning);
+        }
+    }
+
+    /// The warnings held since the last call.
+    pub fn take(&self) -> Vec<Warning> {
+        std::mem::take(&mut self.state.lock().expect("warnings lock poisoned").warnings)
+    }
+
+    /// Print the warnings held since the last call to stderr.
+    pub fn print(&self) {
+        for warning in self.take() {
+            eprintln!("{}", warning);
+        }
+    }
+}
+
+impl Default for Warnings {
+    fn default() -> Self {
+        Warnings::new()
+    }
+}
+
+/// Raise a warning of `kind` with [WARNINGS].
+pub fn warn(kind: Kind, message: impl Into<String>) {
+    WARNINGS.warn(kind, message)
+}
@@ -0,0 +1,119 @@
+use crate::{
+    config::ProviderConfig,
+    network::request_json,
+    pricesource::{self, PriceSource},
+    series::Closes,
+};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::Client;
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "wise";
+
+/// The rate history behind Wise's currency converter, which needs no
+/// account.
+const API_URL: &str = "https://wise.com/rates/history";
+
+/// A point in the rate history.
+#[derive(Deserialize, Debug)]
+struct Rate {
+    value: f64,
+    /// When the rate is from (UNIX time in milliseconds).
+    time: i64,
+}
+
+/// Fetch Wise's daily mid-market rate of the currency `symbol` in `base`
+/// from `start` to `end`, as the prices of `commodity`. The history is of
+/// the days up to today, so a range far in the past fetches all the days
+/// since too.
+pub async fn get_closes(
+    client: &Client,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    base: CommodityTypeID,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Closes> {
+    let length = (Utc::now().date_naive() - *start).num_days().max(0) + 1;
+    let url = format!(
+        "{}?source={}&target={}&length={}&resolution=daily&unit=day",
+        API_URL,
+        url::form_urlencoded::byte_serialize(symbol.as_bytes()).collect::<String>(),
+        base,
+        length,
+    );
+    let rates: Vec<Rate> = request_json(client, &url).await?;
+
+    let mut closes = BTreeMap::new();
+    for rate in rates {
+        let date = match NaiveDateTime::from_timestamp_millis(rate.time) {
+            Some(time) => time.date(),
+            None => continue,
+        };
+        if let (true, Some(value)) = (
+            (start..=end).contains(&&date),
+            Decimal::from_f64(rate.value),
+        ) {
+            closes.insert(date, value);
+        }
+    }
+
+    Ok(Closes {
+        commodity,
+        currency: base,
+        closes,
+    })
+}
+
+/// Wise as a [PriceSource], with the symbols in its `config` and the prices
+/// in `base`.
+pub struct Wise<'a> {
+    client: &'a Client,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> Wise<'a> {
+    pub fn new(client: &'a Client, config: &'a ProviderConfig, base: CommodityTypeID) -> Wise<'a> {
+        Wise {
+            client,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for Wise<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            self.client,
+            commodity,
+            &self.config.symbol(&commodity),
+            self.base,
+            start,
+            end,
+        )
+        .await
+    }
+}
@@ -0,0 +1,229 @@
+use crate::{
+    config::ProviderConfig,
+    network::{send_json, RequestError},
+    pricesource::{self, PriceSource},
+    series::Closes,
+};
+use anyhow::anyhow;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use commodity::{exchange_rate::ExchangeRate, CommodityTypeID};
+use reqwest::{header::USER_AGENT, Client, StatusCode};
+use rust_decimal::{prelude::FromPrimitive, Decimal};
+use serde::Deserialize;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Name of this source, for `--source` and the `providers` section of
+/// the config.
+pub const PROVIDER_NAME: &str = "yahoo";
+
+const API_URL: &str = "https://query1.finance.yahoo.com/v8/finance/chart";
+
+/// Data from the (undocumented) chart api, e.g.
+/// https://query1.finance.yahoo.com/v8/finance/chart/AAPL?interval=1d
+#[derive(Deserialize, Debug)]
+struct ChartResponse {
+    chart: Chart,
+}
+
+#[derive(Deserialize, Debug)]
+struct Chart {
+    result: Option<Vec<ChartResult>>,
+    error: Option<ChartError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChartError {
+    code: String,
+    description: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChartResult {
+    meta: Meta,
+    /// When each trading day's candle starts (UTC), missing when there
+    /// were none in the range.
+    #[serde(default)]
+    timestamp: Vec<i64>,
+    indicators: Indicators,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Meta {
+    currency: Option<String>,
+    symbol: String,
+    /// Offset of the exchange's time zone from UTC, in seconds.
+    gmtoffset: i64,
+    /// Number of decimal places the prices are given to.
+    price_hint: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Indicators {
+    quote: Vec<Quote>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Quote {
+    /// The closing price on each day in `timestamp`, `None` for a day
+    /// without trades.
+    #[serde(default)]
+    close: Vec<Option<f64>>,
+}
+
+/// Fetch the daily closing prices of the Yahoo Finance ticker `symbol`
+/// (e.g. `AAPL`, `VTI` or `BHP.AX`) from `start` to `end`, each dated in
+/// the exchange's time zone, as the prices of `commodity`.
+pub async fn get_closes(
+    client: &Client,
+    commodity: CommodityTypeID,
+    symbol: &str,
+    start: &NaiveDate,
+    end: &NaiveDate,
+) -> anyhow::Result<Closes> {
+    // A day either side, the exchange's time zone isn't known until the
+    // response.
+    let timestamp = |date: NaiveDate| {
+        date.and_hms_opt(0, 0, 0)
+            .expect("midnight is a valid time")
+            .and_utc()
+            .timestamp()
+    };
+    let url = format!(
+        "{api_url}/{symbol}?period1={period1}&period2={period2}&interval=1d",
+        api_url = API_URL,
+        symbol = url::form_urlencoded::byte_serialize(symbol.as_bytes()).collect::<String>(),
+        period1 = timestamp(*start - Duration::days(1)),
+        period2 = timestamp(*end + Duration::days(2)),
+    );
+    // The api refuses requests without a user agent.
+    let response: ChartResponse = send_json(client.get(&url).header(
+        USER_AGENT,
+        concat!("beancount-price-fetcher/", env!("CARGO_PKG_VERSION")),
+    ))
+    .await
+    .map_err(|error| match error.downcast_ref::<RequestError>() {
+        Some(RequestError::Status(StatusCode::NOT_FOUND)) => {
+            anyhow!("{} has no ticker {:?}", PROVIDER_NAME, symbol)
+        }
+        _ => error,
+    })?;
+
+    if let Some(error) = response.chart.error {
+        return Err(anyhow!(
+            "{} has no prices for {}: {} ({})",
+            PROVIDER_NAME,
+            symbol,
+            error.description,
+            error.code
+        ));
+    }
+    let result = response
+        .chart
+        .result
+        .and_then(|results| results.into_iter().next())
+        .ok_or_else(|| anyhow!("{} has no prices for {}", PROVIDER_NAME, symbol))?;
+
+    let currency = result.meta.currency.as_deref().ok_or_else(|| {
+        anyhow!(
+            "{} doesn't say which currency {} is quoted in",
+            PROVIDER_NAME,
+            result.meta.symbol
+        )
+    })?;
+    // London, Johannesburg and Tel Aviv listings are quoted in the minor
+    // unit of their currency.
+    let (currency, in_minor_unit) = match currency {
+        "GBp" | "GBX" => ("GBP", true),
+        "ZAc" => ("ZAR", true),
+        "ILA" => ("ILS", true),
+        currency => (currency, false),
+    };
+    let currency = CommodityTypeID::from_str(currency)
+        .map_err(|err| anyhow!("Unable to parse the currency of {}: {}", symbol, err))?;
+
+    let quote_closes = result
+        .indicators
+        .quote
+        .into_iter()
+        .next()
+        .map(|quote| quote.close)
+        .unwrap_or_default();
+    let mut closes = BTreeMap::new();
+    for (timestamp, close) in result.timestamp.iter().zip(quote_closes) {
+        let close = match close.and_then(Decimal::from_f64) {
+            // The prices are floats, rounded to the decimal places they
+            // are quoted to.
+            Some(close) => match result.meta.price_hint {
+                Some(dp) => close.round_dp(dp),
+                None => close,
+            },
+            None => continue,
+        };
+        let close = if in_minor_unit {
+            close / Decimal::ONE_HUNDRED
+        } else {
+            close
+        };
+        let date = match NaiveDateTime::from_timestamp_opt(timestamp + result.meta.gmtoffset, 0) {
+            Some(local) => local.date(),
+            None => continue,
+        };
+        if (start..=end).contains(&&date) {
+            closes.insert(date, close);
+        }
+    }
+
+    Ok(Closes {
+        commodity,
+        currency,
+        closes,
+    })
+}
+
+/// Yahoo Finance as a [PriceSource], with the symbols in its `config`. The
+/// prices are in each symbol's currency, which must be `base`.
+pub struct Yahoo<'a> {
+    client: &'a Client,
+    config: &'a ProviderConfig,
+    base: CommodityTypeID,
+}
+
+impl<'a> Yahoo<'a> {
+    pub fn new(client: &'a Client, config: &'a ProviderConfig, base: CommodityTypeID) -> Yahoo<'a> {
+        Yahoo {
+            client,
+            config,
+            base,
+        }
+    }
+}
+
+impl PriceSource for Yahoo<'_> {
+    fn name(&self) -> &'static str {
+        PROVIDER_NAME
+    }
+
+    async fn latest(
+        &self,
+        include: Option<Vec<CommodityTypeID>>,
+    ) -> anyhow::Result<(ExchangeRate, Option<NaiveDateTime>)> {
+        pricesource::latest_closes(self, self.base, include).await
+    }
+
+    async fn closes(
+        &self,
+        commodity: CommodityTypeID,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> anyhow::Result<Closes> {
+        get_closes(
+            self.client,
+            commodity,
+            &self.config.symbol(&commodity),
+            start,
+            end,
+        )
+        .await
+    }
+}